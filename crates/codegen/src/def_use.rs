@@ -0,0 +1,97 @@
+//! This module contains def-use distance analysis that is used by scheduling
+//! heuristics to prioritize short-lived values.
+
+use rustc_hash::FxHashSet;
+use sonatina_ir::{Function, ValueId};
+
+/// The span of a value that is live across blocks.
+pub const CROSS_BLOCK_SPAN: usize = usize::MAX;
+
+/// Returns the number of insts in the layout order from the definition of the
+/// `value` to its last use, e.g., `1` if the last use immediately follows the
+/// definition, or `0` if the value is unused.
+///
+/// Returns [`CROSS_BLOCK_SPAN`] if the value is used outside its defining
+/// block, or is used by a phi, which reads the value on an incoming edge.
+/// Values that are not defined by an inst, i.e., arguments, immediates and
+/// global values, are considered to live across blocks.
+pub fn def_use_span(func: &Function, value: ValueId) -> usize {
+    let Some(def) = func.dfg.value_inst(value) else {
+        return CROSS_BLOCK_SPAN;
+    };
+    let block = func.layout.inst_block(def);
+
+    let mut users = FxHashSet::default();
+    for &user in func.dfg.users(value) {
+        if func.layout.inst_block(user) != block || func.dfg.is_phi(user) {
+            return CROSS_BLOCK_SPAN;
+        }
+        users.insert(user);
+    }
+
+    let mut span = 0;
+    let mut distance = 0;
+    let mut next = func.layout.next_inst_of(def);
+    while let Some(inst) = next {
+        if users.is_empty() {
+            break;
+        }
+        distance += 1;
+        if users.remove(&inst) {
+            span = distance;
+        }
+        next = func.layout.next_inst_of(inst);
+    }
+
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::{Add, Mul},
+            control_flow::{Jump, Return},
+        },
+        prelude::*,
+        Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn span_in_block() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        let v1 = builder.insert_inst(Mul::new(is, arg, v0), Type::I32);
+        let v2 = builder.insert_inst(Mul::new(is, v1, arg), Type::I32);
+        let v3 = builder.insert_inst(Add::new(is, v2, v0), Type::I32);
+        let v4 = builder.insert_inst(Add::new(is, v3, v3), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Return::new(is, Some(v3)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            assert_eq!(def_use_span(func, v0), 3);
+            assert_eq!(def_use_span(func, v1), 1);
+            assert_eq!(def_use_span(func, v2), 1);
+            assert_eq!(def_use_span(func, v3), CROSS_BLOCK_SPAN);
+            assert_eq!(def_use_span(func, v4), 0);
+            assert_eq!(def_use_span(func, arg), CROSS_BLOCK_SPAN);
+        });
+    }
+}
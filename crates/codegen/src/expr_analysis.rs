@@ -0,0 +1,544 @@
+//! This module contains dataflow analyses over expressions, which are the
+//! building blocks of partial redundancy elimination (PRE).
+//!
+//! An expression is identified by the shape of the inst computing it, i.e., the
+//! opcode, the operands, and the result type. Operands of commutative insts are
+//! sorted so that, e.g., `add v0 v1` and `add v1 v0` are the same expression.
+//!
+//! Since the IR is in SSA form, an operand is never redefined. Instead, an
+//! expression is killed by a block that defines one of its operands, because
+//! the expression can't be computed before the block.
+//!
+//! Memory loads are also expressions, but they are additionally killed by any
+//! inst that may write to memory. No alias analysis is done here, so every
+//! write is conservatively assumed to alias every load.
+
+use std::collections::BTreeSet;
+
+use cranelift_entity::{packed_option::PackedOption, PrimaryMap, SecondaryMap};
+use rustc_hash::FxHashMap;
+use smallvec::SmallVec;
+use sonatina_ir::{
+    inst::{data::Mload, is_commutative, SideEffect},
+    BlockId, ControlFlowGraph, Function, InstDowncast, InstId, Type, ValueId,
+};
+
+/// An opaque reference to an expression in [`ExprTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ExprId(u32);
+cranelift_entity::entity_impl!(ExprId);
+
+/// A table interning the expressions computed in a function, along with the
+/// expressions used and killed locally in each block.
+#[derive(Debug, Default)]
+pub struct ExprTable {
+    exprs: PrimaryMap<ExprId, ExprData>,
+    lookup: FxHashMap<ExprKey, SmallVec<[ExprId; 1]>>,
+    inst_exprs: SecondaryMap<InstId, PackedOption<ExprId>>,
+    local: LocalSets,
+}
+
+impl ExprTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.exprs.clear();
+        self.lookup.clear();
+        self.inst_exprs.clear();
+        self.local = LocalSets::default();
+    }
+
+    /// Intern the expressions of all insts in the function.
+    pub fn compute(&mut self, func: &Function) {
+        self.clear();
+
+        for block in func.layout.iter_block() {
+            for inst in func.layout.iter_inst(block) {
+                if is_candidate(func, inst) {
+                    let expr = self.intern(func, inst);
+                    self.inst_exprs[inst] = expr.into();
+                }
+            }
+        }
+
+        self.local = LocalSets::compute(func, self);
+    }
+
+    /// Returns the expression computed by the `inst`, or `None` if the inst
+    /// isn't a candidate, e.g., it has a side effect.
+    pub fn expr_of(&self, inst: InstId) -> Option<ExprId> {
+        self.inst_exprs[inst].expand()
+    }
+
+    /// Returns the operands of the `expr`, which are sorted if the expression
+    /// is commutative.
+    pub fn args(&self, expr: ExprId) -> &[ValueId] {
+        &self.exprs[expr].key.args
+    }
+
+    /// Returns `true` if the `expr` is computed in the `block` before any of
+    /// its operands or memory is modified in the block.
+    pub fn is_upward_exposed(&self, block: BlockId, expr: ExprId) -> bool {
+        self.local.upward_exposed[block].contains(&expr)
+    }
+
+    /// Returns `true` if the `expr` is computed in the `block`, and neither of
+    /// its operands nor memory is modified after that in the block.
+    pub fn is_downward_exposed(&self, block: BlockId, expr: ExprId) -> bool {
+        self.local.downward_exposed[block].contains(&expr)
+    }
+
+    /// Returns `true` if the `block` neither defines an operand of the `expr`,
+    /// nor may write to memory that the `expr` reads.
+    pub fn is_transparent(&self, block: BlockId, expr: ExprId) -> bool {
+        !self.local.killed[block].contains(&expr)
+    }
+
+    /// Returns `true` if the `expr` reads memory.
+    pub fn is_memory(&self, expr: ExprId) -> bool {
+        self.exprs[expr].is_memory
+    }
+
+    /// Returns the first inst in the layout order that computes the `expr`.
+    pub fn repr_inst(&self, expr: ExprId) -> InstId {
+        self.exprs[expr].repr
+    }
+
+    pub fn len(&self) -> usize {
+        self.exprs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exprs.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ExprId> {
+        self.exprs.keys()
+    }
+
+    fn intern(&mut self, func: &Function, inst: InstId) -> ExprId {
+        let key = ExprKey::new(func, inst);
+        let data = func.dfg.inst(inst);
+
+        let candidates = self.lookup.entry(key.clone()).or_default();
+        let found = candidates.iter().copied().find(|&expr| {
+            // The key is exhaustive for commutative insts, whose operands may be
+            // swapped.
+            is_commutative(key.name) || func.dfg.inst(self.exprs[expr].repr).is_identical(data)
+        });
+
+        found.unwrap_or_else(|| {
+            let expr = self.exprs.push(ExprData {
+                key,
+                repr: inst,
+                is_memory: is_load(func, inst),
+            });
+            candidates.push(expr);
+            expr
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ExprData {
+    key: ExprKey,
+    repr: InstId,
+    is_memory: bool,
+}
+
+/// A hashable summary of an expression.
+/// Insts sharing a key are compared with `Inst::is_identical` unless they are
+/// commutative, since the key doesn't cover non-value fields like a callee.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ExprKey {
+    pub(crate) name: &'static str,
+    args: SmallVec<[ValueId; 2]>,
+    ty: Type,
+}
+
+impl ExprKey {
+    pub(crate) fn new(func: &Function, inst: InstId) -> Self {
+        let data = func.dfg.inst(inst);
+        let mut args = SmallVec::new();
+        data.for_each_value(&mut |value| args.push(value));
+        if is_commutative(data.as_text()) {
+            args.sort_unstable();
+        }
+        let ty = func.dfg.value_ty(func.dfg.inst_result(inst).unwrap());
+
+        Self {
+            name: data.as_text(),
+            args,
+            ty,
+        }
+    }
+}
+
+fn is_candidate(func: &Function, inst: InstId) -> bool {
+    let is_pure_or_load = match func.dfg.side_effect(inst) {
+        SideEffect::None => true,
+        SideEffect::Read => is_load(func, inst),
+        SideEffect::Write => false,
+    };
+    is_pure_or_load
+        && !(func.dfg.is_phi(inst)
+            || func.dfg.is_terminator(inst)
+            || func.dfg.inst_result(inst).is_none())
+}
+
+fn is_load(func: &Function, inst: InstId) -> bool {
+    <&Mload as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(inst)).is_some()
+}
+
+/// Expressions used and killed locally in each block.
+#[derive(Debug, Default)]
+struct LocalSets {
+    /// Expressions computed in the block before any of their operands is
+    /// defined in the block.
+    upward_exposed: SecondaryMap<BlockId, BTreeSet<ExprId>>,
+    /// Expressions computed in the block, and neither of whose operands nor
+    /// memory is modified after that in the block.
+    downward_exposed: SecondaryMap<BlockId, BTreeSet<ExprId>>,
+    /// Expressions having an operand defined in the block, or reading memory
+    /// that the block may write to.
+    killed: SecondaryMap<BlockId, BTreeSet<ExprId>>,
+}
+
+impl LocalSets {
+    fn compute(func: &Function, table: &ExprTable) -> Self {
+        let mut users: FxHashMap<ValueId, Vec<ExprId>> = FxHashMap::default();
+        for expr in table.iter() {
+            for &arg in table.args(expr) {
+                users.entry(arg).or_default().push(expr);
+            }
+        }
+        let memory_exprs: Vec<_> = table.iter().filter(|&e| table.is_memory(e)).collect();
+
+        let mut sets = Self::default();
+        for block in func.layout.iter_block() {
+            for inst in func.layout.iter_inst(block) {
+                if let Some(expr) = table.expr_of(inst) {
+                    if !sets.killed[block].contains(&expr) {
+                        sets.upward_exposed[block].insert(expr);
+                    }
+                    sets.downward_exposed[block].insert(expr);
+                }
+
+                let mut killed = Vec::new();
+                if matches!(func.dfg.side_effect(inst), SideEffect::Write) {
+                    killed.extend_from_slice(&memory_exprs);
+                }
+                if let Some(exprs) = func.dfg.inst_result(inst).and_then(|r| users.get(&r)) {
+                    killed.extend_from_slice(exprs);
+                }
+                for expr in killed {
+                    sets.downward_exposed[block].remove(&expr);
+                    sets.killed[block].insert(expr);
+                }
+            }
+        }
+
+        sets
+    }
+}
+
+/// An analysis of anticipated (a.k.a. very busy) expressions, i.e., the
+/// expressions that are computed on every path from a point to the exit before
+/// any of their operands is defined.
+///
+/// An expression anticipated at a point can be computed there without
+/// introducing a computation on any path, which makes the point a safe
+/// insertion point for PRE.
+#[derive(Debug, Default)]
+pub struct Anticipated {
+    antic_in: SecondaryMap<BlockId, BTreeSet<ExprId>>,
+    antic_out: SecondaryMap<BlockId, BTreeSet<ExprId>>,
+}
+
+impl Anticipated {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.antic_in.clear();
+        self.antic_out.clear();
+    }
+
+    pub fn compute(&mut self, table: &ExprTable, cfg: &ControlFlowGraph) {
+        self.clear();
+        let local = &table.local;
+
+        // Start from the full set and shrink to the greatest fixed point.
+        let post_order = cfg.order().post_order();
+        let universe: BTreeSet<_> = table.iter().collect();
+        for &block in post_order {
+            self.antic_in[block] = universe.clone();
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in post_order {
+                let mut succs = cfg.succs_of(block);
+                let out = match succs.next() {
+                    Some(&first) => {
+                        let mut out = self.antic_in[first].clone();
+                        for &succ in succs {
+                            out.retain(|expr| self.antic_in[succ].contains(expr));
+                        }
+                        out
+                    }
+                    None => BTreeSet::new(),
+                };
+
+                let mut in_ = local.upward_exposed[block].clone();
+                in_.extend(out.difference(&local.killed[block]).copied());
+
+                if in_ != self.antic_in[block] {
+                    self.antic_in[block] = in_;
+                    changed = true;
+                }
+                self.antic_out[block] = out;
+            }
+        }
+    }
+
+    /// Returns `true` if the `expr` is anticipated at the entry of the `block`.
+    pub fn is_anticipated_in(&self, block: BlockId, expr: ExprId) -> bool {
+        self.antic_in[block].contains(&expr)
+    }
+
+    /// Returns `true` if the `expr` is anticipated at the exit of the `block`.
+    pub fn is_anticipated_out(&self, block: BlockId, expr: ExprId) -> bool {
+        self.antic_out[block].contains(&expr)
+    }
+
+    /// Returns the expressions anticipated at the entry of the `block`.
+    pub fn anticipated_in(&self, block: BlockId) -> impl Iterator<Item = ExprId> + '_ {
+        self.antic_in[block].iter().copied()
+    }
+
+    /// Returns the expressions anticipated at the exit of the `block`.
+    pub fn anticipated_out(&self, block: BlockId) -> impl Iterator<Item = ExprId> + '_ {
+        self.antic_out[block].iter().copied()
+    }
+}
+
+/// An analysis of available expressions, i.e., the expressions that are
+/// computed on every path from the entry to a point, and neither of whose
+/// operands nor memory is modified since then.
+///
+/// An expression available at a point is redundant there, and PRE inserts
+/// computations so that partially available expressions become available.
+#[derive(Debug, Default)]
+pub struct Available {
+    avail_in: SecondaryMap<BlockId, BTreeSet<ExprId>>,
+    avail_out: SecondaryMap<BlockId, BTreeSet<ExprId>>,
+}
+
+impl Available {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.avail_in.clear();
+        self.avail_out.clear();
+    }
+
+    pub fn compute(&mut self, table: &ExprTable, cfg: &ControlFlowGraph) {
+        self.clear();
+        let local = &table.local;
+
+        let rpo = cfg.order().rpo();
+
+        // Start from the full set and shrink to the greatest fixed point.
+        // Blocks unreachable from the entry are never visited, and are ignored
+        // as predecessors.
+        let universe: BTreeSet<_> = table.iter().collect();
+        let mut reachable = SecondaryMap::new();
+        for &block in rpo {
+            self.avail_out[block] = universe.clone();
+            reachable[block] = true;
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in rpo {
+                let mut preds = cfg.preds_of(block).filter(|&&pred| reachable[pred]);
+                let in_ = match preds.next() {
+                    Some(&first) if cfg.entry() != Some(block) => {
+                        let mut in_ = self.avail_out[first].clone();
+                        for &pred in preds {
+                            in_.retain(|expr| self.avail_out[pred].contains(expr));
+                        }
+                        in_
+                    }
+                    _ => BTreeSet::new(),
+                };
+
+                let mut out = local.downward_exposed[block].clone();
+                out.extend(in_.difference(&local.killed[block]).copied());
+
+                if out != self.avail_out[block] {
+                    self.avail_out[block] = out;
+                    changed = true;
+                }
+                self.avail_in[block] = in_;
+            }
+        }
+    }
+
+    /// Returns `true` if the `expr` is available at the entry of the `block`.
+    pub fn is_available(&self, block: BlockId, expr: ExprId) -> bool {
+        self.avail_in[block].contains(&expr)
+    }
+
+    /// Returns `true` if the `expr` is available at the exit of the `block`.
+    pub fn is_available_out(&self, block: BlockId, expr: ExprId) -> bool {
+        self.avail_out[block].contains(&expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::{Add, Mul},
+            control_flow::{Br, Jump, Return},
+            data::Mstore,
+        },
+        prelude::*,
+    };
+
+    use super::*;
+
+    #[test]
+    fn anticipated_at_branch() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let (cond, arg) = (builder.args()[0], builder.args()[1]);
+
+        builder.switch_to_block(b0);
+        let one = builder.make_imm_value(1i32);
+        let v0 = builder.insert_inst(Add::new(is, arg, one), Type::I32);
+        builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+        // Both arms compute `mul v0 arg`, with the operands swapped in `b2`.
+        builder.switch_to_block(b1);
+        let v1 = builder.insert_inst(Mul::new(is, v0, arg), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        let v2 = builder.insert_inst(Mul::new(is, arg, v0), Type::I32);
+        let v3 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result(Return::new(is, Some(arg)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            let mut table = ExprTable::new();
+            table.compute(func);
+            let mut antic = Anticipated::new();
+            antic.compute(&table, &cfg);
+
+            let expr_of = |value| {
+                let inst = func.dfg.value_inst(value).unwrap();
+                table.expr_of(inst).unwrap()
+            };
+            let (add, mul, double) = (expr_of(v0), expr_of(v1), expr_of(v3));
+            assert_eq!(mul, expr_of(v2));
+
+            // `mul` is computed on both arms, but `v0` is defined in `b0`.
+            assert!(antic.is_anticipated_out(b0, mul));
+            assert!(!antic.is_anticipated_in(b0, mul));
+            assert!(antic.is_anticipated_in(b0, add));
+
+            // `add arg arg` is computed only on one arm.
+            assert!(antic.is_anticipated_in(b2, double));
+            assert!(!antic.is_anticipated_out(b0, double));
+            assert_eq!(antic.anticipated_in(b3).count(), 0);
+        });
+    }
+
+    #[test]
+    fn available_at_join() {
+        let mb = test_module_builder();
+        let ptr = mb.ptr_type(Type::I32);
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1, Type::I32, ptr], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let (cond, arg, addr) = (builder.args()[0], builder.args()[1], builder.args()[2]);
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        let v1 = builder.insert_inst(Mload::new(is, addr, Type::I32), Type::I32);
+        builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+        // Only `b1` computes `mul`, and only `b2` writes to memory.
+        builder.switch_to_block(b1);
+        let v2 = builder.insert_inst(Mul::new(is, arg, arg), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result(Mstore::new(is, addr, arg, Type::I32));
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            let mut table = ExprTable::new();
+            table.compute(func);
+            let mut avail = Available::new();
+            avail.compute(&table, &cfg);
+
+            let expr_of = |value| {
+                let inst = func.dfg.value_inst(value).unwrap();
+                table.expr_of(inst).unwrap()
+            };
+            let (add, load, mul) = (expr_of(v0), expr_of(v1), expr_of(v2));
+            assert!(table.is_memory(load));
+
+            assert!(!avail.is_available(b0, add));
+            assert!(avail.is_available(b3, add));
+
+            // The load is killed by the store in `b2`.
+            assert!(avail.is_available(b1, load));
+            assert!(!avail.is_available_out(b2, load));
+            assert!(!avail.is_available(b3, load));
+
+            // `mul` is available on only one of the predecessors.
+            assert!(avail.is_available_out(b1, mul));
+            assert!(!avail.is_available(b3, mul));
+        });
+    }
+}
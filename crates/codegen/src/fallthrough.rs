@@ -0,0 +1,86 @@
+//! This module contains the computation of fallthrough chains.
+//!
+//! When a block is laid out right before the block that it branches to, the
+//! final assembler can omit the jump and let the control fall through to the
+//! next block.
+
+use cranelift_entity::SecondaryMap;
+use sonatina_ir::{inst::control_flow::Br, BlockId, Function, InstDowncast, InstId, Layout};
+
+/// Returns the successor each block falls through to when the blocks are
+/// placed in the order of the `layout`.
+///
+/// A block falls through if it ends with a `jump` to the next block, or with a
+/// `br` whose zero destination is the next block, since the EVM `JUMPI`
+/// continues to the next instruction when the condition is zero. Other blocks
+/// are mapped to `None`.
+pub fn compute_fallthroughs(
+    func: &Function,
+    layout: &Layout,
+) -> SecondaryMap<BlockId, Option<BlockId>> {
+    let mut fallthroughs = SecondaryMap::new();
+
+    for block in layout.iter_block() {
+        let (Some(next), Some(term)) = (layout.next_block_of(block), layout.last_inst_of(block))
+        else {
+            continue;
+        };
+
+        if fallthrough_dest(func, term) == Some(next) {
+            fallthroughs[block] = Some(next);
+        }
+    }
+
+    fallthroughs
+}
+
+/// Returns the destination of the terminator `term` that can be reached by
+/// falling through, i.e., the destination of a `jump`, or the zero
+/// destination of a `br`.
+pub(crate) fn fallthrough_dest(func: &Function, term: InstId) -> Option<BlockId> {
+    if let Some(jump) = func.dfg.cast_jump(term) {
+        Some(*jump.dest())
+    } else {
+        <&Br as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(term)).map(|br| *br.z_dest())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::control_flow::{Jump, Return},
+        isa::Isa,
+        Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn two_block_chain() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let fallthroughs = module
+            .func_store
+            .view(func_ref, |func| compute_fallthroughs(func, &func.layout));
+
+        assert_eq!(fallthroughs[b0], Some(b1));
+        assert_eq!(fallthroughs[b1], None);
+    }
+}
@@ -0,0 +1,418 @@
+//! This module contains induction variable analysis.
+//!
+//! Only basic induction variables are recognized, i.e., a phi in a loop header
+//! that starts with a constant and is incremented by a constant step on every
+//! back edge.
+
+use cranelift_entity::SecondaryMap;
+use smallvec::SmallVec;
+use sonatina_ir::{
+    inst::{
+        arith::{Add, Sub},
+        cmp::{Ge, Gt, Le, Lt, Ne, Sge, Sgt, Sle, Slt},
+        control_flow::Br,
+    },
+    ControlFlowGraph, Function, InstDowncast, Type, ValueId, I256,
+};
+
+use crate::loop_analysis::{Loop, LoopTree};
+
+#[derive(Debug, Default)]
+pub struct IndVarAnalysis {
+    ind_vars: SecondaryMap<Loop, SmallVec<[IndVar; 2]>>,
+    trip_counts: SecondaryMap<Loop, TripCount>,
+}
+
+/// A basic induction variable of a loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndVar {
+    /// The result of the phi in the loop header.
+    pub value: ValueId,
+    /// The initial value on entering the loop.
+    pub start: i128,
+    /// The value added on each iteration.
+    pub step: i128,
+}
+
+/// An estimate of the number of times a loop body is executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TripCount {
+    /// The loop always iterates exactly `n` times.
+    Exact(u128),
+    /// The loop iterates at most `n` times, e.g., it has other exits than the
+    /// one controlled by the induction variable.
+    AtMost(u128),
+    #[default]
+    Unknown,
+}
+
+impl IndVarAnalysis {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.ind_vars.clear();
+        self.trip_counts.clear();
+    }
+
+    pub fn compute(&mut self, func: &Function, cfg: &ControlFlowGraph, lpt: &LoopTree) {
+        self.clear();
+
+        for lp in lpt.loops() {
+            self.collect_ind_vars(func, lpt, lp);
+            self.trip_counts[lp] = self.compute_trip_count(func, cfg, lpt, lp);
+        }
+    }
+
+    /// Returns basic induction variables of the `lp`.
+    pub fn ind_vars(&self, lp: Loop) -> &[IndVar] {
+        &self.ind_vars[lp]
+    }
+
+    /// Returns the trip count estimate of the `lp`.
+    pub fn trip_count(&self, lp: Loop) -> TripCount {
+        self.trip_counts[lp]
+    }
+
+    fn collect_ind_vars(&mut self, func: &Function, lpt: &LoopTree, lp: Loop) {
+        let header = lpt.loop_header(lp);
+        for inst in func.layout.iter_inst(header) {
+            let Some(phi) = func.dfg.cast_phi(inst) else {
+                break;
+            };
+            let [(arg0, pred0), (arg1, pred1)] = phi.args().as_slice() else {
+                continue;
+            };
+            let (start, next) = match (lpt.is_in_loop(*pred0, lp), lpt.is_in_loop(*pred1, lp)) {
+                (false, true) => (*arg0, *arg1),
+                (true, false) => (*arg1, *arg0),
+                _ => continue,
+            };
+
+            let value = func.dfg.inst_result(inst).unwrap();
+            let Some(start) = imm_value(func, start) else {
+                continue;
+            };
+            let Some(step) = step_of(func, value, next) else {
+                continue;
+            };
+
+            self.ind_vars[lp].push(IndVar { value, start, step });
+        }
+    }
+
+    fn compute_trip_count(
+        &self,
+        func: &Function,
+        cfg: &ControlFlowGraph,
+        lpt: &LoopTree,
+        lp: Loop,
+    ) -> TripCount {
+        let header = lpt.loop_header(lp);
+        let Some(term) = func.layout.last_inst_of(header) else {
+            return TripCount::Unknown;
+        };
+        let Some(br) = <&Br as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(term)) else {
+            return TripCount::Unknown;
+        };
+
+        // Make sure the header exits the loop, and find the condition to
+        // continue the loop.
+        let continue_if_nz = match (
+            lpt.is_in_loop(*br.nz_dest(), lp),
+            lpt.is_in_loop(*br.z_dest(), lp),
+        ) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => return TripCount::Unknown,
+        };
+
+        let Some((pred, lhs, rhs)) = cmp_of(func, *br.cond()) else {
+            return TripCount::Unknown;
+        };
+        let (pred, iv, bound) = if let Some(iv) = self.find_ind_var(lp, lhs) {
+            (pred, iv, rhs)
+        } else if let Some(iv) = self.find_ind_var(lp, rhs) {
+            (pred.swap(), iv, lhs)
+        } else {
+            return TripCount::Unknown;
+        };
+        let pred = if continue_if_nz { pred } else { pred.negate() };
+
+        let Some(bound) = imm_value(func, bound) else {
+            return TripCount::Unknown;
+        };
+        let ty = func.dfg.value_ty(iv.value);
+        let Some(count) = pred.trip_count(iv.start, iv.step, bound, ty) else {
+            return TripCount::Unknown;
+        };
+
+        // Other exits may leave the loop earlier.
+        let has_other_exits = lpt.iter_blocks_post_order(cfg, lp).any(|block| {
+            block != header && cfg.succs_of(block).any(|succ| !lpt.is_in_loop(*succ, lp))
+        });
+        if has_other_exits {
+            TripCount::AtMost(count)
+        } else {
+            TripCount::Exact(count)
+        }
+    }
+
+    fn find_ind_var(&self, lp: Loop, value: ValueId) -> Option<IndVar> {
+        self.ind_vars[lp]
+            .iter()
+            .find(|iv| iv.value == value)
+            .copied()
+    }
+}
+
+/// Returns the step if `next` is `phi + imm`, `imm + phi` or `phi - imm`.
+fn step_of(func: &Function, phi: ValueId, next: ValueId) -> Option<i128> {
+    let inst = func.dfg.inst(func.dfg.value_inst(next)?);
+    let is = func.inst_set();
+
+    if let Some(add) = <&Add as InstDowncast>::downcast(is, inst) {
+        if *add.lhs() == phi {
+            imm_value(func, *add.rhs())
+        } else if *add.rhs() == phi {
+            imm_value(func, *add.lhs())
+        } else {
+            None
+        }
+    } else if let Some(sub) = <&Sub as InstDowncast>::downcast(is, inst) {
+        if *sub.lhs() == phi {
+            imm_value(func, *sub.rhs())?.checked_neg()
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+fn imm_value(func: &Function, value: ValueId) -> Option<i128> {
+    let imm = func.dfg.value_imm(value)?.as_i256();
+    let value = imm.trunc_to_i128();
+    (I256::from(value) == imm).then_some(value)
+}
+
+/// Returns the predicate and operands if `cond` is a comparison.
+fn cmp_of(func: &Function, cond: ValueId) -> Option<(Pred, ValueId, ValueId)> {
+    let inst = func.dfg.inst(func.dfg.value_inst(cond)?);
+    let is = func.inst_set();
+
+    macro_rules! match_cmp {
+        ($($ty:ty => $kind:ident, $signed:literal;)*) => {
+            $(
+                if let Some(cmp) = <&$ty as InstDowncast>::downcast(is, inst) {
+                    let pred = Pred { kind: PredKind::$kind, signed: $signed };
+                    return Some((pred, *cmp.lhs(), *cmp.rhs()));
+                }
+            )*
+        };
+    }
+
+    match_cmp! {
+        Lt => Lt, false;
+        Le => Le, false;
+        Gt => Gt, false;
+        Ge => Ge, false;
+        Slt => Lt, true;
+        Sle => Le, true;
+        Sgt => Gt, true;
+        Sge => Ge, true;
+        Ne => Ne, true;
+    }
+
+    None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PredKind {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+/// A predicate `iv PRED bound` that holds while the loop continues.
+#[derive(Debug, Clone, Copy)]
+struct Pred {
+    kind: PredKind,
+    signed: bool,
+}
+
+impl Pred {
+    /// Returns the predicate with swapped operands.
+    fn swap(self) -> Self {
+        let kind = match self.kind {
+            PredKind::Lt => PredKind::Gt,
+            PredKind::Le => PredKind::Ge,
+            PredKind::Gt => PredKind::Lt,
+            PredKind::Ge => PredKind::Le,
+            kind => kind,
+        };
+        Self { kind, ..self }
+    }
+
+    /// Returns the logical negation of the predicate.
+    fn negate(self) -> Self {
+        let kind = match self.kind {
+            PredKind::Lt => PredKind::Ge,
+            PredKind::Le => PredKind::Gt,
+            PredKind::Gt => PredKind::Le,
+            PredKind::Ge => PredKind::Lt,
+            PredKind::Eq => PredKind::Ne,
+            PredKind::Ne => PredKind::Eq,
+        };
+        Self { kind, ..self }
+    }
+
+    /// Returns the number of iterations in which the predicate holds.
+    /// Returns `None` if the number can't be determined, e.g., the induction
+    /// variable would wrap around before the predicate fails.
+    fn trip_count(self, start: i128, step: i128, bound: i128, ty: Type) -> Option<u128> {
+        if !self.signed && (start < 0 || bound < 0) {
+            return None;
+        }
+
+        let count = match self.kind {
+            PredKind::Lt if step > 0 => ceil_div(bound.checked_sub(start)?, step),
+            PredKind::Le if step > 0 => ceil_div(bound.checked_sub(start)?.checked_add(1)?, step),
+            PredKind::Gt if step < 0 => ceil_div(start.checked_sub(bound)?, -step),
+            PredKind::Ge if step < 0 => ceil_div(start.checked_sub(bound)?.checked_add(1)?, -step),
+            PredKind::Ne if step != 0 => {
+                let dist = bound.checked_sub(start)?;
+                if dist % step != 0 || dist / step < 0 {
+                    return None;
+                }
+                dist / step
+            }
+            _ => return None,
+        };
+
+        // The value that makes the predicate fail must be representable in
+        // the type, otherwise the induction variable wraps around.
+        let last = start.checked_add(count.checked_mul(step)?)?;
+        let (min, max) = range_of(ty, self.signed)?;
+        (min <= last && last <= max).then_some(count as u128)
+    }
+}
+
+/// Returns `ceil(lhs / rhs)` for positive `rhs`, or zero if `lhs` isn't
+/// positive.
+fn ceil_div(lhs: i128, rhs: i128) -> i128 {
+    if lhs <= 0 {
+        0
+    } else {
+        (lhs - 1) / rhs + 1
+    }
+}
+
+/// Returns the range of the integral `ty` clamped to `i128`.
+fn range_of(ty: Type, signed: bool) -> Option<(i128, i128)> {
+    let bits = ty.int_bits()?;
+    if bits >= 128 {
+        return Some((i128::MIN, i128::MAX));
+    }
+
+    if signed {
+        Some((-(1 << (bits - 1)), (1 << (bits - 1)) - 1))
+    } else {
+        Some((0, (u128::MAX >> (128 - bits)) as i128))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        domtree::DomTree,
+        inst::control_flow::{Jump, Phi, Return},
+        prelude::*,
+    };
+
+    use super::*;
+
+    fn compute(func: &Function) -> (LoopTree, IndVarAnalysis) {
+        let mut cfg = ControlFlowGraph::new();
+        let mut domtree = DomTree::new();
+        let mut lpt = LoopTree::new();
+        let mut iva = IndVarAnalysis::new();
+        cfg.compute(func);
+        domtree.compute(&cfg);
+        lpt.compute(&cfg, &domtree);
+        iva.compute(func, &cfg, &lpt);
+        (lpt, iva)
+    }
+
+    /// Builds `for (i = 0; i < bound; i++) {}`.
+    fn build_counting_loop(bound: Option<i32>) -> (sonatina_ir::Module, ValueId) {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        let start = builder.make_imm_value(0i32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let i = builder.insert_inst(Phi::new(is, vec![(start, b0)]), Type::I32);
+        let bound = match bound {
+            Some(bound) => builder.make_imm_value(bound),
+            None => builder.args()[0],
+        };
+        let cond = builder.insert_inst(Slt::new(is, i, bound), Type::I1);
+        builder.insert_inst_no_result(Br::new(is, cond, b2, b3));
+
+        builder.switch_to_block(b2);
+        let one = builder.make_imm_value(1i32);
+        let next = builder.insert_inst(Add::new(is, i, one), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+        builder.append_phi_arg(i, next, b2);
+
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result(Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        (mb.build(), i)
+    }
+
+    #[test]
+    fn exact_trip_count() {
+        let (module, i) = build_counting_loop(Some(10));
+        module.func_store.view(module.funcs()[0], |func| {
+            let (lpt, iva) = compute(func);
+            let lp = lpt.loops().next().unwrap();
+            assert_eq!(
+                iva.ind_vars(lp),
+                &[IndVar {
+                    value: i,
+                    start: 0,
+                    step: 1
+                }]
+            );
+            assert_eq!(iva.trip_count(lp), TripCount::Exact(10));
+        });
+    }
+
+    #[test]
+    fn unknown_trip_count() {
+        let (module, _) = build_counting_loop(None);
+        module.func_store.view(module.funcs()[0], |func| {
+            let (lpt, iva) = compute(func);
+            let lp = lpt.loops().next().unwrap();
+            assert_eq!(iva.trip_count(lp), TripCount::Unknown);
+        });
+    }
+}
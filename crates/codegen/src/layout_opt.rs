@@ -0,0 +1,137 @@
+//! This module contains a block reordering that makes as many branches as
+//! possible fall through.
+//!
+//! Sonatina IR has no fallthrough terminator, so branches are kept as they
+//! are. Instead, [`compute_fallthroughs`](crate::fallthrough::compute_fallthroughs)
+//! finds the branches that the final assembler can omit in the new layout.
+
+use cranelift_entity::SecondaryMap;
+use sonatina_ir::{domtree::DomTree, BlockId, ControlFlowGraph, Function};
+
+use crate::fallthrough::fallthrough_dest;
+
+/// Reorder the blocks of the function so that each block is followed by the
+/// destination it can fall through to, if possible.
+/// Returns `true` if any block is moved.
+///
+/// The blocks are visited in RPO, and each visited block starts a chain that
+/// greedily continues with the fallthrough destination of the last block until
+/// it reaches a placed block. The entry block stays first, and blocks
+/// unreachable from it are placed last in their original order.
+pub fn reorder_blocks(func: &mut Function, cfg: &ControlFlowGraph) -> bool {
+    let order = compute_order(func, cfg);
+    let Some((&entry, rest)) = order.split_first() else {
+        return false;
+    };
+    debug_assert_eq!(func.layout.entry_block(), Some(entry));
+
+    let mut changed = false;
+    let mut prev = entry;
+    for &block in rest {
+        if func.layout.next_block_of(prev) != Some(block) {
+            func.layout.move_block_after(block, prev);
+            changed = true;
+        }
+        prev = block;
+    }
+
+    changed
+}
+
+fn compute_order(func: &Function, cfg: &ControlFlowGraph) -> Vec<BlockId> {
+    let mut domtree = DomTree::new();
+    domtree.compute(cfg);
+
+    let mut placed = SecondaryMap::<BlockId, bool>::new();
+    let mut order = Vec::with_capacity(func.layout.block_num());
+    for &start in domtree.rpo() {
+        let mut block = start;
+        while !placed[block] {
+            placed[block] = true;
+            order.push(block);
+
+            let Some(next) = func
+                .layout
+                .last_inst_of(block)
+                .and_then(|term| fallthrough_dest(func, term))
+            else {
+                break;
+            };
+            block = next;
+        }
+    }
+
+    order.extend(func.layout.iter_block().filter(|&block| !placed[block]));
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            cmp::IsZero,
+            control_flow::{Br, Jump, Return},
+        },
+        isa::Isa,
+        InstDowncast, Type,
+    };
+
+    use super::*;
+    use crate::fallthrough::compute_fallthroughs;
+
+    #[test]
+    fn diamond_places_then_after_branch() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::Unit);
+        let is = evm.inst_set();
+
+        // `if x { then } else { else_ }`, where the blocks are appended in an
+        // order that makes no branch fall through.
+        let entry = builder.append_block();
+        let merge = builder.append_block();
+        let else_ = builder.append_block();
+        let then = builder.append_block();
+        let x = builder.args()[0];
+
+        builder.switch_to_block(entry);
+        let cond = builder.insert_inst(IsZero::new(is, x), Type::I1);
+        builder.insert_inst_no_result(Br::new(is, cond, else_, then));
+
+        for block in [then, else_] {
+            builder.switch_to_block(block);
+            builder.insert_inst_no_result(Jump::new(is, merge));
+        }
+
+        builder.switch_to_block(merge);
+        builder.insert_inst_no_result(Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            assert!(compute_fallthroughs(func, &func.layout)
+                .values()
+                .all(Option::is_none));
+
+            assert!(reorder_blocks(func, &cfg));
+            let blocks: Vec<_> = func.layout.iter_block().collect();
+            assert_eq!(blocks, [entry, then, merge, else_]);
+            assert!(!reorder_blocks(func, &cfg));
+
+            // The branch still points at the same blocks.
+            let term = func.layout.last_inst_of(entry).unwrap();
+            let br = <&Br as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(term)).unwrap();
+            assert_eq!((*br.nz_dest(), *br.z_dest()), (else_, then));
+
+            let fallthroughs = compute_fallthroughs(func, &func.layout);
+            assert_eq!(fallthroughs[entry], Some(then));
+            assert_eq!(fallthroughs[then], Some(merge));
+            assert_eq!(fallthroughs[else_], None);
+        });
+    }
+}
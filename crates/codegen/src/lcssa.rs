@@ -0,0 +1,312 @@
+//! This module contains a transformation that rewrites a loop into
+//! `Loop-Closed SSA (LCSSA)` form.
+//!
+//! In LCSSA form, every value defined inside a loop and used outside of it is
+//! passed through a phi inst placed in the loop exit blocks, so that
+//! transformations that move code out of the loop only need to care about
+//! these phis.
+
+use rustc_hash::FxHashMap;
+use sonatina_ir::{
+    domtree::DomTree,
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    BlockId, ControlFlowGraph, Function, InstId, ValueId,
+};
+
+use crate::loop_analysis::{Loop, LoopTree};
+
+/// Rewrite `lp` into LCSSA form.
+/// Returns `true` if any phi inst is inserted.
+///
+/// The analyses are computed from `func`, so `lp` must be a loop of a
+/// [`LoopTree`] computed on the current control flow graph of `func`.
+/// Use [`make_lcssa_with`] to reuse analyses the caller already holds.
+pub fn make_lcssa(func: &mut Function, lp: Loop) -> bool {
+    let mut cfg = ControlFlowGraph::new();
+    let mut domtree = DomTree::new();
+    let mut lpt = LoopTree::new();
+    cfg.compute(func);
+    domtree.compute(&cfg);
+    lpt.compute(&cfg, &domtree);
+
+    make_lcssa_with(func, &cfg, &domtree, &lpt, lp)
+}
+
+/// Rewrite `lp` into LCSSA form using precomputed analyses.
+/// Returns `true` if any phi inst is inserted.
+///
+/// NOTE: This function doesn't modify the control flow graph, so `cfg`,
+/// `domtree` and `lpt` are still valid after the transformation.
+pub fn make_lcssa_with(
+    func: &mut Function,
+    cfg: &ControlFlowGraph,
+    domtree: &DomTree,
+    lpt: &LoopTree,
+    lp: Loop,
+) -> bool {
+    let blocks_in_loop: Vec<_> = lpt.iter_blocks_post_order(cfg, lp).collect();
+
+    // Collect exit blocks of the loop.
+    let mut exits = Vec::new();
+    for &block in &blocks_in_loop {
+        for &succ in cfg.succs_of(block) {
+            if !lpt.is_in_loop(succ, lp) && !exits.contains(&succ) {
+                exits.push(succ);
+            }
+        }
+    }
+
+    let mut defs = Vec::new();
+    for &block in &blocks_in_loop {
+        for inst in func.layout.iter_inst(block) {
+            if let Some(value) = func.dfg.inst_result(inst) {
+                defs.push((block, value));
+            }
+        }
+    }
+
+    let mut changed = false;
+    for (def_block, value) in defs {
+        let mut rewriter = ExitValueRewriter {
+            cfg,
+            lpt,
+            lp,
+            value,
+            phis: FxHashMap::default(),
+        };
+        changed |= rewriter.run(func, domtree, def_block, &exits);
+    }
+
+    changed
+}
+
+struct ExitValueRewriter<'a> {
+    cfg: &'a ControlFlowGraph,
+    lpt: &'a LoopTree,
+    lp: Loop,
+    value: ValueId,
+
+    /// Maps blocks to phis inserted at the top of the block.
+    phis: FxHashMap<BlockId, ValueId>,
+}
+
+impl ExitValueRewriter<'_> {
+    fn run(
+        &mut self,
+        func: &mut Function,
+        domtree: &DomTree,
+        def_block: BlockId,
+        exits: &[BlockId],
+    ) -> bool {
+        let users: Vec<InstId> = func
+            .dfg
+            .users(self.value)
+            .copied()
+            .filter(|user| self.is_use_outside_loop(func, *user))
+            .collect();
+        if users.is_empty() {
+            return false;
+        }
+
+        // Insert phis to exit blocks dominated by the definition.
+        for &exit in exits {
+            if domtree.dominates(def_block, exit) {
+                self.phi_at(func, exit);
+            }
+        }
+
+        for user in users {
+            self.rewrite_user(func, user);
+        }
+
+        // Remove exit phis that turned out to be unused.
+        for phi_value in std::mem::take(&mut self.phis).into_values() {
+            if func.dfg.users_num(phi_value) == 0 {
+                let phi_inst = func.dfg.value_inst(phi_value).unwrap();
+                InstInserter::at_location(CursorLocation::At(phi_inst)).remove_inst(func);
+            }
+        }
+
+        true
+    }
+
+    /// Returns `true` if the `user` uses the value outside of the loop.
+    fn is_use_outside_loop(&self, func: &Function, user: InstId) -> bool {
+        if let Some(phi) = func.dfg.cast_phi(user) {
+            phi.args()
+                .iter()
+                .any(|(arg, pred)| *arg == self.value && !self.lpt.is_in_loop(*pred, self.lp))
+        } else {
+            let block = func.layout.inst_block(user);
+            !self.lpt.is_in_loop(block, self.lp)
+        }
+    }
+
+    fn rewrite_user(&mut self, func: &mut Function, user: InstId) {
+        func.dfg.untrack_inst(user);
+
+        if func.dfg.is_phi(user) {
+            let args: Vec<_> = func.dfg.cast_phi(user).unwrap().args().clone();
+            let mut new_args = Vec::with_capacity(args.len());
+            for (arg, pred) in args {
+                if arg == self.value && !self.lpt.is_in_loop(pred, self.lp) {
+                    new_args.push(self.value_at_end(func, pred));
+                } else {
+                    new_args.push(arg);
+                }
+            }
+
+            let phi = func.dfg.cast_phi_mut(user).unwrap();
+            for ((arg, _), new_arg) in phi.args_mut().iter_mut().zip(new_args) {
+                *arg = new_arg;
+            }
+        } else {
+            let block = func.layout.inst_block(user);
+            let new_value = self.value_at_start(func, block);
+            let value = self.value;
+            func.dfg.inst_mut(user).for_each_value_mut(&mut |v| {
+                if *v == value {
+                    *v = new_value;
+                }
+            });
+        }
+
+        func.dfg.attach_user(user);
+    }
+
+    /// Returns the value that reaches the end of the `block`.
+    fn value_at_end(&mut self, func: &mut Function, block: BlockId) -> ValueId {
+        if self.lpt.is_in_loop(block, self.lp) {
+            self.value
+        } else {
+            self.value_at_start(func, block)
+        }
+    }
+
+    /// Returns the value that reaches the top of the `block`.
+    fn value_at_start(&mut self, func: &mut Function, block: BlockId) -> ValueId {
+        if let Some(&phi_value) = self.phis.get(&block) {
+            return phi_value;
+        }
+        if self.lpt.is_in_loop(block, self.lp) {
+            return self.value;
+        }
+
+        let preds: Vec<_> = self.cfg.preds_of(block).copied().collect();
+        match preds.as_slice() {
+            [pred] => self.value_at_end(func, *pred),
+            _ => self.phi_at(func, block),
+        }
+    }
+
+    /// Insert a phi at the top of the `block` and returns its result.
+    fn phi_at(&mut self, func: &mut Function, block: BlockId) -> ValueId {
+        if let Some(&phi_value) = self.phis.get(&block) {
+            return phi_value;
+        }
+
+        let ty = func.dfg.value_ty(self.value);
+        let phi = func.dfg.make_phi(vec![]);
+        let mut inserter = InstInserter::at_location(CursorLocation::BlockTop(block));
+        let phi_inst = inserter.prepend_inst_data(func, phi);
+        let phi_value = inserter.make_result(func, phi_inst, ty);
+        inserter.attach_result(func, phi_inst, phi_value);
+
+        // Register the phi before resolving its arguments to break cycles.
+        self.phis.insert(block, phi_value);
+
+        let preds: Vec<_> = self.cfg.preds_of(block).copied().collect();
+        for pred in preds {
+            let arg = self.value_at_end(func, pred);
+            func.dfg.append_phi_arg(phi_inst, arg, pred);
+        }
+
+        phi_value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::Add,
+            cmp::Eq,
+            control_flow::{Br, Jump, Phi, Return},
+        },
+        prelude::*,
+        Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn exit_phi_inserted() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        let v0 = builder.make_imm_value(0i32);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let v1 = builder.insert_inst_with(|| Phi::new(is, vec![(v0, b0)]), Type::I32);
+        let c0 = builder.make_imm_value(10i32);
+        let v2 = builder.insert_inst_with(|| Eq::new(is, v1, c0), Type::I1);
+        builder.insert_inst_no_result_with(|| Br::new(is, v2, b3, b2));
+
+        builder.switch_to_block(b2);
+        let c1 = builder.make_imm_value(1i32);
+        let v3 = builder.insert_inst_with(|| Add::new(is, v1, c1), Type::I32);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b1));
+        builder.append_phi_arg(v1, v3, b2);
+
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            let mut domtree = DomTree::new();
+            let mut lpt = LoopTree::new();
+            cfg.compute(func);
+            domtree.compute(&cfg);
+            lpt.compute(&cfg, &domtree);
+
+            let lp = lpt.loops().next().unwrap();
+            assert!(make_lcssa(func, lp));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func() -> i32 {
+    block0:
+        jump block1;
+
+    block1:
+        v1.i32 = phi (0.i32 block0) (v5 block2);
+        v3.i1 = eq v1 10.i32;
+        br v3 block3 block2;
+
+    block2:
+        v5.i32 = add v1 1.i32;
+        jump block1;
+
+    block3:
+        v6.i32 = phi (v1 block1);
+        return v6;
+}
+"
+        );
+    }
+}
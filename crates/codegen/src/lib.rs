@@ -1,5 +1,23 @@
 pub mod critical_edge;
-pub mod domtree;
+pub mod def_use;
+pub mod expr_analysis;
+pub mod fallthrough;
+pub mod ind_var;
+pub mod layout_opt;
+pub mod lcssa;
+pub mod liveness;
 pub mod loop_analysis;
 pub mod optim;
+pub mod out_of_ssa;
+pub mod outline;
+pub mod parallel_copy;
 pub mod post_domtree;
+pub mod regalloc;
+pub mod ssa_check;
+pub mod stack_lower;
+pub mod structural_analysis;
+pub mod trap_check;
+pub mod worklist;
+
+pub use optim::const_fold;
+pub use sonatina_ir::domtree;
@@ -0,0 +1,233 @@
+//! This module contains a liveness analysis of values in SSA form.
+//!
+//! Only values that occupy a location at run time, i.e., function args and
+//! inst results, are tracked. A phi arg is live at the end of the
+//! corresponding predecessor rather than at the top of the phi block.
+
+use std::{collections::BTreeSet, fmt};
+
+use cranelift_entity::SecondaryMap;
+use sonatina_ir::{BlockId, ControlFlowGraph, Function, InstId, Value, ValueId};
+
+use crate::worklist::{Direction, RpoWorklist};
+
+#[derive(Debug, Default)]
+pub struct Liveness {
+    live_ins: SecondaryMap<BlockId, BTreeSet<ValueId>>,
+    live_outs: SecondaryMap<BlockId, BTreeSet<ValueId>>,
+    worklist: RpoWorklist,
+}
+
+impl Liveness {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.live_ins.clear();
+        self.live_outs.clear();
+        self.worklist.clear();
+    }
+
+    pub fn compute(&mut self, func: &Function, cfg: &ControlFlowGraph) {
+        self.clear();
+
+        self.worklist.compute(cfg, Direction::Backward);
+        while let Some(block) = self.worklist.pop() {
+            let mut live_out = BTreeSet::new();
+            for &succ in cfg.succs_of(block) {
+                live_out.extend(self.live_ins[succ].iter().copied());
+                for inst in func.layout.iter_inst(succ) {
+                    let Some(phi) = func.dfg.cast_phi(inst) else {
+                        break;
+                    };
+                    live_out.extend(
+                        phi.args()
+                            .iter()
+                            .filter(|(value, pred)| *pred == block && is_tracked(func, *value))
+                            .map(|(value, _)| *value),
+                    );
+                }
+            }
+
+            let mut live = live_out.clone();
+            for inst in func
+                .layout
+                .iter_inst(block)
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+            {
+                transfer(func, inst, &mut live);
+            }
+            self.live_outs[block] = live_out;
+
+            if live != self.live_ins[block] {
+                self.live_ins[block] = live;
+                for &pred in cfg.preds_of(block) {
+                    self.worklist.push(pred);
+                }
+            }
+        }
+    }
+
+    /// Returns the values live at the top of the `block`, excluding the
+    /// results of its phis.
+    pub fn live_in(&self, block: BlockId) -> &BTreeSet<ValueId> {
+        &self.live_ins[block]
+    }
+
+    /// Returns the values live at the end of the `block`, including the args
+    /// of the phis in its successors coming from the `block`.
+    pub fn live_out(&self, block: BlockId) -> &BTreeSet<ValueId> {
+        &self.live_outs[block]
+    }
+
+    /// Returns the values live immediately after the `inst`.
+    pub fn live_after(&self, func: &Function, inst: InstId) -> BTreeSet<ValueId> {
+        let block = func.layout.inst_block(inst);
+        let mut live = self.live_outs[block].clone();
+
+        let mut next = func.layout.last_inst_of(block);
+        while let Some(cur) = next {
+            if cur == inst {
+                break;
+            }
+            transfer(func, cur, &mut live);
+            next = func.layout.prev_inst_of(cur);
+        }
+
+        live
+    }
+}
+
+/// Prints the live-in and live-out values of each block reachable from the
+/// entry block in RPO, e.g., `block1: in [v0] out [v0, v2]`.
+impl fmt::Display for Liveness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let display_values = |values: &BTreeSet<ValueId>| {
+            let values: Vec<_> = values
+                .iter()
+                .map(|value| format!("v{}", value.as_u32()))
+                .collect();
+            format!("[{}]", values.join(", "))
+        };
+
+        // The worklist is in postorder for the backward analysis.
+        for &block in self.worklist.order().iter().rev() {
+            writeln!(
+                f,
+                "{block}: in {} out {}",
+                display_values(&self.live_ins[block]),
+                display_values(&self.live_outs[block])
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the values that are live both immediately before and after the
+/// `call`, i.e., the values that a backend must preserve across the call, e.g.,
+/// by spilling caller-saved registers.
+pub fn values_live_across_call(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    call: InstId,
+) -> BTreeSet<ValueId> {
+    let mut liveness = Liveness::new();
+    liveness.compute(func, cfg);
+
+    // A value live after the call is also live before it unless the call
+    // defines it.
+    let mut live = liveness.live_after(func, call);
+    if let Some(result) = func.dfg.inst_result(call) {
+        live.remove(&result);
+    }
+    live
+}
+
+/// Update `live` from the values live after the `inst` to the values live
+/// before it.
+pub(crate) fn transfer(func: &Function, inst: InstId, live: &mut BTreeSet<ValueId>) {
+    if let Some(result) = func.dfg.inst_result(inst) {
+        live.remove(&result);
+    }
+    if func.dfg.is_phi(inst) {
+        return;
+    }
+
+    func.dfg.inst(inst).for_each_value(&mut |value| {
+        if is_tracked(func, value) {
+            live.insert(value);
+        }
+    });
+}
+
+pub(crate) fn is_tracked(func: &Function, value: ValueId) -> bool {
+    matches!(
+        func.dfg.value(value),
+        Value::Inst { .. } | Value::Arg { .. }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::{Add, Mul},
+            control_flow::{Call, Jump, Return},
+        },
+        isa::Isa,
+        Linkage, Signature, Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn value_crossing_call() {
+        let mb = test_module_builder();
+        let sig = Signature::new("callee", Linkage::External, &[Type::I32], Type::I32);
+        let callee = mb.declare_function(sig);
+
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        // `crossing` is used after the call, but `before` is used only by it.
+        let crossing = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        let before = builder.insert_inst(Mul::new(is, arg, arg), Type::I32);
+        let result = builder.insert_inst(Call::new(is, callee, vec![before].into()), Type::I32);
+        let call = builder.last_inst().unwrap();
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let sum = builder.insert_inst(Add::new(is, crossing, result), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(sum)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[1], |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+
+            let across = values_live_across_call(func, &cfg, call);
+            assert_eq!(across, BTreeSet::from([crossing]));
+
+            let mut liveness = Liveness::new();
+            liveness.compute(func, &cfg);
+            assert_eq!(
+                liveness.to_string(),
+                "block0: in [v0] out [v1, v3]
+block1: in [v1, v3] out []
+"
+            );
+        });
+    }
+}
@@ -1,9 +1,12 @@
+use std::fmt;
+
 use cranelift_entity::{entity_impl, packed_option::PackedOption, PrimaryMap, SecondaryMap};
 use rustc_hash::FxHashMap;
 use smallvec::SmallVec;
-use sonatina_ir::{BlockId, ControlFlowGraph};
-
-use crate::domtree::DomTree;
+use sonatina_ir::{
+    domtree::{display_blocks, DomTree},
+    BlockId, ControlFlowGraph, Function, ValueId,
+};
 
 #[derive(Debug, Default)]
 pub struct LoopTree {
@@ -16,6 +19,9 @@ pub struct LoopTree {
     /// If the block is contained by multiple nested loops, then the block is
     /// mapped to the innermost loop.
     block_to_loop: SecondaryMap<BlockId, PackedOption<Loop>>,
+
+    /// Retreating edges whose destinations don't dominate their sources.
+    irreducible_edges: Vec<(BlockId, BlockId)>,
 }
 
 impl LoopTree {
@@ -27,6 +33,20 @@ impl LoopTree {
     pub fn compute(&mut self, cfg: &ControlFlowGraph, domtree: &DomTree) {
         self.clear();
 
+        // An edge to a block not after the source in RPO closes a cycle. The
+        // cycle is a natural loop only if the destination dominates the source.
+        let mut rpo_nums = SecondaryMap::with_capacity(domtree.rpo().len());
+        for (i, &block) in domtree.rpo().iter().enumerate() {
+            rpo_nums[block] = i;
+        }
+        for &block in domtree.rpo() {
+            for &succ in cfg.succs_of(block) {
+                if rpo_nums[succ] <= rpo_nums[block] && !domtree.dominates(succ, block) {
+                    self.irreducible_edges.push((block, succ));
+                }
+            }
+        }
+
         // Find loop headers in RPO, this means outer loops are guaranteed to be
         // inserted first, then its inner loops are inserted.
         for &block in domtree.rpo() {
@@ -75,6 +95,29 @@ impl LoopTree {
         false
     }
 
+    /// Returns `true` if the `value` is invariant in the `lp`, i.e., the value
+    /// is defined outside the loop, or is defined by an inst in the loop whose
+    /// operands are all invariant.
+    ///
+    /// Phis in the loop, e.g., a loop-carried phi in the header, and insts with
+    /// side effects are never invariant.
+    pub fn is_invariant(&self, func: &Function, lp: Loop, value: ValueId) -> bool {
+        let mut memo = FxHashMap::default();
+        self.is_invariant_memoized(func, lp, value, &mut memo)
+    }
+
+    /// Returns the edges closing cycles that aren't natural loops, i.e., the
+    /// cycles entered through more than one block. Such cycles aren't
+    /// reported as loops.
+    pub fn irreducible_edges(&self) -> &[(BlockId, BlockId)] {
+        &self.irreducible_edges
+    }
+
+    /// Returns `true` if every cycle of the CFG is a natural loop.
+    pub fn is_reducible(&self) -> bool {
+        self.irreducible_edges.is_empty()
+    }
+
     /// Returns number of loops found.
     pub fn loop_num(&self) -> usize {
         self.loops.len()
@@ -89,6 +132,7 @@ impl LoopTree {
     pub fn clear(&mut self) {
         self.loops.clear();
         self.block_to_loop.clear();
+        self.irreducible_edges.clear();
     }
 
     /// Returns header block of the `lp`.
@@ -155,6 +199,40 @@ impl LoopTree {
         }
     }
 
+    fn is_invariant_memoized(
+        &self,
+        func: &Function,
+        lp: Loop,
+        value: ValueId,
+        memo: &mut FxHashMap<ValueId, bool>,
+    ) -> bool {
+        if let Some(&invariant) = memo.get(&value) {
+            return invariant;
+        }
+
+        let Some(inst) = func.dfg.value_inst(value) else {
+            // Arguments, immediates and global values are defined outside the loop.
+            return true;
+        };
+
+        let invariant = if !self.is_in_loop(func.layout.inst_block(inst), lp) {
+            true
+        } else if func.dfg.is_phi(inst) || func.dfg.side_effect(inst).has_effect() {
+            false
+        } else {
+            let mut operands = Vec::new();
+            func.dfg
+                .inst(inst)
+                .for_each_value(&mut |operand| operands.push(operand));
+            operands
+                .into_iter()
+                .all(|operand| self.is_invariant_memoized(func, lp, operand, memo))
+        };
+
+        memo.insert(value, invariant);
+        invariant
+    }
+
     /// Returns the outermost parent loop of `lp`. If `lp` doesn't have any
     /// parent, then returns `lp` itself.
     fn outermost_parent(&self, mut lp: Loop) -> Loop {
@@ -165,6 +243,47 @@ impl LoopTree {
     }
 }
 
+/// Prints the loop nest with the header of each loop and the blocks whose
+/// innermost loop it is, e.g.,
+///
+/// ```text
+/// loop0: header block1 [block1, block4]
+///   loop1: header block2 [block2, block3]
+/// ```
+impl fmt::Display for LoopTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut stack: Vec<_> = self
+            .loops()
+            .rev()
+            .filter(|&lp| self.parent_loop(lp).is_none())
+            .map(|lp| (lp, 0))
+            .collect();
+
+        while let Some((lp, depth)) = stack.pop() {
+            let blocks: Vec<_> = self
+                .block_to_loop
+                .iter()
+                .filter(|(_, lp_of_block)| lp_of_block.expand() == Some(lp))
+                .map(|(block, _)| block)
+                .collect();
+            writeln!(
+                f,
+                "{:indent$}loop{}: header {} {}",
+                "",
+                lp.as_u32(),
+                self.loop_header(lp),
+                display_blocks(blocks.iter()),
+                indent = depth * 2
+            )?;
+
+            let mut children = self.loops[lp].children.clone();
+            children.sort_by_key(|child| child.as_u32());
+            stack.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Loop(u32);
 entity_impl!(Loop);
@@ -252,7 +371,7 @@ mod tests {
         builder::test_util::*,
         inst::{
             arith::Add,
-            cmp::Eq,
+            cmp::{Eq, Lt},
             control_flow::{Br, Jump, Phi, Return},
         },
         prelude::*,
@@ -260,6 +379,7 @@ mod tests {
     };
 
     use super::*;
+    use crate::liveness::Liveness;
 
     fn compute_loop(func: &Function) -> LoopTree {
         let mut cfg = ControlFlowGraph::new();
@@ -316,6 +436,110 @@ mod tests {
         debug_assert_eq!(lpt.loop_of_block(b3), None);
 
         debug_assert_eq!(lpt.loop_header(lp0), b1);
+
+        assert_eq!(lpt.to_string(), "loop0: header block1 [block1, block2]\n");
+    }
+
+    #[test]
+    fn self_loop() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        let v0 = builder.make_imm_value(0i32);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b1));
+
+        // `b1` branches back to itself until the counter reaches 10.
+        builder.switch_to_block(b1);
+        let v1 = builder.insert_inst_with(|| Phi::new(is, vec![(v0, b0)]), Type::I32);
+        let c1 = builder.make_imm_value(1i32);
+        let v2 = builder.insert_inst_with(|| Add::new(is, v1, c1), Type::I32);
+        let c10 = builder.make_imm_value(10i32);
+        let v3 = builder.insert_inst_with(|| Lt::new(is, v2, c10), Type::I1);
+        builder.insert_inst_no_result_with(|| Br::new(is, v3, b1, b2));
+        builder.append_phi_arg(v1, v2, b1);
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(v2)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let lpt = compute_loop(func);
+            assert_eq!(lpt.loop_num(), 1);
+            let lp0 = lpt.loops().next().unwrap();
+            assert_eq!(lpt.loop_header(lp0), b1);
+            assert_eq!(lpt.loop_of_block(b0), None);
+            assert_eq!(lpt.loop_of_block(b1), Some(lp0));
+            assert_eq!(lpt.loop_of_block(b2), None);
+
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            let mut liveness = Liveness::new();
+            liveness.compute(func, &cfg);
+            assert!(liveness.live_in(b1).is_empty());
+            assert_eq!(liveness.live_out(b1).iter().collect::<Vec<_>>(), [&v2]);
+        });
+    }
+
+    #[test]
+    fn invariant_values() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.make_imm_value(0i32);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let v1 = builder.insert_inst_with(|| Phi::new(is, vec![(v0, b0)]), Type::I32);
+        let c0 = builder.make_imm_value(10i32);
+        let v2 = builder.insert_inst_with(|| Eq::new(is, v1, c0), Type::I1);
+        builder.insert_inst_no_result_with(|| Br::new(is, v2, b3, b2));
+
+        builder.switch_to_block(b2);
+        let c1 = builder.make_imm_value(1i32);
+        let v3 = builder.insert_inst_with(|| Add::new(is, arg, c1), Type::I32);
+        let v4 = builder.insert_inst_with(|| Add::new(is, v3, v3), Type::I32);
+        let v5 = builder.insert_inst_with(|| Add::new(is, v1, v4), Type::I32);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b1));
+        builder.append_phi_arg(v1, v5, b2);
+
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result_with(|| Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let lpt = compute_loop(func);
+            let lp0 = lpt.loops().next().unwrap();
+
+            assert!(lpt.is_invariant(func, lp0, arg));
+            assert!(lpt.is_invariant(func, lp0, v3));
+            assert!(lpt.is_invariant(func, lp0, v4));
+
+            assert!(!lpt.is_invariant(func, lp0, v1));
+            assert!(!lpt.is_invariant(func, lp0, v2));
+            assert!(!lpt.is_invariant(func, lp0, v5));
+        });
     }
 
     #[test]
@@ -516,4 +740,58 @@ mod tests {
         debug_assert_eq!(lpt.loop_header(l2), b4);
         debug_assert_eq!(lpt.loop_header(l3), b7);
     }
+
+    #[test]
+    fn br_table_back_edges() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        // `c` and `d` both branch back to the header `b`.
+        build_cfg! { builder;
+            a -> [b];
+            b -> [c, d, e];
+            c -> [b];
+            d -> [b];
+            e -> [];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let lpt = &module.func_store.view(func_ref, compute_loop);
+
+        assert_eq!(lpt.loop_num(), 1);
+        let lp = lpt.loops().next().unwrap();
+        assert_eq!(lpt.loop_header(lp), b);
+        assert_eq!(lpt.parent_loop(lp), None);
+        for block in [b, c, d] {
+            assert_eq!(lpt.loop_of_block(block), Some(lp));
+        }
+        assert!(!lpt.is_in_loop(a, lp));
+        assert!(!lpt.is_in_loop(e, lp));
+        assert!(lpt.is_reducible());
+    }
+
+    #[test]
+    fn irreducible() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        // `b` and `c` form a cycle entered from both of them.
+        build_cfg! { builder;
+            a -> [b, c, d];
+            b -> [c];
+            c -> [b, d];
+            d -> [];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let lpt = &module.func_store.view(func_ref, compute_loop);
+
+        assert_eq!(lpt.loop_num(), 0);
+        assert!(!lpt.is_reducible());
+        assert_eq!(lpt.irreducible_edges(), [(b, c)]);
+    }
 }
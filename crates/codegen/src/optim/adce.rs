@@ -5,8 +5,8 @@ use std::collections::BTreeSet;
 use cranelift_entity::SecondaryMap;
 use sonatina_ir::{
     func_cursor::{CursorLocation, FuncCursor, InstInserter},
-    inst::SideEffect,
-    BlockId, Function, InstId,
+    inst::{debug::DebugValue, SideEffect},
+    BlockId, Function, InstDowncast, InstId,
 };
 
 use crate::post_domtree::{PDFSet, PDTIdom, PostDomTree};
@@ -51,13 +51,15 @@ impl AdceSolver {
 
         // TODO: We should remove this restriction.
         // ref: <https://reviews.llvm.org/D35851>
-        if self.has_infinite_loop(func) {
+        if self.has_infinite_loop() {
             return false;
         }
 
         for block in func.layout.iter_block() {
             for inst in func.layout.iter_inst(block) {
-                if matches!(func.dfg.side_effect(inst), SideEffect::Write) {
+                if matches!(func.dfg.side_effect(inst), SideEffect::Write)
+                    || is_debug_value(func, inst)
+                {
                     self.mark_inst(func, inst);
                 }
             }
@@ -70,14 +72,8 @@ impl AdceSolver {
         self.eliminate_dead_code(func)
     }
 
-    fn has_infinite_loop(&self, func: &Function) -> bool {
-        for block in func.layout.iter_block() {
-            if !self.post_domtree.is_reachable(block) {
-                return true;
-            }
-        }
-
-        false
+    fn has_infinite_loop(&self) -> bool {
+        !self.post_domtree.sinks().is_empty()
     }
 
     fn mark_inst(&mut self, func: &Function, inst: InstId) {
@@ -246,3 +242,8 @@ impl Default for AdceSolver {
         Self::new()
     }
 }
+
+/// `DebugValue` has no side effect, but must be kept to pin its operand live.
+fn is_debug_value(func: &Function, inst: InstId) -> bool {
+    <&DebugValue as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(inst)).is_some()
+}
@@ -0,0 +1,303 @@
+//! This module contains a cleanup that runs cheap canonicalizations which
+//! never make a function worse. It's meant to be run between optimization
+//! passes, so that each pass sees a tidy CFG.
+
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::control_flow::{Br, BrTable},
+    BlockId, ControlFlowGraph, Function, InstDowncast, InstId,
+};
+
+use super::dce::remove_unreachable_blocks;
+
+/// Run the following canonicalizations in order until none of them changes
+/// the function, and recompute `cfg`.
+/// Returns `true` if the function is modified.
+///
+/// 1. Remove blocks unreachable from the entry block.
+/// 2. Remove phis whose args are all the same value, except for the phi
+///    itself.
+/// 3. Fold branches whose condition is an immediate into `jump`.
+/// 4. Merge a block into its predecessor if it's the only successor of the
+///    predecessor, and the predecessor is its only predecessor.
+///
+/// Each step leaves nothing for itself to do, so running `cleanup` again
+/// right after is a no-op.
+pub fn cleanup(func: &mut Function, cfg: &mut ControlFlowGraph) -> bool {
+    let mut changed = false;
+    loop {
+        cfg.compute(func);
+        let mut step_changed = remove_unreachable_blocks(func, cfg);
+        step_changed |= remove_trivial_phis(func);
+        step_changed |= fold_constant_branches(func);
+
+        cfg.compute(func);
+        step_changed |= merge_blocks(func, cfg);
+
+        if !step_changed {
+            break;
+        }
+        changed = true;
+    }
+
+    cfg.compute(func);
+    changed
+}
+
+/// Returns `true` if any phi is removed.
+fn remove_trivial_phis(func: &mut Function) -> bool {
+    let mut changed = false;
+    let mut removed = true;
+    while removed {
+        removed = false;
+        let phis: Vec<_> = func.phis().map(|(_, inst)| inst).collect();
+        for phi in phis {
+            let result = func.dfg.inst_result(phi).unwrap();
+            let mut args = func
+                .dfg
+                .cast_phi(phi)
+                .unwrap()
+                .args()
+                .iter()
+                .map(|(value, _)| *value)
+                .filter(|&value| value != result);
+            let Some(first) = args.next() else {
+                continue;
+            };
+            if args.all(|value| value == first) {
+                func.dfg.change_to_alias(result, first);
+                InstInserter::at_location(CursorLocation::At(phi)).remove_inst(func);
+                removed = true;
+            }
+        }
+        changed |= removed;
+    }
+
+    changed
+}
+
+/// Returns `true` if any branch is folded.
+fn fold_constant_branches(func: &mut Function) -> bool {
+    let mut changed = false;
+    let blocks: Vec<_> = func.layout.iter_block().collect();
+    for block in blocks {
+        let Some(term) = func.layout.last_inst_of(block) else {
+            continue;
+        };
+        let Some(dest) = constant_dest(func, term) else {
+            continue;
+        };
+
+        let dests = func.dfg.branch_info(term).unwrap().dests();
+        if dests.iter().all(|&other| other == dest) {
+            // Leave it to `SimplifyCfgSolver`, which merges the duplicated phi
+            // args.
+            continue;
+        }
+
+        for other in dests {
+            if other != dest {
+                remove_phi_args(func, block, other);
+            }
+        }
+        let jump = func.dfg.make_jump(dest);
+        InstInserter::at_location(CursorLocation::At(term)).replace(func, jump);
+        changed = true;
+    }
+
+    changed
+}
+
+/// Returns the destination that the branch `inst` always takes, if its
+/// condition is an immediate.
+fn constant_dest(func: &Function, inst: InstId) -> Option<BlockId> {
+    let is = func.inst_set();
+    let data = func.dfg.inst(inst);
+
+    if let Some(br) = <&Br as InstDowncast>::downcast(is, data) {
+        let cond = func.dfg.value_imm(*br.cond())?;
+        return Some(if cond.is_zero() {
+            *br.z_dest()
+        } else {
+            *br.nz_dest()
+        });
+    }
+
+    let br_table = <&BrTable as InstDowncast>::downcast(is, data)?;
+    let scrutinee = func.dfg.value_imm(*br_table.scrutinee())?;
+    let mut default = *br_table.default();
+    for &(value, dest) in br_table.table() {
+        match func.dfg.value_imm(value) {
+            Some(imm) if imm == scrutinee => return Some(dest),
+            Some(_) => {}
+            // The case may match at run time.
+            None => default = None,
+        }
+    }
+    default
+}
+
+/// Returns `true` if any block is merged.
+fn merge_blocks(func: &mut Function, cfg: &mut ControlFlowGraph) -> bool {
+    let mut changed = false;
+    let blocks: Vec<_> = func.layout.iter_block().collect();
+    for block in blocks {
+        if !func.layout.is_block_inserted(block) {
+            continue;
+        }
+        while let Some(succ) = mergeable_succ(func, cfg, block) {
+            merge_into_pred(func, cfg, block, succ);
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+/// Returns the successor that can be merged into the `block`.
+fn mergeable_succ(func: &Function, cfg: &ControlFlowGraph, block: BlockId) -> Option<BlockId> {
+    let term = func.layout.last_inst_of(block)?;
+    let succ = *func.dfg.cast_jump(term)?.dest();
+    (succ != block && cfg.pred_num_of(succ) == 1 && func.layout.entry_block() != Some(succ))
+        .then_some(succ)
+}
+
+/// Move the insts of the `succ` to the end of the `block`, and remove the
+/// `succ`. `cfg` is updated accordingly.
+fn merge_into_pred(func: &mut Function, cfg: &mut ControlFlowGraph, block: BlockId, succ: BlockId) {
+    // The phis in `succ` have only one arg since `block` is the only
+    // predecessor.
+    let phis: Vec<_> = func
+        .layout
+        .iter_inst(succ)
+        .take_while(|&inst| func.dfg.is_phi(inst))
+        .collect();
+    for phi in phis {
+        let result = func.dfg.inst_result(phi).unwrap();
+        let value = func.dfg.cast_phi(phi).unwrap().args()[0].0;
+        func.dfg.change_to_alias(result, value);
+        InstInserter::at_location(CursorLocation::At(phi)).remove_inst(func);
+    }
+
+    let jump = func.layout.last_inst_of(block).unwrap();
+    InstInserter::at_location(CursorLocation::At(jump)).remove_inst(func);
+
+    let insts: Vec<_> = func.layout.iter_inst(succ).collect();
+    for inst in insts {
+        func.layout.remove_inst(inst);
+        func.layout.append_inst(inst, block);
+    }
+    func.layout.remove_block(succ);
+
+    // The successors of `succ` are now reached from `block`.
+    let succ_succs: Vec<_> = cfg.succs_of(succ).copied().collect();
+    for next in succ_succs {
+        let phis: Vec<_> = func
+            .layout
+            .iter_inst(next)
+            .take_while(|&inst| func.dfg.is_phi(inst))
+            .collect();
+        for phi in phis {
+            for (_, pred) in func.dfg.cast_phi_mut(phi).unwrap().args_mut() {
+                if *pred == succ {
+                    *pred = block;
+                }
+            }
+        }
+
+        cfg.remove_edge(succ, next);
+        cfg.add_edge(block, next);
+    }
+    cfg.remove_edge(block, succ);
+}
+
+/// Remove the phi args of `dest` coming from `pred`.
+pub(super) fn remove_phi_args(func: &mut Function, pred: BlockId, dest: BlockId) {
+    let phis: Vec<_> = func
+        .layout
+        .iter_inst(dest)
+        .take_while(|&inst| func.dfg.is_phi(inst))
+        .collect();
+    for inst in phis {
+        let phi = func.dfg.cast_phi_mut(inst).unwrap();
+        let mut removed = Vec::new();
+        while let Some(value) = phi.remove_phi_arg(pred) {
+            removed.push(value);
+        }
+        let remaining: Vec<_> = phi.args().iter().map(|(value, _)| *value).collect();
+        for value in removed {
+            if !remaining.contains(&value) {
+                func.dfg.remove_user(value, inst);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::control_flow::{Jump, Phi, Return},
+        isa::Isa,
+        Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn second_run_is_noop() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let b4 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        let cond = builder.make_imm_value(true);
+        builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b3);
+        let one = builder.make_imm_value(1i32);
+        let two = builder.make_imm_value(2i32);
+        let v0 = builder.insert_inst(Phi::new(is, vec![(one, b1), (two, b2)]), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        // `b4` is unreachable from the entry.
+        builder.switch_to_block(b4);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            assert!(cleanup(func, &mut cfg));
+            assert!(!cleanup(func, &mut cfg));
+
+            let mut expected = ControlFlowGraph::new();
+            expected.compute(func);
+            assert_eq!(cfg, expected);
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func() -> i32 {
+    block0:
+        return 1.i32;
+}
+"
+        );
+    }
+}
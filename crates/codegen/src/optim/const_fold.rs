@@ -0,0 +1,215 @@
+//! This module contains constant folding, which evaluates pure arithmetic,
+//! logic and comparison insts whose operands are all immediates, and replaces
+//! their results with the resulting immediates.
+//!
+//! Unlike [`sccp`](super::sccp), this doesn't propagate constants through phis
+//! or branches, so it's cheap enough to run after every pass that may expose
+//! new constants.
+
+use sonatina_ir::{
+    inst::{
+        arith::{Add, Mul, Sub},
+        cmp::{Eq, Ge, Gt, IsZero, Le, Lt, Ne, Sge, Sgt, Sle, Slt},
+        evm::{EvmSdiv, EvmUdiv},
+        logic::{And, Or, Xor},
+    },
+    DataFlowGraph, Function, Immediate, InstDowncast, InstId,
+};
+
+use super::peephole::{PeepholeRule, PeepholeSolver, Rewrite};
+
+pub use self::const_fold as fold_function;
+
+/// Fold insts with immediate operands until no inst can be folded.
+/// Returns `true` if the function is modified.
+pub fn const_fold(func: &mut Function) -> bool {
+    let mut solver = PeepholeSolver::new();
+    register_rules(&mut solver);
+    solver.run(func)
+}
+
+/// Register [`FoldConst`] to the `solver` for all the opcodes it folds.
+pub fn register_rules(solver: &mut PeepholeSolver) {
+    for opcode in [
+        "add", "sub", "mul", "evm_udiv", "evm_sdiv", "and", "or", "xor", "eq", "ne", "lt", "gt",
+        "le", "ge", "slt", "sgt", "sle", "sge", "is_zero",
+    ] {
+        solver.register(opcode, FoldConst);
+    }
+}
+
+/// Evaluates an inst whose operands are all immediates.
+///
+/// A division by zero is left as is, so that
+/// [`find_guaranteed_traps`](crate::trap_check::find_guaranteed_traps) can
+/// still report it.
+pub struct FoldConst;
+
+impl PeepholeRule for FoldConst {
+    fn try_apply(&self, dfg: &mut DataFlowGraph, inst: InstId) -> Option<Rewrite> {
+        let imm = eval(dfg, inst)?;
+        Some(Rewrite::Value(dfg.make_imm_value(imm)))
+    }
+}
+
+fn eval(dfg: &DataFlowGraph, inst: InstId) -> Option<Immediate> {
+    let is = dfg.inst_set();
+    let data = dfg.inst(inst);
+
+    macro_rules! eval_binary {
+        ($($ty:ident => $f:expr),* $(,)?) => {
+            $(
+                if let Some(data) = <&$ty as InstDowncast>::downcast(is, data) {
+                    let lhs = dfg.value_imm(*data.lhs())?;
+                    let rhs = dfg.value_imm(*data.rhs())?;
+                    let f: fn(Immediate, Immediate) -> Option<Immediate> = $f;
+                    return f(lhs, rhs);
+                }
+            )*
+        };
+    }
+
+    eval_binary! {
+        Add => |lhs, rhs| Some(lhs + rhs),
+        Sub => |lhs, rhs| Some(lhs - rhs),
+        Mul => |lhs, rhs| Some(lhs * rhs),
+        EvmUdiv => Immediate::checked_udiv,
+        EvmSdiv => Immediate::checked_sdiv,
+        And => |lhs, rhs| Some(lhs & rhs),
+        Or => |lhs, rhs| Some(lhs | rhs),
+        Xor => |lhs, rhs| Some(lhs ^ rhs),
+        Eq => |lhs, rhs| Some(lhs.imm_eq(rhs)),
+        Ne => |lhs, rhs| Some(lhs.imm_ne(rhs)),
+        Lt => |lhs, rhs| Some(lhs.lt(rhs)),
+        Gt => |lhs, rhs| Some(lhs.gt(rhs)),
+        Le => |lhs, rhs| Some(lhs.le(rhs)),
+        Ge => |lhs, rhs| Some(lhs.ge(rhs)),
+        Slt => |lhs, rhs| Some(lhs.slt(rhs)),
+        Sgt => |lhs, rhs| Some(lhs.sgt(rhs)),
+        Sle => |lhs, rhs| Some(lhs.sle(rhs)),
+        Sge => |lhs, rhs| Some(lhs.sge(rhs)),
+    }
+
+    let is_zero = <&IsZero as InstDowncast>::downcast(is, data)?;
+    let arg = dfg.value_imm(*is_zero.lhs())?;
+    Some(arg.is_zero().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*, inst::control_flow::Return, inst::data::Mload, isa::Isa, Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn fold_add() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let two = builder.make_imm_value(2i32);
+        let three = builder.make_imm_value(3i32);
+        let v0 = builder.insert_inst(Add::new(is, two, three), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module
+            .func_store
+            .modify(func_ref, |func| assert!(const_fold(func)));
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func() -> i32 {
+    block0:
+        return 5.i32;
+}
+"
+        );
+    }
+
+    #[test]
+    fn fold_signed_cmp() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::I1);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let minus_one = builder.make_imm_value(-1i32);
+        let zero = builder.make_imm_value(0i32);
+        // `-1` is the maximum as an unsigned value.
+        let v0 = builder.insert_inst(Slt::new(is, minus_one, zero), Type::I1);
+        let v1 = builder.insert_inst(Lt::new(is, minus_one, zero), Type::I1);
+        let v2 = builder.insert_inst(Ne::new(is, v0, v1), Type::I1);
+        builder.insert_inst_no_result(Return::new(is, Some(v2)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let slt = func.dfg.value_inst(v0).unwrap();
+            let lt = func.dfg.value_inst(v1).unwrap();
+            assert_eq!(eval(&func.dfg, slt), Some(Immediate::I1(true)));
+            assert_eq!(eval(&func.dfg, lt), Some(Immediate::I1(false)));
+            assert!(const_fold(func));
+        });
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func() -> i1 {
+    block0:
+        return 1.i1;
+}
+"
+        );
+    }
+
+    #[test]
+    fn keep_load_and_division_by_zero() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I256], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let addr = builder.args()[0];
+        builder.switch_to_block(b0);
+        let two = builder.make_imm_value(2i32);
+        let three = builder.make_imm_value(3i32);
+        let v0 = builder.insert_inst(Add::new(is, two, three), Type::I32);
+        let v1 = builder.insert_inst(Mload::new(is, addr, Type::I32), Type::I32);
+        let one = builder.make_imm_value(1i32);
+        let v2 = builder.insert_inst(Add::new(is, v0, one), Type::I32);
+        let zero = builder.make_imm_value(0i32);
+        let v3 = builder.insert_inst(EvmUdiv::new(is, v2, zero), Type::I32);
+        let v4 = builder.insert_inst(Add::new(is, v1, v3), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v4)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            assert!(crate::const_fold::fold_function(func))
+        });
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i256) -> i32 {
+    block0:
+        v4.i32 = mload v0 i32;
+        v8.i32 = evm_udiv 6.i32 0.i32;
+        v9.i32 = add v4 v8;
+        return v9;
+}
+"
+        );
+    }
+}
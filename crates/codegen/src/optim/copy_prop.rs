@@ -0,0 +1,83 @@
+//! This module contains copy propagation, which removes `copy` insts by
+//! replacing the uses of their results with their operands.
+
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::cast,
+    Function, InstDowncast,
+};
+
+/// Remove all `copy` insts in the function.
+/// Returns `true` if the function is modified.
+pub fn copy_propagate(func: &mut Function) -> bool {
+    let copies: Vec<_> = func
+        .layout
+        .iter_block()
+        .flat_map(|block| func.layout.iter_inst(block))
+        .filter(|&inst| {
+            <&cast::CopyVal as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(inst))
+                .is_some()
+        })
+        .collect();
+
+    for &inst in &copies {
+        // The operand is looked up here since it may have been rewritten by
+        // removing a preceding copy in a chain.
+        let copy = <&cast::CopyVal as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(inst))
+            .unwrap();
+        let arg = *copy.arg();
+        let result = func.dfg.inst_result(inst).unwrap();
+
+        func.dfg.change_to_alias(result, arg);
+        InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+    }
+
+    !copies.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{arith::Add, control_flow::Return},
+        isa::Isa,
+        Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn remove_copy_chain() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let arg = builder.args()[0];
+        let v1 = builder.insert_inst(cast::CopyVal::new(is, arg, Type::I32), Type::I32);
+        let v2 = builder.insert_inst(cast::CopyVal::new(is, v1, Type::I32), Type::I32);
+        let v3 = builder.insert_inst(Add::new(is, v2, v1), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v3)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            assert!(copy_propagate(func));
+            assert!(!copy_propagate(func));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32) -> i32 {
+    block0:
+        v3.i32 = add v0 v0;
+        return v3;
+}
+"
+        );
+    }
+}
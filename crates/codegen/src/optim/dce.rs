@@ -0,0 +1,412 @@
+//! This module contains an incremental dead code elimination.
+//!
+//! Unlike [`AdceSolver`](super::adce::AdceSolver), which recomputes liveness of
+//! the whole function, [`IncrementalDce`] only looks at the values that other
+//! passes report as potentially dead, e.g., after replacing their uses. This
+//! makes it cheap enough to run between every transform in a pass pipeline.
+//!
+//! This module also contains [`remove_unreachable_blocks`] and
+//! [`remove_dead_pure_insts`], which sweep the whole function.
+
+use cranelift_entity::SecondaryMap;
+use rustc_hash::FxHashSet;
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::SideEffect,
+    BlockId, ControlFlowGraph, Function, InstId, ValueId,
+};
+
+use super::cleanup::remove_phi_args;
+
+#[derive(Debug, Default)]
+pub struct IncrementalDce {
+    worklist: Vec<ValueId>,
+    in_worklist: FxHashSet<ValueId>,
+}
+
+impl IncrementalDce {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.worklist.clear();
+        self.in_worklist.clear();
+    }
+
+    /// Notify that the `value` may have become dead.
+    ///
+    /// The value is not removed until [`Self::run`] is called, so it's fine to
+    /// notify a value that gets a new use later.
+    pub fn notify(&mut self, value: ValueId) {
+        if self.in_worklist.insert(value) {
+            self.worklist.push(value);
+        }
+    }
+
+    /// Returns `true` if there are notified values that are not processed yet.
+    pub fn has_pending(&self) -> bool {
+        !self.worklist.is_empty()
+    }
+
+    /// Remove the insts defining the notified values if they are dead, and
+    /// continue with their operands that became dead as a result.
+    /// Returns `true` if any inst is removed.
+    ///
+    /// An inst is dead if it has no side effect that must be kept, and its
+    /// result is used by nothing but the inst itself.
+    pub fn run(&mut self, func: &mut Function) -> bool {
+        let mut changed = false;
+        while let Some(value) = self.worklist.pop() {
+            self.in_worklist.remove(&value);
+
+            let Some(inst) = func.dfg.value_inst(value) else {
+                continue;
+            };
+            if !func.layout.is_inst_inserted(inst) || !is_dead(func, inst, value) {
+                continue;
+            }
+
+            let mut operands = Vec::new();
+            func.dfg.inst(inst).for_each_value(&mut |operand| {
+                if operand != value {
+                    operands.push(operand);
+                }
+            });
+
+            InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+            changed = true;
+
+            for operand in operands {
+                self.notify(operand);
+            }
+        }
+
+        changed
+    }
+}
+
+fn is_dead(func: &Function, inst: InstId, result: ValueId) -> bool {
+    !matches!(func.dfg.side_effect(inst), SideEffect::Write)
+        && !func.dfg.is_terminator(inst)
+        && func.dfg.users(result).all(|&user| user == inst)
+}
+
+/// Remove blocks unreachable from the entry block along with their insts,
+/// and the phi args passed from them to reachable blocks. `cfg` must be up to
+/// date with `func`, and is recomputed if any block is removed.
+/// Returns `true` if any block is removed.
+pub fn remove_unreachable_blocks(func: &mut Function, cfg: &mut ControlFlowGraph) -> bool {
+    let Some(entry) = func.layout.entry_block() else {
+        return false;
+    };
+
+    let mut reachable = SecondaryMap::<BlockId, bool>::new();
+    for &block in cfg.order().post_order() {
+        reachable[block] = true;
+    }
+    reachable[entry] = true;
+
+    let unreachable: Vec<_> = func
+        .layout
+        .iter_block()
+        .filter(|&block| !reachable[block])
+        .collect();
+    if unreachable.is_empty() {
+        return false;
+    }
+
+    for &block in &unreachable {
+        for &succ in cfg.succs_of(block) {
+            if reachable[succ] {
+                remove_phi_args(func, block, succ);
+            }
+        }
+    }
+    for &block in &unreachable {
+        InstInserter::at_location(CursorLocation::BlockTop(block)).remove_block(func);
+    }
+
+    cfg.compute(func);
+    true
+}
+
+/// Remove insts that have no side effect at all and whose results are unused,
+/// until no such inst is left.
+/// Returns `true` if any inst is removed.
+///
+/// Unlike [`IncrementalDce`], an inst that reads memory, e.g., `mload`, is
+/// kept even if its result is unused, since [`SideEffect::Read`] is an effect,
+/// too.
+pub fn remove_dead_pure_insts(func: &mut Function) -> bool {
+    let mut changed = false;
+    loop {
+        let dead: Vec<_> = func
+            .layout
+            .iter_block()
+            .flat_map(|block| func.layout.iter_inst(block))
+            .filter(|&inst| is_dead_pure(func, inst))
+            .collect();
+        if dead.is_empty() {
+            return changed;
+        }
+
+        for inst in dead {
+            InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+        }
+        changed = true;
+    }
+}
+
+fn is_dead_pure(func: &Function, inst: InstId) -> bool {
+    let Some(result) = func.dfg.inst_result(inst) else {
+        return false;
+    };
+    !func.dfg.side_effect(inst).has_effect() && is_dead(func, inst, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::{Add, Mul, Sub},
+            control_flow::{Br, Call, Jump, Phi, Return},
+            data::Mload,
+        },
+        prelude::*,
+        Linkage, Signature, Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn cascade_to_operands() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        let v1 = builder.insert_inst(Mul::new(is, v0, arg), Type::I32);
+        let v2 = builder.insert_inst(Sub::new(is, v1, arg), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v2)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            // Make `v2` unused, as if a pass replaced the use of it.
+            let ret = func.layout.last_inst_of(b0).unwrap();
+            func.dfg
+                .replace_inst(ret, Box::new(Return::new(is, Some(arg))));
+
+            let mut dce = IncrementalDce::new();
+            dce.notify(v2);
+            assert!(dce.run(func));
+            assert!(!dce.has_pending());
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32) -> i32 {
+    block0:
+        return v0;
+}
+"
+        );
+    }
+
+    #[test]
+    fn remove_pure_call() {
+        let mb = test_module_builder();
+        let mut pure_sig = Signature::new("pure", Linkage::External, &[Type::I32], Type::I32);
+        pure_sig.set_pure(true);
+        let pure = mb.declare_function(pure_sig);
+        let impure_sig = Signature::new("impure", Linkage::External, &[Type::I32], Type::I32);
+        let impure = mb.declare_function(impure_sig);
+
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Call::new(is, pure, vec![arg].into()), Type::I32);
+        let v1 = builder.insert_inst(Call::new(is, impure, vec![arg].into()), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(arg)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[2];
+        module.func_store.modify(func_ref, |func| {
+            let mut dce = IncrementalDce::new();
+            dce.notify(v0);
+            dce.notify(v1);
+            assert!(dce.run(func));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32) -> i32 {
+    block0:
+        v2.i32 = call %impure v0;
+        return v0;
+}
+"
+        );
+    }
+
+    #[test]
+    fn keep_volatile_load_and_pinned_value() {
+        let mb = test_module_builder();
+        let ptr = mb.ptr_type(Type::I32);
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, ptr], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let (arg, addr) = (builder.args()[0], builder.args()[1]);
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Mload::new(is, addr, Type::I32), Type::I32);
+        let v1 = builder.insert_inst(Mload::new(is, addr, Type::I32), Type::I32);
+        let v2 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(arg)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let load = func.dfg.value_inst(v0).unwrap();
+            func.dfg.set_volatile(load);
+            func.dfg.pin_value(v2);
+
+            let mut dce = IncrementalDce::new();
+            for value in [v0, v1, v2] {
+                dce.notify(value);
+            }
+            assert!(dce.run(func));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32, v1.*i32) -> i32 {
+    block0:
+        v2.i32 = volatile mload v1 i32;
+        v4.i32 = pinned add v0 v0;
+        return v0;
+}
+"
+        );
+    }
+
+    #[test]
+    fn remove_unreachable_block_and_phi_arg() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1], Type::I32);
+        let is = evm.inst_set();
+
+        let a = builder.append_block();
+        let b = builder.append_block();
+        let c = builder.append_block();
+        let d = builder.append_block();
+        let e = builder.append_block();
+        let cond = builder.args()[0];
+
+        builder.switch_to_block(a);
+        builder.insert_inst_no_result(Br::new(is, cond, b, c));
+
+        for block in [b, c, d] {
+            builder.switch_to_block(block);
+            builder.insert_inst_no_result(Jump::new(is, e));
+        }
+
+        builder.switch_to_block(e);
+        let one = builder.make_imm_value(1i32);
+        let two = builder.make_imm_value(2i32);
+        let three = builder.make_imm_value(3i32);
+        let phi = Phi::new(is, vec![(one, b), (two, c), (three, d)]);
+        let v0 = builder.insert_inst(phi, Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            assert!(remove_unreachable_blocks(func, &mut cfg));
+            assert!(!func.layout.is_block_inserted(d));
+            assert_eq!(cfg.pred_num_of(e), 2);
+            assert!(!remove_unreachable_blocks(func, &mut cfg));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i1) -> i32 {
+    block0:
+        br v0 block1 block2;
+
+    block1:
+        jump block4;
+
+    block2:
+        jump block4;
+
+    block4:
+        v4.i32 = phi (1.i32 block1) (2.i32 block2);
+        return v4;
+}
+"
+        );
+    }
+
+    #[test]
+    fn remove_dead_pure_insts_keeps_load() {
+        let mb = test_module_builder();
+        let ptr = mb.ptr_type(Type::I32);
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, ptr], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let (arg, addr) = (builder.args()[0], builder.args()[1]);
+
+        builder.switch_to_block(b0);
+        builder.insert_inst(Mload::new(is, addr, Type::I32), Type::I32);
+        let v1 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        builder.insert_inst(Mul::new(is, v1, arg), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(arg)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            assert!(remove_dead_pure_insts(func));
+            assert!(!remove_dead_pure_insts(func));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32, v1.*i32) -> i32 {
+    block0:
+        v2.i32 = mload v1 i32;
+        return v0;
+}
+"
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! This module contains a solver for dominator-based value numbering.
+//!
+//! The algorithm walks the dominator tree in preorder while keeping a scoped
+//! table of available expressions. A scope is pushed on entering a block and
+//! popped on leaving it, so an expression computed in a block is reused only in
+//! the blocks it dominates.
+//!
+//! This is cheaper than a full GVN, but doesn't find redundancies across
+//! incomparable blocks.
+//!
+//! The operands of commutative insts are compared regardless of their order,
+//! so `add v0 v1` and `add v1 v0` get the same number.
+
+use rustc_hash::FxHashMap;
+use smallvec::SmallVec;
+use sonatina_ir::{
+    domtree::{DomTree, DominatorTreeTraversable},
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::is_commutative,
+    BlockId, Function, InstId, ValueId,
+};
+
+use crate::expr_analysis::ExprKey;
+
+#[derive(Default)]
+pub struct DvntSolver {
+    table: ScopedHashTable,
+    domtree_traversable: DominatorTreeTraversable,
+}
+
+impl DvntSolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.table.clear();
+        self.domtree_traversable.clear();
+    }
+
+    /// Run dominator-based value numbering on the function.
+    /// Returns `true` if any inst is removed.
+    pub fn run(&mut self, func: &mut Function, domtree: &DomTree) -> bool {
+        self.clear();
+
+        let Some(entry) = domtree.entry() else {
+            return false;
+        };
+        self.domtree_traversable.compute(domtree);
+
+        let mut changed = false;
+        // The stack holds a block and the index of its next child to visit.
+        let mut stack = vec![(entry, 0)];
+        self.table.push_scope();
+        changed |= self.visit_block(func, entry);
+
+        while let Some((block, idx)) = stack.last_mut() {
+            match self.domtree_traversable.children_of(*block).get(*idx) {
+                Some(&child) => {
+                    *idx += 1;
+                    stack.push((child, 0));
+                    self.table.push_scope();
+                    changed |= self.visit_block(func, child);
+                }
+                None => {
+                    stack.pop();
+                    self.table.pop_scope();
+                }
+            }
+        }
+
+        changed
+    }
+
+    fn visit_block(&mut self, func: &mut Function, block: BlockId) -> bool {
+        let mut changed = false;
+
+        let insts: Vec<_> = func.layout.iter_inst(block).collect();
+        for inst in insts {
+            if !self.is_candidate(func, inst) {
+                continue;
+            }
+
+            let result = func.dfg.inst_result(inst).unwrap();
+            let key = ExprKey::new(func, inst);
+            match self.table.lookup(func, &key, inst) {
+                Some(value) => {
+                    func.dfg.change_to_alias(result, value);
+                    InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                    changed = true;
+                }
+                None => self.table.insert(key, inst, result),
+            }
+        }
+
+        changed
+    }
+
+    fn is_candidate(&self, func: &Function, inst: InstId) -> bool {
+        !(func.dfg.side_effect(inst).has_effect()
+            || func.dfg.is_phi(inst)
+            || func.dfg.is_terminator(inst)
+            || func.dfg.inst_result(inst).is_none())
+    }
+}
+
+/// A hash table whose entries are removed when the scope they're inserted in
+/// is popped.
+#[derive(Default)]
+struct ScopedHashTable {
+    map: FxHashMap<ExprKey, SmallVec<[(InstId, ValueId); 1]>>,
+    /// Keys inserted in each scope.
+    scopes: Vec<Vec<ExprKey>>,
+}
+
+impl ScopedHashTable {
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn pop_scope(&mut self) {
+        for key in self.scopes.pop().unwrap() {
+            let entries = self.map.get_mut(&key).unwrap();
+            entries.pop();
+            if entries.is_empty() {
+                self.map.remove(&key);
+            }
+        }
+    }
+
+    fn insert(&mut self, key: ExprKey, inst: InstId, value: ValueId) {
+        self.map.entry(key.clone()).or_default().push((inst, value));
+        self.scopes.last_mut().unwrap().push(key);
+    }
+
+    /// Returns the value of an available inst identical to `inst`.
+    fn lookup(&self, func: &Function, key: &ExprKey, inst: InstId) -> Option<ValueId> {
+        let data = func.dfg.inst(inst);
+        self.map
+            .get(key)?
+            .iter()
+            .rev()
+            .find(|(other, _)| {
+                // The key is exhaustive for commutative insts, whose operands
+                // may be swapped.
+                is_commutative(key.name) || func.dfg.inst(*other).is_identical(data)
+            })
+            .map(|(_, value)| *value)
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+        self.scopes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::{Add, Mul},
+            control_flow::{Call, Jump, Return},
+            data::Mload,
+        },
+        isa::Isa,
+        ControlFlowGraph, Linkage, Signature, Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn number_pure_call() {
+        let mb = test_module_builder();
+        let mut pure_sig = Signature::new("pure", Linkage::External, &[Type::I32], Type::I32);
+        pure_sig.set_pure(true);
+        let pure = mb.declare_function(pure_sig);
+        let impure_sig = Signature::new("impure", Linkage::External, &[Type::I32], Type::I32);
+        let impure = mb.declare_function(impure_sig);
+
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Call::new(is, pure, vec![arg].into()), Type::I32);
+        let v1 = builder.insert_inst(Call::new(is, pure, vec![arg].into()), Type::I32);
+        let v2 = builder.insert_inst(Call::new(is, impure, vec![arg].into()), Type::I32);
+        let v3 = builder.insert_inst(Call::new(is, impure, vec![arg].into()), Type::I32);
+        let v4 = builder.insert_inst(Add::new(is, v0, v1), Type::I32);
+        let v5 = builder.insert_inst(Add::new(is, v2, v3), Type::I32);
+        let v6 = builder.insert_inst(Add::new(is, v4, v5), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v6)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[2];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            let mut domtree = DomTree::new();
+            domtree.compute(&cfg);
+
+            let mut solver = DvntSolver::new();
+            assert!(solver.run(func, &domtree));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32) -> i32 {
+    block0:
+        v1.i32 = call %pure v0;
+        v3.i32 = call %impure v0;
+        v4.i32 = call %impure v0;
+        v5.i32 = add v1 v1;
+        v6.i32 = add v3 v4;
+        v7.i32 = add v5 v6;
+        return v7;
+}
+"
+        );
+    }
+
+    #[test]
+    fn number_commutative_in_dominated_block() {
+        let mb = test_module_builder();
+        let ptr = mb.ptr_type(Type::I32);
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, Type::I32, ptr], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let (x, y, addr) = (builder.args()[0], builder.args()[1], builder.args()[2]);
+
+        builder.switch_to_block(b0);
+        builder.insert_inst(Add::new(is, x, y), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let v1 = builder.insert_inst(Add::new(is, y, x), Type::I32);
+        let v2 = builder.insert_inst(Mload::new(is, addr, Type::I32), Type::I32);
+        let v3 = builder.insert_inst(Mload::new(is, addr, Type::I32), Type::I32);
+        let v4 = builder.insert_inst(Add::new(is, v2, v3), Type::I32);
+        let v5 = builder.insert_inst(Mul::new(is, v1, v4), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v5)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            let mut domtree = DomTree::new();
+            domtree.compute(&cfg);
+
+            let mut solver = DvntSolver::new();
+            assert!(solver.run(func, &domtree));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32, v1.i32, v2.*i32) -> i32 {
+    block0:
+        v3.i32 = add v0 v1;
+        jump block1;
+
+    block1:
+        v5.i32 = mload v2 i32;
+        v6.i32 = mload v2 i32;
+        v7.i32 = add v5 v6;
+        v8.i32 = mul v3 v7;
+        return v8;
+}
+"
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! This module contains a solver for hoisting common computations.
+//!
+//! When every successor of a branch computes the same expression, a single copy
+//! of the computation is hoisted to the nearest common dominator of the
+//! successors, and the duplicates are removed.
+
+use sonatina_ir::{
+    domtree::DomTree,
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    BlockId, ControlFlowGraph, Function, InstId,
+};
+
+#[derive(Debug, Default)]
+pub struct HoistCommonSolver {
+    arms: Vec<BlockId>,
+    duplicates: Vec<InstId>,
+}
+
+impl HoistCommonSolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.arms.clear();
+        self.duplicates.clear();
+    }
+
+    /// Run common computation hoisting on the function.
+    /// Returns `true` if any inst is hoisted.
+    ///
+    /// NOTE: This method doesn't modify the control flow graph, so `cfg` and
+    /// `domtree` are still valid after the transformation.
+    pub fn run(&mut self, func: &mut Function, cfg: &ControlFlowGraph, domtree: &DomTree) -> bool {
+        self.clear();
+
+        let mut changed = false;
+        for &block in domtree.rpo() {
+            self.arms.clear();
+            self.arms.extend(cfg.succs_of(block).copied());
+            if self.arms.len() < 2
+                || self
+                    .arms
+                    .iter()
+                    .any(|&arm| domtree.idom_of(arm) != Some(block))
+            {
+                continue;
+            }
+
+            let candidates: Vec<_> = func.layout.iter_inst(self.arms[0]).collect();
+            for inst in candidates {
+                changed |= self.hoist_inst(func, domtree, inst);
+            }
+        }
+
+        changed
+    }
+
+    /// Hoist the `inst` if all other arms contain an identical inst.
+    fn hoist_inst(&mut self, func: &mut Function, domtree: &DomTree, inst: InstId) -> bool {
+        if !self.is_safe_to_hoist(func, inst) {
+            return false;
+        }
+
+        let Some(dest) = domtree.nearest_common_dominator_of(&self.arms) else {
+            return false;
+        };
+        if !self.is_available_at(func, domtree, inst, dest) {
+            return false;
+        }
+
+        self.duplicates.clear();
+        for &arm in &self.arms[1..] {
+            match self.find_identical(func, arm, inst) {
+                Some(dup) => self.duplicates.push(dup),
+                None => return false,
+            }
+        }
+
+        let term = func.layout.last_inst_of(dest).unwrap();
+        func.layout.remove_inst(inst);
+        func.layout.insert_inst_before(inst, term);
+
+        let result = func.dfg.inst_result(inst).unwrap();
+        for &dup in &self.duplicates {
+            let dup_result = func.dfg.inst_result(dup).unwrap();
+            func.dfg.change_to_alias(dup_result, result);
+            InstInserter::at_location(CursorLocation::At(dup)).remove_inst(func);
+        }
+
+        true
+    }
+
+    /// Returns an inst in the `block` that computes the same value as `inst`.
+    /// A volatile or pinned inst is never interchangeable with another one.
+    fn find_identical(&self, func: &Function, block: BlockId, inst: InstId) -> Option<InstId> {
+        let ty = func.dfg.value_ty(func.dfg.inst_result(inst).unwrap());
+        func.layout.iter_inst(block).find(|&other| {
+            !is_volatile_or_pinned(func, other)
+                && func.dfg.inst(other).is_identical(func.dfg.inst(inst))
+                && func
+                    .dfg
+                    .inst_result(other)
+                    .is_some_and(|result| func.dfg.value_ty(result) == ty)
+        })
+    }
+
+    /// Returns `true` if all operands of the `inst` are available at the end
+    /// of the `block`.
+    fn is_available_at(
+        &self,
+        func: &Function,
+        domtree: &DomTree,
+        inst: InstId,
+        block: BlockId,
+    ) -> bool {
+        let mut available = true;
+        func.dfg.inst(inst).for_each_value(&mut |value| {
+            if let Some(def_block) = func.def_block(value) {
+                available &= domtree.dominates(def_block, block);
+            }
+        });
+        available
+    }
+
+    fn is_safe_to_hoist(&self, func: &Function, inst: InstId) -> bool {
+        !(func.dfg.side_effect(inst).has_effect()
+            || func.dfg.is_phi(inst)
+            || func.dfg.is_terminator(inst)
+            || func.dfg.inst_result(inst).is_none()
+            || is_volatile_or_pinned(func, inst))
+    }
+}
+
+fn is_volatile_or_pinned(func: &Function, inst: InstId) -> bool {
+    func.dfg.is_volatile(inst)
+        || func
+            .dfg
+            .inst_result(inst)
+            .is_some_and(|result| func.dfg.is_pinned(result))
+}
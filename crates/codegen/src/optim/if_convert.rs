@@ -0,0 +1,304 @@
+//! This module contains if-conversion, which replaces a simple diamond with
+//! straight-line code that computes both arms and chooses the results with
+//! `select`.
+//!
+//! ```text
+//!        head                    head
+//!       /    \                     |
+//!    then    else      ==>       merge
+//!       \    /
+//!        merge
+//! ```
+//!
+//! Both arms are executed unconditionally after the conversion, so an arm is
+//! only speculated if it's small and contains no inst that has a side effect
+//! or may trap.
+
+use sonatina_ir::{
+    inst::{
+        control_flow::{Br, Call, Jump},
+        evm::{EvmSdiv, EvmSmod, EvmUdiv, EvmUmod},
+        logic::Select,
+    },
+    BlockId, ControlFlowGraph, Function, InstDowncast, InstId,
+};
+
+/// The maximum number of insts, excluding the terminator, in each arm.
+const MAX_ARM_INSTS: usize = 4;
+
+/// Convert the diamonds in the function into `select`s.
+/// Returns `true` if the function is modified, in which case `cfg` is
+/// recomputed.
+///
+/// The merge block is left in place with the head as its single predecessor,
+/// so that [`cleanup`](super::cleanup::cleanup) can merge them.
+pub fn if_convert(func: &mut Function, cfg: &mut ControlFlowGraph) -> bool {
+    if func.inst_set().has_select().is_none() {
+        return false;
+    }
+
+    let mut changed = false;
+    for head in func.layout.iter_block().collect::<Vec<_>>() {
+        if !func.layout.is_block_inserted(head) {
+            continue;
+        }
+        if let Some(diamond) = Diamond::find(func, cfg, head) {
+            diamond.convert(func);
+            cfg.compute(func);
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+struct Diamond {
+    branch: InstId,
+    nz_arm: BlockId,
+    z_arm: BlockId,
+    merge: BlockId,
+}
+
+impl Diamond {
+    fn find(func: &Function, cfg: &ControlFlowGraph, head: BlockId) -> Option<Self> {
+        let branch = func.layout.last_inst_of(head)?;
+        let br = <&Br as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(branch))?;
+        let (nz_arm, z_arm) = (*br.nz_dest(), *br.z_dest());
+        if nz_arm == z_arm || nz_arm == head || z_arm == head {
+            return None;
+        }
+
+        let merge = arm_dest(func, cfg, nz_arm)?;
+        if arm_dest(func, cfg, z_arm)? != merge || merge == head || cfg.pred_num_of(merge) != 2 {
+            return None;
+        }
+
+        Some(Self {
+            branch,
+            nz_arm,
+            z_arm,
+            merge,
+        })
+    }
+
+    fn convert(self, func: &mut Function) {
+        let is = func.inst_set();
+        let cond = *<&Br as InstDowncast>::downcast(is, func.dfg.inst(self.branch))
+            .unwrap()
+            .cond();
+
+        // Move the arms in front of the branch.
+        for arm in [self.nz_arm, self.z_arm] {
+            let insts: Vec<_> = func.layout.iter_inst(arm).collect();
+            let (&term, body) = insts.split_last().unwrap();
+            for &inst in body {
+                func.layout.remove_inst(inst);
+                func.layout.insert_inst_before(inst, self.branch);
+            }
+            func.dfg.untrack_inst(term);
+            func.layout.remove_inst(term);
+            func.layout.remove_block(arm);
+        }
+
+        // Replace each phi in the merge block with a `select` in place so that
+        // the phi result is kept.
+        let phis: Vec<_> = func
+            .layout
+            .iter_inst(self.merge)
+            .take_while(|&inst| func.dfg.is_phi(inst))
+            .collect();
+        for phi in phis {
+            let arg_from = |block| {
+                func.dfg
+                    .cast_phi(phi)
+                    .unwrap()
+                    .args()
+                    .iter()
+                    .find_map(|&(value, pred)| (pred == block).then_some(value))
+                    .unwrap()
+            };
+            let (nz_val, z_val) = (arg_from(self.nz_arm), arg_from(self.z_arm));
+            let select = Select::new(is.has_select().unwrap(), cond, nz_val, z_val);
+            func.dfg.replace_inst(phi, Box::new(select));
+        }
+
+        let jump = Jump::new(is.jump(), self.merge);
+        func.dfg.replace_inst(self.branch, Box::new(jump));
+    }
+}
+
+/// Returns the destination of the `arm` if it can be speculated in its single
+/// predecessor.
+fn arm_dest(func: &Function, cfg: &ControlFlowGraph, arm: BlockId) -> Option<BlockId> {
+    if cfg.pred_num_of(arm) != 1 {
+        return None;
+    }
+
+    let term = func.layout.last_inst_of(arm)?;
+    let jump = <&Jump as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(term))?;
+
+    let mut num_insts = 0;
+    for inst in func.layout.iter_inst(arm) {
+        if inst == term {
+            break;
+        }
+        num_insts += 1;
+        if num_insts > MAX_ARM_INSTS || !is_speculatable(func, inst) {
+            return None;
+        }
+    }
+
+    Some(*jump.dest())
+}
+
+fn is_speculatable(func: &Function, inst: InstId) -> bool {
+    let is = func.inst_set();
+    let data = func.dfg.inst(inst);
+
+    // A call to a pure function has no side effect, but may not terminate.
+    !(func.dfg.side_effect(inst).has_effect()
+        || func.dfg.is_phi(inst)
+        || <&Call as InstDowncast>::downcast(is, data).is_some()
+        || <&EvmUdiv as InstDowncast>::downcast(is, data).is_some()
+        || <&EvmSdiv as InstDowncast>::downcast(is, data).is_some()
+        || <&EvmUmod as InstDowncast>::downcast(is, data).is_some()
+        || <&EvmSmod as InstDowncast>::downcast(is, data).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_interpreter::Machine;
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::{Add, Mul},
+            control_flow::{Phi, Return},
+            data::Mstore,
+        },
+        interpret::EvalValue,
+        isa::Isa,
+        Immediate, Module, Type,
+    };
+
+    use super::*;
+
+    /// The else arm of the diamond built by [`build_module`].
+    #[derive(Clone, Copy)]
+    enum ElseArm {
+        /// `x * 2`.
+        Mul,
+        /// `x * 2` after storing `x` to memory.
+        StoreThenMul,
+        /// `x / 2`.
+        Div,
+    }
+
+    /// Builds `f(c, x) = c ? x + 1 : <else_arm>` as a diamond.
+    fn build_module(else_arm: ElseArm) -> Module {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let (c, x) = (builder.args()[0], builder.args()[1]);
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Br::new(is, c, b1, b2));
+
+        builder.switch_to_block(b1);
+        let one = builder.make_imm_value(1i32);
+        let then_val = builder.insert_inst(Add::new(is, x, one), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        if let ElseArm::StoreThenMul = else_arm {
+            let addr = builder.make_imm_value(0i32);
+            builder.insert_inst_no_result(Mstore::new(is, addr, x, Type::I32));
+        }
+        let two = builder.make_imm_value(2i32);
+        let else_val = match else_arm {
+            ElseArm::Mul | ElseArm::StoreThenMul => {
+                builder.insert_inst(Mul::new(is, x, two), Type::I32)
+            }
+            ElseArm::Div => builder.insert_inst(EvmUdiv::new(is, x, two), Type::I32),
+        };
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b3);
+        let phi = Phi::new(is, vec![(then_val, b1), (else_val, b2)]);
+        let result = builder.insert_inst(phi, Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(result)));
+
+        builder.seal_all();
+        builder.finish();
+        mb.build()
+    }
+
+    #[test]
+    fn diamond_to_select() {
+        let module = build_module(ElseArm::Mul);
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            assert!(if_convert(func, &mut cfg));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i1, v1.i32) -> i32 {
+    block0:
+        v3.i32 = add v1 1.i32;
+        v5.i32 = mul v1 2.i32;
+        jump block3;
+
+    block3:
+        v6.i32 = select v0 v3 v5;
+        return v6;
+}
+"
+        );
+
+        let mut converted = Machine::new(module);
+        let mut original = Machine::new(build_module(ElseArm::Mul));
+        for c in [true, false] {
+            let args = vec![
+                EvalValue::Imm(Immediate::I1(c)),
+                EvalValue::Imm(Immediate::I32(5)),
+            ];
+            let expected = original.run(func_ref, args.clone());
+            assert_eq!(
+                expected,
+                EvalValue::Imm(Immediate::I32(if c { 6 } else { 10 }))
+            );
+            assert_eq!(converted.run(func_ref, args), expected, "c = {c}");
+            converted.clear_state();
+            original.clear_state();
+        }
+    }
+
+    #[test]
+    fn store_is_not_speculated() {
+        let module = build_module(ElseArm::StoreThenMul);
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            assert!(!if_convert(func, &mut cfg));
+        });
+    }
+
+    #[test]
+    fn division_is_not_speculated() {
+        let module = build_module(ElseArm::Div);
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            assert!(!if_convert(func, &mut cfg));
+        });
+    }
+}
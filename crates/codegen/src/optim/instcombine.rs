@@ -0,0 +1,240 @@
+//! This module contains instcombine, which simplifies insts locally with the
+//! built-in [`PeepholeRule`]s.
+
+use sonatina_ir::{
+    inst::{
+        cast::Zext,
+        cmp::{Eq, Ge, Gt, Le, Lt, Ne, Sge, Sgt, Sle, Slt},
+    },
+    DataFlowGraph, Function, Immediate, InstDowncast, InstId, ValueId,
+};
+
+use super::peephole::{PeepholeRule, PeepholeSolver, Rewrite};
+
+/// Run the built-in rules on the function until no rule applies.
+/// Returns `true` if the function is modified.
+pub fn instcombine(func: &mut Function) -> bool {
+    let mut solver = PeepholeSolver::new();
+    register_rules(&mut solver);
+    solver.run(func)
+}
+
+/// Register the built-in rules to the `solver`.
+pub fn register_rules(solver: &mut PeepholeSolver) {
+    for opcode in [
+        "eq", "ne", "lt", "gt", "le", "ge", "slt", "sgt", "sle", "sge",
+    ] {
+        solver.register(opcode, FoldCmp);
+    }
+}
+
+/// Folds comparisons whose result is known at compile time into `true` or
+/// `false`, i.e.,
+/// * comparisons of a value with itself, e.g., `slt x x` into `false`.
+/// * comparisons of values whose unsigned ranges are known, e.g., `lt (zext
+///   x.i8) 256.i32` into `true`. A value has a known range if it's an
+///   immediate or the result of `zext`.
+pub struct FoldCmp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpKind {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Slt,
+    Sgt,
+    Sle,
+    Sge,
+}
+
+impl PeepholeRule for FoldCmp {
+    fn try_apply(&self, dfg: &mut DataFlowGraph, inst: InstId) -> Option<Rewrite> {
+        let (kind, lhs, rhs) = cast_cmp(dfg, inst)?;
+
+        let result = if lhs == rhs {
+            matches!(
+                kind,
+                CmpKind::Eq | CmpKind::Le | CmpKind::Ge | CmpKind::Sle | CmpKind::Sge
+            )
+        } else {
+            fold_by_range(dfg, kind, lhs, rhs)?
+        };
+
+        Some(Rewrite::Value(dfg.make_imm_value(result)))
+    }
+}
+
+fn cast_cmp(dfg: &DataFlowGraph, inst: InstId) -> Option<(CmpKind, ValueId, ValueId)> {
+    let is = dfg.inst_set();
+    let data = dfg.inst(inst);
+
+    macro_rules! cast {
+        ($($ty:ident),*) => {
+            $(
+                if let Some(cmp) = <&$ty as InstDowncast>::downcast(is, data) {
+                    return Some((CmpKind::$ty, *cmp.lhs(), *cmp.rhs()));
+                }
+            )*
+        };
+    }
+    cast!(Eq, Ne, Lt, Gt, Le, Ge, Slt, Sgt, Sle, Sge);
+
+    None
+}
+
+/// Returns the result of the comparison if it's the same for all values in
+/// the known ranges of `lhs` and `rhs`.
+fn fold_by_range(dfg: &DataFlowGraph, kind: CmpKind, lhs: ValueId, rhs: ValueId) -> Option<bool> {
+    let (lhs_lo, lhs_hi) = unsigned_range(dfg, lhs)?;
+    let (rhs_lo, rhs_hi) = unsigned_range(dfg, rhs)?;
+
+    // `true` as `i1` is all one, so check the results against zero.
+    let lt = |a: Immediate, b: Immediate| !a.lt(b).is_zero();
+    let le = |a: Immediate, b: Immediate| !a.le(b).is_zero();
+
+    let kind = match kind {
+        // Signed and unsigned comparisons agree if both operands are
+        // non-negative.
+        CmpKind::Slt | CmpKind::Sgt | CmpKind::Sle | CmpKind::Sge
+            if lhs_hi.is_negative() || rhs_hi.is_negative() =>
+        {
+            return None;
+        }
+        CmpKind::Slt => CmpKind::Lt,
+        CmpKind::Sgt => CmpKind::Gt,
+        CmpKind::Sle => CmpKind::Le,
+        CmpKind::Sge => CmpKind::Ge,
+        kind => kind,
+    };
+
+    match kind {
+        CmpKind::Lt if lt(lhs_hi, rhs_lo) => Some(true),
+        CmpKind::Lt if le(rhs_hi, lhs_lo) => Some(false),
+        CmpKind::Le if le(lhs_hi, rhs_lo) => Some(true),
+        CmpKind::Le if lt(rhs_hi, lhs_lo) => Some(false),
+        CmpKind::Gt if lt(rhs_hi, lhs_lo) => Some(true),
+        CmpKind::Gt if le(lhs_hi, rhs_lo) => Some(false),
+        CmpKind::Ge if le(rhs_hi, lhs_lo) => Some(true),
+        CmpKind::Ge if lt(lhs_hi, rhs_lo) => Some(false),
+        CmpKind::Eq | CmpKind::Ne if lt(lhs_hi, rhs_lo) || lt(rhs_hi, lhs_lo) => {
+            Some(kind == CmpKind::Ne)
+        }
+        _ => None,
+    }
+}
+
+/// Returns the unsigned range `[lo, hi]` of the `value` if it's known.
+fn unsigned_range(dfg: &DataFlowGraph, value: ValueId) -> Option<(Immediate, Immediate)> {
+    if let Some(imm) = dfg.value_imm(value) {
+        return Some((imm, imm));
+    }
+
+    let inst = dfg.value_inst(value)?;
+    let zext = <&Zext as InstDowncast>::downcast(dfg.inst_set(), dfg.inst(inst))?;
+    let ty = *zext.ty();
+    let from_ty = dfg.value_ty(*zext.from());
+    Some((Immediate::zero(ty), Immediate::all_one(from_ty).zext(ty)))
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::control_flow::{Br, Return},
+        isa::Isa,
+        ControlFlowGraph, Type,
+    };
+
+    use super::*;
+    use crate::optim::cleanup::cleanup;
+
+    #[test]
+    fn self_slt_folds_branch() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        let arg = builder.args()[0];
+        let cond = builder.insert_inst(Slt::new(is, arg, arg), Type::I1);
+        builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Return::new(is, Some(arg)));
+
+        builder.switch_to_block(b2);
+        let one = builder.make_imm_value(1i32);
+        builder.insert_inst_no_result(Return::new(is, Some(one)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            assert!(instcombine(func));
+            let mut cfg = ControlFlowGraph::new();
+            assert!(cleanup(func, &mut cfg));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32) -> i32 {
+    block0:
+        return 1.i32;
+}
+"
+        );
+    }
+
+    #[test]
+    fn zext_range() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I8], Type::I1);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let arg = builder.args()[0];
+        let ext = builder.insert_inst(Zext::new(is, arg, Type::I32), Type::I32);
+        let c256 = builder.make_imm_value(256i32);
+        let c255 = builder.make_imm_value(255i32);
+        // Always true.
+        let lt_256 = builder.insert_inst(Lt::new(is, ext, c256), Type::I1);
+        // Always false.
+        let sgt_255 = builder.insert_inst(Sgt::new(is, ext, c255), Type::I1);
+        // Unknown.
+        let lt_255 = builder.insert_inst(Lt::new(is, ext, c255), Type::I1);
+        let ne0 = builder.insert_inst(Ne::new(is, lt_256, sgt_255), Type::I1);
+        let ne1 = builder.insert_inst(Ne::new(is, ne0, lt_255), Type::I1);
+        builder.insert_inst_no_result(Return::new(is, Some(ne1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            assert!(instcombine(func));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i8) -> i1 {
+    block0:
+        v1.i32 = zext v0 i32;
+        v6.i1 = lt v1 255.i32;
+        v8.i1 = ne 1.i1 v6;
+        return v8;
+}
+"
+        );
+    }
+}
@@ -2,7 +2,7 @@
 use rustc_hash::{FxHashMap, FxHashSet};
 use sonatina_ir::{
     func_cursor::{CursorLocation, FuncCursor, InstInserter},
-    BlockId, ControlFlowGraph, Function, InstId, ValueId,
+    BlockFlag, BlockId, ControlFlowGraph, Function, InstId, ValueId,
 };
 
 use crate::loop_analysis::{Loop, LoopTree};
@@ -31,6 +31,7 @@ impl LicmSolver {
 
             if !self.invariants.is_empty() {
                 let preheader = self.create_preheader(func, cfg, lpt, lp);
+                func.set_block_flag(preheader, BlockFlag::Preheader);
                 self.hoist_invariants(func, preheader);
                 self.invariants.clear();
             }
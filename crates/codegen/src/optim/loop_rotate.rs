@@ -0,0 +1,443 @@
+//! This module contains loop rotation, which turns a while-style loop that
+//! tests its condition in the header into a do-while-style loop that tests it
+//! at the bottom.
+//!
+//! ```text
+//! preheader:                      preheader:
+//!     jump header;                    <copy of header>
+//! header:                             br cond' body exit;
+//!     <phis and insts>            body:
+//!     br cond body exit;     =>       <phis>
+//! body:                               ...
+//!     ...                         latch:
+//! latch:                              ...
+//!     ...                             <copy of header>
+//!     jump header;                    br cond'' body exit;
+//! ```
+//!
+//! The copy of the header in the preheader keeps the loop running zero times
+//! if the condition doesn't hold on entry, and the copy in the latch replaces
+//! the back edge to the header, which saves a jump per iteration and gives the
+//! loop a preheader dominating all its blocks.
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::control_flow::Br,
+    BlockId, ControlFlowGraph, Function, InstDowncast, InstId, Type, Value, ValueId,
+};
+
+use crate::loop_analysis::{Loop, LoopTree};
+
+/// Rotate the `lp` if it has the shape below, and recompute `cfg`.
+/// Returns `true` if the loop is rotated.
+///
+/// 1. The header has exactly one predecessor outside the loop, i.e., the
+///    preheader, and exactly one inside the loop, i.e., the latch. Both of
+///    them end with `jump`.
+/// 2. The header ends with `br` to a block in the loop and to a block outside
+///    of it, each of which has the header as its only predecessor.
+/// 3. The loop is left only from the header.
+///
+/// NOTE: The header is removed and the body block becomes the new header, so
+/// `lpt` and any dominator tree must be recomputed after the rotation.
+pub fn rotate_loop(
+    func: &mut Function,
+    cfg: &mut ControlFlowGraph,
+    lpt: &LoopTree,
+    lp: Loop,
+) -> bool {
+    let Some(rotator) = LoopRotator::new(func, cfg, lpt, lp) else {
+        return false;
+    };
+
+    rotator.rotate(func);
+    cfg.compute(func);
+    true
+}
+
+struct LoopRotator {
+    preheader: BlockId,
+    header: BlockId,
+    latch: BlockId,
+    /// The successor of the header in the loop.
+    body: BlockId,
+    /// The successor of the header outside of the loop.
+    exit: BlockId,
+    blocks_in_loop: FxHashSet<BlockId>,
+}
+
+impl LoopRotator {
+    /// Returns `None` if the `lp` can't be rotated.
+    fn new(func: &Function, cfg: &ControlFlowGraph, lpt: &LoopTree, lp: Loop) -> Option<Self> {
+        let header = lpt.loop_header(lp);
+
+        let (mut preheader, mut latch) = (None, None);
+        for &pred in cfg.preds_of(header) {
+            let slot = if lpt.is_in_loop(pred, lp) {
+                &mut latch
+            } else {
+                &mut preheader
+            };
+            if slot.replace(pred).is_some() {
+                return None;
+            }
+        }
+        let (preheader, latch) = (preheader?, latch?);
+        for block in [preheader, latch] {
+            let term = func.layout.last_inst_of(block)?;
+            func.dfg.cast_jump(term)?;
+        }
+
+        let term = func.layout.last_inst_of(header)?;
+        let br = <&Br as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(term))?;
+        let (nz_dest, z_dest) = (*br.nz_dest(), *br.z_dest());
+        let (body, exit) = match (lpt.is_in_loop(nz_dest, lp), lpt.is_in_loop(z_dest, lp)) {
+            (true, false) => (nz_dest, z_dest),
+            (false, true) => (z_dest, nz_dest),
+            _ => return None,
+        };
+        if cfg.pred_num_of(body) != 1 || cfg.pred_num_of(exit) != 1 {
+            return None;
+        }
+
+        let blocks_in_loop: FxHashSet<_> = lpt.iter_blocks_post_order(cfg, lp).collect();
+        for &block in &blocks_in_loop {
+            if block != header
+                && cfg
+                    .succs_of(block)
+                    .any(|succ| !blocks_in_loop.contains(succ))
+            {
+                return None;
+            }
+        }
+
+        Some(Self {
+            preheader,
+            header,
+            latch,
+            body,
+            exit,
+            blocks_in_loop,
+        })
+    }
+
+    fn rotate(&self, func: &mut Function) {
+        let header_insts: Vec<_> = func.layout.iter_inst(self.header).collect();
+        let orig_phis: Vec<_> = [self.body, self.exit]
+            .into_iter()
+            .flat_map(|block| {
+                func.layout
+                    .iter_inst(block)
+                    .take_while(|&inst| func.dfg.is_phi(inst))
+            })
+            .collect();
+
+        // Every value defined in the header gets a phi in the body and the
+        // exit, which merges its copies in the preheader and the latch.
+        let mut body_phis = FxHashMap::default();
+        let mut exit_phis = FxHashMap::default();
+        for &inst in &header_insts {
+            if let Some(value) = func.dfg.inst_result(inst) {
+                let ty = func.dfg.value_ty(value);
+                body_phis.insert(value, insert_phi(func, self.body, ty));
+                exit_phis.insert(value, insert_phi(func, self.exit, ty));
+            }
+        }
+
+        // Copy the header into the preheader and the latch.
+        let mut pre_values = FxHashMap::default();
+        let mut latch_values = FxHashMap::default();
+        let (&term, insts) = header_insts.split_last().unwrap();
+        for &inst in insts {
+            if let Some(phi) = func.dfg.cast_phi(inst) {
+                let result = func.dfg.inst_result(inst).unwrap();
+                for &(arg, pred) in phi.args() {
+                    if pred == self.preheader {
+                        pre_values.insert(result, arg);
+                    } else {
+                        // The arg is computed in the previous iteration, so
+                        // it's the body phi if it's defined in the header.
+                        let arg = body_phis.get(&arg).copied().unwrap_or(arg);
+                        latch_values.insert(result, arg);
+                    }
+                }
+                continue;
+            }
+
+            let pre = copy_inst(func, inst, self.preheader, &pre_values);
+            let latch = copy_inst(func, inst, self.latch, &latch_values);
+            if let Some(result) = func.dfg.inst_result(inst) {
+                pre_values.insert(result, pre.unwrap());
+                latch_values.insert(result, latch.unwrap());
+            }
+        }
+        let map = |values: &FxHashMap<ValueId, ValueId>, value| {
+            values.get(&value).copied().unwrap_or(value)
+        };
+
+        for phis in [&body_phis, &exit_phis] {
+            for (&value, &phi) in phis {
+                let phi_inst = func.dfg.value_inst(phi).unwrap();
+                let pre = map(&pre_values, value);
+                let latch = map(&latch_values, value);
+                func.dfg.append_phi_arg(phi_inst, pre, self.preheader);
+                func.dfg.append_phi_arg(phi_inst, latch, self.latch);
+            }
+        }
+
+        // The phis that were in the body and the exit now have the preheader
+        // and the latch as predecessors instead of the header.
+        for phi in orig_phis {
+            func.dfg.untrack_inst(phi);
+            let value = func
+                .dfg
+                .cast_phi_mut(phi)
+                .unwrap()
+                .remove_phi_arg(self.header)
+                .unwrap();
+            func.dfg
+                .append_phi_arg(phi, map(&pre_values, value), self.preheader);
+            func.dfg
+                .append_phi_arg(phi, map(&latch_values, value), self.latch);
+        }
+
+        // Uses in the loop are dominated by the body, and the others are
+        // dominated by the exit since the loop is left only from the header.
+        for (&value, &body_phi) in &body_phis {
+            let users: Vec<_> = func
+                .dfg
+                .users(value)
+                .copied()
+                .filter(|&user| func.layout.inst_block(user) != self.header)
+                .collect();
+            for user in users {
+                let block = func.layout.inst_block(user);
+                let new_value = if self.blocks_in_loop.contains(&block) {
+                    body_phi
+                } else {
+                    exit_phis[&value]
+                };
+
+                func.dfg.untrack_inst(user);
+                func.dfg.inst_mut(user).for_each_value_mut(&mut |v| {
+                    if *v == value {
+                        *v = new_value;
+                    }
+                });
+                func.dfg.attach_user(user);
+            }
+        }
+
+        // Replace the jumps to the header with the copies of its branch.
+        let br = <&Br as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(term)).unwrap();
+        let (cond, nz_dest, z_dest) = (*br.cond(), *br.nz_dest(), *br.z_dest());
+        for (block, values) in [(self.preheader, &pre_values), (self.latch, &latch_values)] {
+            let jump = func.layout.last_inst_of(block).unwrap();
+            let br = Br::new_unchecked(func.inst_set(), map(values, cond), nz_dest, z_dest);
+            InstInserter::at_location(CursorLocation::At(jump)).replace(func, br);
+            if let Some(loc) = func.dfg.srcloc_of(term) {
+                func.dfg.set_srcloc(jump, loc);
+            }
+        }
+
+        InstInserter::at_location(CursorLocation::BlockTop(self.header)).remove_block(func);
+
+        // Remove the phis that turned out to be unused. A phi may be used only
+        // by another unused phi, so repeat until nothing is removed.
+        let mut phis: Vec<_> = body_phis
+            .into_values()
+            .chain(exit_phis.into_values())
+            .collect();
+        loop {
+            let (unused, used): (Vec<_>, Vec<_>) = phis.into_iter().partition(|&phi| {
+                func.dfg
+                    .users(phi)
+                    .all(|&user| func.dfg.inst_result(user) == Some(phi))
+            });
+            if unused.is_empty() {
+                break;
+            }
+            for phi in unused {
+                let inst = func.dfg.value_inst(phi).unwrap();
+                InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+            }
+            phis = used;
+        }
+    }
+}
+
+/// Insert a phi without args at the top of the `block`, and returns its result.
+fn insert_phi(func: &mut Function, block: BlockId, ty: Type) -> ValueId {
+    let phi = func.dfg.make_phi(vec![]);
+    let mut inserter = InstInserter::at_location(CursorLocation::BlockTop(block));
+    let inst = inserter.prepend_inst_data(func, phi);
+    let result = inserter.make_result(func, inst, ty);
+    inserter.attach_result(func, inst, result);
+    result
+}
+
+/// Insert a copy of the `inst` before the terminator of the `block` with its
+/// operands renamed by `values`, and returns the result of the copy.
+fn copy_inst(
+    func: &mut Function,
+    inst: InstId,
+    block: BlockId,
+    values: &FxHashMap<ValueId, ValueId>,
+) -> Option<ValueId> {
+    let mut data = func.dfg.clone_inst(inst);
+    data.for_each_value_mut(&mut |value| {
+        if let Some(&renamed) = values.get(value) {
+            *value = renamed;
+        }
+    });
+
+    let term = func.layout.last_inst_of(block).unwrap();
+    let copy = func.dfg.make_inst_dyn(data);
+    func.layout.insert_inst_before(copy, term);
+    if func.dfg.is_volatile(inst) {
+        func.dfg.set_volatile(copy);
+    }
+    if let Some(loc) = func.dfg.srcloc_of(inst) {
+        func.dfg.set_srcloc(copy, loc);
+    }
+
+    let result = func.dfg.inst_result(inst)?;
+    let ty = func.dfg.value_ty(result);
+    let copy_result = func.dfg.make_value(Value::Inst { inst: copy, ty });
+    func.dfg.attach_result(copy, copy_result);
+    if func.dfg.is_pinned(result) {
+        func.dfg.pin_value(copy_result);
+    }
+    Some(copy_result)
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_interpreter::Machine;
+    use sonatina_ir::{
+        builder::test_util::*,
+        domtree::DomTree,
+        inst::{
+            arith::Add,
+            cmp::Slt,
+            control_flow::{Jump, Phi, Return},
+        },
+        interpret::EvalValue,
+        isa::Isa,
+        Immediate, Module, SourceLoc,
+    };
+
+    use super::*;
+
+    /// Builds `sum(n) = 0 + 1 + ... + (n - 1)` as a while-style loop.
+    fn build_module() -> Module {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let n = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let zero = builder.make_imm_value(0i32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let i = builder.insert_inst(Phi::new(is, vec![(zero, b0)]), Type::I32);
+        let sum = builder.insert_inst(Phi::new(is, vec![(zero, b0)]), Type::I32);
+        let cond = builder.insert_inst(Slt::new(is, i, n), Type::I1);
+        builder.insert_inst_no_result(Br::new(is, cond, b2, b3));
+
+        builder.switch_to_block(b2);
+        let one = builder.make_imm_value(1i32);
+        let next_sum = builder.insert_inst(Add::new(is, sum, i), Type::I32);
+        let next_i = builder.insert_inst(Add::new(is, i, one), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+        builder.append_phi_arg(i, next_i, b2);
+        builder.append_phi_arg(sum, next_sum, b2);
+
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result(Return::new(is, Some(sum)));
+
+        builder.seal_all();
+        builder.finish();
+        mb.build()
+    }
+
+    #[test]
+    fn rotate_counted_loop() {
+        let module = build_module();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            let mut domtree = DomTree::new();
+            let mut lpt = LoopTree::new();
+            cfg.compute(func);
+            domtree.compute(&cfg);
+            lpt.compute(&cfg, &domtree);
+
+            let lp = lpt.loops().next().unwrap();
+            let header = lpt.loop_header(lp);
+            assert!(rotate_loop(func, &mut cfg, &lpt, lp));
+            assert!(!func.layout.is_block_inserted(header));
+
+            // The rotated loop still has a single loop, which is now headed
+            // by the former body.
+            domtree.compute(&cfg);
+            lpt.compute(&cfg, &domtree);
+            assert_eq!(lpt.loop_num(), 1);
+        });
+
+        let mut rotated = Machine::new(module);
+        let mut original = Machine::new(build_module());
+        let imm = |value: i32| EvalValue::Imm(Immediate::I32(value));
+        for n in [0, 1, 2, 10, -3] {
+            let expected = original.run(func_ref, vec![imm(n)]);
+            assert_eq!(expected, imm((0..n).sum()), "n = {n}");
+            assert_eq!(rotated.run(func_ref, vec![imm(n)]), expected, "n = {n}");
+            rotated.clear_state();
+            original.clear_state();
+        }
+    }
+
+    #[test]
+    fn rotate_keeps_srcloc() {
+        let module = build_module();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            let mut domtree = DomTree::new();
+            let mut lpt = LoopTree::new();
+            cfg.compute(func);
+            domtree.compute(&cfg);
+            lpt.compute(&cfg, &domtree);
+
+            let lp = lpt.loops().next().unwrap();
+            let header = lpt.loop_header(lp);
+            let is_slt = |func: &Function, inst| func.dfg.inst(inst).as_text() == "slt";
+            let cmp = func
+                .layout
+                .iter_inst(header)
+                .find(|&inst| is_slt(func, inst))
+                .unwrap();
+            func.dfg.set_srcloc(cmp, SourceLoc::new(3, 7));
+            assert!(rotate_loop(func, &mut cfg, &lpt, lp));
+
+            // The header is duplicated into the preheader and the latch.
+            let copies: Vec<_> = func
+                .layout
+                .iter_block()
+                .flat_map(|block| func.layout.iter_inst(block))
+                .filter(|&inst| is_slt(func, inst))
+                .collect();
+            assert_eq!(copies.len(), 2);
+            for copy in copies {
+                assert_eq!(func.dfg.srcloc_of(copy), Some(SourceLoc::new(3, 7)));
+            }
+        });
+    }
+}
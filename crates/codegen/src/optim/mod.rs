@@ -1,3 +1,18 @@
 pub mod adce;
+pub mod cleanup;
+pub mod const_fold;
+pub mod copy_prop;
+pub mod dce;
+pub mod dvnt;
+pub mod hoist_common;
+pub mod if_convert;
+pub mod instcombine;
 pub mod licm;
+pub mod loop_rotate;
+pub mod peephole;
+pub mod pipeline;
+pub mod pre;
 pub mod sccp;
+pub mod simplify_cfg;
+pub mod sink;
+pub mod tail_merge;
@@ -0,0 +1,192 @@
+//! This module contains a peephole optimization framework.
+//!
+//! Each simplification is written as a [`PeepholeRule`] that inspects a single
+//! inst, and is registered to a [`PeepholeSolver`] under the opcode name of
+//! the insts it applies to. This keeps rules small, pluggable, and testable in
+//! isolation.
+
+use std::collections::VecDeque;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    DataFlowGraph, Function, Inst, InstId, ValueId,
+};
+
+/// A rewrite of an inst proposed by a [`PeepholeRule`].
+pub enum Rewrite {
+    /// Replace all uses of the inst result with the value, and remove the inst.
+    Value(ValueId),
+    /// Replace the inst with the new inst, keeping the result.
+    Inst(Box<dyn Inst>),
+}
+
+pub trait PeepholeRule {
+    /// Returns a rewrite of the `inst` if the rule matches it.
+    ///
+    /// A rule may only add values to `dfg`, e.g., immediates used by the
+    /// rewrite, and must not modify it otherwise.
+    fn try_apply(&self, dfg: &mut DataFlowGraph, inst: InstId) -> Option<Rewrite>;
+}
+
+#[derive(Default)]
+pub struct PeepholeSolver {
+    /// Rules keyed by the opcode name of the insts they apply to.
+    rules: FxHashMap<&'static str, Vec<Box<dyn PeepholeRule>>>,
+    worklist: VecDeque<InstId>,
+    in_worklist: FxHashSet<InstId>,
+}
+
+impl PeepholeSolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the `rule` for the insts of the `opcode`, e.g., `"mul"`.
+    /// Rules of the same opcode are tried in the registration order.
+    pub fn register(&mut self, opcode: &'static str, rule: impl PeepholeRule + 'static) {
+        self.rules.entry(opcode).or_default().push(Box::new(rule));
+    }
+
+    pub fn clear(&mut self) {
+        self.worklist.clear();
+        self.in_worklist.clear();
+    }
+
+    /// Run the registered rules on the function until no rule applies.
+    /// Returns `true` if any inst is rewritten.
+    ///
+    /// Only the first matching rule is applied to an inst at a time. A
+    /// rewritten inst and the users of its result are revisited, since they
+    /// may match other rules after the rewrite.
+    pub fn run(&mut self, func: &mut Function) -> bool {
+        self.clear();
+
+        for block in func.layout.iter_block() {
+            for inst in func.layout.iter_inst(block) {
+                self.push(inst);
+            }
+        }
+
+        let mut changed = false;
+        while let Some(inst) = self.worklist.pop_front() {
+            self.in_worklist.remove(&inst);
+            if !func.layout.is_inst_inserted(inst) {
+                continue;
+            }
+
+            let Some(rewrite) = self.try_rules(func, inst) else {
+                continue;
+            };
+            changed = true;
+
+            if let Some(result) = func.dfg.inst_result(inst) {
+                let users: Vec<_> = func.dfg.users(result).copied().collect();
+                for user in users {
+                    self.push(user);
+                }
+            }
+
+            match rewrite {
+                Rewrite::Value(value) => {
+                    if let Some(result) = func.dfg.inst_result(inst) {
+                        func.dfg.change_to_alias(result, value);
+                    }
+                    InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                }
+                Rewrite::Inst(new) => {
+                    func.dfg.replace_inst(inst, new);
+                    self.push(inst);
+                }
+            }
+        }
+
+        changed
+    }
+
+    fn try_rules(&self, func: &mut Function, inst: InstId) -> Option<Rewrite> {
+        let rules = self.rules.get(func.dfg.inst(inst).as_text())?;
+        rules
+            .iter()
+            .find_map(|rule| rule.try_apply(&mut func.dfg, inst))
+    }
+
+    fn push(&mut self, inst: InstId) {
+        if self.in_worklist.insert(inst) {
+            self.worklist.push_back(inst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::{Mul, Shl},
+            control_flow::Return,
+        },
+        prelude::*,
+        Immediate, Type,
+    };
+
+    use super::*;
+
+    /// Rewrites `mul x 2` to `shl 1 x`.
+    struct MulByTwo;
+
+    impl PeepholeRule for MulByTwo {
+        fn try_apply(&self, dfg: &mut DataFlowGraph, inst: InstId) -> Option<Rewrite> {
+            let is = dfg.inst_set();
+            let mul = <&Mul as InstDowncast>::downcast(is, dfg.inst(inst))?;
+            let (lhs, rhs) = (*mul.lhs(), *mul.rhs());
+            if !dfg.value_imm(rhs).is_some_and(|imm| imm.is_two()) {
+                return None;
+            }
+
+            let ty = dfg.value_ty(lhs);
+            let one = dfg.make_imm_value(Immediate::one(ty));
+            Some(Rewrite::Inst(Box::new(Shl::new_unchecked(is, one, lhs))))
+        }
+    }
+
+    #[test]
+    fn mul_by_two() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let two = builder.make_imm_value(2i32);
+        let three = builder.make_imm_value(3i32);
+        let v0 = builder.insert_inst(Mul::new(is, arg, two), Type::I32);
+        let v1 = builder.insert_inst(Mul::new(is, v0, three), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut solver = PeepholeSolver::new();
+            solver.register("mul", MulByTwo);
+            assert!(solver.run(func));
+            assert!(!solver.run(func));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32) -> i32 {
+    block0:
+        v3.i32 = shl 1.i32 v0;
+        v4.i32 = mul v3 3.i32;
+        return v4;
+}
+"
+        );
+    }
+}
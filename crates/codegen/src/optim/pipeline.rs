@@ -0,0 +1,220 @@
+//! This module contains the canonical optimization pipeline, which runs the
+//! passes in this module in a sensible order with a single call.
+
+use rustc_hash::FxHashSet;
+use sonatina_ir::{domtree::DomTree, ControlFlowGraph, Function, Linkage, Module};
+
+use super::{
+    adce::AdceSolver, cleanup::cleanup, dvnt::DvntSolver, hoist_common::HoistCommonSolver,
+    if_convert::if_convert, instcombine::instcombine, licm::LicmSolver, pre::pre, sccp::SccpSolver,
+    sink::SinkSolver,
+};
+use crate::loop_analysis::LoopTree;
+
+/// The amount of optimization done by [`optimize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+    /// Only `cleanup` and dead code elimination.
+    O0,
+
+    /// `cleanup`, SCCP, value numbering, `instcombine`, LICM, dead code
+    /// elimination, and `cleanup` again.
+    #[default]
+    O1,
+
+    /// [`OptLevel::O1`] plus PRE, common hoisting, sinking, and
+    /// if-conversion.
+    O2,
+}
+
+/// Optimize all functions in the `module` independently, then drop the bodies
+/// of the private functions that are never called from a non-private one.
+///
+/// The dead functions are kept as declarations because `FuncRef`s are dense
+/// indices into the module.
+pub fn optimize(module: &mut Module, level: OptLevel) {
+    module
+        .func_store
+        .par_for_each(|_, func| optimize_function(func, level));
+    eliminate_dead_funcs(module);
+}
+
+/// Run the pipeline of the `level` on a single function.
+pub fn optimize_function(func: &mut Function, level: OptLevel) {
+    // Skip declarations.
+    if func.layout.entry_block().is_none() {
+        return;
+    }
+
+    let mut cfg = ControlFlowGraph::new();
+    let mut domtree = DomTree::new();
+    let mut lpt = LoopTree::new();
+
+    cfg.compute(func);
+    cleanup(func, &mut cfg);
+
+    if level >= OptLevel::O1 {
+        SccpSolver::new().run(func, &mut cfg);
+
+        cfg.compute(func);
+        domtree.compute(&cfg);
+        DvntSolver::new().run(func, &domtree);
+        if level >= OptLevel::O2 {
+            pre(func, &mut cfg);
+        }
+        instcombine(func);
+
+        cfg.compute(func);
+        domtree.compute(&cfg);
+        lpt.compute(&cfg, &domtree);
+        LicmSolver::new().run(func, &mut cfg, &mut lpt);
+
+        if level >= OptLevel::O2 {
+            cfg.compute(func);
+            domtree.compute(&cfg);
+            lpt.compute(&cfg, &domtree);
+            HoistCommonSolver::new().run(func, &cfg, &domtree);
+            SinkSolver::new().run(func, &domtree, &lpt);
+            if_convert(func, &mut cfg);
+        }
+    }
+
+    AdceSolver::new().run(func);
+    cfg.compute(func);
+    cleanup(func, &mut cfg);
+}
+
+/// Replace the bodies of the private functions unreachable from the
+/// non-private ones in the call graph with empty ones.
+fn eliminate_dead_funcs(module: &mut Module) {
+    let call_graph = module.call_graph();
+    let is_private =
+        |func_ref| module.ctx.func_sig(func_ref, |sig| sig.linkage()) == Linkage::Private;
+
+    let mut worklist: Vec<_> = call_graph
+        .funcs()
+        .iter()
+        .copied()
+        .filter(|&func_ref| !is_private(func_ref))
+        .collect();
+    let mut live: FxHashSet<_> = worklist.iter().copied().collect();
+    while let Some(func_ref) = worklist.pop() {
+        for &callee in call_graph.callees_of(func_ref) {
+            if live.insert(callee) {
+                worklist.push(callee);
+            }
+        }
+    }
+
+    for &func_ref in call_graph.funcs() {
+        if !live.contains(&func_ref) {
+            let func = module
+                .ctx
+                .func_sig(func_ref, |sig| Function::new(&module.ctx, sig));
+            module.func_store.update(func_ref, func);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_interpreter::Machine;
+    use sonatina_ir::{
+        builder::test_util::*,
+        func_cursor::InstInserter,
+        inst::{
+            arith::{Add, Mul},
+            control_flow::{Call, Return},
+        },
+        interpret::EvalValue,
+        isa::Isa,
+        module::FuncRef,
+        Immediate, Signature, Type,
+    };
+
+    use super::*;
+
+    /// Builds a module with a private `g(x) = x` and a public `f(a, b)` that
+    /// computes `(a + b) * (a + b) + 0` with the sum computed twice. `f` calls
+    /// `g` with the result only if `call_g` is `true`.
+    fn build_module(call_g: bool) -> Module {
+        let mb = test_module_builder();
+
+        let sig = Signature::new("g", Linkage::Private, &[Type::I32], Type::I32);
+        let g = mb.declare_function(sig);
+        let (evm, f_builder) = test_func_builder(&mb, &[Type::I32, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let mut builder = mb.func_builder::<InstInserter>(g);
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let arg = builder.args()[0];
+        builder.insert_inst_no_result(Return::new(is, Some(arg)));
+        builder.seal_all();
+        builder.finish();
+
+        let mut builder = f_builder;
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let (a, b) = (builder.args()[0], builder.args()[1]);
+        let sum0 = builder.insert_inst(Add::new(is, a, b), Type::I32);
+        let sum1 = builder.insert_inst(Add::new(is, a, b), Type::I32);
+        let prod = builder.insert_inst(Mul::new(is, sum0, sum1), Type::I32);
+        let zero = builder.make_imm_value(0i32);
+        let mut result = builder.insert_inst(Add::new(is, prod, zero), Type::I32);
+        if call_g {
+            result = builder.insert_inst(Call::new(is, g, vec![result].into()), Type::I32);
+        }
+        builder.insert_inst_no_result(Return::new(is, Some(result)));
+        builder.seal_all();
+        builder.finish();
+
+        mb.build()
+    }
+
+    fn inst_num(module: &Module, func_ref: FuncRef) -> usize {
+        module.func_store.view(func_ref, |func| {
+            func.layout
+                .iter_block()
+                .map(|block| func.layout.iter_inst(block).count())
+                .sum()
+        })
+    }
+
+    #[test]
+    fn optimize_o2() {
+        let mut module = build_module(false);
+        let (g, f) = (module.funcs()[0], module.funcs()[1]);
+        let before = inst_num(&module, f);
+
+        optimize(&mut module, OptLevel::O2);
+        assert!(inst_num(&module, f) < before);
+        // `g` is never called.
+        assert_eq!(inst_num(&module, g), 0);
+
+        let mut optimized = Machine::new(module);
+        let mut original = Machine::new(build_module(false));
+        for (a, b) in [(1, 2), (-3, 7), (0, 0)] {
+            let args = vec![
+                EvalValue::Imm(Immediate::I32(a)),
+                EvalValue::Imm(Immediate::I32(b)),
+            ];
+            assert_eq!(
+                optimized.run(f, args.clone()),
+                original.run(f, args),
+                "a = {a}, b = {b}"
+            );
+            optimized.clear_state();
+            original.clear_state();
+        }
+    }
+
+    #[test]
+    fn called_private_func_is_kept() {
+        let mut module = build_module(true);
+        let g = module.funcs()[0];
+
+        optimize(&mut module, OptLevel::O0);
+        assert_eq!(inst_num(&module, g), 1);
+    }
+}
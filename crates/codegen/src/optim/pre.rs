@@ -0,0 +1,466 @@
+//! This module contains partial redundancy elimination (PRE) based on lazy code
+//! motion (LCM) by Knoop, Rüthing, and Steffen.
+//!
+//! LCM places each expression at the latest points where it's anticipated and
+//! not yet available, so that every path computes the expression at most as
+//! many times as before, and the computed values live as short as possible.
+//! The computations made redundant by the placement are then removed.
+//!
+//! Placement is computed on edges, and critical edges are split beforehand so
+//! that an insertion on an edge can always be done at the end of its source
+//! block. The values of a moved expression are reconnected in SSA form by
+//! inserting phis where different computations meet.
+
+use std::collections::BTreeSet;
+
+use cranelift_entity::SecondaryMap;
+use rustc_hash::FxHashMap;
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::SideEffect,
+    BlockId, ControlFlowGraph, Function, InstId, Type, Value, ValueId,
+};
+
+use crate::{
+    critical_edge::CriticalEdgeSplitter,
+    expr_analysis::{Anticipated, Available, ExprId, ExprTable},
+};
+
+/// Run lazy code motion on the function.
+/// Returns `true` if any inst is inserted or removed.
+///
+/// Critical edges are split beforehand, and `cfg` is updated accordingly.
+pub fn pre(func: &mut Function, cfg: &mut ControlFlowGraph) -> bool {
+    let mut splitter = CriticalEdgeSplitter::new();
+    splitter.run(func, cfg);
+
+    let mut table = ExprTable::new();
+    table.compute(func);
+    if table.is_empty() {
+        return false;
+    }
+
+    let mut antic = Anticipated::new();
+    antic.compute(&table, cfg);
+    let mut avail = Available::new();
+    avail.compute(&table, cfg);
+
+    let placement = Placement::compute(&table, &antic, &avail, cfg);
+
+    let mut changed = false;
+    for expr in table.iter() {
+        changed |= rewrite_expr(func, cfg, &table, &placement, expr);
+    }
+    changed
+}
+
+/// Where each expression is inserted and deleted.
+#[derive(Debug, Default)]
+struct Placement {
+    /// Expressions inserted at the end of each block.
+    insert: SecondaryMap<BlockId, BTreeSet<ExprId>>,
+    /// Expressions whose upward exposed computations in each block are
+    /// redundant after the insertion.
+    delete: SecondaryMap<BlockId, BTreeSet<ExprId>>,
+}
+
+impl Placement {
+    fn compute(
+        table: &ExprTable,
+        antic: &Anticipated,
+        avail: &Available,
+        cfg: &ControlFlowGraph,
+    ) -> Self {
+        let mut placement = Self::default();
+        let Some(entry) = cfg.entry() else {
+            return placement;
+        };
+
+        let rpo = cfg.order().rpo();
+        let mut reachable = SecondaryMap::new();
+        for &block in rpo {
+            reachable[block] = true;
+        }
+
+        // An expression can be placed on an edge at the earliest if it's
+        // anticipated at the destination, and it can't be placed before the
+        // source, either because it's not available or anticipated there, or
+        // because the source kills it.
+        let earliest = |from: BlockId, to: BlockId| -> BTreeSet<ExprId> {
+            antic
+                .anticipated_in(to)
+                .filter(|&expr| {
+                    !avail.is_available_out(from, expr)
+                        && (!table.is_transparent(from, expr)
+                            || !antic.is_anticipated_out(from, expr))
+                })
+                .collect()
+        };
+
+        // The placement can be delayed along an edge if it's earliest there,
+        // or if it's delayable to the entry of the source and not used in it.
+        let later =
+            |later_in: &SecondaryMap<BlockId, BTreeSet<ExprId>>, from: BlockId, to: BlockId| {
+                let mut later = earliest(from, to);
+                later.extend(
+                    later_in[from]
+                        .iter()
+                        .filter(|&&expr| !table.is_upward_exposed(from, expr)),
+                );
+                later
+            };
+
+        // Start from the full set and shrink to the greatest fixed point. The
+        // entry is reached by a virtual edge, on which every expression
+        // anticipated there is earliest.
+        let universe: BTreeSet<_> = table.iter().collect();
+        let mut later_in = SecondaryMap::new();
+        for &block in rpo {
+            later_in[block] = universe.clone();
+        }
+        later_in[entry] = antic.anticipated_in(entry).collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in rpo {
+                if block == entry {
+                    continue;
+                }
+
+                let mut in_ = universe.clone();
+                for &pred in cfg.preds_of(block) {
+                    if reachable[pred] {
+                        let edge = later(&later_in, pred, block);
+                        in_.retain(|expr| edge.contains(expr));
+                    }
+                }
+
+                if in_ != later_in[block] {
+                    later_in[block] = in_;
+                    changed = true;
+                }
+            }
+        }
+
+        for &block in rpo {
+            for &succ in cfg.succs_of(block) {
+                let insert = later(&later_in, block, succ);
+                placement.insert[block].extend(insert.difference(&later_in[succ]));
+            }
+
+            if block != entry {
+                placement.delete[block] = table
+                    .iter()
+                    .filter(|&expr| {
+                        table.is_upward_exposed(block, expr) && !later_in[block].contains(&expr)
+                    })
+                    .collect();
+            }
+        }
+
+        placement
+    }
+}
+
+/// Insert and delete the computations of the `expr` as the `placement`
+/// directs, and replace the uses of the deleted computations.
+/// Returns `true` if any inst is inserted or removed.
+fn rewrite_expr(
+    func: &mut Function,
+    cfg: &ControlFlowGraph,
+    table: &ExprTable,
+    placement: &Placement,
+    expr: ExprId,
+) -> bool {
+    let mut deleted = Vec::new();
+    let mut ssa = SsaBuilder::new(cfg, expr_ty(func, table, expr));
+
+    for block in func.layout.iter_block() {
+        let is_deleted = placement.delete[block].contains(&expr);
+        let mut exposed = true;
+        for inst in func.layout.iter_inst(block) {
+            if table.expr_of(inst) == Some(expr) {
+                if is_deleted && exposed {
+                    deleted.push(inst);
+                } else {
+                    ssa.def(block, func.dfg.inst_result(inst).unwrap());
+                }
+            } else if exposed && kills(func, table, inst, expr) {
+                exposed = false;
+            }
+        }
+    }
+
+    let mut inserted = false;
+    let repr = table.repr_inst(expr);
+    let blocks: Vec<_> = func.layout.iter_block().collect();
+    for block in blocks {
+        if !placement.insert[block].contains(&expr) {
+            continue;
+        }
+        // Critical edges are already split, so the source block of an edge
+        // that needs the insertion has only one successor.
+        debug_assert_eq!(cfg.succ_num_of(block), 1);
+
+        let data = func.dfg.insts[repr].clone();
+        let inst = func.dfg.make_inst_dyn(data);
+        let result = func.dfg.make_value(Value::Inst { inst, ty: ssa.ty });
+        func.dfg.attach_result(inst, result);
+        let term = func.layout.last_inst_of(block).unwrap();
+        func.layout.insert_inst_before(inst, term);
+
+        ssa.def(block, result);
+        inserted = true;
+    }
+
+    for &inst in &deleted {
+        let block = func.layout.inst_block(inst);
+        let value = ssa.value_in(func, block);
+        let result = func.dfg.inst_result(inst).unwrap();
+        func.dfg.change_to_alias(result, value);
+        InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+    }
+
+    ssa.remove_trivial_phis(func);
+    inserted || !deleted.is_empty()
+}
+
+fn expr_ty(func: &Function, table: &ExprTable, expr: ExprId) -> Type {
+    let result = func.dfg.inst_result(table.repr_inst(expr)).unwrap();
+    func.dfg.value_ty(result)
+}
+
+/// Returns `true` if the `inst` kills the `expr`.
+fn kills(func: &Function, table: &ExprTable, inst: InstId, expr: ExprId) -> bool {
+    (table.is_memory(expr) && matches!(func.dfg.side_effect(inst), SideEffect::Write))
+        || func
+            .dfg
+            .inst_result(inst)
+            .is_some_and(|result| table.args(expr).contains(&result))
+}
+
+/// Reconnects the computations of an expression in SSA form.
+///
+/// The value of the expression at the entry of a block is looked up from its
+/// predecessors, and a phi is inserted if the block has multiple predecessors.
+struct SsaBuilder<'a> {
+    cfg: &'a ControlFlowGraph,
+    ty: Type,
+    /// The last computation of the expression in each block.
+    defs: FxHashMap<BlockId, ValueId>,
+    /// The value of the expression at the entry of each visited block.
+    entry_values: FxHashMap<BlockId, ValueId>,
+    phis: Vec<InstId>,
+}
+
+impl<'a> SsaBuilder<'a> {
+    fn new(cfg: &'a ControlFlowGraph, ty: Type) -> Self {
+        Self {
+            cfg,
+            ty,
+            defs: FxHashMap::default(),
+            entry_values: FxHashMap::default(),
+            phis: Vec::new(),
+        }
+    }
+
+    /// Record the `value` as the last computation in the `block` so far.
+    fn def(&mut self, block: BlockId, value: ValueId) {
+        self.defs.insert(block, value);
+    }
+
+    fn value_out(&mut self, func: &mut Function, block: BlockId) -> ValueId {
+        match self.defs.get(&block) {
+            Some(&value) => value,
+            None => self.value_in(func, block),
+        }
+    }
+
+    fn value_in(&mut self, func: &mut Function, block: BlockId) -> ValueId {
+        if let Some(&value) = self.entry_values.get(&block) {
+            return value;
+        }
+
+        let cfg = self.cfg;
+        let preds = cfg.preds_slice(block);
+        if let [pred] = preds {
+            let value = self.value_out(func, *pred);
+            self.entry_values.insert(block, value);
+            return value;
+        }
+
+        // Register the phi before visiting the predecessors to break cycles.
+        let phi = func.dfg.make_phi(vec![]);
+        let phi = func.dfg.make_inst(phi);
+        let result = func.dfg.make_value(Value::Inst {
+            inst: phi,
+            ty: self.ty,
+        });
+        func.dfg.attach_result(phi, result);
+        func.layout.prepend_inst(phi, block);
+        self.entry_values.insert(block, result);
+        self.phis.push(phi);
+
+        for &pred in preds {
+            let value = self.value_out(func, pred);
+            func.dfg.append_phi_arg(phi, value, pred);
+        }
+
+        result
+    }
+
+    /// Remove the inserted phis that merge only one value other than
+    /// themselves.
+    fn remove_trivial_phis(&mut self, func: &mut Function) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &phi in &self.phis {
+                if !func.layout.is_inst_inserted(phi) {
+                    continue;
+                }
+
+                let result = func.dfg.inst_result(phi).unwrap();
+                let mut args = func
+                    .dfg
+                    .cast_phi(phi)
+                    .unwrap()
+                    .args()
+                    .iter()
+                    .map(|(value, _)| *value)
+                    .filter(|&value| value != result);
+                let Some(first) = args.next() else {
+                    continue;
+                };
+                if args.all(|value| value == first) {
+                    func.dfg.change_to_alias(result, first);
+                    InstInserter::at_location(CursorLocation::At(phi)).remove_inst(func);
+                    changed = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_interpreter::Machine;
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::{Add, Mul, Sub},
+            cmp::{Ne, Sgt},
+            control_flow::{Br, Jump, Phi, Return},
+        },
+        interpret::EvalValue,
+        prelude::*,
+        Immediate, Module,
+    };
+
+    use super::*;
+
+    /// Builds a function where `a * b` is computed in a loop, and also on one
+    /// of the paths to the loop.
+    ///
+    /// ```text
+    /// if a != b { a * b }
+    /// acc = 0
+    /// do { acc += a * b; n -= 1 } while n > 0
+    /// return acc
+    /// ```
+    fn build_module() -> Module {
+        let mb = test_module_builder();
+        let (evm, mut builder) =
+            test_func_builder(&mb, &[Type::I32, Type::I32, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let b4 = builder.append_block();
+        let b5 = builder.append_block();
+        let (a, b, n) = (builder.args()[0], builder.args()[1], builder.args()[2]);
+
+        builder.switch_to_block(b0);
+        let cond = builder.insert_inst(Ne::new(is, a, b), Type::I1);
+        builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst(Mul::new(is, a, b), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result(Jump::new(is, b4));
+
+        builder.switch_to_block(b4);
+        let zero = builder.make_imm_value(0i32);
+        let one = builder.make_imm_value(1i32);
+        let i = builder.insert_inst(Phi::new(is, vec![(n, b3)]), Type::I32);
+        let acc = builder.insert_inst(Phi::new(is, vec![(zero, b3)]), Type::I32);
+        let prod = builder.insert_inst(Mul::new(is, a, b), Type::I32);
+        let next_acc = builder.insert_inst(Add::new(is, acc, prod), Type::I32);
+        let next_i = builder.insert_inst(Sub::new(is, i, one), Type::I32);
+        let cond = builder.insert_inst(Sgt::new(is, next_i, zero), Type::I1);
+        builder.insert_inst_no_result(Br::new(is, cond, b4, b5));
+        builder.append_phi_arg(i, next_i, b4);
+        builder.append_phi_arg(acc, next_acc, b4);
+
+        builder.switch_to_block(b5);
+        builder.insert_inst_no_result(Return::new(is, Some(next_acc)));
+
+        builder.seal_all();
+        builder.finish();
+
+        mb.build()
+    }
+
+    fn has_mul(func: &Function, block: BlockId) -> bool {
+        func.layout
+            .iter_inst(block)
+            .any(|inst| func.dfg.inst(inst).as_text() == "mul")
+    }
+
+    #[test]
+    fn hoist_partially_redundant_loop_invariant() {
+        let module = build_module();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            assert!(pre(func, &mut cfg));
+
+            let blocks: Vec<_> = func.layout.iter_block().collect();
+            let (b2, b3, b4) = (blocks[2], blocks[3], blocks[4]);
+
+            // The computation is inserted on the path that lacks it, merged in
+            // the preheader, and removed from the loop.
+            assert!(has_mul(func, b2));
+            assert!(func.layout.iter_inst(b3).any(|inst| func.dfg.is_phi(inst)));
+            assert!(!has_mul(func, b4));
+
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            assert!(!pre(func, &mut cfg));
+        });
+
+        let mut optimized = Machine::new(module);
+        let mut original = Machine::new(build_module());
+        for (a, b, n) in [(3, 4, 1), (3, 4, 5), (7, 7, 2), (-2, 5, 3)] {
+            let args: Vec<_> = [a, b, n]
+                .into_iter()
+                .map(|arg| EvalValue::Imm(Immediate::I32(arg)))
+                .collect();
+            assert_eq!(
+                optimized.run(func_ref, args.clone()),
+                original.run(func_ref, args)
+            );
+            optimized.clear_state();
+            original.clear_state();
+        }
+    }
+}
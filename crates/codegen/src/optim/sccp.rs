@@ -302,10 +302,7 @@ impl SccpSolver {
     }
 
     fn fold_insts(&mut self, func: &mut Function, cfg: &ControlFlowGraph) {
-        let mut rpo: Vec<_> = cfg.post_order().collect();
-        rpo.reverse();
-
-        for block in rpo {
+        for &block in cfg.order().rpo() {
             let mut next_inst = func.layout.first_inst_of(block);
             while let Some(inst) = next_inst {
                 next_inst = func.layout.next_inst_of(inst);
@@ -0,0 +1,226 @@
+//! This module contains a solver for control flow graph simplification.
+
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::{
+        cmp::Eq,
+        control_flow::{Br, BrTable, BranchKind},
+        logic::Or,
+    },
+    BlockId, ControlFlowGraph, Function, Inst, InstDowncast, InstId, Type, ValueId,
+};
+
+#[derive(Debug, Default)]
+pub struct SimplifyCfgSolver {}
+
+impl SimplifyCfgSolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {}
+
+    /// Run control flow graph simplification on the function.
+    /// Returns `true` if the function is modified.
+    pub fn run(&mut self, func: &mut Function, cfg: &mut ControlFlowGraph) -> bool {
+        self.clear();
+
+        let mut changed = false;
+        let blocks: Vec<_> = func.layout.iter_block().collect();
+        for block in blocks {
+            changed |= self.fold_redundant_branch(func, block)
+                || self.fold_two_dests_br_table(func, block);
+        }
+
+        if changed {
+            cfg.compute(func);
+        }
+        changed
+    }
+
+    /// Turn a branch inst whose destinations are all the same into `jump`, and
+    /// merge the duplicated phi args in the destination.
+    /// The condition of the branch loses its use, so it might be removed by
+    /// dead code elimination later.
+    ///
+    /// Returns `true` if the branch is folded.
+    fn fold_redundant_branch(&mut self, func: &mut Function, block: BlockId) -> bool {
+        let Some(last_inst) = func.layout.last_inst_of(block) else {
+            return false;
+        };
+        let Some(branch_info) = func.dfg.branch_info(last_inst) else {
+            return false;
+        };
+        if matches!(branch_info.branch_kind(), BranchKind::Jump(_)) {
+            return false;
+        }
+
+        let dests = branch_info.dests();
+        let Some(&first_dest) = dests.first() else {
+            return false;
+        };
+        if dests.iter().any(|dest| *dest != first_dest)
+            || !can_merge_phi_args(func, block, first_dest)
+        {
+            return false;
+        }
+
+        let jump = func.dfg.make_jump(first_dest);
+        InstInserter::at_location(CursorLocation::At(last_inst)).replace(func, jump);
+        merge_phi_args(func, block, first_dest);
+        true
+    }
+
+    /// Turn a `br_table` whose destinations reduce to two distinct blocks into
+    /// `br`. The condition is a disjunction of the comparisons between the
+    /// scrutinee and the table values leading to the non-default block.
+    ///
+    /// Returns `true` if the `br_table` is folded.
+    fn fold_two_dests_br_table(&mut self, func: &mut Function, block: BlockId) -> bool {
+        let Some(last_inst) = func.layout.last_inst_of(block) else {
+            return false;
+        };
+        let Some(br_table) =
+            <&BrTable as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(last_inst))
+        else {
+            return false;
+        };
+
+        let scrutinee = *br_table.scrutinee();
+        let Some(&(_, first_case_dest)) = br_table.table().first() else {
+            return false;
+        };
+        // The block taken when the condition doesn't hold. If the table has no
+        // default, it's the destination of any case that differs from the first
+        // one.
+        let (z_dest, nz_dest) = match *br_table.default() {
+            Some(default) => {
+                let Some(nz_dest) = br_table
+                    .table()
+                    .iter()
+                    .map(|(_, dest)| *dest)
+                    .find(|dest| *dest != default)
+                else {
+                    return false;
+                };
+                (default, nz_dest)
+            }
+            None => {
+                let Some(z_dest) = br_table
+                    .table()
+                    .iter()
+                    .map(|(_, dest)| *dest)
+                    .find(|dest| *dest != first_case_dest)
+                else {
+                    return false;
+                };
+                (z_dest, first_case_dest)
+            }
+        };
+
+        if br_table
+            .table()
+            .iter()
+            .any(|(_, dest)| *dest != z_dest && *dest != nz_dest)
+            || !can_merge_phi_args(func, block, z_dest)
+            || !can_merge_phi_args(func, block, nz_dest)
+        {
+            return false;
+        }
+
+        let nz_values: Vec<ValueId> = br_table
+            .table()
+            .iter()
+            .filter(|(_, dest)| *dest == nz_dest)
+            .map(|(value, _)| *value)
+            .collect();
+
+        let cond = self.make_cond(func, last_inst, scrutinee, &nz_values);
+        let br = Br::new_unchecked(func.inst_set(), cond, nz_dest, z_dest);
+        InstInserter::at_location(CursorLocation::At(last_inst)).replace(func, br);
+        merge_phi_args(func, block, z_dest);
+        merge_phi_args(func, block, nz_dest);
+        true
+    }
+
+    /// Insert the insts computing `scrutinee == values[0] || scrutinee ==
+    /// values[1] || ...` before the `inst`, and returns the result.
+    fn make_cond(
+        &mut self,
+        func: &mut Function,
+        inst: InstId,
+        scrutinee: ValueId,
+        values: &[ValueId],
+    ) -> ValueId {
+        let prev = func.layout.prev_inst_of(inst);
+        let mut inserter = match prev {
+            Some(prev) => InstInserter::at_location(CursorLocation::At(prev)),
+            None => {
+                InstInserter::at_location(CursorLocation::BlockTop(func.layout.inst_block(inst)))
+            }
+        };
+
+        let mut cond = None;
+        for &value in values {
+            let eq = Eq::new_unchecked(func.inst_set(), scrutinee, value);
+            let eq = insert_with_result(func, &mut inserter, eq);
+            cond = Some(match cond {
+                Some(lhs) => {
+                    let or = Or::new_unchecked(func.inst_set(), lhs, eq);
+                    insert_with_result(func, &mut inserter, or)
+                }
+                None => eq,
+            });
+        }
+
+        cond.unwrap()
+    }
+}
+
+fn insert_with_result<I: Inst>(
+    func: &mut Function,
+    inserter: &mut InstInserter,
+    data: I,
+) -> ValueId {
+    let inst = inserter.insert_inst_data(func, data);
+    let result = inserter.make_result(func, inst, Type::I1);
+    inserter.attach_result(func, inst, result);
+    inserter.set_location(CursorLocation::At(inst));
+    result
+}
+
+/// Returns `true` if the phi args of `dest` from `pred` can be merged into a
+/// single one, i.e., the duplicated args agree on the value.
+fn can_merge_phi_args(func: &Function, pred: BlockId, dest: BlockId) -> bool {
+    func.layout.iter_inst(dest).all(|inst| {
+        let Some(phi) = func.dfg.cast_phi(inst) else {
+            return true;
+        };
+        let mut args = phi.args().iter().filter(|(_, block)| *block == pred);
+        match args.next() {
+            Some((first, _)) => args.all(|(value, _)| value == first),
+            None => true,
+        }
+    })
+}
+
+/// Collapse the phi args of `dest` so that `pred` appears once.
+/// The args must be checked by [`can_merge_phi_args`] beforehand.
+fn merge_phi_args(func: &mut Function, pred: BlockId, dest: BlockId) {
+    let phis: Vec<_> = func
+        .layout
+        .iter_inst(dest)
+        .take_while(|inst| func.dfg.is_phi(*inst))
+        .collect();
+    for inst in phis {
+        let phi = func.dfg.cast_phi_mut(inst).unwrap();
+        let dup_num = phi
+            .args()
+            .iter()
+            .filter(|(_, block)| *block == pred)
+            .count();
+        for _ in 1..dup_num {
+            phi.remove_phi_arg(pred);
+        }
+    }
+}
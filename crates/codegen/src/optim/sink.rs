@@ -0,0 +1,121 @@
+//! This module contains a solver for instruction sinking.
+//!
+//! Sinking moves an instruction without side effects down to the nearest
+//! block that dominates all of its uses, so that the result is not computed on
+//! paths where it's never used.
+
+use sonatina_ir::{domtree::DomTree, BlockId, Function, InstId};
+
+use crate::loop_analysis::LoopTree;
+
+#[derive(Debug, Default)]
+pub struct SinkSolver {
+    use_blocks: Vec<BlockId>,
+}
+
+impl SinkSolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.use_blocks.clear();
+    }
+
+    /// Run instruction sinking on the function.
+    /// Returns `true` if any inst is moved.
+    ///
+    /// NOTE: This method doesn't modify the control flow graph, so `domtree`
+    /// and `lpt` are still valid after the transformation.
+    pub fn run(&mut self, func: &mut Function, domtree: &DomTree, lpt: &LoopTree) -> bool {
+        self.clear();
+
+        let mut changed = false;
+        // Visit users before definitions so that chains of insts are sunk
+        // together.
+        for &block in domtree.rpo().iter().rev() {
+            let insts: Vec<_> = func.layout.iter_inst(block).collect();
+            for &inst in insts.iter().rev() {
+                changed |= self.sink_inst(func, domtree, lpt, block, inst);
+            }
+        }
+
+        changed
+    }
+
+    fn sink_inst(
+        &mut self,
+        func: &mut Function,
+        domtree: &DomTree,
+        lpt: &LoopTree,
+        block: BlockId,
+        inst: InstId,
+    ) -> bool {
+        if !self.is_safe_to_sink(func, inst) {
+            return false;
+        }
+
+        let Some(mut dest) = self.collect_use_blocks(func, domtree, inst) else {
+            return false;
+        };
+
+        // Never sink an inst into a loop that doesn't contain its original block.
+        while let Some(lp) = lpt.loop_of_block(dest) {
+            if lpt.is_in_loop(block, lp) {
+                break;
+            }
+            dest = domtree.idom_of(lpt.loop_header(lp)).unwrap();
+        }
+
+        if dest == block || !domtree.strictly_dominates(block, dest) {
+            return false;
+        }
+
+        // Insert the inst before its first user in `dest` if exists, otherwise
+        // insert it before the terminator.
+        let result = func.dfg.inst_result(inst).unwrap();
+        let insert_before = func
+            .layout
+            .iter_inst(dest)
+            .find(|&i| !func.dfg.is_phi(i) && func.dfg.users(result).any(|&user| user == i))
+            .unwrap_or_else(|| func.layout.last_inst_of(dest).unwrap());
+
+        func.layout.remove_inst(inst);
+        func.layout.insert_inst_before(inst, insert_before);
+        true
+    }
+
+    /// Returns the nearest common dominator of all use blocks of the inst
+    /// result. A use in a phi is regarded as a use at the end of the
+    /// corresponding predecessor.
+    fn collect_use_blocks(
+        &mut self,
+        func: &Function,
+        domtree: &DomTree,
+        inst: InstId,
+    ) -> Option<BlockId> {
+        let result = func.dfg.inst_result(inst)?;
+
+        self.use_blocks.clear();
+        for &user in func.dfg.users(result) {
+            if let Some(phi) = func.dfg.cast_phi(user) {
+                self.use_blocks.extend(
+                    phi.args()
+                        .iter()
+                        .filter_map(|(arg, pred)| (*arg == result).then_some(*pred)),
+                );
+            } else {
+                self.use_blocks.push(func.layout.inst_block(user));
+            }
+        }
+
+        domtree.nearest_common_dominator_of(&self.use_blocks)
+    }
+
+    fn is_safe_to_sink(&self, func: &Function, inst: InstId) -> bool {
+        !(func.dfg.side_effect(inst).has_effect()
+            || func.dfg.is_phi(inst)
+            || func.dfg.is_terminator(inst)
+            || func.dfg.inst_result(inst).is_none())
+    }
+}
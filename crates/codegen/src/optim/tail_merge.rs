@@ -0,0 +1,228 @@
+//! This module contains tail merging, which merges blocks computing the same
+//! thing into one, e.g., identical return blocks left by tail duplication or
+//! macro expansion.
+//!
+//! Two blocks are identical if their insts are pairwise identical, where a
+//! value defined in one block is matched with the corresponding value defined
+//! in the other one, and they pass matching values to the phis in their
+//! successors. Since a value defined in the removed block is replaced with
+//! nothing, a block whose results are used outside of it, except by the phis
+//! in its successors, is never merged.
+
+use rustc_hash::FxHashMap;
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    BlockId, ControlFlowGraph, Function, ValueId,
+};
+
+/// Merge identical blocks into the first of them in the layout order, and
+/// redirect the predecessors of the merged blocks.
+/// Returns `true` if the function is modified, in which case `cfg` is
+/// recomputed.
+pub fn tail_merge(func: &mut Function, cfg: &mut ControlFlowGraph) -> bool {
+    let mut changed = false;
+    let mut reprs: Vec<BlockId> = Vec::new();
+    for block in func.layout.iter_block().collect::<Vec<_>>() {
+        let is_entry = func.layout.entry_block() == Some(block);
+        match reprs
+            .iter()
+            .find(|&&repr| !is_entry && is_identical_block(func, cfg, repr, block))
+        {
+            Some(&repr) => {
+                merge_block(func, cfg, repr, block);
+                cfg.compute(func);
+                changed = true;
+            }
+            None => reprs.push(block),
+        }
+    }
+
+    changed
+}
+
+/// Returns `true` if `block` can be replaced with `repr`.
+fn is_identical_block(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    repr: BlockId,
+    block: BlockId,
+) -> bool {
+    let repr_insts: Vec<_> = func.layout.iter_inst(repr).collect();
+    let insts: Vec<_> = func.layout.iter_inst(block).collect();
+    if repr_insts.len() != insts.len() {
+        return false;
+    }
+
+    // Maps the values defined in `block` to the ones defined in `repr`.
+    let mut values: FxHashMap<ValueId, ValueId> = FxHashMap::default();
+    for (&repr_inst, &inst) in repr_insts.iter().zip(&insts) {
+        if func.dfg.is_phi(repr_inst) || func.dfg.is_phi(inst) {
+            return false;
+        }
+
+        let mut data = func.dfg.clone_inst(inst);
+        data.for_each_value_mut(&mut |value| {
+            if let Some(&mapped) = values.get(value) {
+                *value = mapped;
+            }
+        });
+        if !func.dfg.inst(repr_inst).is_identical(data.as_ref())
+            || func.dfg.is_volatile(repr_inst) != func.dfg.is_volatile(inst)
+            || func.dfg.srcloc_of(repr_inst) != func.dfg.srcloc_of(inst)
+        {
+            return false;
+        }
+
+        match (func.dfg.inst_result(repr_inst), func.dfg.inst_result(inst)) {
+            (Some(repr_result), Some(result)) => {
+                if func.dfg.value_ty(repr_result) != func.dfg.value_ty(result)
+                    || func.dfg.is_pinned(repr_result) != func.dfg.is_pinned(result)
+                    || !is_used_only_locally(func, block, result)
+                {
+                    return false;
+                }
+                values.insert(result, repr_result);
+            }
+            (None, None) => {}
+            _ => return false,
+        }
+    }
+
+    // The terminators are identical, so both blocks have the same successors.
+    let map = |value| values.get(&value).copied().unwrap_or(value);
+    cfg.succs_of(block).all(|&succ| {
+        func.layout
+            .iter_inst(succ)
+            .map_while(|inst| func.dfg.cast_phi(inst))
+            .all(|phi| {
+                let arg_from = |pred| {
+                    phi.args()
+                        .iter()
+                        .find_map(|&(value, block)| (block == pred).then_some(value))
+                };
+                arg_from(repr) == arg_from(block).map(map)
+            })
+    })
+}
+
+/// Returns `true` if the `value` defined in the `block` is used only in the
+/// `block` or as the args of phis coming from the `block`.
+fn is_used_only_locally(func: &Function, block: BlockId, value: ValueId) -> bool {
+    func.dfg.users(value).all(|&user| {
+        if func.layout.inst_block(user) == block {
+            return true;
+        }
+        func.dfg.cast_phi(user).is_some_and(|phi| {
+            phi.args()
+                .iter()
+                .all(|&(arg, pred)| arg != value || pred == block)
+        })
+    })
+}
+
+/// Redirect the predecessors of the `block` to the `repr`, and remove the
+/// `block`.
+fn merge_block(func: &mut Function, cfg: &ControlFlowGraph, repr: BlockId, block: BlockId) {
+    for &pred in cfg.preds_of(block) {
+        let term = func.layout.last_inst_of(pred).unwrap();
+        func.dfg.rewrite_branch_dest(term, block, repr);
+    }
+
+    for &succ in cfg.succs_of(block) {
+        let phis: Vec<_> = func
+            .layout
+            .iter_inst(succ)
+            .take_while(|&inst| func.dfg.is_phi(inst))
+            .collect();
+        for phi in phis {
+            func.dfg.untrack_inst(phi);
+            func.dfg
+                .cast_phi_mut(phi)
+                .unwrap()
+                .retain(|pred| pred != block);
+            func.dfg.attach_user(phi);
+        }
+    }
+
+    InstInserter::at_location(CursorLocation::BlockTop(block)).remove_block(func);
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::Add,
+            control_flow::{Br, Return},
+        },
+        isa::Isa,
+        Module, Type,
+    };
+
+    use super::*;
+
+    /// Builds `f(c, x)` that branches to two blocks returning `x + 1` and
+    /// `x + rhs` respectively.
+    fn build_module(rhs: i32) -> Module {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let (c, x) = (builder.args()[0], builder.args()[1]);
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Br::new(is, c, b1, b2));
+
+        builder.switch_to_block(b1);
+        let one = builder.make_imm_value(1i32);
+        let v1 = builder.insert_inst(Add::new(is, x, one), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v1)));
+
+        builder.switch_to_block(b2);
+        let rhs = builder.make_imm_value(rhs);
+        let v2 = builder.insert_inst(Add::new(is, x, rhs), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v2)));
+
+        builder.seal_all();
+        builder.finish();
+        mb.build()
+    }
+
+    #[test]
+    fn merge_identical_returns() {
+        let module = build_module(1);
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            assert!(tail_merge(func, &mut cfg));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i1, v1.i32) -> i32 {
+    block0:
+        jump block1;
+
+    block1:
+        v3.i32 = add v1 1.i32;
+        return v3;
+}
+"
+        );
+    }
+
+    #[test]
+    fn different_blocks_are_kept() {
+        let module = build_module(2);
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            assert!(!tail_merge(func, &mut cfg));
+        });
+    }
+}
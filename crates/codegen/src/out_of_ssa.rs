@@ -0,0 +1,285 @@
+//! This module contains a transformation that converts a function out of SSA
+//! form.
+//!
+//! Each phi is replaced with copies to its result at the end of every
+//! predecessor, so the phi result is defined once per incoming edge and the
+//! function is no longer in SSA form.
+//!
+//! The copies of an edge read all their sources before writing any
+//! destination, i.e., they form a parallel copy. A source may be the result of
+//! another phi of the same block, e.g., when values are swapped in a loop, so
+//! the copies are ordered with [`sequence_parallel_copies`], which breaks
+//! cycles with a temporary. This avoids the lost-copy and swap problems.
+
+use cranelift_entity::SecondaryMap;
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::cast::CopyVal,
+    BlockId, ControlFlowGraph, Function, Value, ValueId,
+};
+
+use crate::{
+    critical_edge::CriticalEdgeSplitter,
+    parallel_copy::{sequence_parallel_copies, CopyLoc},
+};
+
+/// Replace all phis in the function with copies on incoming edges.
+/// Returns `true` if any phi is replaced.
+///
+/// Critical edges are split beforehand, so that copies of an edge are executed
+/// only when the edge is taken. `cfg` is updated accordingly.
+pub fn destruct_ssa(func: &mut Function, cfg: &mut ControlFlowGraph) -> bool {
+    let mut splitter = CriticalEdgeSplitter::new();
+    splitter.run(func, cfg);
+
+    let mut phis = Vec::new();
+    for block in func.layout.iter_block() {
+        for inst in func.layout.iter_inst(block) {
+            if !func.dfg.is_phi(inst) {
+                break;
+            }
+            phis.push(inst);
+        }
+    }
+
+    // Collect the `(dst, src)` pairs of the parallel copy at the end of each
+    // predecessor.
+    let mut copies: SecondaryMap<BlockId, Vec<(ValueId, ValueId)>> = SecondaryMap::default();
+    for &phi_inst in &phis {
+        let result = func.dfg.inst_result(phi_inst).unwrap();
+        for &(arg, pred) in func.dfg.cast_phi(phi_inst).unwrap().args() {
+            copies[pred].push((result, arg));
+        }
+
+        InstInserter::at_location(CursorLocation::At(phi_inst)).remove_inst(func);
+    }
+
+    let preds: Vec<_> = func.layout.iter_block().collect();
+    for pred in preds {
+        emit_copies(func, pred, &copies[pred]);
+    }
+
+    !phis.is_empty()
+}
+
+/// Emit the parallel `copies` right before the terminator of the `block`.
+fn emit_copies(func: &mut Function, block: BlockId, copies: &[(ValueId, ValueId)]) {
+    let term = func.layout.last_inst_of(block).unwrap();
+    let mut temp = None;
+
+    for copy in sequence_parallel_copies(copies) {
+        let src = match copy.src {
+            CopyLoc::Value(value) => value,
+            CopyLoc::Temp => temp.unwrap(),
+        };
+        let ty = func.dfg.value_ty(src);
+        let copy_inst = CopyVal::new_unchecked(func.inst_set(), src, ty);
+        let inst = func.dfg.make_inst(copy_inst);
+        func.layout.insert_inst_before(inst, term);
+
+        let dst = match copy.dst {
+            CopyLoc::Value(value) => value,
+            CopyLoc::Temp => {
+                let value = func.dfg.make_value(Value::Inst { inst, ty });
+                temp = Some(value);
+                value
+            }
+        };
+        func.dfg.attach_result(inst, dst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_interpreter::Machine;
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::{Add, Mul, Sub},
+            cmp::{Lt, Ne},
+            control_flow::{Br, Jump, Phi, Return},
+        },
+        interpret::EvalValue,
+        prelude::*,
+        Immediate, Module, Type,
+    };
+
+    use super::*;
+
+    /// Builds a function that rotates `a` and `b` `n` times while counting
+    /// down, which exercises both lost-copy and swap problems.
+    ///
+    /// ```text
+    /// a, b = 1, 2
+    /// while n != 0 { a, b = b, a; n -= 1 }
+    /// return a * 10 + b
+    /// ```
+    fn build_module() -> Module {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        let n0 = builder.args()[0];
+        let one = builder.make_imm_value(1i32);
+        let two = builder.make_imm_value(2i32);
+        let zero = builder.make_imm_value(0i32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let n = builder.insert_inst(Phi::new(is, vec![(n0, b0)]), Type::I32);
+        let a = builder.insert_inst(Phi::new(is, vec![(one, b0)]), Type::I32);
+        let b = builder.insert_inst(Phi::new(is, vec![(two, b0)]), Type::I32);
+        let cond = builder.insert_inst(Ne::new(is, n, zero), Type::I1);
+        builder.insert_inst_no_result(Br::new(is, cond, b2, b3));
+
+        builder.switch_to_block(b2);
+        let next_n = builder.insert_inst(Sub::new(is, n, one), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+        builder.append_phi_arg(n, next_n, b2);
+        builder.append_phi_arg(a, b, b2);
+        builder.append_phi_arg(b, a, b2);
+
+        builder.switch_to_block(b3);
+        let ten = builder.make_imm_value(10i32);
+        let a10 = builder.insert_inst(Mul::new(is, a, ten), Type::I32);
+        let ret = builder.insert_inst(Add::new(is, a10, b), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(ret)));
+
+        builder.seal_all();
+        builder.finish();
+
+        mb.build()
+    }
+
+    #[test]
+    fn destruct_swap_loop() {
+        let module = build_module();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            assert!(destruct_ssa(func, &mut cfg));
+
+            for block in func.layout.iter_block() {
+                assert!(func.layout.iter_inst(block).all(|i| !func.dfg.is_phi(i)));
+            }
+
+            // Swapping `a` and `b` in the latch needs a temporary in addition
+            // to the copies to `n`, `a` and `b`.
+            let is = func.inst_set();
+            let latch = func.layout.iter_block().nth(2).unwrap();
+            let copies = func
+                .layout
+                .iter_inst(latch)
+                .filter(|&inst| {
+                    <&CopyVal as InstDowncast>::downcast(is, func.dfg.inst(inst)).is_some()
+                })
+                .count();
+            assert_eq!(copies, 4);
+        });
+
+        // `a` and `b` are swapped `n` times, so the result is `12` if `n` is
+        // even and `21` otherwise.
+        let mut destructed = Machine::new(module);
+        for n in 0..4 {
+            let args = vec![EvalValue::Imm(Immediate::I32(n))];
+            let expected = if n % 2 == 0 { 12 } else { 21 };
+            assert_eq!(
+                destructed.run(func_ref, args),
+                EvalValue::Imm(Immediate::I32(expected)),
+                "n = {n}"
+            );
+            destructed.clear_state();
+        }
+    }
+
+    /// Builds `if x < y { x * 2 } else { y + 1 }` with the result merged by a
+    /// phi.
+    fn build_diamond() -> Module {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let (x, y) = (builder.args()[0], builder.args()[1]);
+
+        builder.switch_to_block(b0);
+        let cond = builder.insert_inst(Lt::new(is, x, y), Type::I1);
+        builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+        builder.switch_to_block(b1);
+        let two = builder.make_imm_value(2i32);
+        let v1 = builder.insert_inst(Mul::new(is, x, two), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        let one = builder.make_imm_value(1i32);
+        let v2 = builder.insert_inst(Add::new(is, y, one), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b3);
+        let merged = builder.insert_inst(Phi::new(is, vec![(v1, b1), (v2, b2)]), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(merged)));
+
+        builder.seal_all();
+        builder.finish();
+
+        mb.build()
+    }
+
+    #[test]
+    fn destruct_diamond() {
+        let module = build_diamond();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            assert!(destruct_ssa(func, &mut cfg));
+
+            // No edge is critical, so no block is added.
+            let blocks: Vec<_> = func.layout.iter_block().collect();
+            assert_eq!(blocks.len(), 4);
+
+            // Both arms copy their result to the phi result right before
+            // jumping to the merge block.
+            let is = func.inst_set();
+            let mut dsts = Vec::new();
+            for &arm in &blocks[1..3] {
+                let term = func.layout.last_inst_of(arm).unwrap();
+                let copy = func.layout.prev_inst_of(term).unwrap();
+                assert!(<&CopyVal as InstDowncast>::downcast(is, func.dfg.inst(copy)).is_some());
+                dsts.push(func.dfg.inst_result(copy).unwrap());
+            }
+            assert_eq!(dsts[0], dsts[1]);
+
+            // The phi is removed, and the merge block returns the phi result.
+            let first = func.layout.first_inst_of(blocks[3]).unwrap();
+            let ret = <&Return as InstDowncast>::downcast(is, func.dfg.inst(first)).unwrap();
+            assert_eq!(*ret.arg(), Some(dsts[0]));
+        });
+
+        let mut destructed = Machine::new(module);
+        let mut original = Machine::new(build_diamond());
+        for (x, y) in [(1, 2), (2, 1), (3, 3)] {
+            let args = vec![
+                EvalValue::Imm(Immediate::I32(x)),
+                EvalValue::Imm(Immediate::I32(y)),
+            ];
+            assert_eq!(
+                destructed.run(func_ref, args.clone()),
+                original.run(func_ref, args)
+            );
+            destructed.clear_state();
+            original.clear_state();
+        }
+    }
+}
@@ -0,0 +1,505 @@
+//! This module contains outlining, which extracts a single-entry single-exit
+//! region of a function into a new function and replaces the region with a
+//! call to it. This is the inverse of inlining, and is useful to deduplicate
+//! code or to move a cold path out of a hot function.
+//!
+//! The values defined outside the region and used in it are passed as the
+//! arguments of the new function. Since a function returns at most one value,
+//! the region can have at most one live-out value, i.e., a value defined in the
+//! region and used outside of it, or a phi in the exit block merging values
+//! from the region.
+
+use std::collections::BTreeSet;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use sonatina_ir::{
+    inst::control_flow::{Call, Return},
+    module::{FuncRef, ModuleCtx},
+    visitor::OperandMut,
+    BlockId, ControlFlowGraph, Function, InstId, Linkage, Module, Signature, Type, Value, ValueId,
+};
+
+/// An error returned by [`outline_region`] if the blocks can't be outlined.
+/// The function is left unmodified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutlineError {
+    /// The region is empty, contains the entry block of the function or a
+    /// block not in the layout, its entry block has phis, or some of its
+    /// blocks are unreachable from its entry block.
+    InvalidRegion,
+
+    /// A block other than the first one is entered from outside the region.
+    NotSingleEntry,
+
+    /// The region is left to more than one block, or returns from the
+    /// function.
+    NotSingleExit,
+
+    /// More than one value defined in the region is used outside of it.
+    TooManyLiveOuts,
+}
+
+/// Extract the `blocks` of the function into a new private function, and
+/// replace them with a block calling it. The first block of `blocks` must be
+/// the entry of the region.
+///
+/// Returns the new function.
+pub fn outline_region(
+    module: &Module,
+    func_ref: FuncRef,
+    blocks: &[BlockId],
+) -> Result<FuncRef, OutlineError> {
+    let region = module
+        .func_store
+        .view(func_ref, |func| Region::analyze(func, blocks))?;
+
+    let caller_name = module.ctx.func_sig(func_ref, |sig| sig.name().to_string());
+    let name = format!("{caller_name}_outlined{}", module.funcs().len());
+    let (callee, sig) = module.func_store.view(func_ref, |func| {
+        region.build_callee(func, &module.ctx, &name)
+    });
+    let callee_ref = module.func_store.insert(callee);
+    module.ctx.declared_funcs.insert(callee_ref, sig);
+
+    module
+        .func_store
+        .modify(func_ref, |func| region.replace_with_call(func, callee_ref));
+    Ok(callee_ref)
+}
+
+struct Region {
+    entry: BlockId,
+    /// The blocks of the region in RPO.
+    blocks: Vec<BlockId>,
+    block_set: FxHashSet<BlockId>,
+    /// `true` if the entry block has a predecessor in the region, i.e., the
+    /// region has a loop through its entry.
+    is_entry_looped: bool,
+    exit: BlockId,
+    live_ins: Vec<ValueId>,
+    live_out: Option<LiveOut>,
+}
+
+#[derive(Clone, Copy)]
+enum LiveOut {
+    /// A value defined in the region and used outside of it.
+    Value(ValueId),
+    /// A phi in the exit block with args coming from the region.
+    Phi(InstId),
+}
+
+impl Region {
+    fn analyze(func: &Function, blocks: &[BlockId]) -> Result<Self, OutlineError> {
+        let Some(&entry) = blocks.first() else {
+            return Err(OutlineError::InvalidRegion);
+        };
+        let block_set: FxHashSet<_> = blocks.iter().copied().collect();
+        if block_set.contains(&func.layout.entry_block().unwrap())
+            || blocks
+                .iter()
+                .any(|&block| !func.layout.is_block_inserted(block))
+            || func
+                .layout
+                .first_inst_of(entry)
+                .is_some_and(|inst| func.dfg.is_phi(inst))
+        {
+            return Err(OutlineError::InvalidRegion);
+        }
+
+        let mut cfg = ControlFlowGraph::new();
+        cfg.compute(func);
+
+        let mut exits = BTreeSet::new();
+        for &block in &block_set {
+            if block != entry && cfg.preds_of(block).any(|pred| !block_set.contains(pred)) {
+                return Err(OutlineError::NotSingleEntry);
+            }
+
+            let term = func.layout.last_inst_of(block).unwrap();
+            if func.dfg.is_exit(term) {
+                return Err(OutlineError::NotSingleExit);
+            }
+            exits.extend(
+                cfg.succs_of(block)
+                    .copied()
+                    .filter(|succ| !block_set.contains(succ)),
+            );
+        }
+        if exits.len() != 1 {
+            return Err(OutlineError::NotSingleExit);
+        }
+        let exit = exits.pop_first().unwrap();
+
+        let rpo: Vec<_> = cfg
+            .rpo_from(entry)
+            .into_iter()
+            .filter(|block| block_set.contains(block))
+            .collect();
+        if rpo.len() != block_set.len() {
+            return Err(OutlineError::InvalidRegion);
+        }
+
+        let is_outside = |value: ValueId| match func.dfg.value(value) {
+            Value::Arg { .. } => true,
+            Value::Inst { inst, .. } => !block_set.contains(&func.layout.inst_block(*inst)),
+            _ => false,
+        };
+        let mut live_ins = BTreeSet::new();
+        let mut live_outs = Vec::new();
+        for &block in &rpo {
+            for inst in func.layout.iter_inst(block) {
+                func.dfg.inst(inst).for_each_value(&mut |value| {
+                    if is_outside(value) {
+                        live_ins.insert(value);
+                    }
+                });
+
+                // A use by a phi in the exit block is covered by the phi.
+                if let Some(result) = func.dfg.inst_result(inst) {
+                    if func.dfg.users(result).any(|&user| {
+                        let user_block = func.layout.inst_block(user);
+                        !block_set.contains(&user_block)
+                            && (user_block != exit || !func.dfg.is_phi(user))
+                    }) {
+                        live_outs.push(LiveOut::Value(result));
+                    }
+                }
+            }
+        }
+        for inst in func.layout.iter_inst(exit) {
+            let Some(phi) = func.dfg.cast_phi(inst) else {
+                break;
+            };
+            if phi.args().iter().any(|(_, pred)| block_set.contains(pred)) {
+                live_outs.push(LiveOut::Phi(inst));
+            }
+        }
+        if live_outs.len() > 1 {
+            return Err(OutlineError::TooManyLiveOuts);
+        }
+
+        let is_entry_looped = cfg.preds_of(entry).any(|pred| block_set.contains(pred));
+        Ok(Self {
+            entry,
+            blocks: rpo,
+            is_entry_looped,
+            block_set,
+            exit,
+            live_ins: live_ins.into_iter().collect(),
+            live_out: live_outs.pop(),
+        })
+    }
+
+    fn live_out_ty(&self, func: &Function) -> Type {
+        match self.live_out {
+            Some(LiveOut::Value(value)) => func.dfg.value_ty(value),
+            Some(LiveOut::Phi(phi)) => func.dfg.value_ty(func.dfg.inst_result(phi).unwrap()),
+            None => Type::Unit,
+        }
+    }
+
+    /// Builds the function that computes the region, and its signature.
+    fn build_callee(&self, func: &Function, ctx: &ModuleCtx, name: &str) -> (Function, Signature) {
+        let arg_tys: Vec<_> = self
+            .live_ins
+            .iter()
+            .map(|&value| func.dfg.value_ty(value))
+            .collect();
+        let sig = Signature::new(name, Linkage::Private, &arg_tys, self.live_out_ty(func));
+        let mut callee = Function::new(ctx, &sig);
+        let is = callee.inst_set();
+
+        let mut values: FxHashMap<_, _> = self
+            .live_ins
+            .iter()
+            .copied()
+            .zip(callee.arg_values.iter().copied())
+            .collect();
+        let mut blocks = FxHashMap::default();
+
+        // The entry block of a function can't have a predecessor.
+        if self.is_entry_looped {
+            let entry = callee.dfg.make_block();
+            callee.layout.append_block(entry);
+        }
+        for &block in &self.blocks {
+            let new_block = callee.dfg.make_block();
+            callee.layout.append_block(new_block);
+            blocks.insert(block, new_block);
+        }
+        let ret_block = callee.dfg.make_block();
+        blocks.insert(self.exit, ret_block);
+        if self.is_entry_looped {
+            let entry = callee.layout.entry_block().unwrap();
+            let jump = callee.dfg.make_jump(blocks[&self.entry]);
+            let jump = callee.dfg.make_inst(jump);
+            callee.layout.append_inst(jump, entry);
+        }
+
+        // Copy the insts in RPO, so that every operand is copied before its
+        // users except for phi args, which are filled in afterward.
+        let mut phis = Vec::new();
+        for &block in &self.blocks {
+            for inst in func.layout.iter_inst(block) {
+                let new_inst = if func.dfg.is_phi(inst) {
+                    let phi = callee.dfg.make_phi(Vec::new());
+                    phis.push((inst, callee.dfg.make_inst(phi)));
+                    phis.last().unwrap().1
+                } else {
+                    let mut data = func.dfg.clone_inst(inst);
+                    data.for_each_operand_mut(&mut |operand| match operand {
+                        OperandMut::Value(value) => {
+                            *value = copy_value(func, &mut callee, &mut values, *value)
+                        }
+                        OperandMut::Block(block) => *block = blocks[&*block],
+                    });
+                    callee.dfg.make_inst_dyn(data)
+                };
+                if func.dfg.is_volatile(inst) {
+                    callee.dfg.set_volatile(new_inst);
+                }
+                if let Some(loc) = func.dfg.srcloc_of(inst) {
+                    callee.dfg.set_srcloc(new_inst, loc);
+                }
+
+                if let Some(result) = func.dfg.inst_result(inst) {
+                    let ty = func.dfg.value_ty(result);
+                    let new_result = callee.dfg.make_value(Value::Inst { inst: new_inst, ty });
+                    callee.dfg.attach_result(new_inst, new_result);
+                    if func.dfg.is_pinned(result) {
+                        callee.dfg.pin_value(new_result);
+                    }
+                    values.insert(result, new_result);
+                }
+                callee.layout.append_inst(new_inst, blocks[&block]);
+            }
+        }
+        for (phi, new_phi) in phis {
+            for &(value, pred) in func.dfg.cast_phi(phi).unwrap().args() {
+                let value = copy_value(func, &mut callee, &mut values, value);
+                callee.dfg.append_phi_arg(new_phi, value, blocks[&pred]);
+            }
+        }
+
+        callee.layout.append_block(ret_block);
+        let ret_value = match self.live_out {
+            Some(LiveOut::Value(value)) => Some(values[&value]),
+            Some(LiveOut::Phi(phi)) => {
+                let args = func
+                    .dfg
+                    .cast_phi(phi)
+                    .unwrap()
+                    .args()
+                    .iter()
+                    .filter(|(_, pred)| self.block_set.contains(pred))
+                    .map(|&(value, pred)| {
+                        (
+                            copy_value(func, &mut callee, &mut values, value),
+                            blocks[&pred],
+                        )
+                    })
+                    .collect();
+                let phi = callee.dfg.make_phi(args);
+                let phi = callee.dfg.make_inst(phi);
+                let result = callee.dfg.make_value(Value::Inst {
+                    inst: phi,
+                    ty: self.live_out_ty(func),
+                });
+                callee.dfg.attach_result(phi, result);
+                callee.layout.append_inst(phi, ret_block);
+                Some(result)
+            }
+            None => None,
+        };
+        let ret = callee
+            .dfg
+            .make_inst(Return::new(is.has_return().unwrap(), ret_value));
+        callee.layout.append_inst(ret, ret_block);
+
+        (callee, sig)
+    }
+
+    /// Replace the region in the caller with a block calling the `callee`.
+    fn replace_with_call(&self, func: &mut Function, callee: FuncRef) {
+        let is = func.inst_set();
+        let mut cfg = ControlFlowGraph::new();
+        cfg.compute(func);
+
+        let call_block = func.dfg.make_block();
+        func.layout.insert_block_before(call_block, self.entry);
+        let call = Call::new(
+            is.has_call().unwrap(),
+            callee,
+            self.live_ins.iter().copied().collect(),
+        );
+        let call = func.dfg.make_inst(call);
+        func.layout.append_inst(call, call_block);
+        let result = self.live_out.map(|_| {
+            let ty = self.live_out_ty(func);
+            let result = func.dfg.make_value(Value::Inst { inst: call, ty });
+            func.dfg.attach_result(call, result);
+            result
+        });
+        let jump = func.dfg.make_jump(self.exit);
+        let jump = func.dfg.make_inst(jump);
+        func.layout.append_inst(jump, call_block);
+
+        let outside_preds: Vec<_> = cfg
+            .preds_of(self.entry)
+            .copied()
+            .filter(|pred| !self.block_set.contains(pred))
+            .collect();
+        for pred in outside_preds {
+            let term = func.layout.last_inst_of(pred).unwrap();
+            func.dfg.rewrite_branch_dest(term, self.entry, call_block);
+        }
+
+        if let Some(LiveOut::Phi(phi)) = self.live_out {
+            func.dfg.untrack_inst(phi);
+            func.dfg
+                .cast_phi_mut(phi)
+                .unwrap()
+                .retain(|pred| !self.block_set.contains(&pred));
+            func.dfg.attach_user(phi);
+            func.dfg.append_phi_arg(phi, result.unwrap(), call_block);
+        }
+
+        for &block in &self.blocks {
+            let insts: Vec<_> = func.layout.iter_inst(block).collect();
+            for inst in insts {
+                func.dfg.untrack_inst(inst);
+                func.layout.remove_inst(inst);
+            }
+            func.layout.remove_block(block);
+        }
+
+        // Only the users outside the region are left.
+        if let Some(LiveOut::Value(value)) = self.live_out {
+            func.dfg.change_to_alias(value, result.unwrap());
+        }
+    }
+}
+
+/// Returns the value in the `callee` corresponding to the `value` in the
+/// `func`. Immediates, global values and undefs are created on demand.
+fn copy_value(
+    func: &Function,
+    callee: &mut Function,
+    values: &mut FxHashMap<ValueId, ValueId>,
+    value: ValueId,
+) -> ValueId {
+    if let Some(&copied) = values.get(&value) {
+        return copied;
+    }
+
+    let copied = match *func.dfg.value(value) {
+        Value::Immediate { imm, .. } => callee.dfg.make_imm_value(imm),
+        Value::Global { gv, .. } => callee.dfg.make_global_value(gv),
+        Value::Undef { ty } => callee.dfg.make_undef_value(ty),
+        Value::Arg { .. } | Value::Inst { .. } => {
+            unreachable!("values defined outside the region must be live-ins")
+        }
+    };
+    values.insert(value, copied);
+    copied
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_interpreter::Machine;
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::{Add, Mul, Sub},
+            cmp::Slt,
+            control_flow::{Br, Jump, Phi},
+        },
+        interpret::EvalValue,
+        isa::Isa,
+        Immediate,
+    };
+
+    use super::*;
+
+    /// Builds `f(x, y)` whose blocks `b1`-`b3` form a diamond computing
+    /// `a < 10 ? a * 2 : a - 3` for `a = x + y`, which is then added to `x`.
+    fn build_module() -> (Module, [BlockId; 5]) {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let b4 = builder.append_block();
+        let (x, y) = (builder.args()[0], builder.args()[1]);
+
+        builder.switch_to_block(b0);
+        let a = builder.insert_inst(Add::new(is, x, y), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let ten = builder.make_imm_value(10i32);
+        let cond = builder.insert_inst(Slt::new(is, a, ten), Type::I1);
+        builder.insert_inst_no_result(Br::new(is, cond, b2, b3));
+
+        builder.switch_to_block(b2);
+        let two = builder.make_imm_value(2i32);
+        let then_val = builder.insert_inst(Mul::new(is, a, two), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b4));
+
+        builder.switch_to_block(b3);
+        let three = builder.make_imm_value(3i32);
+        let else_val = builder.insert_inst(Sub::new(is, a, three), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b4));
+
+        builder.switch_to_block(b4);
+        let phi = Phi::new(is, vec![(then_val, b2), (else_val, b3)]);
+        let merged = builder.insert_inst(phi, Type::I32);
+        let result = builder.insert_inst(Add::new(is, merged, x), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(result)));
+
+        builder.seal_all();
+        builder.finish();
+        (mb.build(), [b0, b1, b2, b3, b4])
+    }
+
+    #[test]
+    fn outline_diamond() {
+        let (module, [_, b1, b2, b3, _]) = build_module();
+        let func_ref = module.funcs()[0];
+
+        // `b1` and `b2` are left to both `b3` and `b4`.
+        assert_eq!(
+            outline_region(&module, func_ref, &[b1, b2]),
+            Err(OutlineError::NotSingleExit)
+        );
+
+        let callee = outline_region(&module, func_ref, &[b1, b2, b3]).unwrap();
+        module.ctx.func_sig(callee, |sig| {
+            assert_eq!(sig.args(), [Type::I32]);
+            assert_eq!(sig.ret_ty(), Type::I32);
+        });
+        module.func_store.view(func_ref, |func| {
+            assert!(!func.layout.is_block_inserted(b1));
+            assert_eq!(func.layout.block_num(), 3);
+        });
+
+        let mut outlined = Machine::new(module);
+        let mut original = Machine::new(build_module().0);
+        for (x, y) in [(1, 2), (5, 5), (-20, 3), (100, -1)] {
+            let args = vec![
+                EvalValue::Imm(Immediate::I32(x)),
+                EvalValue::Imm(Immediate::I32(y)),
+            ];
+            assert_eq!(
+                outlined.run(func_ref, args.clone()),
+                original.run(func_ref, args),
+                "x = {x}, y = {y}"
+            );
+            outlined.clear_state();
+            original.clear_state();
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! This module contains a utility to sequentialize parallel copies.
+//!
+//! A parallel copy is a set of copies that read all their sources before
+//! writing any destination, e.g. the copies on a CFG edge that implement phis,
+//! or the moves that shuffle registers before a call. Emitting them one by one
+//! in an arbitrary order may clobber a source before it's read, so they must be
+//! ordered, and a temporary is needed to break cycles like a swap.
+
+use std::hash::Hash;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// A location that a sequentialized copy reads from or writes to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CopyLoc<T> {
+    /// A location that appears in the original parallel copy.
+    Value(T),
+    /// The temporary that is used to break cycles.
+    /// A single temporary is enough since cycles are broken one at a time.
+    Temp,
+}
+
+/// A copy `dst <- src`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Copy<T> {
+    pub dst: CopyLoc<T>,
+    pub src: CopyLoc<T>,
+}
+
+impl<T> Copy<T> {
+    pub fn new(dst: CopyLoc<T>, src: CopyLoc<T>) -> Self {
+        Self { dst, src }
+    }
+}
+
+/// Orders `copies`, each of which is a `(dst, src)` pair, so that emitting the
+/// returned copies sequentially has the same effect as performing `copies` in
+/// parallel.
+///
+/// Self copies are dropped, and [`CopyLoc::Temp`] is used to break cycles.
+///
+/// # Panics
+/// Panics if the same destination appears more than once.
+pub fn sequence_parallel_copies<T>(copies: &[(T, T)]) -> Vec<Copy<T>>
+where
+    T: Clone + Eq + Hash,
+{
+    // Maps each pending destination to its current source.
+    let mut pending: FxHashMap<T, CopyLoc<T>> = FxHashMap::default();
+    // Number of pending copies that still read each location.
+    let mut readers: FxHashMap<T, usize> = FxHashMap::default();
+    // Keep the original order to make the result deterministic.
+    let mut order = Vec::with_capacity(copies.len());
+
+    for (dst, src) in copies {
+        if dst == src {
+            continue;
+        }
+        let prev = pending.insert(dst.clone(), CopyLoc::Value(src.clone()));
+        assert!(prev.is_none(), "multiple copies to the same destination");
+        *readers.entry(src.clone()).or_default() += 1;
+        order.push(dst.clone());
+    }
+
+    let mut seq = Vec::with_capacity(order.len());
+    let mut done = FxHashSet::default();
+
+    // Destinations that are not read by any pending copy are safe to write.
+    let mut ready: Vec<T> = order
+        .iter()
+        .filter(|dst| !readers.contains_key(dst))
+        .cloned()
+        .collect();
+    ready.reverse();
+
+    let mut next_in_order = 0;
+    while done.len() < order.len() {
+        while let Some(dst) = ready.pop() {
+            let src = pending.remove(&dst).unwrap();
+            done.insert(dst.clone());
+
+            if let CopyLoc::Value(src) = &src {
+                let count = readers.get_mut(src).unwrap();
+                *count -= 1;
+                if *count == 0 && pending.contains_key(src) {
+                    ready.push(src.clone());
+                }
+            }
+
+            seq.push(Copy::new(CopyLoc::Value(dst), src));
+        }
+
+        // All remaining copies form cycles. Save one of the destinations in the
+        // cycle to the temporary, and let its readers read the temporary
+        // instead, which makes the destination ready to be overwritten.
+        while next_in_order < order.len() && done.contains(&order[next_in_order]) {
+            next_in_order += 1;
+        }
+        let Some(dst) = order.get(next_in_order) else {
+            break;
+        };
+
+        for src in pending.values_mut() {
+            if *src == CopyLoc::Value(dst.clone()) {
+                *src = CopyLoc::Temp;
+            }
+        }
+        readers.insert(dst.clone(), 0);
+        seq.push(Copy::new(CopyLoc::Temp, CopyLoc::Value(dst.clone())));
+        ready.push(dst.clone());
+    }
+
+    seq
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Performs `seq` on `regs` sequentially.
+    fn execute(seq: &[Copy<u32>], regs: &mut FxHashMap<u32, i64>) {
+        let mut temp = None;
+        for copy in seq {
+            let value = match copy.src {
+                CopyLoc::Value(src) => regs[&src],
+                CopyLoc::Temp => temp.unwrap(),
+            };
+            match copy.dst {
+                CopyLoc::Value(dst) => {
+                    regs.insert(dst, value);
+                }
+                CopyLoc::Temp => temp = Some(value),
+            }
+        }
+    }
+
+    /// Checks that `seq` has the same effect as performing `copies` in
+    /// parallel.
+    fn check(copies: &[(u32, u32)], seq: &[Copy<u32>]) {
+        let mut regs: FxHashMap<u32, i64> = (0..8).map(|r| (r, r as i64 * 100)).collect();
+
+        let mut expected = regs.clone();
+        for &(dst, src) in copies {
+            expected.insert(dst, regs[&src]);
+        }
+
+        execute(seq, &mut regs);
+        assert_eq!(regs, expected);
+    }
+
+    fn temps_num(seq: &[Copy<u32>]) -> usize {
+        seq.iter().filter(|copy| copy.dst == CopyLoc::Temp).count()
+    }
+
+    #[test]
+    fn chain() {
+        // r1 <- r0, r2 <- r1, r3 <- r2
+        let copies = [(1, 0), (2, 1), (3, 2)];
+        let seq = sequence_parallel_copies(&copies);
+
+        check(&copies, &seq);
+        assert_eq!(seq.len(), 3);
+        assert_eq!(temps_num(&seq), 0);
+    }
+
+    #[test]
+    fn swap() {
+        // r0 <- r1, r1 <- r0
+        let copies = [(0, 1), (1, 0)];
+        let seq = sequence_parallel_copies(&copies);
+
+        check(&copies, &seq);
+        assert_eq!(seq.len(), 3);
+        assert_eq!(temps_num(&seq), 1);
+    }
+
+    #[test]
+    fn rotation() {
+        // r0 <- r1, r1 <- r2, r2 <- r0
+        let copies = [(0, 1), (1, 2), (2, 0)];
+        let seq = sequence_parallel_copies(&copies);
+
+        check(&copies, &seq);
+        assert_eq!(seq.len(), 4);
+        assert_eq!(temps_num(&seq), 1);
+    }
+
+    #[test]
+    fn cycle_with_fan_out() {
+        // r0 <- r1, r1 <- r0, r2 <- r0, r3 <- r3
+        let copies = [(0, 1), (1, 0), (2, 0), (3, 3)];
+        let seq = sequence_parallel_copies(&copies);
+
+        check(&copies, &seq);
+        assert_eq!(seq.len(), 4);
+    }
+
+    #[test]
+    fn fan_out_inside_cycle() {
+        // r0 <- r2, r1 <- r0, r2 <- r0, r3 <- r1
+        let copies = [(0, 2), (1, 0), (2, 0), (3, 1)];
+        let seq = sequence_parallel_copies(&copies);
+
+        check(&copies, &seq);
+        assert_eq!(temps_num(&seq), 1);
+    }
+}
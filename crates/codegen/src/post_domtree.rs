@@ -1,8 +1,10 @@
 //! This module contains implementation of `Post Dominator Tree`.
 
-use sonatina_ir::{BlockId, ControlFlowGraph, Function};
-
-use super::domtree::{DFSet, DomTree};
+use cranelift_entity::SecondaryMap;
+use sonatina_ir::{
+    domtree::{DFSet, DomTree},
+    BlockId, ControlFlowGraph, Function,
+};
 
 #[derive(Debug)]
 pub struct PostDomTree {
@@ -17,6 +19,10 @@ pub struct PostDomTree {
 
     /// Dominator tree of reverse control flow graph.
     domtree: DomTree,
+
+    /// Blocks in infinite loops that are connected to the dummy exit block as
+    /// if they ended with `return`.
+    sinks: Vec<BlockId>,
 }
 
 impl Default for PostDomTree {
@@ -26,6 +32,7 @@ impl Default for PostDomTree {
             exit: BlockId(0),
             rcfg: ControlFlowGraph::default(),
             domtree: DomTree::default(),
+            sinks: Vec::new(),
         }
     }
 }
@@ -43,6 +50,7 @@ impl PostDomTree {
             return;
         }
         let real_entry = self.rcfg.entry().unwrap();
+        self.sinks = self.find_sinks(func);
 
         self.entry = BlockId(func.dfg.blocks.len() as u32);
         self.exit = BlockId(self.entry.0 + 1);
@@ -53,7 +61,7 @@ impl PostDomTree {
 
         // Add edges from real exit blocks to dummy exit block.
         let real_exits = std::mem::take(&mut self.rcfg.exits);
-        for exit in &real_exits {
+        for exit in real_exits.iter().chain(&self.sinks) {
             self.rcfg.add_edge(*exit, self.exit);
         }
 
@@ -69,9 +77,64 @@ impl PostDomTree {
         }
     }
 
+    /// Returns the immediate post dominator of the `block` unless it's a dummy
+    /// block. Returns `None` if the `block` is an exit block, a synthetic sink,
+    /// or its paths only meet at the function exit.
+    pub fn ipdom_of(&self, block: BlockId) -> Option<BlockId> {
+        match self.idom_of(block)? {
+            PDTIdom::Real(block) => Some(block),
+            PDTIdom::DummyEntry(_) | PDTIdom::DummyExit(_) => None,
+        }
+    }
+
+    /// Returns the nearest block where all paths from the `block` reconverge,
+    /// i.e., the immediate post dominator of the `block`, e.g., the merge
+    /// block of an if-else. Returns `None` if the paths only meet at the
+    /// function exit.
+    pub fn reconvergence_point(&self, block: BlockId) -> Option<BlockId> {
+        self.ipdom_of(block)
+    }
+
+    /// Returns `true` if every path from `block2` to the function exit passes
+    /// through `block1`. A block post dominates itself.
+    pub fn post_dominates(&self, block1: BlockId, block2: BlockId) -> bool {
+        self.domtree.dominates(block1, block2)
+    }
+
+    /// Returns the synthetic sinks, i.e., the blocks in infinite loops that are
+    /// treated as exit blocks so that every block is post dominated by the
+    /// dummy exit block.
+    pub fn sinks(&self) -> &[BlockId] {
+        &self.sinks
+    }
+
+    /// Returns the nearest block that post dominates all the `blocks`, e.g.,
+    /// the earliest point where all paths from a set of uses meet.
+    ///
+    /// Returns `None` if `blocks` is empty, any of them can't reach the exit,
+    /// or the paths only meet at the function exit.
+    pub fn nearest_common_post_dominator(
+        &self,
+        blocks: impl IntoIterator<Item = BlockId>,
+    ) -> Option<BlockId> {
+        let mut common: Option<BlockId> = None;
+        for block in blocks {
+            if !self.is_reachable(block) {
+                return None;
+            }
+            common = Some(match common {
+                Some(common) => self.domtree.nearest_common_dominator(common, block)?,
+                None => block,
+            });
+        }
+
+        common.filter(|&block| block != self.entry && block != self.exit)
+    }
+
     pub fn clear(&mut self) {
         self.rcfg.clear();
         self.domtree.clear();
+        self.sinks.clear();
     }
 
     /// Compute post dominance frontiers of each blocks.
@@ -85,10 +148,63 @@ impl PostDomTree {
         }
     }
 
-    /// Returns `true` if block is reachable from the exit blocks.
+    /// Returns `true` if block is reachable from the exit blocks, including
+    /// the synthetic sinks.
     pub fn is_reachable(&self, block: BlockId) -> bool {
         self.domtree.is_reachable(block)
     }
+
+    /// Returns the blocks to be made synthetic sinks so that every block can
+    /// reach an exit.
+    ///
+    /// The first block that can't reach an exit in the post order of the CFG
+    /// is in an infinite loop rather than on a path leading to one, so it's
+    /// made a sink, and then the search is repeated for the remaining blocks.
+    /// `self.rcfg` must be the forward CFG of the `func`.
+    fn find_sinks(&self, func: &Function) -> Vec<BlockId> {
+        let mut reaches_exit = SecondaryMap::new();
+        for &exit in &self.rcfg.exits {
+            mark_reaching(&self.rcfg, exit, &mut reaches_exit);
+        }
+
+        let mut sinks = Vec::new();
+        // Blocks unreachable from the entry block are visited in the layout
+        // order.
+        let candidates: Vec<_> = self
+            .rcfg
+            .order()
+            .post_order()
+            .iter()
+            .copied()
+            .chain(func.layout.iter_block())
+            .collect();
+        for block in candidates {
+            if !reaches_exit[block] {
+                sinks.push(block);
+                mark_reaching(&self.rcfg, block, &mut reaches_exit);
+            }
+        }
+
+        sinks
+    }
+}
+
+/// Marks the `block` and the blocks that can reach it.
+fn mark_reaching(cfg: &ControlFlowGraph, block: BlockId, marks: &mut SecondaryMap<BlockId, bool>) {
+    if marks[block] {
+        return;
+    }
+
+    marks[block] = true;
+    let mut stack = vec![block];
+    while let Some(block) = stack.pop() {
+        for &pred in cfg.preds_of(block) {
+            if !marks[pred] {
+                marks[pred] = true;
+                stack.push(pred);
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -225,6 +341,28 @@ mod tests {
         assert!(test_pdf(&pdf, then_block, &[entry_block]));
         assert!(test_pdf(&pdf, else_block, &[entry_block]));
         assert!(test_pdf(&pdf, merge_block, &[]));
+
+        assert_eq!(
+            post_dom_tree.reconvergence_point(entry_block),
+            Some(merge_block)
+        );
+        assert_eq!(post_dom_tree.reconvergence_point(merge_block), None);
+        assert!(post_dom_tree.post_dominates(merge_block, entry_block));
+        assert!(!post_dom_tree.post_dominates(then_block, entry_block));
+
+        assert_eq!(
+            post_dom_tree.nearest_common_post_dominator([then_block, else_block]),
+            Some(merge_block)
+        );
+        assert_eq!(
+            post_dom_tree.nearest_common_post_dominator([entry_block, then_block]),
+            Some(merge_block)
+        );
+        assert_eq!(
+            post_dom_tree.nearest_common_post_dominator([then_block]),
+            Some(then_block)
+        );
+        assert_eq!(post_dom_tree.nearest_common_post_dominator([]), None);
     }
 
     #[test]
@@ -244,8 +382,40 @@ mod tests {
         let func_ref = module.funcs()[0];
         let (post_dom_tree, pdf) = module.func_store.view(func_ref, calc_dom);
 
-        assert!(!post_dom_tree.is_reachable(a));
-        assert!(test_pdf(&pdf, a, &[]));
+        // `a` is made a synthetic sink.
+        assert!(post_dom_tree.is_reachable(a));
+        assert_eq!(post_dom_tree.sinks(), [a]);
+        assert_eq!(post_dom_tree.ipdom_of(a), None);
+        assert!(test_pdf(&pdf, a, &[a]));
+    }
+
+    #[test]
+    fn infinite_loop_with_exit() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        // `b` and `c` loop forever.
+        build_cfg! { builder;
+            a -> [b, d];
+            b -> [c];
+            c -> [b];
+            d -> [];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let (post_dom_tree, _) = module.func_store.view(func_ref, calc_dom);
+
+        assert_eq!(post_dom_tree.sinks(), [c]);
+        for block in [a, b, c, d] {
+            assert!(post_dom_tree.is_reachable(block));
+        }
+        assert_eq!(post_dom_tree.ipdom_of(a), None);
+        assert_eq!(post_dom_tree.ipdom_of(b), Some(c));
+        assert_eq!(post_dom_tree.ipdom_of(c), None);
+        assert!(post_dom_tree.post_dominates(c, b));
+        assert!(!post_dom_tree.post_dominates(d, a));
     }
 
     #[test]
@@ -361,4 +531,72 @@ mod tests {
         assert!(test_pdf(&pdf, g, &[g]));
         assert!(test_pdf(&pdf, h, &[]));
     }
+
+    #[test]
+    fn pd_br_table() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        build_cfg! { builder;
+            a -> [b, c, d];
+            b -> [a, e];
+            c -> [f];
+            d -> [f];
+            e -> [];
+            f -> [];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let (post_dom_tree, pdf) = module.func_store.view(func_ref, calc_dom);
+
+        // Paths to `e` and `f` only meet at the function exit.
+        for block in [a, b, e, f] {
+            assert_eq!(post_dom_tree.ipdom_of(block), None, "{block}");
+        }
+        assert_eq!(post_dom_tree.ipdom_of(c), Some(f));
+        assert_eq!(post_dom_tree.ipdom_of(d), Some(f));
+        assert!(post_dom_tree.post_dominates(f, c));
+        assert!(!post_dom_tree.post_dominates(f, a));
+        assert!(post_dom_tree.sinks().is_empty());
+
+        assert!(test_pdf(&pdf, a, &[b]));
+        assert!(test_pdf(&pdf, b, &[a]));
+        assert!(test_pdf(&pdf, c, &[a]));
+        assert!(test_pdf(&pdf, d, &[a]));
+        assert!(test_pdf(&pdf, e, &[b]));
+        assert!(test_pdf(&pdf, f, &[a]));
+    }
+
+    #[test]
+    fn pd_irreducible() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        // `b` and `c` form a loop with two entries.
+        build_cfg! { builder;
+            a -> [b, c];
+            b -> [c];
+            c -> [b, d];
+            d -> [];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let (post_dom_tree, pdf) = module.func_store.view(func_ref, calc_dom);
+
+        assert_eq!(post_dom_tree.ipdom_of(a), Some(c));
+        assert_eq!(post_dom_tree.ipdom_of(b), Some(c));
+        assert_eq!(post_dom_tree.ipdom_of(c), Some(d));
+        assert_eq!(post_dom_tree.ipdom_of(d), None);
+        assert!(post_dom_tree.post_dominates(d, b));
+        assert!(!post_dom_tree.post_dominates(b, a));
+
+        assert!(test_pdf(&pdf, a, &[]));
+        assert!(test_pdf(&pdf, b, &[a, c]));
+        assert!(test_pdf(&pdf, c, &[c]));
+        assert!(test_pdf(&pdf, d, &[]));
+    }
 }
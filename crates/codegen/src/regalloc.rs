@@ -0,0 +1,761 @@
+//! This module contains a register allocator based on Chaitin-Briggs graph
+//! coloring.
+//!
+//! Two values interfere if one of them is defined where the other one is live,
+//! which is computed from the live-out sets of [`Liveness`]. The result of a
+//! `copy` doesn't interfere with its arg since they hold the same value.
+//!
+//! Copy-related values, i.e., a `copy` result and its arg, or a phi result and
+//! its args, are coalesced into one node before coloring if they don't
+//! interfere, so that the copy becomes a no-op. Coalescing is conservative
+//! (Briggs), so it never makes a colorable graph uncolorable. The graph is then
+//! colored with a fixed number of registers, and a value that can't be colored
+//! is assigned its own spill slot, whose loads and stores are inserted by
+//! [`insert_spills`].
+
+use std::collections::BTreeSet;
+
+use cranelift_entity::{packed_option::PackedOption, SecondaryMap};
+use rustc_hash::{FxHashMap, FxHashSet};
+use sonatina_ir::{
+    inst::{
+        cast,
+        data::{Alloca, Mload, Mstore},
+    },
+    BlockId, Function, Inst, InstDowncast, InstId, Type, Value, ValueId,
+};
+
+use crate::liveness::{is_tracked, transfer, Liveness};
+
+/// The location of a value assigned by [`RegAlloc`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Location {
+    /// The register of the index, which is less than the register count.
+    Reg(u32),
+    /// The spill slot of the index.
+    Spill(u32),
+}
+
+#[derive(Debug, Default)]
+pub struct InterferenceGraph {
+    values: BTreeSet<ValueId>,
+    edges: SecondaryMap<ValueId, BTreeSet<ValueId>>,
+    /// Pairs of the result and the arg of a `copy` or a phi.
+    copies: BTreeSet<(ValueId, ValueId)>,
+    /// The node each coalesced value is merged into.
+    merged_into: SecondaryMap<ValueId, PackedOption<ValueId>>,
+    /// Values removed from the graph by coalescing.
+    merged: Vec<ValueId>,
+}
+
+impl InterferenceGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the graph of the `func`. See [`Self::compute`].
+    pub fn build(func: &Function, liveness: &Liveness) -> Self {
+        let mut graph = Self::new();
+        graph.compute(func, liveness);
+        graph
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.edges.clear();
+        self.copies.clear();
+        self.merged_into.clear();
+        self.merged.clear();
+    }
+
+    /// Build the graph of the function args and the inst results of the
+    /// `func`. `liveness` must be computed for the `func`.
+    pub fn compute(&mut self, func: &Function, liveness: &Liveness) {
+        self.clear();
+
+        // The args are defined at once at the top of the function.
+        self.values.extend(func.arg_values.iter().copied());
+        for (i, &arg1) in func.arg_values.iter().enumerate() {
+            for &arg2 in &func.arg_values[i + 1..] {
+                self.add_edge(arg1, arg2);
+            }
+        }
+
+        for block in func.layout.iter_block() {
+            let mut live = liveness.live_out(block).clone();
+            let insts: Vec<_> = func.layout.iter_inst(block).collect();
+            for &inst in insts.iter().rev() {
+                if let Some(result) = func.dfg.inst_result(inst) {
+                    let copy_arg = self.record_copies(func, inst, result);
+                    self.values.insert(result);
+                    for &value in &live {
+                        if value != result && Some(value) != copy_arg {
+                            self.add_edge(result, value);
+                        }
+                    }
+                }
+                transfer(func, inst, &mut live);
+            }
+        }
+    }
+
+    /// Returns `true` if `value1` and `value2` can't share a register.
+    pub fn interferes(&self, value1: ValueId, value2: ValueId) -> bool {
+        self.edges[value1].contains(&value2)
+    }
+
+    pub fn neighbors(&self, value: ValueId) -> impl Iterator<Item = ValueId> + '_ {
+        self.edges[value].iter().copied()
+    }
+
+    /// Returns the pairs of the result and the arg of a `copy` or a phi that
+    /// don't interfere and aren't coalesced yet, so that they can share a
+    /// location to eliminate the copy.
+    pub fn coalesce_candidates(&self) -> impl Iterator<Item = (ValueId, ValueId)> + '_ {
+        self.copies.iter().copied().filter(|&(result, arg)| {
+            let (result, arg) = (self.representative(result), self.representative(arg));
+            result != arg && !self.interferes(result, arg)
+        })
+    }
+
+    /// Merge the coalescing candidates as long as the merged node has fewer
+    /// than `reg_num` neighbors of degree `reg_num` or more, so that the
+    /// graph stays colorable with `reg_num` registers if it was (Briggs).
+    /// Returns the number of merged pairs.
+    pub fn coalesce(&mut self, reg_num: usize) -> usize {
+        let copies: Vec<_> = self.copies.iter().copied().collect();
+        let mut merged_num = 0;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &(result, arg) in &copies {
+                let (value1, value2) = (self.representative(result), self.representative(arg));
+                if value1 != value2
+                    && !self.interferes(value1, value2)
+                    && self.is_safe_to_merge(value1, value2, reg_num)
+                {
+                    self.merge(value1, value2);
+                    merged_num += 1;
+                    changed = true;
+                }
+            }
+        }
+
+        merged_num
+    }
+
+    /// Returns the node that the `value` is coalesced into, or the `value`
+    /// itself if it isn't coalesced.
+    pub fn representative(&self, mut value: ValueId) -> ValueId {
+        while let Some(into) = self.merged_into[value].expand() {
+            value = into;
+        }
+        value
+    }
+
+    /// Returns all the values in the graph in ascending order. Values merged
+    /// into another one by [`Self::coalesce`] aren't included.
+    pub fn values(&self) -> impl Iterator<Item = ValueId> + '_ {
+        self.values.iter().copied()
+    }
+
+    /// Returns the values merged into another one by [`Self::coalesce`].
+    pub fn merged_values(&self) -> &[ValueId] {
+        &self.merged
+    }
+
+    /// Record the copy-related pairs of the `inst` defining the `result`, and
+    /// returns the arg if the `inst` is a `copy`.
+    fn record_copies(&mut self, func: &Function, inst: InstId, result: ValueId) -> Option<ValueId> {
+        if let Some(phi) = func.dfg.cast_phi(inst) {
+            self.copies.extend(
+                phi.args()
+                    .iter()
+                    .filter(|(arg, _)| is_tracked(func, *arg))
+                    .map(|&(arg, _)| (result, arg)),
+            );
+            return None;
+        }
+
+        let copy =
+            <&cast::CopyVal as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(inst))?;
+        let arg = *copy.arg();
+        if !is_tracked(func, arg) {
+            return None;
+        }
+        self.copies.insert((result, arg));
+        Some(arg)
+    }
+
+    /// Returns `true` if merging `value1` and `value2` doesn't make the graph
+    /// uncolorable with `reg_num` registers.
+    fn is_safe_to_merge(&self, value1: ValueId, value2: ValueId, reg_num: usize) -> bool {
+        let neighbors: BTreeSet<_> = self
+            .neighbors(value1)
+            .chain(self.neighbors(value2))
+            .collect();
+        let significant = neighbors
+            .into_iter()
+            .filter(|&neighbor| {
+                // A common neighbor loses an edge by the merge.
+                let is_common =
+                    self.interferes(neighbor, value1) && self.interferes(neighbor, value2);
+                self.edges[neighbor].len() - usize::from(is_common) >= reg_num
+            })
+            .count();
+        significant < reg_num
+    }
+
+    /// Merge `value2` into `value1`.
+    fn merge(&mut self, value1: ValueId, value2: ValueId) {
+        for neighbor in std::mem::take(&mut self.edges[value2]) {
+            self.edges[neighbor].remove(&value2);
+            self.add_edge(value1, neighbor);
+        }
+        self.values.remove(&value2);
+        self.merged_into[value2] = value1.into();
+        self.merged.push(value2);
+    }
+
+    fn add_edge(&mut self, value1: ValueId, value2: ValueId) {
+        self.edges[value1].insert(value2);
+        self.edges[value2].insert(value1);
+    }
+}
+
+#[derive(Debug)]
+pub struct RegAlloc {
+    reg_num: u32,
+    graph: InterferenceGraph,
+    locations: SecondaryMap<ValueId, Option<Location>>,
+    spill_slot_num: u32,
+}
+
+impl RegAlloc {
+    /// Creates an allocator assigning `reg_num` registers.
+    pub fn new(reg_num: u32) -> Self {
+        Self {
+            reg_num,
+            graph: InterferenceGraph::new(),
+            locations: SecondaryMap::new(),
+            spill_slot_num: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.graph.clear();
+        self.locations.clear();
+        self.spill_slot_num = 0;
+    }
+
+    /// Assign a location to every value in the interference graph of the
+    /// `func`. `liveness` must be computed for the `func`.
+    pub fn compute(&mut self, func: &Function, liveness: &Liveness) {
+        self.clear();
+        self.graph.compute(func, liveness);
+        self.graph.coalesce(self.reg_num as usize);
+
+        let stack = self.simplify();
+        self.select(stack);
+        for &value in self.graph.merged_values() {
+            self.locations[value] = self.locations[self.graph.representative(value)];
+        }
+    }
+
+    /// Returns the location of the `value`, or `None` if the `value` isn't
+    /// allocated, e.g., it's an immediate.
+    pub fn location_of(&self, value: ValueId) -> Option<Location> {
+        self.locations[value]
+    }
+
+    pub fn locations(&self) -> &SecondaryMap<ValueId, Option<Location>> {
+        &self.locations
+    }
+
+    pub fn graph(&self) -> &InterferenceGraph {
+        &self.graph
+    }
+
+    /// Returns the number of spill slots used.
+    pub fn spill_slot_num(&self) -> u32 {
+        self.spill_slot_num
+    }
+
+    /// Remove the values from the graph one by one, and returns them in the
+    /// removal order.
+    ///
+    /// A value with fewer neighbors than registers is always colorable, so
+    /// it's removed first. If there's no such value, the one with the most
+    /// neighbors is removed as a potential spill; following Briggs, it's
+    /// actually spilled only if no register is left for it in
+    /// [`Self::select`].
+    fn simplify(&self) -> Vec<ValueId> {
+        let mut remaining: BTreeSet<_> = self.graph.values().collect();
+        let mut stack = Vec::with_capacity(remaining.len());
+        while !remaining.is_empty() {
+            let degree = |value| {
+                self.graph
+                    .neighbors(value)
+                    .filter(|neighbor| remaining.contains(neighbor))
+                    .count()
+            };
+            let value = remaining
+                .iter()
+                .copied()
+                .find(|&value| degree(value) < self.reg_num as usize)
+                .or_else(|| remaining.iter().copied().max_by_key(|&value| degree(value)))
+                .unwrap();
+
+            remaining.remove(&value);
+            stack.push(value);
+        }
+
+        stack
+    }
+
+    /// Color the values in the reverse order of the removal, spilling the ones
+    /// whose neighbors use up all the registers.
+    fn select(&mut self, mut stack: Vec<ValueId>) {
+        while let Some(value) = stack.pop() {
+            let used: BTreeSet<_> = self
+                .graph
+                .neighbors(value)
+                .filter_map(|neighbor| match self.locations[neighbor] {
+                    Some(Location::Reg(reg)) => Some(reg),
+                    _ => None,
+                })
+                .collect();
+
+            let location = match (0..self.reg_num).find(|reg| !used.contains(reg)) {
+                Some(reg) => Location::Reg(reg),
+                None => {
+                    self.spill_slot_num += 1;
+                    Location::Spill(self.spill_slot_num - 1)
+                }
+            };
+            self.locations[value] = Some(location);
+        }
+    }
+}
+
+/// Insert the spill code for the values assigned spill slots by `alloc`, and
+/// returns `true` if any value is spilled.
+///
+/// Each spill slot is allocated in the entry block, and a spilled value is
+/// stored to its slot right after the definition. Each block reloads the value
+/// before its first use in the block, and the following uses in the block
+/// share the reload since the slot is written only at the definition. A phi
+/// arg is reloaded at the end of the corresponding predecessor.
+pub fn insert_spills(func: &mut Function, alloc: &RegAlloc) -> bool {
+    let Some(entry) = func.layout.entry_block() else {
+        return false;
+    };
+    let spilled: FxHashMap<ValueId, u32> = alloc
+        .locations()
+        .iter()
+        .filter_map(|(value, location)| match location {
+            Some(Location::Spill(slot)) => Some((value, *slot)),
+            _ => None,
+        })
+        .collect();
+    if spilled.is_empty() {
+        return false;
+    }
+
+    let entry_top = func.layout.first_inst_of(entry).unwrap();
+    let mut sorted: Vec<_> = spilled
+        .iter()
+        .map(|(&value, &slot)| (value, slot))
+        .collect();
+    sorted.sort_unstable();
+    let mut slots = FxHashMap::default();
+    for &(value, slot) in &sorted {
+        slots.entry(slot).or_insert_with(|| {
+            let ty = func.dfg.value_ty(value);
+            let ptr_ty = func.dfg.ctx.with_ty_store_mut(|s| s.make_ptr(ty));
+            let alloca = Alloca::new_unchecked(func.inst_set(), ty);
+            insert_before(func, Box::new(alloca), entry_top, Some(ptr_ty)).unwrap()
+        });
+    }
+    let slot_of = |value| slots[&spilled[&value]];
+
+    // Store the args before any reload. The stores read the args themselves,
+    // so they are skipped when operands are replaced with reloads.
+    let mut arg_stores = FxHashSet::default();
+    for &(value, _) in &sorted {
+        if func.dfg.value_inst(value).is_none() {
+            arg_stores.insert(store_after(func, value, slot_of(value), None, entry_top));
+        }
+    }
+
+    for block in func.layout.iter_block().collect::<Vec<_>>() {
+        let mut reloads = FxHashMap::default();
+        for inst in func.layout.iter_inst(block).collect::<Vec<_>>() {
+            if func.dfg.is_phi(inst) || arg_stores.contains(&inst) {
+                continue;
+            }
+
+            let mut operands = Vec::new();
+            func.dfg
+                .inst(inst)
+                .for_each_value(&mut |value| operands.push(value));
+            for value in operands {
+                if spilled.contains_key(&value) {
+                    let reload = reload(func, &mut reloads, value, slot_of(value), inst);
+                    replace_operand(func, inst, value, reload);
+                }
+            }
+
+            if func.dfg.is_terminator(inst) {
+                reload_phi_args(func, &spilled, &slot_of, &mut reloads, block, inst);
+            }
+        }
+    }
+
+    for (value, _) in sorted {
+        if let Some(mut def) = func.dfg.value_inst(value) {
+            // Phis must stay at the top of the block.
+            while let Some(next) = func.layout.next_inst_of(def) {
+                if !func.dfg.is_phi(next) {
+                    break;
+                }
+                def = next;
+            }
+            store_after(func, value, slot_of(value), Some(def), entry_top);
+        }
+    }
+
+    true
+}
+
+/// Store the `value` to the `addr` right after the `def`, or before the
+/// `entry_top` if `def` is `None`, and returns the store.
+fn store_after(
+    func: &mut Function,
+    value: ValueId,
+    addr: ValueId,
+    def: Option<InstId>,
+    entry_top: InstId,
+) -> InstId {
+    let ty = func.dfg.value_ty(value);
+    let store = Mstore::new_unchecked(func.inst_set(), addr, value, ty);
+    let store = func.dfg.make_inst(store);
+    match def {
+        Some(def) => func.layout.insert_inst_after(store, def),
+        None => func.layout.insert_inst_before(store, entry_top),
+    }
+    store
+}
+
+/// Reload the spilled args of the phis in the successors of the `block`
+/// before its terminator `term`.
+fn reload_phi_args(
+    func: &mut Function,
+    spilled: &FxHashMap<ValueId, u32>,
+    slot_of: &impl Fn(ValueId) -> ValueId,
+    reloads: &mut FxHashMap<ValueId, ValueId>,
+    block: BlockId,
+    term: InstId,
+) {
+    let Some(branch_info) = func.dfg.branch_info(term) else {
+        return;
+    };
+    for succ in branch_info.dests() {
+        let phis: Vec<_> = func
+            .layout
+            .iter_inst(succ)
+            .take_while(|&inst| func.dfg.is_phi(inst))
+            .collect();
+        for phi in phis {
+            let args = func.dfg.cast_phi(phi).unwrap().args().clone();
+            for (i, (arg, pred)) in args.into_iter().enumerate() {
+                if pred != block || !spilled.contains_key(&arg) {
+                    continue;
+                }
+                let reload = reload(func, reloads, arg, slot_of(arg), term);
+                func.dfg.untrack_inst(phi);
+                func.dfg.cast_phi_mut(phi).unwrap().args_mut()[i].0 = reload;
+                func.dfg.attach_user(phi);
+            }
+        }
+    }
+}
+
+/// Returns the reload of the `value` in the block of `before`, inserting a
+/// load from the `addr` before `before` if the block has none yet.
+fn reload(
+    func: &mut Function,
+    reloads: &mut FxHashMap<ValueId, ValueId>,
+    value: ValueId,
+    addr: ValueId,
+    before: InstId,
+) -> ValueId {
+    if let Some(&reload) = reloads.get(&value) {
+        return reload;
+    }
+
+    let ty = func.dfg.value_ty(value);
+    let load = Mload::new_unchecked(func.inst_set(), addr, ty);
+    let reload = insert_before(func, Box::new(load), before, Some(ty)).unwrap();
+    reloads.insert(value, reload);
+    reload
+}
+
+/// Insert the `data` before the `before`, and returns its result if `ty` is
+/// given.
+fn insert_before(
+    func: &mut Function,
+    data: Box<dyn Inst>,
+    before: InstId,
+    ty: Option<Type>,
+) -> Option<ValueId> {
+    let inst = func.dfg.make_inst_dyn(data);
+    func.layout.insert_inst_before(inst, before);
+    let ty = ty?;
+    let result = func.dfg.make_value(Value::Inst { inst, ty });
+    func.dfg.attach_result(inst, result);
+    Some(result)
+}
+
+fn replace_operand(func: &mut Function, inst: InstId, from: ValueId, to: ValueId) {
+    func.dfg.untrack_inst(inst);
+    func.dfg.inst_mut(inst).for_each_value_mut(&mut |value| {
+        if *value == from {
+            *value = to;
+        }
+    });
+    func.dfg.attach_user(inst);
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_interpreter::Machine;
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::{Add, Mul, Sub},
+            cast,
+            control_flow::{Br, Jump, Phi, Return},
+        },
+        interpret::EvalValue,
+        isa::Isa,
+        ControlFlowGraph, Immediate, Module, Type,
+    };
+
+    use super::*;
+
+    /// Builds `f(a, b, c) = (a + b) * a + c`.
+    fn build_module() -> Module {
+        let mb = test_module_builder();
+        let (evm, mut builder) =
+            test_func_builder(&mb, &[Type::I32, Type::I32, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let (a, b, c) = (builder.args()[0], builder.args()[1], builder.args()[2]);
+
+        builder.switch_to_block(b0);
+        let v3 = builder.insert_inst(Add::new(is, a, b), Type::I32);
+        let v4 = builder.insert_inst(Mul::new(is, v3, a), Type::I32);
+        let v5 = builder.insert_inst(Add::new(is, v4, c), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v5)));
+
+        builder.seal_all();
+        builder.finish();
+        mb.build()
+    }
+
+    fn alloc(module: &Module, reg_num: u32) -> RegAlloc {
+        module.func_store.view(module.funcs()[0], |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            let mut liveness = Liveness::new();
+            liveness.compute(func, &cfg);
+
+            let mut regalloc = RegAlloc::new(reg_num);
+            regalloc.compute(func, &liveness);
+            regalloc
+        })
+    }
+
+    fn assert_valid_coloring(regalloc: &RegAlloc) {
+        let graph = regalloc.graph();
+        for value in graph.values() {
+            let location = regalloc.location_of(value).unwrap();
+            for neighbor in graph.neighbors(value) {
+                if let Location::Reg(_) = location {
+                    assert_ne!(Some(location), regalloc.location_of(neighbor));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn color_without_spill() {
+        let module = build_module();
+        let regalloc = alloc(&module, 3);
+        let graph = regalloc.graph();
+
+        let values: Vec<_> = graph.values().collect();
+        let [a, b, c, v3, v4, v5] = values[..] else {
+            panic!("unexpected values {values:?}");
+        };
+        assert!(graph.interferes(a, b));
+        assert!(graph.interferes(v3, a));
+        assert!(graph.interferes(v3, c));
+        assert!(graph.interferes(v4, c));
+        assert!(!graph.interferes(v4, a));
+        assert!(!graph.interferes(v5, c));
+
+        assert_valid_coloring(&regalloc);
+        assert_eq!(regalloc.spill_slot_num(), 0);
+        assert!(graph
+            .values()
+            .all(|value| matches!(regalloc.location_of(value), Some(Location::Reg(_)))));
+        assert_eq!(regalloc.location_of(v4), regalloc.location_of(v5));
+    }
+
+    #[test]
+    fn spill() {
+        let module = build_module();
+        // The three args are live at once.
+        let regalloc = alloc(&module, 2);
+
+        assert_valid_coloring(&regalloc);
+        assert_eq!(regalloc.spill_slot_num(), 1);
+    }
+
+    #[test]
+    fn interference_of_phi() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let (cond, x) = (builder.args()[0], builder.args()[1]);
+
+        builder.switch_to_block(b0);
+        let sum = builder.insert_inst(Add::new(is, x, x), Type::I32);
+        builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+        builder.switch_to_block(b1);
+        let prod = builder.insert_inst(Mul::new(is, sum, sum), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        let diff = builder.insert_inst(Sub::new(is, sum, x), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b3);
+        let phi = builder.insert_inst(Phi::new(is, vec![(prod, b1), (diff, b2)]), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(phi)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            let mut liveness = Liveness::new();
+            liveness.compute(func, &cfg);
+            let graph = InterferenceGraph::build(func, &liveness);
+
+            // `x` is still live when `sum` is defined.
+            assert!(graph.interferes(sum, x));
+            assert!(graph.neighbors(x).any(|value| value == sum));
+            // `prod` and `diff` are in different arms, and `sum` dies at them.
+            assert!(!graph.interferes(prod, diff));
+            assert!(!graph.interferes(prod, sum));
+            assert!(!graph.interferes(diff, sum));
+
+            let candidates: Vec<_> = graph.coalesce_candidates().collect();
+            assert_eq!(candidates, [(phi, prod), (phi, diff)]);
+        });
+    }
+
+    #[test]
+    fn coalesce_copy() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let x = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        // `x` is live after the copy, but holds the same value as `copied`.
+        let copied = builder.insert_inst(cast::CopyVal::new(is, x, Type::I32), Type::I32);
+        let sum = builder.insert_inst(Add::new(is, copied, x), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(sum)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            let mut liveness = Liveness::new();
+            liveness.compute(func, &cfg);
+
+            let mut graph = InterferenceGraph::build(func, &liveness);
+            assert!(!graph.interferes(copied, x));
+            assert_eq!(
+                graph.coalesce_candidates().collect::<Vec<_>>(),
+                [(copied, x)]
+            );
+
+            assert_eq!(graph.coalesce(2), 1);
+            assert_eq!(graph.representative(x), copied);
+            assert_eq!(graph.values().collect::<Vec<_>>(), [copied, sum]);
+            assert_eq!(graph.merged_values(), [x]);
+            assert_eq!(graph.coalesce_candidates().count(), 0);
+
+            let mut regalloc = RegAlloc::new(1);
+            regalloc.compute(func, &liveness);
+            assert_eq!(regalloc.spill_slot_num(), 0);
+            assert_eq!(regalloc.location_of(x), regalloc.location_of(copied));
+        });
+    }
+
+    #[test]
+    fn spill_and_reload() {
+        let module = build_module();
+        let func_ref = module.funcs()[0];
+        let regalloc = alloc(&module, 1);
+        assert_eq!(regalloc.spill_slot_num(), 2);
+
+        module.func_store.modify(func_ref, |func| {
+            assert!(insert_spills(func, &regalloc));
+
+            let count = |name| {
+                let block = func.layout.entry_block().unwrap();
+                func.layout
+                    .iter_inst(block)
+                    .filter(|&inst| func.dfg.inst(inst).as_text() == name)
+                    .count()
+            };
+            assert_eq!(count("alloca"), 2);
+            assert_eq!(count("mstore"), 2);
+            // `a` is used twice, but reloaded once.
+            assert_eq!(count("mload"), 2);
+        });
+
+        let mut spilled = Machine::new(module);
+        let mut original = Machine::new(build_module());
+        let imm = |value: i32| EvalValue::Imm(Immediate::I32(value));
+        for (a, b, c) in [(1, 2, 3), (-4, 5, 0), (7, 0, -1)] {
+            assert_eq!(
+                spilled.run(func_ref, vec![imm(a), imm(b), imm(c)]),
+                original.run(func_ref, vec![imm(a), imm(b), imm(c)]),
+                "a = {a}, b = {b}, c = {c}"
+            );
+            spilled.clear_state();
+            original.clear_state();
+        }
+    }
+}
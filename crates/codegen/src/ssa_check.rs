@@ -0,0 +1,145 @@
+//! This module contains a checker for the SSA dominance property, i.e., every
+//! use of a value is dominated by its definition.
+
+use rustc_hash::FxHashSet;
+use sonatina_ir::{domtree::DomTree, BlockId, Function, InstId, Value, ValueId};
+
+/// Returns the first inst in the layout order that uses a value whose
+/// definition doesn't dominate the use, together with the value.
+///
+/// A use in a phi is regarded as a use at the end of the corresponding
+/// predecessor. Uses in blocks unreachable from the entry block are ignored.
+pub fn find_ssa_violation(func: &Function, domtree: &DomTree) -> Option<(InstId, ValueId)> {
+    let mut defined = FxHashSet::default();
+
+    for block in func.layout.iter_block() {
+        if !is_reachable(domtree, block) {
+            continue;
+        }
+
+        defined.clear();
+        for inst in func.layout.iter_inst(block) {
+            let mut violation = None;
+            if let Some(phi) = func.dfg.cast_phi(inst) {
+                for &(arg, pred) in phi.args() {
+                    if is_reachable(domtree, pred) && !is_available_at_end(func, domtree, arg, pred)
+                    {
+                        violation = Some(arg);
+                        break;
+                    }
+                }
+            } else {
+                func.dfg.inst(inst).for_each_value(&mut |value| {
+                    if violation.is_none()
+                        && !is_available_before(func, domtree, &defined, value, block)
+                    {
+                        violation = Some(value);
+                    }
+                });
+            }
+
+            if let Some(value) = violation {
+                return Some((inst, value));
+            }
+            if let Some(result) = func.dfg.inst_result(inst) {
+                defined.insert(result);
+            }
+        }
+    }
+
+    None
+}
+
+/// Returns `true` if the `value` is available at the end of the `block`.
+fn is_available_at_end(func: &Function, domtree: &DomTree, value: ValueId, block: BlockId) -> bool {
+    match def_block(func, value) {
+        Ok(Some(def_block)) => domtree.dominates(def_block, block),
+        Ok(None) => true,
+        Err(()) => false,
+    }
+}
+
+/// Returns `true` if the `value` is available at the current position in the
+/// `block`. `defined` holds the values defined so far in the `block`.
+fn is_available_before(
+    func: &Function,
+    domtree: &DomTree,
+    defined: &FxHashSet<ValueId>,
+    value: ValueId,
+    block: BlockId,
+) -> bool {
+    match def_block(func, value) {
+        Ok(Some(def_block)) if def_block == block => defined.contains(&value),
+        Ok(Some(def_block)) => domtree.strictly_dominates(def_block, block),
+        Ok(None) => true,
+        Err(()) => false,
+    }
+}
+
+/// Returns the block defining the `value` if it's an inst result, or `None` if
+/// the value is available everywhere.
+/// Returns an error if the defining inst is not in the layout.
+fn def_block(func: &Function, value: ValueId) -> Result<Option<BlockId>, ()> {
+    match func.dfg.value(value) {
+        Value::Inst { inst, .. } => {
+            if func.layout.is_inst_inserted(*inst) {
+                Ok(Some(func.layout.inst_block(*inst)))
+            } else {
+                Err(())
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+fn is_reachable(domtree: &DomTree, block: BlockId) -> bool {
+    domtree.entry() == Some(block) || domtree.is_reachable(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{arith::Add, control_flow::Return},
+        prelude::*,
+        ControlFlowGraph, Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn use_before_def() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let one = builder.make_imm_value(1i32);
+        let v0 = builder.insert_inst(Add::new(is, arg, one), Type::I32);
+        let v1 = builder.insert_inst(Add::new(is, v0, one), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.modify(module.funcs()[0], |func| {
+            let mut cfg = ControlFlowGraph::new();
+            let mut domtree = DomTree::new();
+            cfg.compute(func);
+            domtree.compute(&cfg);
+            assert_eq!(find_ssa_violation(func, &domtree), None);
+
+            // Move the second add before the first one.
+            let inst0 = func.dfg.value_inst(v0).unwrap();
+            let inst1 = func.dfg.value_inst(v1).unwrap();
+            func.layout.remove_inst(inst1);
+            func.layout.insert_inst_before(inst1, inst0);
+
+            assert_eq!(find_ssa_violation(func, &domtree), Some((inst1, v0)));
+        });
+    }
+}
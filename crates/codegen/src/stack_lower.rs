@@ -0,0 +1,614 @@
+//! This module contains a minimal lowering of a function into a sequence of
+//! stack machine ops modeled on the EVM.
+//!
+//! Values are kept on the stack within a block, and are dropped as soon as
+//! they die according to [`Liveness`]. The stack is empty at block
+//! boundaries; a value used outside its defining block, e.g., a phi arg, is
+//! spilled to a memory slot dedicated to the value when defined, and reloaded
+//! in the blocks using it.
+//!
+//! All values are treated as 256-bit words, so arithmetic on narrower types
+//! doesn't wrap at their width.
+
+use std::fmt;
+
+use sonatina_ir::{
+    inst::{
+        arith::{Add, Mul, Sar, Shl, Shr, Sub},
+        cmp::{Eq, Gt, IsZero, Lt, Sgt, Slt},
+        control_flow::{Br, Jump, Return},
+        evm::{EvmSdiv, EvmSmod, EvmUdiv, EvmUmod},
+        logic::{And, Not, Or, Xor},
+    },
+    BlockId, ControlFlowGraph, Function, InstDowncast, InstId, ValueId, U256,
+};
+
+use crate::liveness::Liveness;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackOp {
+    Push(U256),
+    /// Push a copy of the `n`th value from the top, which is `1`.
+    Dup(u8),
+    /// Swap the top value with the `n + 1`th value from the top.
+    Swap(u8),
+    Pop,
+
+    Add,
+    Mul,
+    Sub,
+    Div,
+    SDiv,
+    Mod,
+    SMod,
+    Lt,
+    Gt,
+    SLt,
+    SGt,
+    Eq,
+    IsZero,
+    And,
+    Or,
+    Xor,
+    Not,
+    Shl,
+    Shr,
+    Sar,
+    /// Pop an address, and push the word at the address.
+    MLoad,
+    /// Pop an address and a word, and store the word at the address.
+    MStore,
+
+    /// Marks the start of the block.
+    JumpDest(BlockId),
+    /// Push the address of the block.
+    PushLabel(BlockId),
+    /// Pop an address and jump to it.
+    Jump,
+    /// Pop an address and a condition, and jump to the address if the
+    /// condition is non-zero.
+    JumpI,
+    /// Return from the function with the top value as the result if the
+    /// function has one.
+    Return,
+}
+
+/// The error returned if a function contains an inst that has no stack
+/// machine counterpart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedInst(pub InstId);
+
+impl fmt::Display for UnsupportedInst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} can't be lowered to stack ops", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedInst {}
+
+/// Lower the function into a sequence of stack ops.
+///
+/// On entry, the function args are on the stack with the last arg on top.
+/// Only arithmetic, comparison and logic insts, phis, and `jump`, `br` and
+/// `return` are supported, where a `br` may not jump to a block with phis.
+/// The stack depth isn't checked against the limit of `DUP` and `SWAP`.
+pub fn lower_to_stack(func: &Function) -> Result<Vec<StackOp>, UnsupportedInst> {
+    let mut cfg = ControlFlowGraph::new();
+    cfg.compute(func);
+    let mut liveness = Liveness::new();
+    liveness.compute(func, &cfg);
+
+    let mut lowerer = StackLowerer {
+        func,
+        liveness: &liveness,
+        ops: Vec::new(),
+        stack: Vec::new(),
+    };
+    for block in func.layout.iter_block() {
+        lowerer.lower_block(block)?;
+    }
+
+    Ok(lowerer.ops)
+}
+
+struct StackLowerer<'a> {
+    func: &'a Function,
+    liveness: &'a Liveness,
+    ops: Vec<StackOp>,
+    /// The values on the stack, with the top value last.
+    stack: Vec<ValueId>,
+}
+
+impl StackLowerer<'_> {
+    fn lower_block(&mut self, block: BlockId) -> Result<(), UnsupportedInst> {
+        let func = self.func;
+        self.ops.push(StackOp::JumpDest(block));
+        debug_assert!(self.stack.is_empty());
+
+        if func.layout.entry_block() == Some(block) {
+            self.stack.extend(func.arg_values.iter().copied());
+            for &arg in &func.arg_values {
+                if self.is_used_across_blocks(arg, block) {
+                    self.spill(arg);
+                }
+            }
+        }
+
+        for inst in func.layout.iter_inst(block) {
+            if func.dfg.is_phi(inst) {
+                // Phi results are stored to their slots by the predecessors.
+                continue;
+            }
+            if func.dfg.is_terminator(inst) {
+                self.lower_terminator(block, inst)?;
+                continue;
+            }
+
+            let op = stack_op(func, inst).ok_or(UnsupportedInst(inst))?;
+            let mut args = Vec::new();
+            func.dfg
+                .inst(inst)
+                .for_each_value(&mut |value| args.push(value));
+            // The first operand is on top, e.g., `sub lhs rhs` is `lhs - rhs`.
+            for &arg in args.iter().rev() {
+                self.push_value(arg);
+            }
+            self.ops.push(op);
+            self.stack.truncate(self.stack.len() - args.len());
+
+            if let Some(result) = func.dfg.inst_result(inst) {
+                self.stack.push(result);
+                if self.is_used_across_blocks(result, block) {
+                    self.spill(result);
+                }
+            }
+            self.drop_dead_values(inst);
+        }
+
+        Ok(())
+    }
+
+    fn lower_terminator(&mut self, block: BlockId, inst: InstId) -> Result<(), UnsupportedInst> {
+        let func = self.func;
+        let is = func.inst_set();
+        let data = func.dfg.inst(inst);
+
+        if let Some(ret) = <&Return as InstDowncast>::downcast(is, data) {
+            match *ret.arg() {
+                Some(arg) => self.keep_only(arg),
+                None => self.clear_stack(),
+            }
+            self.ops.push(StackOp::Return);
+            self.stack.clear();
+            return Ok(());
+        }
+
+        let dests = if let Some(jump) = <&Jump as InstDowncast>::downcast(is, data) {
+            vec![*jump.dest()]
+        } else if let Some(br) = <&Br as InstDowncast>::downcast(is, data) {
+            // Storing phi args before a conditional jump would clobber the
+            // slots of phi results still live on the other path.
+            let dests = [*br.nz_dest(), *br.z_dest()];
+            if dests.iter().any(|&dest| self.has_phi(dest)) {
+                return Err(UnsupportedInst(inst));
+            }
+            dests.to_vec()
+        } else {
+            return Err(UnsupportedInst(inst));
+        };
+        self.store_phi_args(block, &dests);
+
+        if let Some(br) = <&Br as InstDowncast>::downcast(is, data) {
+            self.keep_only(*br.cond());
+            self.ops.push(StackOp::PushLabel(*br.nz_dest()));
+            self.ops.push(StackOp::JumpI);
+            self.stack.clear();
+        } else {
+            self.clear_stack();
+        }
+        self.ops.push(StackOp::PushLabel(*dests.last().unwrap()));
+        self.ops.push(StackOp::Jump);
+
+        Ok(())
+    }
+
+    /// Store the phi args coming from the `block` to the slots of the phis in
+    /// the `dests`.
+    ///
+    /// All args are pushed before any store, since an arg may be a phi result
+    /// whose slot is overwritten by the stores, e.g., when two phis swap their
+    /// values in a loop.
+    fn store_phi_args(&mut self, block: BlockId, dests: &[BlockId]) {
+        let mut phis = Vec::new();
+        for &dest in dests {
+            for inst in self.func.layout.iter_inst(dest) {
+                let Some(phi) = self.func.dfg.cast_phi(inst) else {
+                    break;
+                };
+                let result = self.func.dfg.inst_result(inst).unwrap();
+                for &(arg, pred) in phi.args() {
+                    if pred == block && !phis.contains(&(result, arg)) {
+                        phis.push((result, arg));
+                    }
+                }
+            }
+        }
+
+        for &(_, arg) in &phis {
+            self.push_value(arg);
+        }
+        for &(result, _) in phis.iter().rev() {
+            self.ops.push(StackOp::Push(slot_of(result)));
+            self.ops.push(StackOp::MStore);
+            self.stack.pop();
+        }
+    }
+
+    /// Push a copy of the `value` on top of the stack.
+    fn push_value(&mut self, value: ValueId) {
+        if let Some(imm) = self.func.dfg.value_imm(value) {
+            self.ops.push(StackOp::Push(imm.as_i256().to_u256()));
+        } else if let Some(depth) = self.depth_of(value) {
+            self.ops.push(StackOp::Dup(depth));
+        } else {
+            self.ops.push(StackOp::Push(slot_of(value)));
+            self.ops.push(StackOp::MLoad);
+        }
+        self.stack.push(value);
+    }
+
+    /// Store a copy of the `value`, which is on top of the stack, to its slot.
+    fn spill(&mut self, value: ValueId) {
+        let depth = self.depth_of(value).unwrap();
+        self.ops.push(StackOp::Dup(depth));
+        self.ops.push(StackOp::Push(slot_of(value)));
+        self.ops.push(StackOp::MStore);
+    }
+
+    /// Remove the values that are dead after the `inst` from the stack.
+    fn drop_dead_values(&mut self, inst: InstId) {
+        let live = self.liveness.live_after(self.func, inst);
+        let dead: Vec<_> = self
+            .stack
+            .iter()
+            .copied()
+            .filter(|value| !live.contains(value))
+            .collect();
+        for value in dead {
+            // A value may be on the stack more than once, e.g., an arg of a
+            // phi that is pushed twice.
+            while let Some(depth) = self.depth_of(value) {
+                self.remove(depth);
+            }
+        }
+    }
+
+    /// Remove all values from the stack except for a single copy of the
+    /// `value` left on top.
+    fn keep_only(&mut self, value: ValueId) {
+        if self.depth_of(value) != Some(1) {
+            self.push_value(value);
+        }
+        while self.stack.len() > 1 {
+            self.remove(2);
+        }
+    }
+
+    fn clear_stack(&mut self) {
+        for _ in 0..self.stack.len() {
+            self.ops.push(StackOp::Pop);
+        }
+        self.stack.clear();
+    }
+
+    /// Remove the value at the `depth` from the stack. The order of the
+    /// values above it may change.
+    fn remove(&mut self, depth: u8) {
+        let len = self.stack.len();
+        if depth > 1 {
+            self.ops.push(StackOp::Swap(depth - 1));
+            self.stack.swap(len - 1, len - depth as usize);
+        }
+        self.ops.push(StackOp::Pop);
+        self.stack.pop();
+    }
+
+    /// Returns the depth of the topmost copy of the `value` on the stack,
+    /// where the top is `1`.
+    fn depth_of(&self, value: ValueId) -> Option<u8> {
+        let pos = self.stack.iter().rposition(|&v| v == value)?;
+        Some((self.stack.len() - pos) as u8)
+    }
+
+    fn has_phi(&self, block: BlockId) -> bool {
+        self.func
+            .layout
+            .first_inst_of(block)
+            .is_some_and(|inst| self.func.dfg.is_phi(inst))
+    }
+
+    /// Returns `true` if the `value` available in the `block` is used in
+    /// another block or by a phi.
+    fn is_used_across_blocks(&self, value: ValueId, block: BlockId) -> bool {
+        self.func
+            .dfg
+            .users(value)
+            .any(|&user| self.func.layout.inst_block(user) != block || self.func.dfg.is_phi(user))
+    }
+}
+
+/// Returns the memory slot where the `value` is spilled.
+fn slot_of(value: ValueId) -> U256 {
+    U256::from(value.as_u32()) * U256::from(32)
+}
+
+fn stack_op(func: &Function, inst: InstId) -> Option<StackOp> {
+    let is = func.inst_set();
+    let data = func.dfg.inst(inst);
+
+    macro_rules! lower {
+        ($($ty:ident => $op:ident),* $(,)?) => {
+            $(
+                if <&$ty as InstDowncast>::downcast(is, data).is_some() {
+                    return Some(StackOp::$op);
+                }
+            )*
+        };
+    }
+    lower!(
+        Add => Add,
+        Mul => Mul,
+        Sub => Sub,
+        EvmUdiv => Div,
+        EvmSdiv => SDiv,
+        EvmUmod => Mod,
+        EvmSmod => SMod,
+        Lt => Lt,
+        Gt => Gt,
+        Slt => SLt,
+        Sgt => SGt,
+        Eq => Eq,
+        IsZero => IsZero,
+        And => And,
+        Or => Or,
+        Xor => Xor,
+        Not => Not,
+        Shl => Shl,
+        Shr => Shr,
+        Sar => Sar,
+    );
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashMap;
+    use sonatina_ir::{builder::test_util::*, inst::control_flow::Phi, isa::Isa, Type, I256};
+
+    use super::*;
+
+    /// Runs the `ops` with the `args` on the stack, and returns the result.
+    fn run(ops: &[StackOp], args: &[u64]) -> U256 {
+        let labels: FxHashMap<_, _> = ops
+            .iter()
+            .enumerate()
+            .filter_map(|(pc, op)| match op {
+                StackOp::JumpDest(block) => Some((*block, pc)),
+                _ => None,
+            })
+            .collect();
+        let mut stack: Vec<U256> = args.iter().map(|&arg| arg.into()).collect();
+        let mut memory = FxHashMap::default();
+
+        let mut pc = 0;
+        loop {
+            let op = ops[pc];
+            pc += 1;
+
+            let len = stack.len();
+            match op {
+                StackOp::Push(value) => stack.push(value),
+                StackOp::Dup(n) => stack.push(stack[len - n as usize]),
+                StackOp::Swap(n) => stack.swap(len - 1, len - 1 - n as usize),
+                StackOp::Pop => {
+                    stack.pop().unwrap();
+                }
+                StackOp::IsZero => {
+                    let a = stack.pop().unwrap();
+                    stack.push(U256::from(a.is_zero() as u8));
+                }
+                StackOp::Not => {
+                    let a = stack.pop().unwrap();
+                    stack.push(!a);
+                }
+                StackOp::Add
+                | StackOp::Mul
+                | StackOp::Sub
+                | StackOp::Div
+                | StackOp::SDiv
+                | StackOp::Mod
+                | StackOp::SMod
+                | StackOp::Lt
+                | StackOp::Gt
+                | StackOp::SLt
+                | StackOp::SGt
+                | StackOp::Eq
+                | StackOp::And
+                | StackOp::Or
+                | StackOp::Xor
+                | StackOp::Shl
+                | StackOp::Shr
+                | StackOp::Sar => {
+                    let a = stack.pop().unwrap();
+                    let b = stack.pop().unwrap();
+                    stack.push(binary(op, a, b));
+                }
+                StackOp::MLoad => {
+                    let addr = stack.pop().unwrap();
+                    stack.push(memory.get(&addr).copied().unwrap_or_default());
+                }
+                StackOp::MStore => {
+                    let addr = stack.pop().unwrap();
+                    let value = stack.pop().unwrap();
+                    memory.insert(addr, value);
+                }
+                StackOp::JumpDest(_) => {}
+                StackOp::PushLabel(block) => stack.push(labels[&block].into()),
+                StackOp::Jump => pc = stack.pop().unwrap().as_usize(),
+                StackOp::JumpI => {
+                    let dest = stack.pop().unwrap();
+                    if !stack.pop().unwrap().is_zero() {
+                        pc = dest.as_usize();
+                    }
+                }
+                StackOp::Return => {
+                    let result = stack.pop().unwrap();
+                    assert!(stack.is_empty(), "{stack:?}");
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Applies the binary `op` to the top value `a` and the next value `b`
+    /// as the EVM does, e.g., `Sub` is `a - b` and `Shl` is `b << a`.
+    fn binary(op: StackOp, a: U256, b: U256) -> U256 {
+        let (sa, sb) = (I256::from_u256(a), I256::from_u256(b));
+        let shift = (a < U256::from(256)).then(|| a.as_usize());
+        match op {
+            StackOp::Add => a.overflowing_add(b).0,
+            StackOp::Mul => a.overflowing_mul(b).0,
+            StackOp::Sub => a.overflowing_sub(b).0,
+            StackOp::Div => a.checked_div(b).unwrap_or_default(),
+            StackOp::SDiv => sa.checked_div(sb).map_or_else(
+                // `MIN / -1` wraps around to `MIN`.
+                || if b.is_zero() { U256::zero() } else { a },
+                |q| q.to_u256(),
+            ),
+            StackOp::Mod => a.checked_rem(b).unwrap_or_default(),
+            StackOp::SMod => sa.checked_rem(sb).map_or_else(U256::zero, |r| r.to_u256()),
+            StackOp::Lt => U256::from((a < b) as u8),
+            StackOp::Gt => U256::from((a > b) as u8),
+            StackOp::SLt => U256::from((sa < sb) as u8),
+            StackOp::SGt => U256::from((sa > sb) as u8),
+            StackOp::Eq => U256::from((a == b) as u8),
+            StackOp::And => a & b,
+            StackOp::Or => a | b,
+            StackOp::Xor => a ^ b,
+            StackOp::Shl => shift.map_or_else(U256::zero, |shift| b << shift),
+            StackOp::Shr => shift.map_or_else(U256::zero, |shift| b >> shift),
+            StackOp::Sar => sb.sar(sa).to_u256(),
+            _ => unreachable!("{op:?} isn't a binary op"),
+        }
+    }
+
+    #[test]
+    fn lower_add() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I64, Type::I64], Type::I64);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let (a, b) = (builder.args()[0], builder.args()[1]);
+        let v0 = builder.insert_inst(Add::new(is, a, b), Type::I64);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let ops = lower_to_stack(func).unwrap();
+            assert_eq!(
+                ops,
+                [
+                    StackOp::JumpDest(b0),
+                    StackOp::Dup(1),
+                    StackOp::Dup(3),
+                    StackOp::Add,
+                    StackOp::Swap(2),
+                    StackOp::Pop,
+                    StackOp::Pop,
+                    StackOp::Return,
+                ]
+            );
+            assert_eq!(run(&ops, &[3, 4]), U256::from(7));
+        });
+    }
+
+    #[test]
+    fn lower_signed_arith() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I256, Type::I256], Type::I256);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let (a, b) = (builder.args()[0], builder.args()[1]);
+        // `((a - b) << 2) / b - ((a - b) << 2) % b`.
+        let v0 = builder.insert_inst(Sub::new(is, a, b), Type::I256);
+        let two = builder.make_imm_value(I256::from(2i32));
+        let v1 = builder.insert_inst(Shl::new(is, two, v0), Type::I256);
+        let v2 = builder.insert_inst(EvmSdiv::new(is, v1, b), Type::I256);
+        let v3 = builder.insert_inst(EvmSmod::new(is, v1, b), Type::I256);
+        let v4 = builder.insert_inst(Sub::new(is, v2, v3), Type::I256);
+        builder.insert_inst_no_result(Return::new(is, Some(v4)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let ops = lower_to_stack(func).unwrap();
+            // `-8 / 5 - -8 % 5` is `-1 - -3`.
+            assert_eq!(run(&ops, &[3, 5]), U256::from(2));
+        });
+    }
+
+    #[test]
+    fn lower_loop() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I64], Type::I64);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let n = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let zero = builder.make_imm_value(0i64);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let i = builder.insert_inst(Phi::new(is, vec![(zero, b0)]), Type::I64);
+        let sum = builder.insert_inst(Phi::new(is, vec![(zero, b0)]), Type::I64);
+        let cond = builder.insert_inst(Lt::new(is, i, n), Type::I1);
+        builder.insert_inst_no_result(Br::new(is, cond, b2, b3));
+
+        builder.switch_to_block(b2);
+        let one = builder.make_imm_value(1i64);
+        let next_sum = builder.insert_inst(Add::new(is, sum, i), Type::I64);
+        let next_i = builder.insert_inst(Add::new(is, i, one), Type::I64);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+        builder.append_phi_arg(i, next_i, b2);
+        builder.append_phi_arg(sum, next_sum, b2);
+
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result(Return::new(is, Some(sum)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let ops = lower_to_stack(func).unwrap();
+            for n in [0, 1, 2, 10] {
+                assert_eq!(run(&ops, &[n]), U256::from((0..n).sum::<u64>()), "n = {n}");
+            }
+        });
+    }
+}
@@ -0,0 +1,622 @@
+//! This module contains a structural analysis, which collapses the CFG into a
+//! tree of regions corresponding to high-level control flow constructs.
+//!
+//! The algorithm is based on Micha Sharir.: Structural Analysis: A New Approach
+//! to Flow Analysis in Optimizing Compilers, as described in Steven S.
+//! Muchnick.: Advanced Compiler Design and Implementation.
+//!
+//! Nodes of the CFG are visited in post order, and each time a subgraph
+//! matching a region pattern is found, it's collapsed into a single node, until
+//! only one node remains. When no pattern matches, e.g., in an irreducible
+//! loop, the smallest subgraph dominated by a node is collapsed into a proper
+//! region.
+
+use cranelift_entity::{entity_impl, packed_option::PackedOption, PrimaryMap, SecondaryMap};
+use sonatina_ir::{BlockId, ControlFlowGraph, Function};
+
+#[derive(Debug, Default)]
+pub struct StructuralAnalysis {
+    regions: PrimaryMap<Region, RegionData>,
+    /// Maps blocks to their leaf regions.
+    block_regions: SecondaryMap<BlockId, PackedOption<Region>>,
+    root: PackedOption<Region>,
+}
+
+impl StructuralAnalysis {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.regions.clear();
+        self.block_regions.clear();
+        self.root = None.into();
+    }
+
+    /// Compute the region tree of the CFG of the `func`.
+    /// Blocks unreachable from the entry block are not included in the tree.
+    pub fn compute(&mut self, func: &Function, cfg: &ControlFlowGraph) {
+        self.clear();
+
+        let Some(entry) = cfg.entry() else {
+            return;
+        };
+
+        let rpo = cfg.order().rpo();
+        for &block in rpo {
+            let region = self.make_region(RegionKind::Block(block), Vec::new());
+            self.block_regions[block] = region.into();
+        }
+
+        let mut graph = Graph::new(self.block_regions[entry].unwrap());
+        for &block in rpo {
+            let from = self.block_regions[block].unwrap();
+            graph.nodes.push(from);
+            // Add the edges in the order of the branch destinations so that the
+            // arms of an if-then-else follow the order of its condition.
+            let dests = func
+                .layout
+                .last_inst_of(block)
+                .and_then(|inst| func.dfg.branch_info(inst))
+                .map(|branch| branch.dests())
+                .unwrap_or_default();
+            for dest in dests {
+                graph.add_edge(from, self.block_regions[dest].unwrap());
+            }
+        }
+
+        while graph.nodes.len() > 1 {
+            if !self.reduce_pattern(&mut graph) {
+                self.reduce_proper(&mut graph);
+            }
+        }
+
+        self.root = graph.entry.into();
+    }
+
+    /// Returns the root region, which covers the whole CFG.
+    pub fn root(&self) -> Option<Region> {
+        self.root.expand()
+    }
+
+    pub fn kind(&self, region: Region) -> RegionKind {
+        self.regions[region].kind
+    }
+
+    /// Returns the child regions of the `region`.
+    /// See [`RegionKind`] for the order of the children.
+    pub fn children(&self, region: Region) -> &[Region] {
+        &self.regions[region].children
+    }
+
+    /// Returns the region that directly contains the `region`.
+    pub fn parent_of(&self, region: Region) -> Option<Region> {
+        self.regions[region].parent.expand()
+    }
+
+    /// Returns the leaf region of the `block`.
+    /// Returns `None` if the `block` is unreachable from the entry block.
+    pub fn region_of(&self, block: BlockId) -> Option<Region> {
+        self.block_regions[block].expand()
+    }
+
+    /// Returns the block through which the control enters the `region`.
+    pub fn entry_block_of(&self, mut region: Region) -> BlockId {
+        loop {
+            match self.kind(region) {
+                RegionKind::Block(block) => return block,
+                _ => region = self.children(region)[0],
+            }
+        }
+    }
+
+    fn make_region(&mut self, kind: RegionKind, children: Vec<Region>) -> Region {
+        let region = self.regions.push(RegionData {
+            kind,
+            children,
+            parent: None.into(),
+        });
+        for i in 0..self.regions[region].children.len() {
+            let child = self.regions[region].children[i];
+            self.regions[child].parent = region.into();
+        }
+        region
+    }
+
+    /// Collapse the first subgraph in post order that matches a region
+    /// pattern. Returns `true` if a subgraph is collapsed.
+    fn reduce_pattern(&mut self, graph: &mut Graph) -> bool {
+        let doms = graph.dominators();
+        for node in graph.post_order() {
+            let matched = graph
+                .match_acyclic(node)
+                .or_else(|| graph.match_cyclic(node, &doms));
+            if let Some((kind, members)) = matched {
+                let region = self.make_region(kind, members);
+                graph.collapse(region, self.children(region), kind.is_cyclic());
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Collapse the smallest subgraph dominated by a node into a proper region.
+    fn reduce_proper(&mut self, graph: &mut Graph) {
+        let doms = graph.dominators();
+        let post_order = graph.post_order();
+
+        let mut smallest: Option<Vec<Region>> = None;
+        for &header in &post_order {
+            let mut members = vec![header];
+            members.extend(
+                post_order
+                    .iter()
+                    .rev()
+                    .copied()
+                    .filter(|&node| node != header && doms.dominates(header, node)),
+            );
+            if members.len() > 1 && smallest.as_ref().is_none_or(|s| members.len() < s.len()) {
+                smallest = Some(members);
+            }
+        }
+
+        // The entry dominates all the other nodes, so a region is always found.
+        let region = self.make_region(RegionKind::Proper, smallest.unwrap());
+        graph.collapse(region, self.children(region), true);
+    }
+}
+
+/// An opaque reference to a region in [`StructuralAnalysis`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Region(u32);
+entity_impl!(Region);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    /// A single block, which has no children.
+    Block(BlockId),
+    /// Children executed one after another, in the execution order.
+    Sequence,
+    /// A condition and a region executed only on one of its branches.
+    /// The children are `[condition, then]`.
+    IfThen,
+    /// A condition and two regions, either of which is executed before they
+    /// join. The children are `[condition, nz_arm, z_arm]`, where the arms
+    /// are in the order of the branch destinations of the condition.
+    IfThenElse,
+    /// A region that branches back to itself.
+    /// The only child is the body.
+    SelfLoop,
+    /// A header that either exits or enters a body branching back to it.
+    /// The children are `[header, body]`.
+    WhileLoop,
+    /// A loop dominated by its header that doesn't match the other loop
+    /// patterns, e.g., a loop with multiple exits.
+    /// The header is the first child.
+    NaturalLoop,
+    /// A subgraph dominated by its first child that matches no other
+    /// patterns, e.g., an irreducible loop.
+    Proper,
+}
+
+impl RegionKind {
+    /// Returns `true` if the region owns the edges back to its entry.
+    fn is_cyclic(self) -> bool {
+        matches!(
+            self,
+            Self::SelfLoop | Self::WhileLoop | Self::NaturalLoop | Self::Proper
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RegionData {
+    kind: RegionKind,
+    children: Vec<Region>,
+    parent: PackedOption<Region>,
+}
+
+/// A CFG whose nodes are regions that are collapsed so far.
+struct Graph {
+    entry: Region,
+    nodes: Vec<Region>,
+    /// Successors and predecessors of each node, without duplicates.
+    succs: SecondaryMap<Region, Vec<Region>>,
+    preds: SecondaryMap<Region, Vec<Region>>,
+}
+
+impl Graph {
+    fn new(entry: Region) -> Self {
+        Self {
+            entry,
+            nodes: Vec::new(),
+            succs: SecondaryMap::default(),
+            preds: SecondaryMap::default(),
+        }
+    }
+
+    fn add_edge(&mut self, from: Region, to: Region) {
+        if !self.succs[from].contains(&to) {
+            self.succs[from].push(to);
+            self.preds[to].push(from);
+        }
+    }
+
+    fn post_order(&self) -> Vec<Region> {
+        let mut visited = SecondaryMap::<Region, bool>::default();
+        let mut order = Vec::with_capacity(self.nodes.len());
+
+        // Each frame holds a node and the position of the next successor to
+        // visit.
+        let mut frames = vec![(self.entry, 0)];
+        visited[self.entry] = true;
+        while let Some((node, next)) = frames.last_mut() {
+            let node = *node;
+            if let Some(&succ) = self.succs[node].get(*next) {
+                *next += 1;
+                if !visited[succ] {
+                    visited[succ] = true;
+                    frames.push((succ, 0));
+                }
+            } else {
+                order.push(node);
+                frames.pop();
+            }
+        }
+
+        order
+    }
+
+    /// Compute the immediate dominators of the nodes with the algorithm of
+    /// [`DomTree`](sonatina_ir::domtree::DomTree).
+    fn dominators(&self) -> Dominators {
+        let post_order = self.post_order();
+        let mut doms = Dominators {
+            entry: self.entry,
+            idoms: SecondaryMap::default(),
+            post_num: SecondaryMap::default(),
+        };
+        for (num, &node) in post_order.iter().enumerate() {
+            doms.post_num[node] = num;
+        }
+        doms.idoms[self.entry] = self.entry.into();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in post_order.iter().rev() {
+                if node == self.entry {
+                    continue;
+                }
+
+                let mut preds = self.preds[node]
+                    .iter()
+                    .copied()
+                    .filter(|&pred| doms.idoms[pred].is_some());
+                let Some(first) = preds.next() else {
+                    continue;
+                };
+                let idom = preds.fold(first, |idom, pred| doms.intersect(idom, pred));
+
+                if doms.idoms[node].expand() != Some(idom) {
+                    doms.idoms[node] = idom.into();
+                    changed = true;
+                }
+            }
+        }
+
+        doms
+    }
+
+    fn match_acyclic(&self, node: Region) -> Option<(RegionKind, Vec<Region>)> {
+        // Find the longest chain through the node.
+        let mut chain = vec![node];
+        while let [succ] = self.succs[*chain.last().unwrap()].as_slice() {
+            if !self.is_sequence_edge(*chain.last().unwrap(), *succ) || chain.contains(succ) {
+                break;
+            }
+            chain.push(*succ);
+        }
+        while let [pred] = self.preds[chain[0]].as_slice() {
+            if !self.is_sequence_edge(*pred, chain[0]) || chain.contains(pred) {
+                break;
+            }
+            chain.insert(0, *pred);
+        }
+        if chain.len() > 1 {
+            return Some((RegionKind::Sequence, chain));
+        }
+
+        let &[s, t] = self.succs[node].as_slice() else {
+            return None;
+        };
+        if self.is_arm(node, s)
+            && self.is_arm(node, t)
+            && self.succs[s] == self.succs[t]
+            && self.succs[s].len() <= 1
+        {
+            return Some((RegionKind::IfThenElse, vec![node, s, t]));
+        }
+        for (then, join) in [(s, t), (t, s)] {
+            if self.is_arm(node, then) && self.succs[then] == [join] {
+                return Some((RegionKind::IfThen, vec![node, then]));
+            }
+        }
+
+        None
+    }
+
+    fn match_cyclic(&self, node: Region, doms: &Dominators) -> Option<(RegionKind, Vec<Region>)> {
+        if self.succs[node].contains(&node) {
+            return Some((RegionKind::SelfLoop, vec![node]));
+        }
+
+        if let &[s, t] = self.succs[node].as_slice() {
+            for body in [s, t] {
+                if body != self.entry && self.preds[body] == [node] && self.succs[body] == [node] {
+                    return Some((RegionKind::WhileLoop, vec![node, body]));
+                }
+            }
+        }
+
+        // Collect the nodes that reach a back edge to the node without passing
+        // through it. They are all dominated by the node, so the loop has the
+        // node as its only entry.
+        let mut members = vec![node];
+        let mut worklist: Vec<_> = self.preds[node]
+            .iter()
+            .copied()
+            .filter(|&pred| doms.dominates(node, pred))
+            .collect();
+        if worklist.is_empty() {
+            return None;
+        }
+        while let Some(member) = worklist.pop() {
+            if !members.contains(&member) {
+                members.push(member);
+                worklist.extend_from_slice(&self.preds[member]);
+            }
+        }
+
+        Some((RegionKind::NaturalLoop, members))
+    }
+
+    /// Returns `true` if `from` and `to` can be merged into a sequence.
+    fn is_sequence_edge(&self, from: Region, to: Region) -> bool {
+        from != to && to != self.entry && self.succs[from].len() == 1 && self.preds[to].len() == 1
+    }
+
+    /// Returns `true` if the `arm` is entered only from the `cond` and doesn't
+    /// branch back to it.
+    fn is_arm(&self, cond: Region, arm: Region) -> bool {
+        arm != cond
+            && arm != self.entry
+            && self.preds[arm] == [cond]
+            && !self.succs[arm].contains(&cond)
+    }
+
+    /// Replace the `members` with the `region` whose entry is the first
+    /// member. Edges back to the entry are removed if the region is cyclic,
+    /// otherwise they become a self loop of the region.
+    fn collapse(&mut self, region: Region, members: &[Region], is_cyclic: bool) {
+        let header = members[0];
+        let mut succs = Vec::new();
+        for &member in members {
+            for &succ in &self.succs[member] {
+                let succ = if succ == header {
+                    if is_cyclic {
+                        continue;
+                    }
+                    region
+                } else if members.contains(&succ) {
+                    continue;
+                } else {
+                    succ
+                };
+                if !succs.contains(&succ) {
+                    succs.push(succ);
+                }
+            }
+        }
+
+        let preds: Vec<_> = self.preds[header]
+            .iter()
+            .copied()
+            .filter(|pred| !members.contains(pred))
+            .collect();
+
+        for &succ in &succs {
+            if succ != region {
+                replace_members(&mut self.preds[succ], members, region);
+            }
+        }
+        for &pred in &preds {
+            replace_members(&mut self.succs[pred], members, region);
+        }
+        for &member in members {
+            self.succs[member].clear();
+            self.preds[member].clear();
+        }
+
+        self.nodes.retain(|node| !members.contains(node));
+        self.nodes.push(region);
+        // The preds of the succs already point to the region.
+        for succ in succs {
+            self.succs[region].push(succ);
+            if !self.preds[succ].contains(&region) {
+                self.preds[succ].push(region);
+            }
+        }
+        for pred in preds {
+            if !self.preds[region].contains(&pred) {
+                self.preds[region].push(pred);
+            }
+        }
+        if members.contains(&self.entry) {
+            self.entry = region;
+        }
+    }
+}
+
+/// Replace the `members` in the `nodes` with the `region` without duplicates,
+/// keeping the order.
+fn replace_members(nodes: &mut Vec<Region>, members: &[Region], region: Region) {
+    let mut replaced = Vec::with_capacity(nodes.len());
+    for node in nodes.drain(..) {
+        let node = if members.contains(&node) {
+            region
+        } else {
+            node
+        };
+        if !replaced.contains(&node) {
+            replaced.push(node);
+        }
+    }
+    *nodes = replaced;
+}
+
+/// Immediate dominators of the nodes of a [`Graph`].
+struct Dominators {
+    entry: Region,
+    idoms: SecondaryMap<Region, PackedOption<Region>>,
+    post_num: SecondaryMap<Region, usize>,
+}
+
+impl Dominators {
+    fn dominates(&self, dom: Region, mut node: Region) -> bool {
+        loop {
+            if node == dom {
+                return true;
+            } else if node == self.entry {
+                return false;
+            }
+            node = self.idoms[node].unwrap();
+        }
+    }
+
+    fn intersect(&self, mut a: Region, mut b: Region) -> Region {
+        while a != b {
+            while self.post_num[a] < self.post_num[b] {
+                a = self.idoms[a].unwrap();
+            }
+            while self.post_num[b] < self.post_num[a] {
+                b = self.idoms[b].unwrap();
+            }
+        }
+        a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::control_flow::{Br, Jump, Return},
+        isa::Isa,
+        Function, Type,
+    };
+
+    use super::*;
+
+    fn calc_regions(func: &Function) -> StructuralAnalysis {
+        let mut cfg = ControlFlowGraph::default();
+        cfg.compute(func);
+        let mut sa = StructuralAnalysis::new();
+        sa.compute(func, &cfg);
+        sa
+    }
+
+    #[test]
+    fn if_else() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let entry_block = builder.append_block();
+        let then_block = builder.append_block();
+        let else_block = builder.append_block();
+        let merge_block = builder.append_block();
+
+        builder.switch_to_block(entry_block);
+        let v0 = builder.make_imm_value(true);
+        builder.insert_inst_no_result_with(|| Br::new(is, v0, else_block, then_block));
+
+        builder.switch_to_block(then_block);
+        builder.insert_inst_no_result_with(|| Jump::new(is, merge_block));
+
+        builder.switch_to_block(else_block);
+        builder.insert_inst_no_result_with(|| Jump::new(is, merge_block));
+
+        builder.switch_to_block(merge_block);
+        builder.insert_inst_no_result_with(|| Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let sa = module.func_store.view(func_ref, calc_regions);
+        let leaf = |block| sa.region_of(block).unwrap();
+
+        let root = sa.root().unwrap();
+        assert_eq!(sa.kind(root), RegionKind::Sequence);
+        let &[if_else, merge] = sa.children(root) else {
+            panic!("root must be a sequence of two regions");
+        };
+        assert_eq!(merge, leaf(merge_block));
+
+        assert_eq!(sa.kind(if_else), RegionKind::IfThenElse);
+        assert_eq!(sa.children(if_else)[0], leaf(entry_block));
+        assert_eq!(
+            sa.children(if_else)[1..],
+            [leaf(else_block), leaf(then_block)]
+        );
+
+        assert_eq!(sa.parent_of(if_else), Some(root));
+        assert_eq!(sa.entry_block_of(root), entry_block);
+    }
+
+    #[test]
+    fn while_loop() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let v0 = builder.make_imm_value(true);
+        builder.insert_inst_no_result_with(|| Br::new(is, v0, b3, b2));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b1));
+
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result_with(|| Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let sa = module.func_store.view(func_ref, calc_regions);
+        let leaf = |block| sa.region_of(block).unwrap();
+
+        let root = sa.root().unwrap();
+        assert_eq!(sa.kind(root), RegionKind::Sequence);
+        let &[entry, while_loop, exit] = sa.children(root) else {
+            panic!("root must be a sequence of three regions");
+        };
+        assert_eq!(entry, leaf(b0));
+        assert_eq!(exit, leaf(b3));
+        assert_eq!(sa.kind(while_loop), RegionKind::WhileLoop);
+        assert_eq!(sa.children(while_loop), [leaf(b1), leaf(b2)]);
+    }
+}
@@ -0,0 +1,109 @@
+//! This module contains a lightweight checker that finds insts that always trap
+//! once executed, so that front-ends can report obvious bugs as warnings.
+
+use sonatina_ir::{
+    domtree::DomTree,
+    inst::evm::{EvmInvalid, EvmSdiv, EvmSmod, EvmUdiv, EvmUmod},
+    BlockId, ControlFlowGraph, Function, InstDowncast, InstId, ValueId,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TrapKind {
+    /// A division or remainder by a constant zero. The EVM evaluates it to
+    /// zero instead of halting, which almost always hides a bug.
+    DivisionByZero,
+    /// An unreachable inst that is reachable from the entry block.
+    Unreachable,
+}
+
+/// Returns the insts that are guaranteed to trap when executed, in the layout
+/// order.
+///
+/// Insts in blocks unreachable from the entry block are never executed, so
+/// they are not reported.
+pub fn find_guaranteed_traps(func: &Function) -> Vec<(InstId, TrapKind)> {
+    let mut cfg = ControlFlowGraph::new();
+    let mut domtree = DomTree::new();
+    cfg.compute(func);
+    domtree.compute(&cfg);
+
+    let mut traps = Vec::new();
+    for block in func.layout.iter_block() {
+        if !is_reachable(&domtree, block) {
+            continue;
+        }
+
+        for inst in func.layout.iter_inst(block) {
+            if let Some(kind) = trap_kind(func, inst) {
+                traps.push((inst, kind));
+            }
+        }
+    }
+
+    traps
+}
+
+fn trap_kind(func: &Function, inst_id: InstId) -> Option<TrapKind> {
+    let is = func.inst_set();
+    let inst = func.dfg.inst(inst_id);
+
+    let divisor = if let Some(div) = <&EvmUdiv as InstDowncast>::downcast(is, inst) {
+        *div.rhs()
+    } else if let Some(div) = <&EvmSdiv as InstDowncast>::downcast(is, inst) {
+        *div.rhs()
+    } else if let Some(rem) = <&EvmUmod as InstDowncast>::downcast(is, inst) {
+        *rem.rhs()
+    } else if let Some(rem) = <&EvmSmod as InstDowncast>::downcast(is, inst) {
+        *rem.rhs()
+    } else if <&EvmInvalid as InstDowncast>::downcast(is, inst).is_some() {
+        return Some(TrapKind::Unreachable);
+    } else {
+        return None;
+    };
+
+    is_zero(func, divisor).then_some(TrapKind::DivisionByZero)
+}
+
+fn is_zero(func: &Function, value: ValueId) -> bool {
+    func.dfg.value_imm(value).is_some_and(|imm| imm.is_zero())
+}
+
+fn is_reachable(domtree: &DomTree, block: BlockId) -> bool {
+    domtree.entry() == Some(block) || domtree.is_reachable(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{builder::test_util::*, inst::control_flow::Return, prelude::*, Type};
+
+    use super::*;
+
+    #[test]
+    fn udiv_by_constant_zero() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let zero = builder.make_imm_value(0i32);
+        let one = builder.make_imm_value(1i32);
+        let v0 = builder.insert_inst(EvmUdiv::new(is, arg, one), Type::I32);
+        let v1 = builder.insert_inst(EvmUdiv::new(is, v0, zero), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let trapping = func.dfg.value_inst(v1).unwrap();
+            assert_eq!(
+                find_guaranteed_traps(func),
+                [(trapping, TrapKind::DivisionByZero)]
+            );
+        });
+    }
+}
@@ -0,0 +1,208 @@
+//! This module contains a block worklist for iterative dataflow analyses.
+//!
+//! A dataflow analysis converges in fewer iterations if blocks are processed
+//! after their predecessors (forward analyses) or after their successors
+//! (backward analyses) as far as possible. [`RpoWorklist`] always dequeues the
+//! pending block that comes first in RPO or postorder respectively.
+
+use std::collections::BTreeSet;
+
+use cranelift_entity::SecondaryMap;
+use sonatina_ir::{BlockId, ControlFlowGraph};
+
+/// The direction in which an analysis propagates facts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Facts flow from predecessors to successors, e.g., available
+    /// expressions. Blocks are dequeued in RPO.
+    Forward,
+    /// Facts flow from successors to predecessors, e.g., liveness. Blocks are
+    /// dequeued in postorder.
+    Backward,
+}
+
+#[derive(Debug, Default)]
+pub struct RpoWorklist {
+    /// Blocks reachable from the entry in the processing order.
+    order: Vec<BlockId>,
+    /// Position of each block in `order`.
+    position: SecondaryMap<BlockId, Option<u32>>,
+    pending: BTreeSet<u32>,
+}
+
+impl RpoWorklist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.order.clear();
+        self.position.clear();
+        self.pending.clear();
+    }
+
+    /// Compute the processing order for the `direction`, and push all blocks
+    /// reachable from the entry block.
+    pub fn compute(&mut self, cfg: &ControlFlowGraph, direction: Direction) {
+        self.clear();
+
+        self.order.extend_from_slice(cfg.order().post_order());
+        if direction == Direction::Forward {
+            self.order.reverse();
+        }
+
+        for (i, &block) in self.order.iter().enumerate() {
+            self.position[block] = Some(i as u32);
+        }
+        self.pending.extend(0..self.order.len() as u32);
+    }
+
+    /// Push the `block` unless it's already pending.
+    /// Blocks unreachable from the entry block are ignored.
+    pub fn push(&mut self, block: BlockId) {
+        if let Some(position) = self.position[block] {
+            self.pending.insert(position);
+        }
+    }
+
+    /// Pop the pending block that comes first in the processing order.
+    pub fn pop(&mut self) -> Option<BlockId> {
+        let position = self.pending.pop_first()?;
+        Some(self.order[position as usize])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Returns the blocks reachable from the entry block in the processing
+    /// order.
+    pub fn order(&self) -> &[BlockId] {
+        &self.order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashSet;
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::control_flow::{Br, Jump, Return},
+        prelude::*,
+        Function, Type, ValueId,
+    };
+
+    use super::*;
+
+    type LiveIns = SecondaryMap<BlockId, FxHashSet<ValueId>>;
+
+    /// Update the live-in set of the `block` and returns `true` if it changed.
+    /// The function defines no value other than its args, so the live-in set
+    /// is the values used in the block plus the live-out set.
+    fn transfer(
+        func: &Function,
+        cfg: &ControlFlowGraph,
+        live_ins: &mut LiveIns,
+        block: BlockId,
+    ) -> bool {
+        let mut live_in: FxHashSet<_> = cfg
+            .succs_of(block)
+            .flat_map(|&succ| live_ins[succ].iter().copied())
+            .collect();
+        for inst in func.layout.iter_inst(block) {
+            func.dfg.inst(inst).for_each_value(&mut |value| {
+                if func.dfg.value_imm(value).is_none() {
+                    live_in.insert(value);
+                }
+            });
+        }
+
+        if live_in == live_ins[block] {
+            false
+        } else {
+            live_ins[block] = live_in;
+            true
+        }
+    }
+
+    #[test]
+    fn liveness_converges_faster() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let a = builder.append_block();
+        let b = builder.append_block();
+        let c = builder.append_block();
+        let d = builder.append_block();
+        let e = builder.append_block();
+        let f = builder.append_block();
+        let g = builder.append_block();
+        let h = builder.append_block();
+        let i = builder.append_block();
+        let j = builder.append_block();
+        let k = builder.append_block();
+        let l = builder.append_block();
+        let m = builder.append_block();
+
+        let (cond, x) = (builder.args()[0], builder.args()[1]);
+        for (block, z_dest, nz_dest) in [
+            (a, c, b),
+            (b, g, d),
+            (c, h, e),
+            (d, g, f),
+            (e, h, c),
+            (f, k, i),
+            (l, m, b),
+        ] {
+            builder.switch_to_block(block);
+            builder.insert_inst_no_result(Br::new(is, cond, z_dest, nz_dest));
+        }
+        for (block, dest) in [(g, j), (h, m), (i, l), (j, i), (k, l)] {
+            builder.switch_to_block(block);
+            builder.insert_inst_no_result(Jump::new(is, dest));
+        }
+        builder.switch_to_block(m);
+        builder.insert_inst_no_result(Return::new(is, Some(x)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+
+            // Sweep all blocks in the layout order until nothing changes.
+            let mut naive = LiveIns::new();
+            let mut naive_visits = 0;
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for block in func.layout.iter_block() {
+                    changed |= transfer(func, &cfg, &mut naive, block);
+                    naive_visits += 1;
+                }
+            }
+
+            let mut worklist = RpoWorklist::new();
+            worklist.compute(&cfg, Direction::Backward);
+            let mut live_ins = LiveIns::new();
+            let mut visits = 0;
+            while let Some(block) = worklist.pop() {
+                visits += 1;
+                if transfer(func, &cfg, &mut live_ins, block) {
+                    for &pred in cfg.preds_of(block) {
+                        worklist.push(pred);
+                    }
+                }
+            }
+
+            for block in func.layout.iter_block() {
+                assert_eq!(live_ins[block], naive[block], "{block}");
+            }
+            assert!(visits < naive_visits, "{visits} >= {naive_visits}");
+        });
+    }
+}
@@ -0,0 +1,25 @@
+use std::path::{Path, PathBuf};
+
+use sonatina_codegen::optim::dvnt::DvntSolver;
+use sonatina_ir::{domtree::DomTree, ControlFlowGraph, Function};
+
+use super::{FuncTransform, FIXTURE_ROOT};
+
+#[derive(Default)]
+pub struct DvntTransform {
+    cfg: ControlFlowGraph,
+    domtree: DomTree,
+}
+
+impl FuncTransform for DvntTransform {
+    fn transform(&mut self, func: &mut Function) {
+        self.cfg.compute(func);
+        self.domtree.compute(&self.cfg);
+        let mut solver = DvntSolver::new();
+        solver.run(func, &self.domtree);
+    }
+
+    fn test_root(&self) -> PathBuf {
+        Path::new(FIXTURE_ROOT).join("dvnt")
+    }
+}
@@ -0,0 +1,25 @@
+use std::path::{Path, PathBuf};
+
+use sonatina_codegen::optim::hoist_common::HoistCommonSolver;
+use sonatina_ir::{domtree::DomTree, ControlFlowGraph, Function};
+
+use super::{FuncTransform, FIXTURE_ROOT};
+
+#[derive(Default)]
+pub struct HoistCommonTransform {
+    cfg: ControlFlowGraph,
+    domtree: DomTree,
+}
+
+impl FuncTransform for HoistCommonTransform {
+    fn transform(&mut self, func: &mut Function) {
+        self.cfg.compute(func);
+        self.domtree.compute(&self.cfg);
+        let mut solver = HoistCommonSolver::new();
+        solver.run(func, &self.cfg, &self.domtree);
+    }
+
+    fn test_root(&self) -> PathBuf {
+        Path::new(FIXTURE_ROOT).join("hoist_common")
+    }
+}
@@ -1,8 +1,12 @@
 pub mod adce;
+pub mod dvnt;
 pub mod gvn;
+pub mod hoist_common;
 pub mod insn_simplify;
 pub mod licm;
 pub mod sccp;
+pub mod simplify_cfg;
+pub mod sink;
 
 use std::{
     fs,
@@ -1,7 +1,7 @@
 use std::path::{Path, PathBuf};
 
-use sonatina_codegen::{domtree::DomTree, loop_analysis::LoopTree, optim::licm::LicmSolver};
-use sonatina_ir::{ControlFlowGraph, Function};
+use sonatina_codegen::{loop_analysis::LoopTree, optim::licm::LicmSolver};
+use sonatina_ir::{domtree::DomTree, ControlFlowGraph, Function};
 
 use super::{FuncTransform, FIXTURE_ROOT};
 
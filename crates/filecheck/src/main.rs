@@ -1,5 +1,7 @@
 use sonatina_filecheck::{
-    adce::AdceTransform, licm::LicmTransformer, sccp::SccpTransform, FileCheckRunner,
+    adce::AdceTransform, dvnt::DvntTransform, hoist_common::HoistCommonTransform,
+    licm::LicmTransformer, sccp::SccpTransform, simplify_cfg::SimplifyCfgTransform,
+    sink::SinkTransform, FileCheckRunner,
 };
 
 fn main() {
@@ -19,6 +21,18 @@ fn main() {
     runner.attach_transformer(LicmTransformer::default());
     runner.run();
 
+    runner.attach_transformer(SimplifyCfgTransform::default());
+    runner.run();
+
+    runner.attach_transformer(SinkTransform::default());
+    runner.run();
+
+    runner.attach_transformer(HoistCommonTransform::default());
+    runner.run();
+
+    runner.attach_transformer(DvntTransform::default());
+    runner.run();
+
     runner.print_results();
     if !runner.is_ok() {
         std::process::exit(101);
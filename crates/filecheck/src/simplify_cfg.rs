@@ -0,0 +1,23 @@
+use std::path::{Path, PathBuf};
+
+use sonatina_codegen::optim::simplify_cfg::SimplifyCfgSolver;
+use sonatina_ir::{ControlFlowGraph, Function};
+
+use super::{FuncTransform, FIXTURE_ROOT};
+
+#[derive(Default)]
+pub struct SimplifyCfgTransform {
+    cfg: ControlFlowGraph,
+}
+
+impl FuncTransform for SimplifyCfgTransform {
+    fn transform(&mut self, func: &mut Function) {
+        self.cfg.compute(func);
+        let mut solver = SimplifyCfgSolver::new();
+        solver.run(func, &mut self.cfg);
+    }
+
+    fn test_root(&self) -> PathBuf {
+        Path::new(FIXTURE_ROOT).join("simplify_cfg")
+    }
+}
@@ -0,0 +1,27 @@
+use std::path::{Path, PathBuf};
+
+use sonatina_codegen::{loop_analysis::LoopTree, optim::sink::SinkSolver};
+use sonatina_ir::{domtree::DomTree, ControlFlowGraph, Function};
+
+use super::{FuncTransform, FIXTURE_ROOT};
+
+#[derive(Default)]
+pub struct SinkTransform {
+    cfg: ControlFlowGraph,
+    domtree: DomTree,
+    lpt: LoopTree,
+}
+
+impl FuncTransform for SinkTransform {
+    fn transform(&mut self, func: &mut Function) {
+        self.cfg.compute(func);
+        self.domtree.compute(&self.cfg);
+        self.lpt.compute(&self.cfg, &self.domtree);
+        let mut solver = SinkSolver::new();
+        solver.run(func, &self.domtree, &self.lpt);
+    }
+
+    fn test_root(&self) -> PathBuf {
+        Path::new(FIXTURE_ROOT).join("sink")
+    }
+}
@@ -1,13 +1,86 @@
+use std::fmt;
+
 use cranelift_entity::SecondaryMap;
 use sonatina_ir::{
+    global_variable::GvInitializer,
     interpret::{Action, EvalValue, Interpret, State},
     isa::Endian,
     module::{FuncRef, ModuleCtx, RoFuncStore},
     prelude::*,
     types::CompoundType,
-    BlockId, DataFlowGraph, Function, Immediate, InstId, Module, Type, Value, ValueId, I256,
+    BlockId, DataFlowGraph, Function, GlobalVariableRef, Immediate, InstId, Module, Type, Value,
+    ValueId, I256,
 };
 
+/// The configuration of a [`Machine`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// The maximum number of frames on the call stack, including the frame of
+    /// the function passed to [`Machine::run`].
+    pub max_call_depth: usize,
+    /// If `true`, global variables keep the values stored by a run in the
+    /// following runs. Otherwise, each run starts with the global variables
+    /// set to their initializers.
+    pub persist_globals: bool,
+    /// The size of the memory in bytes. A load or store that accesses any
+    /// byte beyond it aborts the interpretation.
+    pub memory_limit: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_call_depth: 1024,
+            persist_globals: false,
+            memory_limit: 1 << 24,
+        }
+    }
+}
+
+/// An error that aborts the interpretation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterpretError {
+    /// The call stack grew deeper than [`Config::max_call_depth`].
+    /// `call_chain` holds the callee of each frame from the outermost one,
+    /// including the call that exceeded the limit.
+    CallDepthExceeded { call_chain: Vec<FuncRef> },
+    /// A load or store of `size` bytes at `addr` reached beyond
+    /// [`Config::memory_limit`].
+    MemoryOutOfBounds { addr: I256, size: usize },
+    /// A store through an undef address, e.g., a pointer loaded from memory
+    /// that has never been written.
+    UndefStoreAddress,
+    /// The control reached an unreachable point at `inst`, e.g., a `br_table`
+    /// without a default destination whose table doesn't cover the selector.
+    UnreachableReached { inst: InstId },
+}
+
+impl fmt::Display for InterpretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CallDepthExceeded { call_chain } => {
+                write!(f, "call depth exceeded the limit: ")?;
+                for (i, func_ref) in call_chain.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " -> ")?;
+                    }
+                    write!(f, "func{}", func_ref.as_u32())?;
+                }
+                Ok(())
+            }
+            Self::MemoryOutOfBounds { addr, size } => {
+                write!(f, "memory access out of bounds: {size} bytes at {addr}")
+            }
+            Self::UndefStoreAddress => write!(f, "store to an undef address"),
+            Self::UnreachableReached { inst } => {
+                write!(f, "unreachable reached at inst{}", inst.as_u32())
+            }
+        }
+    }
+}
+
+impl std::error::Error for InterpretError {}
+
 pub struct Machine {
     frames: Vec<Frame>,
     pc: InstId,
@@ -16,10 +89,18 @@ pub struct Machine {
     pub module_ctx: ModuleCtx,
     memory: Vec<u8>,
     free_region: usize,
+    config: Config,
+    error: Option<InterpretError>,
+    /// Addresses of the global variables allocated in the memory.
+    globals: SecondaryMap<GlobalVariableRef, Option<usize>>,
 }
 
 impl Machine {
     pub fn new(module: Module) -> Self {
+        Self::with_config(module, Config::default())
+    }
+
+    pub fn with_config(module: Module, config: Config) -> Self {
         Self {
             frames: Vec::new(),
             // Dummy pc
@@ -29,20 +110,156 @@ impl Machine {
             module_ctx: module.ctx,
             memory: Vec::new(),
             free_region: 0,
+            config,
+            error: None,
+            globals: SecondaryMap::default(),
         }
     }
 
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Runs the function and returns its result.
+    ///
+    /// # Panics
+    /// Panics if the interpretation is aborted, see [`Self::try_run`].
     pub fn run(&mut self, func_ref: FuncRef, args: Vec<EvalValue>) -> EvalValue {
-        let func = self.funcs.get(&func_ref).unwrap();
-        let frame = Frame::new(func_ref, func, args);
-        self.frames.push(frame);
+        self.try_run(func_ref, args)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Runs the function and returns its result, or the error that aborted the
+    /// interpretation.
+    pub fn try_run(
+        &mut self,
+        func_ref: FuncRef,
+        args: Vec<EvalValue>,
+    ) -> Result<EvalValue, InterpretError> {
         self.action = Action::Continue;
-        self.run_on_func()
+        if !self.config.persist_globals {
+            // Global variables are allocated and initialized again on their
+            // first access.
+            self.globals.clear();
+        }
+        let result = if self.push_frame(func_ref, args) {
+            self.run_on_func()
+        } else {
+            EvalValue::Undef
+        };
+
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(result),
+        }
+    }
+
+    /// Returns the memory as it was left by the last run. Bytes that have
+    /// never been written are zero.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
     }
 
     pub fn clear_state(&mut self) {
         self.frames.clear();
         self.memory.clear();
+        self.error = None;
+        self.globals.clear();
+    }
+
+    /// Returns the address of the global variable, allocating it in the memory
+    /// and storing its initializer on the first access.
+    fn global_addr(&mut self, gv: GlobalVariableRef) -> usize {
+        if let Some(addr) = self.globals[gv] {
+            return addr;
+        }
+
+        let (ty, init) = self
+            .module_ctx
+            .with_gv_store(|s| (s.ty(gv), s.init_data(gv).cloned()));
+        let addr = self.free_region;
+        let size = self.module_ctx.size_of_unchecked(ty);
+        self.free_region += size;
+        if self.memory.len() < addr + size {
+            // A global variable without initializer is zero-initialized.
+            self.memory.resize(addr + size, 0);
+        }
+
+        if let Some(init) = init {
+            let value = self.eval_gv_initializer(&init, ty);
+            let addr_value = EvalValue::Imm(Immediate::I256(I256::from(addr)));
+            self.store(addr_value, value, ty);
+        }
+
+        self.globals[gv] = Some(addr);
+        addr
+    }
+
+    fn eval_gv_initializer(&self, init: &GvInitializer, ty: Type) -> EvalValue {
+        match init {
+            GvInitializer::Immediate(imm) => EvalValue::Imm(*imm),
+            GvInitializer::Array(elems) => {
+                let Some(CompoundType::Array { elem, .. }) = ty.resolve_compound(&self.module_ctx)
+                else {
+                    unreachable!()
+                };
+                let fields = elems
+                    .iter()
+                    .map(|init| self.eval_gv_initializer(init, elem))
+                    .collect();
+                EvalValue::Aggregate { fields, ty }
+            }
+            GvInitializer::Struct(elems) => {
+                let Some(CompoundType::Struct(s)) = ty.resolve_compound(&self.module_ctx) else {
+                    unreachable!()
+                };
+                let fields = elems
+                    .iter()
+                    .zip(s.fields)
+                    .map(|(init, field_ty)| self.eval_gv_initializer(init, field_ty))
+                    .collect();
+                EvalValue::Aggregate { fields, ty }
+            }
+        }
+    }
+
+    /// Returns `addr` as an offset in the memory if `size` bytes from it are
+    /// within [`Config::memory_limit`]. Otherwise, returns `None` and records
+    /// the error.
+    ///
+    /// EVM has no alignment requirement, so any address is accepted as long
+    /// as the access is in bounds.
+    fn checked_offset(&mut self, addr: Immediate, size: usize) -> Option<usize> {
+        let addr = addr.zext(Type::I256).as_i256();
+        let end = addr.checked_add(I256::from_usize(size));
+        match end {
+            Some(end)
+                if !addr.is_negative() && end <= I256::from_usize(self.config.memory_limit) =>
+            {
+                Some(addr.to_u256().as_usize())
+            }
+            _ => {
+                self.error = Some(InterpretError::MemoryOutOfBounds { addr, size });
+                None
+            }
+        }
+    }
+
+    /// Pushes a new frame for the `func_ref`.
+    /// Returns `false` and records the error if the call depth exceeds the
+    /// limit.
+    fn push_frame(&mut self, func_ref: FuncRef, args: Vec<EvalValue>) -> bool {
+        if self.frames.len() >= self.config.max_call_depth {
+            let mut call_chain: Vec<_> = self.frames.iter().map(|frame| frame.func).collect();
+            call_chain.push(func_ref);
+            self.error = Some(InterpretError::CallDepthExceeded { call_chain });
+            return false;
+        }
+
+        let func = self.funcs.get(&func_ref).unwrap();
+        let frame = Frame::new(func_ref, func, args);
+        self.frames.push(frame);
+        true
     }
 
     fn top_frame(&self) -> &Frame {
@@ -73,6 +290,10 @@ impl Machine {
             };
 
             let e_val = interpretable.interpret(self);
+            if self.error.is_some() {
+                // Unwind the call stack.
+                return EvalValue::Undef;
+            }
             if let Some(inst_result) = self.top_func().dfg.inst_result(self.pc) {
                 self.top_frame_mut().map_val(inst_result, e_val);
             };
@@ -92,6 +313,11 @@ impl Machine {
                     panic!("fall through detected!")
                 }
 
+                Action::UnreachableReached => {
+                    self.error = Some(InterpretError::UnreachableReached { inst: self.pc });
+                    return EvalValue::Undef;
+                }
+
                 Action::Return(e_val) => return e_val,
             }
         }
@@ -129,12 +355,23 @@ impl Frame {
 
 impl State for Machine {
     fn lookup_val(&mut self, value_id: ValueId) -> EvalValue {
+        if let Value::Global { gv, .. } = *self.top_func().dfg.value(value_id) {
+            let addr = self.global_addr(gv);
+            return EvalValue::Imm(Immediate::I256(I256::from(addr)));
+        }
+
         let value = self.top_func().dfg.value(value_id);
         match value {
             Value::Immediate { imm, .. } => (*imm).into(),
-            Value::Global { .. } => {
-                todo!()
+            Value::Global { .. } => unreachable!(),
+            // An undef value may be any value, so the interpreter just picks zero
+            // for an integer or a pointer. An undef aggregate is left as is, and
+            // its fields are treated as undef.
+            Value::Undef { ty } if ty.is_integral() => Immediate::zero(*ty).into(),
+            Value::Undef { ty } if ty.is_pointer(&self.module_ctx) => {
+                EvalValue::Imm(Immediate::I256(I256::zero()))
             }
+            Value::Undef { .. } => EvalValue::Undef,
             _ => self.top_frame().locals[value_id].clone(),
         }
     }
@@ -142,9 +379,9 @@ impl State for Machine {
     fn call_func(&mut self, func_ref: FuncRef, args: Vec<EvalValue>) -> EvalValue {
         let ret_addr = self.pc;
 
-        let func = self.funcs.get(&func_ref).unwrap();
-        let new_frame = Frame::new(func_ref, func, args);
-        self.frames.push(new_frame);
+        if !self.push_frame(func_ref, args) {
+            return EvalValue::Undef;
+        }
 
         let result = self.run_on_func();
 
@@ -168,8 +405,10 @@ impl State for Machine {
             return EvalValue::Undef;
         };
 
-        let addr = addr.as_usize();
         let size = self.module_ctx.size_of_unchecked(ty);
+        let Some(addr) = self.checked_offset(addr, size) else {
+            return EvalValue::Undef;
+        };
         if addr + size > self.memory.len() {
             return EvalValue::Undef;
         }
@@ -229,10 +468,13 @@ impl State for Machine {
         }
 
         let Some(addr) = addr.as_imm() else {
-            panic!("udnef address in store")
+            self.error = Some(InterpretError::UndefStoreAddress);
+            return EvalValue::Undef;
         };
-        let addr = addr.as_usize();
         let size = self.module_ctx.size_of_unchecked(ty);
+        let Some(addr) = self.checked_offset(addr, size) else {
+            return EvalValue::Undef;
+        };
         if addr + size > self.memory.len() {
             self.memory.resize(addr + size, 0);
         }
@@ -248,7 +490,7 @@ impl State for Machine {
                     let elem_size = self.module_ctx.size_of_unchecked(elem_ty);
                     for field in &fields {
                         let elem_addr = EvalValue::Imm(Immediate::I256(I256::from(addr)));
-                        self.store(field.clone(), elem_addr, elem_ty);
+                        self.store(elem_addr, field.clone(), elem_ty);
                         addr += elem_size;
                     }
                 }
@@ -257,7 +499,7 @@ impl State for Machine {
                     let mut addr = addr;
                     for (i, field_ty) in s.fields.into_iter().enumerate() {
                         let elem_addr = EvalValue::Imm(Immediate::I256(I256::from(addr)));
-                        self.store(fields[i].clone(), elem_addr, field_ty);
+                        self.store(elem_addr, fields[i].clone(), field_ty);
                         addr += self.module_ctx.size_of_unchecked(field_ty);
                     }
                 }
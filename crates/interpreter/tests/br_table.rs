@@ -0,0 +1,54 @@
+//! Tests that a `br_table` whose table doesn't cover the selector traps.
+
+use sonatina_interpreter::{InterpretError, Machine};
+use sonatina_ir::{
+    builder::test_util::*,
+    inst::control_flow::{BrTable, Return},
+    interpret::EvalValue,
+    isa::Isa,
+    Immediate, Type,
+};
+
+#[test]
+fn uncovered_selector() {
+    let mb = test_module_builder();
+    let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+    let is = evm.inst_set();
+
+    let b0 = builder.append_block();
+    let b1 = builder.append_block();
+    let b2 = builder.append_block();
+    let arg = builder.args()[0];
+
+    builder.switch_to_block(b0);
+    let one = builder.make_imm_value(1i32);
+    let two = builder.make_imm_value(2i32);
+    let table = vec![(one, b1), (two, b2)];
+    builder.insert_inst_no_result(BrTable::new(is, arg, None, table));
+    let br_table = builder.last_inst().unwrap();
+
+    for (block, value) in [(b1, 10i32), (b2, 20i32)] {
+        builder.switch_to_block(block);
+        let value = builder.make_imm_value(value);
+        builder.insert_inst_no_result(Return::new(is, Some(value)));
+    }
+
+    builder.seal_all();
+    builder.finish();
+
+    let module = mb.build();
+    let func_ref = module.funcs()[0];
+    let mut machine = Machine::new(module);
+
+    let arg = |value| vec![EvalValue::Imm(Immediate::I32(value))];
+    assert_eq!(
+        machine.try_run(func_ref, arg(2)),
+        Ok(EvalValue::Imm(Immediate::I32(20)))
+    );
+    machine.clear_state();
+
+    assert_eq!(
+        machine.try_run(func_ref, arg(3)),
+        Err(InterpretError::UnreachableReached { inst: br_table })
+    );
+}
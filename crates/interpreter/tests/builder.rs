@@ -0,0 +1,66 @@
+//! Tests that IR emitted by builder helpers is evaluated as intended.
+
+use sonatina_interpreter::Machine;
+use sonatina_ir::{
+    builder::{test_util::*, FunctionBuilder, ModuleBuilder},
+    func_cursor::InstInserter,
+    inst::control_flow::Return,
+    interpret::EvalValue,
+    isa::Isa,
+    module::FuncRef,
+    Immediate, Linkage, Signature, Type, ValueId,
+};
+
+/// Declares a function that returns `in_range(x, lo, hi)`, where `x` is the
+/// first argument and the bounds are made by `bounds`.
+fn declare_in_range<F>(mb: &ModuleBuilder, name: &str, args: &[Type], bounds: F) -> FuncRef
+where
+    F: FnOnce(&mut FunctionBuilder<InstInserter>) -> (ValueId, ValueId),
+{
+    let sig = Signature::new(name, Linkage::Public, args, Type::I1);
+    let func_ref = mb.declare_function(sig);
+    let mut builder = mb.func_builder::<InstInserter>(func_ref);
+    let is = test_isa().inst_set();
+
+    let b0 = builder.append_block();
+    builder.switch_to_block(b0);
+    let x = builder.args()[0];
+    let (lo, hi) = bounds(&mut builder);
+    let result = builder.in_range(x, lo, hi);
+    builder.insert_inst_no_result(Return::new(is, Some(result)));
+
+    builder.seal_all();
+    builder.finish();
+    func_ref
+}
+
+#[test]
+fn in_range_matches_naive_check() {
+    let mb = test_module_builder();
+    // Lowered to `(x - 10) < 10`.
+    let imm_bounds = declare_in_range(&mb, "imm_bounds", &[Type::I32], |builder| {
+        (builder.make_imm_value(10i32), builder.make_imm_value(20i32))
+    });
+    // Lowered to `x >= lo && x < hi`.
+    let arg_bounds = declare_in_range(
+        &mb,
+        "arg_bounds",
+        &[Type::I32, Type::I32, Type::I32],
+        |builder| (builder.args()[1], builder.args()[2]),
+    );
+    let mut machine = Machine::new(mb.build());
+
+    let imm = |value: i32| EvalValue::Imm(Immediate::I32(value));
+    for x in [i32::MIN, -1, 0, 9, 10, 11, 19, 20, 21, i32::MAX] {
+        let expected = EvalValue::Imm(Immediate::I1((10..20).contains(&(x as u32))));
+
+        assert_eq!(machine.run(imm_bounds, vec![imm(x)]), expected, "x = {x}");
+        machine.clear_state();
+        assert_eq!(
+            machine.run(arg_bounds, vec![imm(x), imm(10), imm(20)]),
+            expected,
+            "x = {x}"
+        );
+        machine.clear_state();
+    }
+}
@@ -0,0 +1,44 @@
+//! Tests that the interpreter aborts a too deep recursion.
+
+use sonatina_interpreter::{Config, InterpretError, Machine};
+use sonatina_ir::{
+    builder::test_util::*,
+    func_cursor::InstInserter,
+    inst::control_flow::{Call, Return},
+    interpret::EvalValue,
+    isa::Isa,
+    Immediate, Linkage, Signature, Type,
+};
+
+#[test]
+fn self_recursion_exceeds_limit() {
+    let mb = test_module_builder();
+    let sig = Signature::new("recurse", Linkage::Public, &[Type::I32], Type::I32);
+    let func_ref = mb.declare_function(sig);
+    let mut builder = mb.func_builder::<InstInserter>(func_ref);
+    let is = test_isa().inst_set();
+
+    let b0 = builder.append_block();
+    builder.switch_to_block(b0);
+    let arg = builder.args()[0];
+    let v0 = builder.insert_inst(Call::new(is, func_ref, vec![arg].into()), Type::I32);
+    builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+    builder.seal_all();
+    builder.finish();
+
+    let config = Config {
+        max_call_depth: 16,
+        ..Config::default()
+    };
+    let mut machine = Machine::with_config(mb.build(), config);
+
+    let err = machine
+        .try_run(func_ref, vec![EvalValue::Imm(Immediate::I32(0))])
+        .unwrap_err();
+    let InterpretError::CallDepthExceeded { call_chain } = err else {
+        panic!("unexpected error: {err}");
+    };
+    assert_eq!(call_chain.len(), 17);
+    assert!(call_chain.iter().all(|&callee| callee == func_ref));
+}
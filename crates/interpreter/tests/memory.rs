@@ -0,0 +1,130 @@
+//! Tests of the memory model of the interpreter.
+
+use sonatina_interpreter::{Config, InterpretError, Machine};
+use sonatina_ir::{
+    builder::test_util::*,
+    inst::{
+        cast::IntToPtr,
+        control_flow::Return,
+        data::{Mload, Mstore},
+    },
+    interpret::EvalValue,
+    isa::Isa,
+    module::FuncRef,
+    Immediate, Module, Type, I256,
+};
+
+/// Build a function that stores its second argument at the address of its
+/// first argument as `i32`, and loads it back.
+fn build_store_load() -> (Module, FuncRef) {
+    let mb = test_module_builder();
+    let (evm, mut builder) = test_func_builder(&mb, &[Type::I256, Type::I32], Type::I32);
+    let is = evm.inst_set();
+
+    let b0 = builder.append_block();
+    builder.switch_to_block(b0);
+    let (addr, value) = (builder.args()[0], builder.args()[1]);
+    let ptr_ty = mb.ptr_type(Type::I32);
+    let ptr = builder.insert_inst(IntToPtr::new(is, addr, ptr_ty), ptr_ty);
+    builder.insert_inst_no_result(Mstore::new(is, ptr, value, Type::I32));
+    let loaded = builder.insert_inst(Mload::new(is, ptr, Type::I32), Type::I32);
+    builder.insert_inst_no_result(Return::new(is, Some(loaded)));
+
+    builder.seal_all();
+    builder.finish();
+
+    let module = mb.build();
+    let func_ref = module.funcs()[0];
+    (module, func_ref)
+}
+
+fn args(addr: I256, value: i32) -> Vec<EvalValue> {
+    vec![
+        EvalValue::Imm(Immediate::I256(addr)),
+        EvalValue::Imm(Immediate::I32(value)),
+    ]
+}
+
+#[test]
+fn store_load_round_trip() {
+    let (module, func_ref) = build_store_load();
+    let mut machine = Machine::new(module);
+
+    let result = machine.run(func_ref, args(I256::from(8u8), 0x1234_5678));
+    assert_eq!(result, EvalValue::Imm(Immediate::I32(0x1234_5678)));
+
+    // EVM is big endian.
+    let memory = machine.memory();
+    assert_eq!(memory.len(), 12);
+    assert_eq!(memory[..8], [0; 8]);
+    assert_eq!(memory[8..], [0x12, 0x34, 0x56, 0x78]);
+}
+
+#[test]
+fn out_of_bounds_access() {
+    let (module, func_ref) = build_store_load();
+    let config = Config {
+        memory_limit: 64,
+        ..Config::default()
+    };
+    let mut machine = Machine::with_config(module, config);
+
+    let result = machine.try_run(func_ref, args(I256::from(60u8), 1));
+    assert_eq!(result, Ok(EvalValue::Imm(Immediate::I32(1))));
+
+    let err = machine
+        .try_run(func_ref, args(I256::from(61u8), 1))
+        .unwrap_err();
+    assert_eq!(
+        err,
+        InterpretError::MemoryOutOfBounds {
+            addr: I256::from(61u8),
+            size: 4,
+        }
+    );
+    assert!(machine.memory().len() <= 64);
+
+    // An address that doesn't fit in `usize` traps instead of overflowing.
+    let err = machine
+        .try_run(func_ref, args(I256::all_one(), 1))
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        InterpretError::MemoryOutOfBounds { size: 4, .. }
+    ));
+}
+
+#[test]
+fn store_to_undef_address() {
+    let mb = test_module_builder();
+    let (evm, mut builder) = test_func_builder(&mb, &[Type::I256], Type::Unit);
+    let is = evm.inst_set();
+
+    // The pointer is loaded from memory that has never been written, so it's
+    // undef.
+    let b0 = builder.append_block();
+    builder.switch_to_block(b0);
+    let addr = builder.args()[0];
+    let i32_ptr = mb.ptr_type(Type::I32);
+    let ptr_ptr = mb.ptr_type(i32_ptr);
+    let ptr = builder.insert_inst(IntToPtr::new(is, addr, ptr_ptr), ptr_ptr);
+    let loaded = builder.insert_inst(Mload::new(is, ptr, i32_ptr), i32_ptr);
+    let one = builder.make_imm_value(1i32);
+    builder.insert_inst_no_result(Mstore::new(is, loaded, one, Type::I32));
+    builder.insert_inst_no_result(Return::new(is, None));
+
+    builder.seal_all();
+    builder.finish();
+
+    let module = mb.build();
+    let func_ref = module.funcs()[0];
+    let mut machine = Machine::new(module);
+
+    let err = machine
+        .try_run(
+            func_ref,
+            vec![EvalValue::Imm(Immediate::I256(I256::from(8u8)))],
+        )
+        .unwrap_err();
+    assert_eq!(err, InterpretError::UndefStoreAddress);
+}
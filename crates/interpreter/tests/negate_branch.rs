@@ -0,0 +1,91 @@
+//! Tests that negating a branch swaps its destinations without changing the
+//! semantics of a function.
+
+use sonatina_interpreter::Machine;
+use sonatina_ir::{
+    builder::test_util::*,
+    inst::{
+        arith::{Add, Sub},
+        cast::Zext,
+        cmp::Slt,
+        control_flow::{Br, Jump, Phi, Return},
+    },
+    interpret::EvalValue,
+    isa::Isa,
+    Immediate, InstDowncast, Module, Type,
+};
+
+/// Builds `f(a, b) = a < b ? a + b : a - b`. If `reuse_cond` is `true`, the
+/// condition is also added to the result, so that it has a user other than
+/// the branch.
+fn build_module(reuse_cond: bool) -> Module {
+    let mb = test_module_builder();
+    let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, Type::I32], Type::I32);
+    let is = evm.inst_set();
+
+    let b0 = builder.append_block();
+    let b1 = builder.append_block();
+    let b2 = builder.append_block();
+    let b3 = builder.append_block();
+
+    builder.switch_to_block(b0);
+    let (a, b) = (builder.args()[0], builder.args()[1]);
+    let cond = builder.insert_inst(Slt::new(is, a, b), Type::I1);
+    builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+    builder.switch_to_block(b1);
+    let v1 = builder.insert_inst(Add::new(is, a, b), Type::I32);
+    builder.insert_inst_no_result(Jump::new(is, b3));
+
+    builder.switch_to_block(b2);
+    let v2 = builder.insert_inst(Sub::new(is, a, b), Type::I32);
+    builder.insert_inst_no_result(Jump::new(is, b3));
+
+    builder.switch_to_block(b3);
+    let mut result = builder.insert_inst(Phi::new(is, vec![(v1, b1), (v2, b2)]), Type::I32);
+    if reuse_cond {
+        let ext = builder.insert_inst(Zext::new(is, cond, Type::I32), Type::I32);
+        result = builder.insert_inst(Add::new(is, result, ext), Type::I32);
+    }
+    builder.insert_inst_no_result(Return::new(is, Some(result)));
+
+    builder.seal_all();
+    builder.finish();
+    mb.build()
+}
+
+#[test]
+fn negate_branch() {
+    for reuse_cond in [false, true] {
+        let module = build_module(reuse_cond);
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let b0 = func.layout.entry_block().unwrap();
+            let term = func.layout.last_inst_of(b0).unwrap();
+            let old_dests = func.dfg.branch_info(term).unwrap().dests();
+
+            let cond = func.negate_branch(b0);
+            let cond_inst = func.dfg.value_inst(cond).unwrap();
+            let expected = if reuse_cond { "is_zero" } else { "sge" };
+            assert_eq!(func.dfg.inst(cond_inst).as_text(), expected);
+
+            let br = <&Br as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(term)).unwrap();
+            assert_eq!(*br.cond(), cond);
+            assert_eq!(*br.nz_dest(), old_dests[1]);
+            assert_eq!(*br.z_dest(), old_dests[0]);
+        });
+
+        let mut negated = Machine::new(module);
+        let mut original = Machine::new(build_module(reuse_cond));
+        let imm = |value: i32| EvalValue::Imm(Immediate::I32(value));
+        for (a, b) in [(1, 2), (2, 1), (-5, 7), (0, 0)] {
+            assert_eq!(
+                negated.run(func_ref, vec![imm(a), imm(b)]),
+                original.run(func_ref, vec![imm(a), imm(b)]),
+                "a = {a}, b = {b}, reuse_cond = {reuse_cond}"
+            );
+            negated.clear_state();
+            original.clear_state();
+        }
+    }
+}
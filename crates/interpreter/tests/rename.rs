@@ -0,0 +1,72 @@
+//! Tests that renaming values doesn't change the semantics of a function.
+
+use sonatina_interpreter::Machine;
+use sonatina_ir::{
+    builder::test_util::*,
+    inst::{
+        arith::{Add, Mul, Sub},
+        cmp::Slt,
+        control_flow::{Br, Jump, Phi, Return},
+    },
+    interpret::EvalValue,
+    isa::Isa,
+    Immediate, Module, Type, ValueId,
+};
+
+fn build_module() -> Module {
+    let mb = test_module_builder();
+    let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, Type::I32], Type::I32);
+    let is = evm.inst_set();
+
+    let b0 = builder.append_block();
+    let b1 = builder.append_block();
+    let b2 = builder.append_block();
+    let b3 = builder.append_block();
+
+    builder.switch_to_block(b0);
+    let (a, b) = (builder.args()[0], builder.args()[1]);
+    let cond = builder.insert_inst(Slt::new(is, a, b), Type::I1);
+    builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+    builder.switch_to_block(b1);
+    let v1 = builder.insert_inst(Add::new(is, a, b), Type::I32);
+    builder.insert_inst_no_result(Jump::new(is, b3));
+
+    builder.switch_to_block(b2);
+    let v2 = builder.insert_inst(Sub::new(is, a, b), Type::I32);
+    builder.insert_inst_no_result(Jump::new(is, b3));
+
+    builder.switch_to_block(b3);
+    let v3 = builder.insert_inst(Phi::new(is, vec![(v1, b1), (v2, b2)]), Type::I32);
+    let three = builder.make_imm_value(3i32);
+    let v4 = builder.insert_inst(Mul::new(is, v3, three), Type::I32);
+    builder.insert_inst_no_result(Return::new(is, Some(v4)));
+
+    builder.seal_all();
+    builder.finish();
+    mb.build()
+}
+
+#[test]
+fn rename_through_permutation() {
+    let module = build_module();
+    let func_ref = module.funcs()[0];
+    module.func_store.modify(func_ref, |func| {
+        // Reverse the order of all values, which swaps them pairwise.
+        let value_num = func.dfg.values.len();
+        func.rename_values(|value| ValueId::from_u32((value_num - 1) as u32 - value.as_u32()));
+    });
+
+    let mut renamed = Machine::new(module);
+    let mut original = Machine::new(build_module());
+    let imm = |value: i32| EvalValue::Imm(Immediate::I32(value));
+    for (a, b) in [(1, 2), (2, 1), (-5, 7), (0, 0)] {
+        assert_eq!(
+            renamed.run(func_ref, vec![imm(a), imm(b)]),
+            original.run(func_ref, vec![imm(a), imm(b)]),
+            "a = {a}, b = {b}"
+        );
+        renamed.clear_state();
+        original.clear_state();
+    }
+}
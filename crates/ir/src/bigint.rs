@@ -16,21 +16,43 @@ const I256_MASK: U256 = primitive_types::U256([
 ]);
 
 impl I256 {
+    /// Returns the sum wrapping around at the boundary of the type, and
+    /// whether a signed overflow occurred.
     pub fn overflowing_add(self, rhs: Self) -> (Self, bool) {
-        let (val, flag) = self.to_u256().overflowing_add(rhs.to_u256());
-        (Self::from_u256(val), flag)
+        let val = Self::from_u256(self.to_u256().overflowing_add(rhs.to_u256()).0);
+        let overflow = self.is_negative == rhs.is_negative && val.is_negative != self.is_negative;
+        (val, overflow)
     }
 
+    /// Returns the difference wrapping around at the boundary of the type, and
+    /// whether a signed overflow occurred.
     pub fn overflowing_sub(self, rhs: Self) -> (Self, bool) {
-        let (val, flag) = self.to_u256().overflowing_sub(rhs.to_u256());
-        (Self::from_u256(val), flag)
+        let val = Self::from_u256(self.to_u256().overflowing_sub(rhs.to_u256()).0);
+        let overflow = self.is_negative != rhs.is_negative && val.is_negative != self.is_negative;
+        (val, overflow)
     }
 
+    /// Returns the product wrapping around at the boundary of the type, and
+    /// whether a signed overflow occurred.
     pub fn overflowing_mul(self, rhs: Self) -> (Self, bool) {
-        let (val, flag) = self.to_u256().overflowing_mul(rhs.to_u256());
-        (Self::from_u256(val), flag)
+        let val = Self::from_u256(self.to_u256().overflowing_mul(rhs.to_u256()).0);
+
+        // The absolute value of the minimum is one larger than the maximum.
+        let (abs, abs_overflow) = self.abs.overflowing_mul(rhs.abs);
+        let limit = if self.is_negative != rhs.is_negative {
+            U256::one() << 255
+        } else {
+            (U256::one() << 255) - U256::one()
+        };
+        (val, abs_overflow || abs > limit)
     }
 
+    /// Returns the quotient rounded toward zero, and whether a signed overflow
+    /// occurred, which only happens for `MIN / -1`.
+    ///
+    /// # Panics
+    /// Panics if `rhs` is zero. See [`Self::checked_div`] for a non-panicking
+    /// version.
     pub fn overflowing_div(self, rhs: I256) -> (I256, bool) {
         if rhs.is_zero() {
             panic!("attempt to divide by zero");
@@ -44,10 +66,16 @@ impl I256 {
 
         match (self.is_negative, rhs.is_negative) {
             (true, true) | (false, false) => (I256::make_positive(div_abs), false),
-            _ => (I256::make_negative(div_abs), false),
+            _ => (I256::make_positive(div_abs).wrapping_neg(), false),
         }
     }
 
+    /// Returns the remainder whose sign is the same as `self`, and whether a
+    /// signed overflow occurred, which only happens for `MIN % -1`.
+    ///
+    /// # Panics
+    /// Panics if `rhs` is zero. See [`Self::checked_rem`] for a non-panicking
+    /// version.
     pub fn overflowing_rem(self, rhs: I256) -> (I256, bool) {
         if rhs.is_zero() {
             panic!("attempt to divide by zero");
@@ -60,11 +88,78 @@ impl I256 {
         let rem_abs = self.abs % rhs.abs;
 
         match self.is_negative {
-            true => (I256::make_negative(rem_abs), false),
+            true => (I256::make_positive(rem_abs).wrapping_neg(), false),
             false => (I256::make_positive(rem_abs), false),
         }
     }
 
+    pub fn wrapping_add(self, rhs: Self) -> Self {
+        self.overflowing_add(rhs).0
+    }
+
+    pub fn wrapping_sub(self, rhs: Self) -> Self {
+        self.overflowing_sub(rhs).0
+    }
+
+    pub fn wrapping_mul(self, rhs: Self) -> Self {
+        self.overflowing_mul(rhs).0
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        Self::checked(self.overflowing_add(rhs))
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        Self::checked(self.overflowing_sub(rhs))
+    }
+
+    pub fn checked_mul(self, rhs: Self) -> Option<Self> {
+        Self::checked(self.overflowing_mul(rhs))
+    }
+
+    /// Returns `None` if `rhs` is zero or the division overflows.
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        if rhs.is_zero() {
+            None
+        } else {
+            Self::checked(self.overflowing_div(rhs))
+        }
+    }
+
+    /// Returns `None` if `rhs` is zero or the division overflows.
+    pub fn checked_rem(self, rhs: Self) -> Option<Self> {
+        if rhs.is_zero() {
+            None
+        } else {
+            Self::checked(self.overflowing_rem(rhs))
+        }
+    }
+
+    /// Shifts right with the sign bit filled in. A shift amount of 256 or more
+    /// results in `0` or `-1` depending on the sign.
+    pub fn sar(self, rhs: Self) -> Self {
+        if self.is_negative {
+            !(!self >> rhs)
+        } else {
+            self >> rhs
+        }
+    }
+
+    /// Returns `-self`, where `-MIN` wraps around to `MIN`.
+    pub fn wrapping_neg(self) -> Self {
+        Self::from_u256(self.to_u256().overflowing_neg().0)
+    }
+
+    fn checked((val, overflow): (Self, bool)) -> Option<Self> {
+        (!overflow).then_some(val)
+    }
+
+    /// Returns the shift amount if it is less than 256.
+    fn shift_amount(self) -> Option<usize> {
+        let amount = self.to_u256();
+        (amount < U256::from(256u32)).then(|| amount.as_usize())
+    }
+
     pub fn from_be_bytes(bytes: &[u8]) -> Self {
         let u256_val = U256::from_big_endian(bytes);
         Self::from_u256(u256_val)
@@ -130,6 +225,42 @@ impl I256 {
         self.to_u256().low_u128() as i128
     }
 
+    /// Truncates the value to the low `bits` bits, and sign-extends the
+    /// result back to 256 bits.
+    ///
+    /// # Panics
+    /// Panics if `bits` is not in `1..=256`.
+    pub fn trunc_to_bits(self, bits: u16) -> Self {
+        assert!(
+            (1..=256).contains(&bits),
+            "bit width must be in 1..=256, but got {bits}"
+        );
+        if bits == 256 {
+            return self;
+        }
+
+        let val = self.to_u256() & Self::low_mask(bits);
+        if val.bit(bits as usize - 1) {
+            Self::from_u256(val | !Self::low_mask(bits))
+        } else {
+            Self::from_u256(val)
+        }
+    }
+
+    /// Truncates the value to the low `bits` bits, and zero-extends the
+    /// result back to 256 bits.
+    pub fn zext_from_bits(self, bits: u16) -> Self {
+        if bits >= 256 {
+            return self;
+        }
+
+        Self::from_u256(self.to_u256() & Self::low_mask(bits))
+    }
+
+    fn low_mask(bits: u16) -> U256 {
+        (U256::one() << bits as usize) - U256::one()
+    }
+
     pub fn is_positive(&self) -> bool {
         !self.is_negative && !self.is_zero()
     }
@@ -189,7 +320,7 @@ impl ops::Neg for I256 {
     type Output = Self;
 
     fn neg(self) -> Self {
-        (!self).overflowing_add(Self::one()).0
+        self.wrapping_neg()
     }
 }
 
@@ -201,17 +332,26 @@ impl ops::BitXor for I256 {
     }
 }
 
+/// A shift amount of 256 or more, including a negative one, results in `0`.
 impl ops::Shl for I256 {
     type Output = Self;
     fn shl(self, rhs: Self) -> Self::Output {
-        Self::from_u256(self.to_u256() << rhs.to_u256())
+        match rhs.shift_amount() {
+            Some(amount) => Self::from_u256(self.to_u256() << amount),
+            None => Self::zero(),
+        }
     }
 }
 
+/// Shifts right logically. A shift amount of 256 or more, including a
+/// negative one, results in `0`.
 impl ops::Shr for I256 {
     type Output = Self;
     fn shr(self, rhs: Self) -> Self::Output {
-        Self::from_u256(self.to_u256() >> rhs.to_u256())
+        match rhs.shift_amount() {
+            Some(amount) => Self::from_u256(self.to_u256() >> amount),
+            None => Self::zero(),
+        }
     }
 }
 
@@ -242,6 +382,13 @@ impl fmt::Display for I256 {
     }
 }
 
+/// Prints the two's complement bit pattern of the value.
+impl fmt::LowerHex for I256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.to_u256(), f)
+    }
+}
+
 impl hash::Hash for I256 {
     fn hash<H>(&self, state: &mut H)
     where
@@ -322,3 +469,140 @@ impl_from!(u32, unsigned);
 impl_from!(u64, unsigned);
 impl_from!(u128, unsigned);
 impl_from!(usize, unsigned);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn max() -> I256 {
+        I256::from_u256(I256_MASK)
+    }
+
+    fn min() -> I256 {
+        !max()
+    }
+
+    fn minus_one() -> I256 {
+        I256::all_one()
+    }
+
+    #[test]
+    fn boundaries() {
+        assert!(min().is_minimum());
+        assert!(min().is_negative());
+        assert!(max().is_positive());
+        assert_eq!(max().wrapping_add(I256::one()), min());
+        assert_eq!(min().wrapping_sub(I256::one()), max());
+        assert_eq!(-min(), min());
+        assert_eq!(min().wrapping_neg(), min());
+        assert_eq!(-max(), min().wrapping_add(I256::one()));
+    }
+
+    #[test]
+    fn overflowing_add_sub() {
+        assert_eq!(max().overflowing_add(I256::one()), (min(), true));
+        assert_eq!(min().overflowing_add(minus_one()), (max(), true));
+        assert_eq!(
+            minus_one().overflowing_add(I256::one()),
+            (I256::zero(), false)
+        );
+        assert_eq!(min().overflowing_add(max()), (minus_one(), false));
+
+        assert_eq!(min().overflowing_sub(I256::one()), (max(), true));
+        assert_eq!(max().overflowing_sub(minus_one()), (min(), true));
+        assert_eq!(
+            I256::zero().overflowing_sub(max()),
+            (min().wrapping_add(I256::one()), false)
+        );
+        assert_eq!(I256::zero().overflowing_sub(min()), (min(), true));
+        assert_eq!(minus_one().overflowing_sub(max()), (min(), false));
+
+        assert_eq!(max().checked_add(I256::one()), None);
+        assert_eq!(I256::from(2).checked_add(I256::from(-3)), Some(minus_one()));
+        assert_eq!(min().checked_sub(I256::one()), None);
+        assert_eq!(
+            I256::from(-2).checked_sub(I256::from(-3)),
+            Some(I256::one())
+        );
+    }
+
+    #[test]
+    fn overflowing_mul() {
+        let two = I256::from(2);
+        let half_min = I256::make_negative(U256::one() << 254);
+
+        assert_eq!(half_min.overflowing_mul(two), (min(), false));
+        assert_eq!(half_min.overflowing_mul(-two), (min(), true));
+        assert_eq!(max().overflowing_mul(two), (I256::from(-2), true));
+        assert_eq!(min().overflowing_mul(minus_one()), (min(), true));
+        assert_eq!(min().overflowing_mul(I256::one()), (min(), false));
+        assert_eq!(
+            max().overflowing_mul(minus_one()),
+            (min().wrapping_add(I256::one()), false)
+        );
+        assert_eq!(min().overflowing_mul(I256::zero()), (I256::zero(), false));
+        assert_eq!(min().overflowing_mul(min()), (I256::zero(), true));
+
+        assert_eq!(
+            I256::from(-3).checked_mul(I256::from(7)),
+            Some(I256::from(-21))
+        );
+        assert_eq!(max().checked_mul(two), None);
+        assert_eq!(max().wrapping_mul(two), I256::from(-2));
+    }
+
+    #[test]
+    fn div_rem() {
+        let seven = I256::from(7);
+        let two = I256::from(2);
+
+        assert_eq!(seven.checked_div(two), Some(I256::from(3)));
+        assert_eq!((-seven).checked_div(two), Some(I256::from(-3)));
+        assert_eq!(seven.checked_div(-two), Some(I256::from(-3)));
+        assert_eq!((-seven).checked_div(-two), Some(I256::from(3)));
+        assert_eq!(minus_one().checked_div(two), Some(I256::zero()));
+
+        assert_eq!((-seven).checked_rem(two), Some(minus_one()));
+        assert_eq!(seven.checked_rem(-two), Some(I256::one()));
+        assert_eq!((-seven).checked_rem(seven), Some(I256::zero()));
+
+        assert_eq!(seven.checked_div(I256::zero()), None);
+        assert_eq!(seven.checked_rem(I256::zero()), None);
+        assert_eq!(min().checked_div(minus_one()), None);
+        assert_eq!(min().checked_rem(minus_one()), None);
+        assert_eq!(min().overflowing_div(minus_one()), (min(), true));
+        assert_eq!(min().overflowing_rem(minus_one()), (I256::zero(), true));
+        assert_eq!(min().checked_div(I256::one()), Some(min()));
+        assert_eq!(min().checked_div(min()), Some(I256::one()));
+        assert_eq!(max().checked_rem(min()), Some(max()));
+
+        // `U256` from `primitive-types` provides the unsigned counterparts.
+        assert_eq!(U256::from(7).checked_div(U256::zero()), None);
+        assert_eq!(U256::from(7).checked_rem(U256::zero()), None);
+        assert_eq!(U256::MAX.overflowing_add(U256::one()), (U256::zero(), true));
+        assert_eq!(U256::MAX.checked_mul(U256::from(2)), None);
+    }
+
+    #[test]
+    fn shift() {
+        let one = I256::one();
+        let amount = |n: usize| I256::from_usize(n);
+
+        assert_eq!(one << amount(255), min());
+        assert_eq!(one << amount(256), I256::zero());
+        assert_eq!(one << minus_one(), I256::zero());
+        assert_eq!(min() >> amount(255), one);
+        assert_eq!(minus_one() >> amount(1), max());
+        assert_eq!(minus_one() >> amount(256), I256::zero());
+
+        assert_eq!(min().sar(amount(255)), minus_one());
+        assert_eq!(
+            min().sar(amount(1)),
+            I256::make_negative(U256::one() << 254)
+        );
+        assert_eq!(I256::from(-7).sar(amount(1)), I256::from(-4));
+        assert_eq!(minus_one().sar(amount(300)), minus_one());
+        assert_eq!(max().sar(amount(254)), one);
+        assert_eq!(max().sar(amount(256)), I256::zero());
+    }
+}
@@ -0,0 +1,155 @@
+use std::ops::{Deref, DerefMut};
+
+use super::FunctionBuilder;
+use crate::{
+    func_cursor::{CursorLocation, FuncCursor},
+    Inst, Type, Value, ValueId,
+};
+
+/// A wrapper of [`FunctionBuilder`] that checks cheap invariants on every
+/// inst insertion, and panics at the offending call with a precise message.
+///
+/// This trades speed for debuggability, so it's meant to be used in tests.
+/// All the other methods of [`FunctionBuilder`] are available through
+/// [`Deref`].
+pub struct CheckedFunctionBuilder<C> {
+    inner: FunctionBuilder<C>,
+}
+
+impl<C> CheckedFunctionBuilder<C>
+where
+    C: FuncCursor,
+{
+    pub fn new(inner: FunctionBuilder<C>) -> Self {
+        Self { inner }
+    }
+
+    pub fn into_inner(self) -> FunctionBuilder<C> {
+        self.inner
+    }
+
+    pub fn finish(self) {
+        self.inner.finish()
+    }
+
+    #[track_caller]
+    pub fn insert_inst<I: Inst>(&mut self, inst: I, ret_ty: Type) -> ValueId {
+        self.check_insertion(&inst);
+        self.inner.insert_inst(inst, ret_ty)
+    }
+
+    #[track_caller]
+    pub fn insert_inst_dyn(&mut self, inst: Box<dyn Inst>, ret_ty: Type) -> ValueId {
+        self.check_insertion(inst.as_ref());
+        self.inner.insert_inst_dyn(inst, ret_ty)
+    }
+
+    #[track_caller]
+    pub fn insert_inst_with<F, I>(&mut self, f: F, ret_ty: Type) -> ValueId
+    where
+        F: FnOnce() -> I,
+        I: Inst,
+    {
+        self.insert_inst(f(), ret_ty)
+    }
+
+    #[track_caller]
+    pub fn insert_inst_no_result<I: Inst>(&mut self, inst: I) {
+        self.check_insertion(&inst);
+        self.inner.insert_inst_no_result(inst)
+    }
+
+    #[track_caller]
+    pub fn insert_inst_no_result_dyn(&mut self, inst: Box<dyn Inst>) {
+        self.check_insertion(inst.as_ref());
+        self.inner.insert_inst_no_result_dyn(inst)
+    }
+
+    #[track_caller]
+    pub fn insert_inst_no_result_with<F, I>(&mut self, f: F)
+    where
+        F: FnOnce() -> I,
+        I: Inst,
+    {
+        self.insert_inst_no_result(f())
+    }
+
+    /// Checks that `inst` can be inserted at the current cursor location.
+    #[track_caller]
+    fn check_insertion(&self, inst: &dyn Inst) {
+        let func = &self.inner.func;
+        let name = inst.as_text();
+
+        let Some(block) = self.inner.current_block() else {
+            panic!("inserting `{name}` without a current block; call `switch_to_block` first");
+        };
+
+        let prev_inst = match self.inner.cursor.loc() {
+            CursorLocation::At(inst) => Some(inst),
+            CursorLocation::BlockBottom(block) => func.layout.last_inst_of(block),
+            CursorLocation::BlockTop(_) | CursorLocation::NoWhere => None,
+        };
+        if let Some(prev_inst) = prev_inst.filter(|&inst| func.dfg.is_terminator(inst)) {
+            let terminator = func.dfg.inst(prev_inst).as_text();
+            panic!("inserting `{name}` after the terminator `{terminator}` of `{block}`");
+        }
+
+        // Collect operands first so that the panic location is tracked, which
+        // doesn't work inside a closure.
+        let mut operands = Vec::new();
+        inst.for_each_value(&mut |value| operands.push(value));
+        for value in operands {
+            let Some(value_data) = func.dfg.values.get(value) else {
+                panic!("operand `v{}` of `{name}` doesn't exist", value.as_u32());
+            };
+            if let Value::Inst { inst, .. } = value_data {
+                if !func.layout.is_inst_inserted(*inst) {
+                    panic!(
+                        "operand `v{}` of `{name}` is defined by a removed inst",
+                        value.as_u32()
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl<C> Deref for CheckedFunctionBuilder<C> {
+    type Target = FunctionBuilder<C>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<C> DerefMut for CheckedFunctionBuilder<C> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::test_util::*,
+        inst::{arith::Add, control_flow::Return},
+        isa::Isa,
+    };
+
+    #[test]
+    #[should_panic(expected = "inserting `add` after the terminator `return` of `block0`")]
+    fn insert_after_return() {
+        let mb = test_module_builder();
+        let (evm, builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+        let mut builder = CheckedFunctionBuilder::new(builder);
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Return::new(is, Some(arg)));
+        builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+    }
+}
@@ -4,6 +4,13 @@ use super::{
 };
 use crate::{
     func_cursor::{CursorLocation, FuncCursor},
+    inst::{
+        arith::Sub,
+        cast::{CopyVal, Sext, Zext},
+        cmp::{Ge, Lt},
+        control_flow::Phi,
+        logic::{And, Select},
+    },
     module::{FuncRef, ModuleCtx},
     BlockId, Function, GlobalVariableRef, Immediate, Inst, InstId, InstSetBase, Type, Value,
     ValueId,
@@ -84,6 +91,15 @@ where
         self.func.dfg.append_phi_arg(phi_inst, value, block);
     }
 
+    /// Returns a [`PhiBuilder`] that builds a phi at the current location from
+    /// the incoming values added one by one.
+    pub fn phi_builder(&mut self) -> PhiBuilder<'_, C> {
+        PhiBuilder {
+            builder: self,
+            args: Vec::new(),
+        }
+    }
+
     pub fn switch_to_block(&mut self, block: BlockId) {
         self.cursor.set_location(CursorLocation::BlockBottom(block));
     }
@@ -95,6 +111,20 @@ where
         self.func.dfg.make_imm_value(imm)
     }
 
+    /// Returns the `i1` constant `1`, which makes a branch take its non-zero
+    /// destination. Immediates are interned, so the same value is returned
+    /// across calls.
+    pub fn true_value(&mut self) -> ValueId {
+        self.make_imm_value(true)
+    }
+
+    /// Returns the `i1` constant `0`, which makes a branch take its zero
+    /// destination. Immediates are interned, so the same value is returned
+    /// across calls.
+    pub fn false_value(&mut self) -> ValueId {
+        self.make_imm_value(false)
+    }
+
     pub fn make_undef_value(&mut self, ty: Type) -> ValueId {
         self.func.dfg.make_undef_value(ty)
     }
@@ -168,6 +198,23 @@ where
         self.insert_inst(i, ret_ty)
     }
 
+    /// Inserts the instruction made by `f`, and returns both the instruction
+    /// and its result value.
+    ///
+    /// # Panics
+    /// Panics if `ret_ty` is `Type::Unit`. Use [`Self::insert_inst_no_result`]
+    /// for instructions without a result.
+    pub fn insert_inst_with_id<F, I>(&mut self, f: F, ret_ty: Type) -> (InstId, ValueId)
+    where
+        F: FnOnce() -> I,
+        I: Inst,
+    {
+        assert!(ret_ty != Type::Unit, "the instruction must have a result");
+
+        let result = self.insert_inst_with(f, ret_ty);
+        (self.func.dfg.value_inst(result).unwrap(), result)
+    }
+
     /// Inserts an instruction into the function without creating a result value
     /// (i.e., for instructions that have no return type).
     ///
@@ -197,6 +244,106 @@ where
         self.insert_inst_no_result(i);
     }
 
+    /// Inserts the comparison `cmp` and zero-extends its `i1` result to `ty`.
+    /// Returns the extended value.
+    ///
+    /// # Panics
+    /// Panics if `ty` is not an integral type.
+    pub fn cmp_as<I: Inst>(&mut self, cmp: I, ty: Type) -> ValueId {
+        assert!(ty.is_integral(), "`{ty:?}` is not an integral type");
+
+        let cond = self.insert_inst(cmp, Type::I1);
+        if ty == Type::I1 {
+            return cond;
+        }
+
+        let zext = Zext::new(self.inst_set().has_zext().unwrap(), cond, ty);
+        self.insert_inst(zext, ty)
+    }
+
+    /// Inserts `select cond nz_val z_val`, whose result type is the common
+    /// type of `nz_val` and `z_val`. Returns the selected value.
+    ///
+    /// # Panics
+    /// Panics if `nz_val` and `z_val` have different types.
+    pub fn select(&mut self, cond: ValueId, nz_val: ValueId, z_val: ValueId) -> ValueId {
+        let ty = self.type_of(nz_val);
+        let z_ty = self.type_of(z_val);
+        assert_eq!(ty, z_ty, "`select` arms have different types");
+
+        let select = Select::new(self.inst_set().has_select().unwrap(), cond, nz_val, z_val);
+        self.insert_inst(select, ty)
+    }
+
+    /// Inserts the range check `lo <= x && x < hi` in unsigned comparison.
+    /// Returns the `i1` result.
+    ///
+    /// If both bounds are immediates, the check is lowered to the single
+    /// comparison `(x - lo) < (hi - lo)`, where values below `lo` wrap around
+    /// to be out of range. The check is folded into an immediate if the range
+    /// is empty or `x` is also an immediate. Otherwise, two comparisons are
+    /// combined with `and`.
+    pub fn in_range(&mut self, x: ValueId, lo: ValueId, hi: ValueId) -> ValueId {
+        let is = self.inst_set();
+        let bounds = (self.func.dfg.value_imm(lo), self.func.dfg.value_imm(hi));
+
+        if let (Some(lo_imm), Some(hi_imm)) = bounds {
+            // `true` as `i1` is all one, so the results are checked against
+            // zero.
+            if !lo_imm.ge(hi_imm).is_zero() {
+                return self.make_imm_value(false);
+            }
+            if let Some(x_imm) = self.func.dfg.value_imm(x) {
+                let in_range = !x_imm.ge(lo_imm).is_zero() && !x_imm.lt(hi_imm).is_zero();
+                return self.make_imm_value(in_range);
+            }
+
+            let offset = if lo_imm.is_zero() {
+                x
+            } else {
+                let ty = self.type_of(x);
+                self.insert_inst(Sub::new(is.has_sub().unwrap(), x, lo), ty)
+            };
+            let width = self.make_imm_value(hi_imm - lo_imm);
+            return self.insert_inst(Lt::new(is.has_lt().unwrap(), offset, width), Type::I1);
+        }
+
+        let lower = self.insert_inst(Ge::new(is.has_ge().unwrap(), x, lo), Type::I1);
+        let upper = self.insert_inst(Lt::new(is.has_lt().unwrap(), x, hi), Type::I1);
+        self.insert_inst(And::new(is.has_and().unwrap(), lower, upper), Type::I1)
+    }
+
+    /// Extends the index `value` to the pointer representation of the ISA for
+    /// address computation, with `sext` if `signed` and `zext` otherwise.
+    /// A `copy` is inserted instead if the `value` already has the pointer
+    /// width.
+    ///
+    /// # Panics
+    /// Panics if the `value` is not an integer or is wider than a pointer.
+    pub fn index_to_ptr(&mut self, value: ValueId, signed: bool) -> ValueId {
+        let is = self.inst_set();
+        let ptr_ty = self.ctx().type_layout.pointer_repl();
+        let ty = self.type_of(value);
+        let bits = ty
+            .int_bits()
+            .unwrap_or_else(|| panic!("`{ty:?}` is not an integer type"));
+        assert!(
+            bits <= ptr_ty.int_bits().unwrap(),
+            "`{ty:?}` is wider than the pointer type `{ptr_ty:?}`"
+        );
+
+        if ty == ptr_ty {
+            self.insert_inst(
+                CopyVal::new(is.has_copy_val().unwrap(), value, ptr_ty),
+                ptr_ty,
+            )
+        } else if signed {
+            self.insert_inst(Sext::new(is.has_sext().unwrap(), value, ptr_ty), ptr_ty)
+        } else {
+            self.insert_inst(Zext::new(is.has_zext().unwrap(), value, ptr_ty), ptr_ty)
+        }
+    }
+
     pub fn declare_var(&mut self, ty: Type) -> Variable {
         self.ssa_builder.declare_var(ty)
     }
@@ -230,8 +377,19 @@ where
         self.func.dfg.is_terminator(inst)
     }
 
+    /// Seal the current block. See [`Self::seal`].
     pub fn seal_block(&mut self) {
         let block = self.cursor.block(&self.func).unwrap();
+        self.seal(block);
+    }
+
+    /// Seal the `block`, i.e., declare that all its predecessors are known, and
+    /// complete the phis inserted to the block while it was unsealed.
+    /// Unlike [`Self::seal_block`], the block doesn't need to be the current
+    /// one, so a loop header can be sealed right after its back edge is added.
+    ///
+    /// Sealing a block that is already sealed is a no-op.
+    pub fn seal(&mut self, block: BlockId) {
         self.ssa_builder.seal_block(&mut self.func, block);
     }
 
@@ -271,13 +429,82 @@ where
     }
 }
 
+/// A builder of a phi that checks the incoming values on [`Self::finish`].
+/// Obtained by [`FunctionBuilder::phi_builder`].
+pub struct PhiBuilder<'a, C> {
+    builder: &'a mut FunctionBuilder<C>,
+    args: Vec<(ValueId, BlockId)>,
+}
+
+impl<C> PhiBuilder<'_, C>
+where
+    C: FuncCursor,
+{
+    /// Adds the `value` flowing in from the `pred`.
+    pub fn add_incoming(&mut self, value: ValueId, pred: BlockId) -> &mut Self {
+        self.args.push((value, pred));
+        self
+    }
+
+    /// Inserts the phi and returns its result.
+    ///
+    /// Fails without inserting anything if a predecessor of the current block
+    /// has no incoming value, or the incoming values don't share a type.
+    /// Only the predecessors whose branches are already inserted are known to
+    /// the builder.
+    pub fn finish(self) -> Result<ValueId, PhiBuildError> {
+        let Self { builder, args } = self;
+        let block = builder.current_block().expect("no current block");
+
+        if let Some(&pred) = builder
+            .ssa_builder
+            .preds_of(block)
+            .iter()
+            .find(|pred| !args.iter().any(|(_, block)| block == *pred))
+        {
+            return Err(PhiBuildError::MissingPred(pred));
+        }
+
+        let Some(&(first, _)) = args.first() else {
+            return Err(PhiBuildError::NoIncoming);
+        };
+        let ty = builder.type_of(first);
+        if let Some(&(value, _)) = args.iter().find(|(value, _)| builder.type_of(*value) != ty) {
+            return Err(PhiBuildError::TypeMismatch {
+                value,
+                expected: ty,
+                found: builder.type_of(value),
+            });
+        }
+
+        let phi = Phi::new(builder.inst_set().has_phi().unwrap(), args);
+        Ok(builder.insert_inst(phi, ty))
+    }
+}
+
+/// An error returned by [`PhiBuilder::finish`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhiBuildError {
+    /// The predecessor has no incoming value.
+    MissingPred(BlockId),
+
+    /// No incoming value is added, so the type of the phi is unknown.
+    NoIncoming,
+
+    /// The type of the `value` differs from that of the first incoming value.
+    TypeMismatch {
+        value: ValueId,
+        expected: Type,
+        found: Type,
+    },
+}
+
 #[cfg(test)]
 mod tests {
     use super::{super::test_util::*, *};
     use crate::{
         inst::{
             arith::{Add, Mul, Sub},
-            cast::Sext,
             control_flow::{Br, Jump, Phi, Return},
         },
         isa::Isa,
@@ -318,6 +545,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn insert_inst_with_id() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let arg = builder.args()[0];
+        let (add, v1) = builder.insert_inst_with_id(|| Add::new(is, arg, arg), Type::I32);
+        assert_eq!(builder.last_inst(), Some(add));
+        assert_eq!(builder.func.dfg.inst_result(add), Some(v1));
+
+        // The result is usable right away.
+        let v2 = builder.insert_inst(Mul::new(is, v1, v1), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v2)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32) -> i32 {
+    block0:
+        v1.i32 = add v0 v0;
+        v2.i32 = mul v1 v1;
+        return v2;
+}
+"
+        );
+    }
+
     #[test]
     fn entry_block_with_args() {
         let mb = test_module_builder();
@@ -353,6 +614,193 @@ mod tests {
         );
     }
 
+    #[test]
+    fn bool_values_are_interned() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+
+        let t = builder.true_value();
+        let f = builder.false_value();
+        assert_ne!(t, f);
+        assert_eq!(builder.true_value(), t);
+        assert_eq!(builder.false_value(), f);
+        assert_eq!(builder.make_imm_value(true), t);
+        assert_eq!(builder.type_of(t), Type::I1);
+    }
+
+    #[test]
+    fn cmp_as() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, Type::I32], Type::I64);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let (arg0, arg1) = (builder.args()[0], builder.args()[1]);
+        let v = builder.cmp_as(Lt::new(is, arg0, arg1), Type::I64);
+        assert_eq!(builder.type_of(v), Type::I64);
+        builder.insert_inst_no_result(Return::new(is, Some(v)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32, v1.i32) -> i64 {
+    block0:
+        v2.i1 = lt v0 v1;
+        v3.i64 = zext v2 i64;
+        return v3;
+}
+"
+        );
+    }
+
+    #[test]
+    fn select() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let (arg0, arg1) = (builder.args()[0], builder.args()[1]);
+        let cond = builder.insert_inst(Lt::new(is, arg0, arg1), Type::I1);
+        let v = builder.select(cond, arg0, arg1);
+        assert_eq!(builder.type_of(v), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32, v1.i32) -> i32 {
+    block0:
+        v2.i1 = lt v0 v1;
+        v3.i32 = select v2 v0 v1;
+        return v3;
+}
+"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "`select` arms have different types")]
+    fn select_different_types() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[Type::I1, Type::I32], Type::Unit);
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let (cond, arg) = (builder.args()[0], builder.args()[1]);
+        let imm = builder.make_imm_value(1i8);
+        builder.select(cond, arg, imm);
+    }
+
+    #[test]
+    #[should_panic]
+    fn cmp_as_non_integral() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let arg = builder.args()[0];
+        builder.cmp_as(Lt::new(is, arg, arg), Type::Unit);
+    }
+
+    #[test]
+    fn index_to_ptr() {
+        let mb = test_module_builder();
+        let (evm, mut builder) =
+            test_func_builder(&mb, &[Type::I32, Type::I32, Type::I256], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let (signed, unsigned, wide) = (builder.args()[0], builder.args()[1], builder.args()[2]);
+        for (index, signed) in [(signed, true), (unsigned, false), (wide, true)] {
+            let ptr = builder.index_to_ptr(index, signed);
+            assert_eq!(builder.type_of(ptr), Type::I256);
+        }
+        builder.insert_inst_no_result(Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32, v1.i32, v2.i256) {
+    block0:
+        v3.i256 = sext v0 i256;
+        v4.i256 = zext v1 i256;
+        v5.i256 = copy v2 i256;
+        return;
+}
+"
+        );
+    }
+
+    #[test]
+    fn in_range() {
+        let mb = test_module_builder();
+        let (evm, mut builder) =
+            test_func_builder(&mb, &[Type::I32, Type::I32, Type::I32], Type::I1);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let (x, lo, hi) = (builder.args()[0], builder.args()[1], builder.args()[2]);
+        let ten = builder.make_imm_value(10i32);
+        let twenty = builder.make_imm_value(20i32);
+        let zero = builder.make_imm_value(0i32);
+        let fifteen = builder.make_imm_value(15i32);
+
+        let v0 = builder.in_range(x, ten, twenty);
+        let v1 = builder.in_range(x, zero, twenty);
+        // The range is empty.
+        let v2 = builder.in_range(x, twenty, ten);
+        let v3 = builder.in_range(x, lo, hi);
+        let v4 = builder.in_range(fifteen, ten, twenty);
+        let v5 = builder.insert_inst(And::new(is, v0, v1), Type::I1);
+        let v6 = builder.insert_inst(And::new(is, v2, v3), Type::I1);
+        let v7 = builder.insert_inst(And::new(is, v5, v6), Type::I1);
+        let v8 = builder.insert_inst(And::new(is, v7, v4), Type::I1);
+        builder.insert_inst_no_result(Return::new(is, Some(v8)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32, v1.i32, v2.i32) -> i1 {
+    block0:
+        v7.i32 = sub v0 10.i32;
+        v8.i1 = lt v7 10.i32;
+        v9.i1 = lt v0 20.i32;
+        v11.i1 = ge v0 v1;
+        v12.i1 = lt v0 v2;
+        v13.i1 = and v11 v12;
+        v15.i1 = and v8 v9;
+        v16.i1 = and 0.i1 v13;
+        v17.i1 = and v15 v16;
+        v18.i1 = and v17 1.i1;
+        return v18;
+}
+"
+        );
+    }
+
     #[test]
     fn entry_block_with_return() {
         let mb = test_module_builder();
@@ -437,6 +885,73 @@ mod tests {
         v4.i64 = add v3 v0;
         return;
 }
+"
+        );
+    }
+
+    #[test]
+    fn phi_builder() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1], Type::I64);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        let arg0 = builder.args()[0];
+        builder.insert_inst_no_result(Br::new(is, arg0, b1, b2));
+        builder.switch_to_block(b1);
+        let v1 = builder.make_imm_value(1i64);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+        builder.switch_to_block(b2);
+        let v2 = builder.make_imm_value(2i64);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b3);
+        let mut phi = builder.phi_builder();
+        phi.add_incoming(v1, b1);
+        assert_eq!(phi.finish(), Err(PhiBuildError::MissingPred(b2)));
+
+        let mut phi = builder.phi_builder();
+        phi.add_incoming(v1, b1).add_incoming(arg0, b2);
+        assert_eq!(
+            phi.finish(),
+            Err(PhiBuildError::TypeMismatch {
+                value: arg0,
+                expected: Type::I64,
+                found: Type::I1,
+            })
+        );
+
+        let mut phi = builder.phi_builder();
+        phi.add_incoming(v1, b1).add_incoming(v2, b2);
+        let v3 = phi.finish().unwrap();
+        builder.insert_inst_no_result(Return::new(is, Some(v3)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i1) -> i64 {
+    block0:
+        br v0 block1 block2;
+
+    block1:
+        jump block3;
+
+    block2:
+        jump block3;
+
+    block3:
+        v3.i64 = phi (1.i64 block1) (2.i64 block2);
+        return v3;
+}
 "
         );
     }
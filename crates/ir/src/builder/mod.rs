@@ -1,21 +1,59 @@
+mod checked_builder;
 mod func_builder;
 mod module_builder;
 mod ssa;
 
-pub use func_builder::FunctionBuilder;
+pub use checked_builder::CheckedFunctionBuilder;
+pub use func_builder::{FunctionBuilder, PhiBuildError, PhiBuilder};
 pub use module_builder::ModuleBuilder;
 pub use ssa::Variable;
 
+/// Build blocks and their terminators from a list of edges, e.g.,
+///
+/// ```ignore
+/// build_cfg! { builder;
+///     a -> [b, c];
+///     b -> [d];
+///     c -> [d];
+///     d -> [];
+/// }
+/// ```
+///
+/// Blocks are appended in the order they appear on the left-hand side, and
+/// every block must have its own line. See [`test_util::insert_terminator`]
+/// for the terminators being inserted.
+///
+/// Only available in tests and with the `test-util` feature.
+#[cfg(any(test, feature = "test-util"))]
+#[macro_export]
+macro_rules! build_cfg {
+    ($builder:ident; $($block:ident -> [$($succ:ident),* $(,)?]);* $(;)?) => {
+        $(let $block = $builder.append_block();)*
+        $(
+            $builder.switch_to_block($block);
+            $crate::builder::test_util::insert_terminator(&mut $builder, &[$($succ),*]);
+        )*
+    };
+}
+
 pub mod test_util {
     use sonatina_triple::{Architecture, EvmVersion, OperatingSystem, TargetTriple, Vendor};
 
     use super::*;
+    #[cfg(any(test, feature = "test-util"))]
+    pub use crate::build_cfg;
     use crate::{
         func_cursor::InstInserter,
+        inst::{
+            arith::{Add, Mul, Sub},
+            cmp::Lt,
+            control_flow::{Br, BrTable, Jump, Return},
+            logic::Xor,
+        },
         ir_writer::FuncWriter,
-        isa::evm::Evm,
+        isa::{evm::Evm, Isa},
         module::{FuncRef, ModuleCtx},
-        Linkage, Module, Signature, Type,
+        BlockId, Function, Linkage, Module, Signature, Type,
     };
 
     pub fn test_isa() -> Evm {
@@ -43,6 +81,126 @@ pub mod test_util {
         (test_isa(), mb.func_builder(func_ref))
     }
 
+    /// Insert a terminator that transfers control to `succs` at the current
+    /// block: `return` for no successor, `jump` for one, `br` for two and
+    /// `br_table` for more.
+    pub fn insert_terminator(builder: &mut FunctionBuilder<InstInserter>, succs: &[BlockId]) {
+        let is = test_isa().inst_set();
+        match succs {
+            [] => builder.insert_inst_no_result(Return::new(is, None)),
+            [dest] => builder.insert_inst_no_result(Jump::new(is, *dest)),
+            [nz_dest, z_dest] => {
+                let cond = builder.make_imm_value(true);
+                builder.insert_inst_no_result(Br::new(is, cond, *nz_dest, *z_dest));
+            }
+            [default, rest @ ..] => {
+                let scrutinee = builder.make_imm_value(0i32);
+                let table = rest
+                    .iter()
+                    .enumerate()
+                    .map(|(i, dest)| (builder.make_imm_value(i as i32 + 1), *dest))
+                    .collect();
+                builder.insert_inst_no_result(BrTable::new(is, scrutinee, Some(*default), table));
+            }
+        }
+    }
+
+    /// Generate a random well-formed function with `block_count` blocks from
+    /// the `seed`, which is useful to fuzz analyses and transformations.
+    ///
+    /// The function takes two `i32` arguments and returns an `i32`. Every block
+    /// is terminated and reachable from the entry block, and the SSA form is
+    /// built by the SSA builder from a few mutable variables, so phis are
+    /// inserted wherever they are needed.
+    ///
+    /// # Panics
+    /// Panics if `block_count` is zero.
+    pub fn gen_random_function(seed: u64, block_count: usize) -> Function {
+        const VAR_NUM: usize = 4;
+        assert!(block_count > 0, "function must have at least one block");
+
+        let mut rng = XorShift::new(seed);
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let blocks: Vec<_> = (0..block_count).map(|_| builder.append_block()).collect();
+        let vars: Vec<_> = (0..VAR_NUM)
+            .map(|_| builder.declare_var(Type::I32))
+            .collect();
+
+        builder.switch_to_block(blocks[0]);
+        for (i, &var) in vars.iter().enumerate() {
+            let arg = builder.args()[i % 2];
+            builder.def_var(var, arg);
+        }
+
+        for (idx, &block) in blocks.iter().enumerate() {
+            builder.switch_to_block(block);
+
+            for _ in 0..rng.below(4) {
+                let lhs = builder.use_var(vars[rng.below(VAR_NUM)]);
+                let rhs = if rng.below(2) == 0 {
+                    builder.use_var(vars[rng.below(VAR_NUM)])
+                } else {
+                    builder.make_imm_value(rng.next() as i32)
+                };
+                let value = match rng.below(4) {
+                    0 => builder.insert_inst(Add::new(is, lhs, rhs), Type::I32),
+                    1 => builder.insert_inst(Sub::new(is, lhs, rhs), Type::I32),
+                    2 => builder.insert_inst(Mul::new(is, lhs, rhs), Type::I32),
+                    _ => builder.insert_inst(Xor::new(is, lhs, rhs), Type::I32),
+                };
+                builder.def_var(vars[rng.below(VAR_NUM)], value);
+            }
+
+            let Some(&next) = blocks.get(idx + 1) else {
+                let ret = builder.use_var(vars[0]);
+                builder.insert_inst_no_result(Return::new(is, Some(ret)));
+                continue;
+            };
+
+            // Falling through to the next block keeps all blocks reachable, and
+            // the other destination makes both forward edges and loops. The
+            // entry block is never a destination.
+            let other = blocks[1 + rng.below(block_count - 1)];
+            if other == next || rng.below(3) == 0 {
+                builder.insert_inst_no_result(Jump::new(is, next));
+            } else {
+                let lhs = builder.use_var(vars[rng.below(VAR_NUM)]);
+                let rhs = builder.use_var(vars[rng.below(VAR_NUM)]);
+                let cond = builder.insert_inst(Lt::new(is, lhs, rhs), Type::I1);
+                builder.insert_inst_no_result(Br::new(is, cond, other, next));
+            }
+        }
+
+        builder.seal_all();
+        builder.func
+    }
+
+    /// A tiny xorshift PRNG to keep generated functions reproducible without
+    /// depending on an external crate.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn new(seed: u64) -> Self {
+            // The state must not be zero.
+            Self((seed ^ 0x9e37_79b9_7f4a_7c15).max(1))
+        }
+
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        /// Returns a random number in `0..n`.
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
     pub fn dump_func(module: &Module, func_ref: FuncRef) -> String {
         module.func_store.view(func_ref, |func| {
             FuncWriter::new(func_ref, func).dump_string()
@@ -1,18 +1,30 @@
 //! SSA construction algorithm here is based on [`Simple and Efficient
 //! Construction of Static Single Assignment Form`](https://link.springer.com/chapter/10.1007/978-3-642-37051-9_6).
 
-use cranelift_entity::{packed_option::PackedOption, PrimaryMap, SecondaryMap, SparseSet};
+use cranelift_entity::{
+    entity_impl, packed_option::PackedOption, PrimaryMap, SecondaryMap, SparseSet,
+};
 use rustc_hash::FxHashMap;
 
 use crate::{
     func_cursor::{CursorLocation, FuncCursor, InstInserter},
     inst::control_flow,
+    ir_writer::IrWrite,
     BlockId, Function, InstId, Type, Value, ValueId,
 };
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Variable(u32);
-cranelift_entity::entity_impl!(Variable);
+entity_impl!(Variable, "var");
+
+impl<Ctx> IrWrite<Ctx> for Variable {
+    fn write<W>(&self, w: &mut W, _ctx: &Ctx) -> std::io::Result<()>
+    where
+        W: std::io::Write + ?Sized,
+    {
+        write!(w, "var{}", self.0)
+    }
+}
 
 pub struct VariableData {
     ty: Type,
@@ -68,6 +80,12 @@ impl SsaBuilder {
         self.blocks[block].append_pred(pred);
     }
 
+    pub(super) fn preds_of(&self, block: BlockId) -> &[BlockId] {
+        self.blocks
+            .get(block)
+            .map_or(&[][..], |block| block.preds())
+    }
+
     pub(super) fn seal_block(&mut self, func: &mut Function, block: BlockId) {
         if self.is_sealed(block) {
             return;
@@ -506,6 +524,56 @@ mod tests {
         );
     }
 
+    #[test]
+    fn seal_loop_header_after_back_edge() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let var = builder.declare_var(Type::I32);
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        let one = builder.make_imm_value(1i32);
+        builder.def_var(var, one);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+        builder.seal(b0);
+
+        builder.switch_to_block(b1);
+        let phi_value = builder.use_var(var);
+        builder.insert_inst_no_result(Br::new(is, phi_value, b2, b3));
+
+        builder.switch_to_block(b2);
+        let next = builder.insert_inst(Add::new(is, phi_value, one), Type::I32);
+        builder.def_var(var, next);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+        builder.seal(b2);
+
+        // All the predecessors of the loop header are known now.
+        builder.seal(b1);
+        assert!(builder.is_sealed(b1));
+        builder.seal(b1);
+
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result(Return::new(is, None));
+        builder.seal(b3);
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let phi = func.dfg.value_inst(phi_value).unwrap();
+            assert_eq!(
+                func.dfg.cast_phi(phi).unwrap().args(),
+                &[(one, b0), (next, b2)]
+            );
+        });
+    }
+
     #[test]
     fn use_var_global_complex() {
         let mb = test_module_builder();
@@ -0,0 +1,206 @@
+//! This module contains the call graph of a module.
+
+use cranelift_entity::SecondaryMap;
+use smallvec::SmallVec;
+
+use crate::{inst::control_flow::Call, module::FuncRef, InstDowncast, Module};
+
+/// A call graph whose nodes are the functions in a module, and which has an
+/// edge from a caller to a callee for each direct call.
+///
+/// Sonatina has no indirect call, so the graph is exact.
+#[derive(Default, Debug, Clone)]
+pub struct CallGraph {
+    funcs: Vec<FuncRef>,
+    /// Callees of each function, sorted by `FuncRef`.
+    callees: SecondaryMap<FuncRef, SmallVec<[FuncRef; 4]>>,
+}
+
+impl CallGraph {
+    pub fn compute(module: &Module) -> Self {
+        let mut graph = Self {
+            funcs: module.funcs(),
+            callees: SecondaryMap::default(),
+        };
+
+        for &func_ref in &graph.funcs {
+            let callees = &mut graph.callees[func_ref];
+            module.func_store.view(func_ref, |func| {
+                for block in func.layout.iter_block() {
+                    for inst in func.layout.iter_inst(block) {
+                        let inst = func.dfg.inst(inst);
+                        if let Some(call) = <&Call as InstDowncast>::downcast(func.inst_set(), inst)
+                        {
+                            callees.push(*call.callee());
+                        }
+                    }
+                }
+            });
+            callees.sort_unstable();
+            callees.dedup();
+        }
+
+        graph
+    }
+
+    pub fn funcs(&self) -> &[FuncRef] {
+        &self.funcs
+    }
+
+    /// Returns the functions that `func` directly calls, sorted by `FuncRef`.
+    pub fn callees_of(&self, func: FuncRef) -> &[FuncRef] {
+        &self.callees[func]
+    }
+
+    /// Returns `true` if the `func` may call itself, either directly or
+    /// through other functions.
+    pub fn is_recursive(&self, func: FuncRef) -> bool {
+        self.sccs()
+            .iter()
+            .find(|scc| scc.contains(&func))
+            .is_some_and(|scc| scc.len() > 1 || self.callees_of(func).contains(&func))
+    }
+
+    /// Returns the strongly connected components of the graph.
+    ///
+    /// The components are in the reverse topological order, i.e., a component
+    /// comes after all the components that it calls, which is the order that
+    /// bottom-up passes like the inliner want. A function that is not a part
+    /// of any cycle forms a component by itself.
+    pub fn sccs(&self) -> Vec<Vec<FuncRef>> {
+        Tarjan::new(self).run()
+    }
+}
+
+impl Module {
+    pub fn call_graph(&self) -> CallGraph {
+        CallGraph::compute(self)
+    }
+}
+
+/// An iterative version of Tarjan's SCC algorithm.
+struct Tarjan<'a> {
+    graph: &'a CallGraph,
+    /// The visit order of each function, starting from 1. 0 means unvisited.
+    index: SecondaryMap<FuncRef, u32>,
+    low_link: SecondaryMap<FuncRef, u32>,
+    on_stack: SecondaryMap<FuncRef, bool>,
+    stack: Vec<FuncRef>,
+    next_index: u32,
+    sccs: Vec<Vec<FuncRef>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn new(graph: &'a CallGraph) -> Self {
+        Self {
+            graph,
+            index: SecondaryMap::default(),
+            low_link: SecondaryMap::default(),
+            on_stack: SecondaryMap::default(),
+            stack: Vec::new(),
+            next_index: 1,
+            sccs: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Vec<Vec<FuncRef>> {
+        for &func in self.graph.funcs() {
+            if self.index[func] == 0 {
+                self.visit(func);
+            }
+        }
+        self.sccs
+    }
+
+    fn visit(&mut self, root: FuncRef) {
+        // Each frame holds a function and the position of the next callee to
+        // visit.
+        let mut frames = vec![(root, 0)];
+        self.enter(root);
+
+        while let Some((func, next)) = frames.last_mut() {
+            let func = *func;
+            if let Some(&callee) = self.graph.callees_of(func).get(*next) {
+                *next += 1;
+                if self.index[callee] == 0 {
+                    self.enter(callee);
+                    frames.push((callee, 0));
+                } else if self.on_stack[callee] {
+                    self.low_link[func] = self.low_link[func].min(self.index[callee]);
+                }
+                continue;
+            }
+
+            frames.pop();
+            if let Some(&(caller, _)) = frames.last() {
+                self.low_link[caller] = self.low_link[caller].min(self.low_link[func]);
+            }
+
+            if self.low_link[func] == self.index[func] {
+                let mut scc = Vec::new();
+                loop {
+                    let member = self.stack.pop().unwrap();
+                    self.on_stack[member] = false;
+                    scc.push(member);
+                    if member == func {
+                        break;
+                    }
+                }
+                scc.sort_unstable();
+                self.sccs.push(scc);
+            }
+        }
+    }
+
+    fn enter(&mut self, func: FuncRef) {
+        self.index[func] = self.next_index;
+        self.low_link[func] = self.next_index;
+        self.next_index += 1;
+        self.on_stack[func] = true;
+        self.stack.push(func);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smallvec::smallvec;
+
+    use super::*;
+    use crate::{
+        builder::test_util::*, func_cursor::InstInserter, inst::control_flow::Return, isa::Isa,
+        Linkage, Signature, Type,
+    };
+
+    #[test]
+    fn mutual_recursion() {
+        let mb = test_module_builder();
+        let is = test_isa().inst_set();
+        let sig = |name| Signature::new(name, Linkage::Public, &[Type::I32], Type::I32);
+        let even = mb.declare_function(sig("even"));
+        let odd = mb.declare_function(sig("odd"));
+        let main = mb.declare_function(sig("main"));
+
+        // `even` and `odd` call each other, and `main` calls `even`.
+        for (caller, callee) in [(even, odd), (odd, even), (main, even)] {
+            let mut builder = mb.func_builder::<InstInserter>(caller);
+            let b0 = builder.append_block();
+            let arg = builder.args()[0];
+
+            builder.switch_to_block(b0);
+            let ret = builder.insert_inst(Call::new(is, callee, smallvec![arg]), Type::I32);
+            builder.insert_inst_no_result(Return::new(is, Some(ret)));
+
+            builder.seal_all();
+            builder.finish();
+        }
+
+        let module = mb.build();
+        let graph = module.call_graph();
+
+        assert_eq!(graph.callees_of(main), [even]);
+        assert_eq!(graph.sccs(), [vec![even, odd], vec![main]]);
+        assert!(graph.is_recursive(even));
+        assert!(graph.is_recursive(odd));
+        assert!(!graph.is_recursive(main));
+    }
+}
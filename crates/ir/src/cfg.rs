@@ -1,16 +1,61 @@
-use std::collections::BTreeSet;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    OnceLock,
+};
 
 use cranelift_entity::{packed_option::PackedOption, SecondaryMap};
+use smallvec::SmallVec;
 
-use crate::{BlockId, Function};
+use crate::{inst::control_flow::BranchKind, BlockId, Function};
 
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct ControlFlowGraph {
     entry: PackedOption<BlockId>,
-    blocks: SecondaryMap<BlockId, BlockNode>,
+    /// Predecessors of each block, sorted by `BlockId`.
+    preds: SecondaryMap<BlockId, SmallVec<[BlockId; 4]>>,
+    /// Successors of each block, sorted by `BlockId`.
+    succs: SecondaryMap<BlockId, SmallVec<[BlockId; 4]>>,
     pub exits: smallvec::SmallVec<[BlockId; 8]>,
+    /// Replaced with a fresh value on every modification of the graph.
+    revision: u64,
+    /// The traversal orders of the current revision, computed on first use.
+    order: OnceLock<CfgOrder>,
 }
 
+/// The source of revisions, which are unique across all graphs so that a
+/// revision identifies a state of a single graph.
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(0);
+
+fn next_revision() -> u64 {
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Default for ControlFlowGraph {
+    fn default() -> Self {
+        Self {
+            entry: PackedOption::default(),
+            preds: SecondaryMap::default(),
+            succs: SecondaryMap::default(),
+            exits: SmallVec::default(),
+            revision: next_revision(),
+            order: OnceLock::new(),
+        }
+    }
+}
+
+impl PartialEq for ControlFlowGraph {
+    fn eq(&self, other: &Self) -> bool {
+        // The revision and the cached orders are bookkeeping, not a part of the
+        // graph.
+        self.entry == other.entry
+            && self.preds == other.preds
+            && self.succs == other.succs
+            && self.exits == other.exits
+    }
+}
+
+impl Eq for ControlFlowGraph {}
+
 impl ControlFlowGraph {
     pub fn new() -> Self {
         Self::default()
@@ -19,7 +64,7 @@ impl ControlFlowGraph {
     pub fn compute(&mut self, func: &Function) {
         self.clear();
 
-        self.entry = func.layout.entry_block().into();
+        self.entry = func.entry_block().into();
 
         for block in func.layout.iter_block() {
             if let Some(last_inst) = func.layout.last_inst_of(block) {
@@ -29,53 +74,203 @@ impl ControlFlowGraph {
     }
 
     pub fn preds_of(&self, block: BlockId) -> impl Iterator<Item = &BlockId> {
-        self.blocks[block].preds()
+        self.preds_slice(block).iter()
     }
 
     pub fn succs_of(&self, block: BlockId) -> impl Iterator<Item = &BlockId> {
-        self.blocks[block].succs()
+        self.succs_slice(block).iter()
+    }
+
+    /// Returns predecessors of the `block` as a slice sorted by `BlockId`.
+    pub fn preds_slice(&self, block: BlockId) -> &[BlockId] {
+        &self.preds[block]
+    }
+
+    /// Returns successors of the `block` as a slice sorted by `BlockId`.
+    pub fn succs_slice(&self, block: BlockId) -> &[BlockId] {
+        &self.succs[block]
+    }
+
+    /// Returns the incoming edges of the `block` together with their kinds,
+    /// ordered by the predecessors.
+    ///
+    /// A predecessor reaching the `block` through several destinations of its
+    /// terminator, e.g., `br v0 block1 block1`, yields an edge for each of
+    /// them, which tells apart the edges that [`Self::preds_of`] merges.
+    pub fn incoming_edges(
+        &self,
+        func: &Function,
+        block: BlockId,
+    ) -> impl Iterator<Item = (BlockId, EdgeKind)> {
+        let mut edges = Vec::new();
+        for &pred in self.preds_of(block) {
+            let Some(branch) = func
+                .layout
+                .last_inst_of(pred)
+                .and_then(|term| func.dfg.branch_info(term))
+            else {
+                continue;
+            };
+
+            match branch.branch_kind() {
+                BranchKind::Jump(_) => edges.push((pred, EdgeKind::Jump)),
+                BranchKind::Br(br) => {
+                    if *br.nz_dest() == block {
+                        edges.push((pred, EdgeKind::BranchTaken));
+                    }
+                    if *br.z_dest() == block {
+                        edges.push((pred, EdgeKind::BranchNotTaken));
+                    }
+                }
+                BranchKind::BrTable(br_table) => {
+                    if *br_table.default() == Some(block) {
+                        edges.push((pred, EdgeKind::TableDefault));
+                    }
+                    for (idx, (_, dest)) in br_table.table().iter().enumerate() {
+                        if *dest == block {
+                            edges.push((pred, EdgeKind::TableCase(idx)));
+                        }
+                    }
+                }
+            }
+        }
+
+        edges.into_iter()
     }
 
     pub fn pred_num_of(&self, block: BlockId) -> usize {
-        self.blocks[block].pred_num()
+        self.preds[block].len()
     }
 
     pub fn succ_num_of(&self, block: BlockId) -> usize {
-        self.blocks[block].succ_num()
+        self.succs[block].len()
     }
 
     pub fn entry(&self) -> Option<BlockId> {
         self.entry.expand()
     }
 
+    /// Returns the revision of the graph, which changes whenever the graph is
+    /// modified. Analyses can use it to detect that their results are stale.
+    ///
+    /// Revisions are unique across graphs, so a revision of another graph
+    /// never matches, except for a clone that hasn't been modified since.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns all blocks in the `func` that have no predecessors, in the
+    /// layout order. A well-formed function has exactly one such block, its
+    /// entry block.
+    pub fn entry_blocks<'a>(&'a self, func: &'a Function) -> impl Iterator<Item = BlockId> + 'a {
+        func.layout
+            .iter_block()
+            .filter(|&block| self.preds[block].is_empty())
+    }
+
+    /// Returns the traversal orders of the graph, which are shared by all
+    /// analyses of the graph until it's modified.
+    pub fn order(&self) -> &CfgOrder {
+        self.order.get_or_init(|| {
+            let mut order = CfgOrder::new();
+            order.update(self);
+            order
+        })
+    }
+
+    /// Traverses the graph from the entry block in post order.
+    /// Use [`Self::order`] to reuse the traversal instead.
     pub fn post_order(&self) -> CfgPostOrder {
-        CfgPostOrder::new(self)
+        CfgPostOrder::new(self, self.entry())
+    }
+
+    /// Returns blocks reachable from the `root` in RPO.
+    /// This is useful to analyze a region, e.g., a loop, in isolation.
+    pub fn rpo_from(&self, root: BlockId) -> Vec<BlockId> {
+        let mut rpo: Vec<_> = CfgPostOrder::new(self, Some(root)).collect();
+        rpo.reverse();
+        rpo
+    }
+
+    /// Returns the adjacency matrix of the blocks reachable from the entry,
+    /// indexed by their RPO positions, i.e., `matrix[i][j]` is `true` iff
+    /// there is an edge from the `i`-th block to the `j`-th block in RPO.
+    ///
+    /// The matrix is quadratic in the number of blocks, so this returns an
+    /// error if there are more than `max_blocks` reachable blocks.
+    pub fn adjacency_matrix(&self, max_blocks: usize) -> Result<Vec<Vec<bool>>, TooManyBlocks> {
+        let rpo: Vec<_> = match self.entry() {
+            Some(entry) => self.rpo_from(entry),
+            None => Vec::new(),
+        };
+        if rpo.len() > max_blocks {
+            return Err(TooManyBlocks {
+                block_num: rpo.len(),
+                max_blocks,
+            });
+        }
+
+        let mut pos = SecondaryMap::new();
+        for (i, &block) in rpo.iter().enumerate() {
+            pos[block] = i;
+        }
+
+        let mut matrix = vec![vec![false; rpo.len()]; rpo.len()];
+        for (i, &block) in rpo.iter().enumerate() {
+            for &succ in self.succs_of(block) {
+                matrix[i][pos[succ]] = true;
+            }
+        }
+        Ok(matrix)
     }
 
     pub fn add_edge(&mut self, from: BlockId, to: BlockId) {
-        self.blocks[to].push_pred(from);
-        self.blocks[from].push_succ(to);
+        self.bump_revision();
+        insert_sorted(&mut self.preds[to], from);
+        insert_sorted(&mut self.succs[from], to);
     }
 
     pub fn remove_edge(&mut self, from: BlockId, to: BlockId) {
-        self.blocks[to].remove_pred(from);
-        self.blocks[from].remove_succ(to);
+        self.bump_revision();
+        remove_sorted(&mut self.preds[to], from);
+        remove_sorted(&mut self.succs[from], to);
     }
 
     pub fn reverse_edges(&mut self, new_entry: BlockId, new_exits: &[BlockId]) {
-        for node in self.blocks.values_mut() {
-            node.reverse_edge();
-        }
+        self.bump_revision();
+        std::mem::swap(&mut self.preds, &mut self.succs);
         self.entry = new_entry.into();
         self.exits = new_exits.into();
     }
 
     pub fn clear(&mut self) {
+        self.bump_revision();
         self.entry = None.into();
-        self.blocks.clear();
+        self.preds.clear();
+        self.succs.clear();
         self.exits.clear();
     }
 
+    /// Returns the number of block ids that the graph may contain, i.e., one
+    /// more than the largest id.
+    #[cfg(feature = "petgraph")]
+    pub(crate) fn block_bound(&self) -> usize {
+        let entry_bound = self
+            .entry
+            .expand()
+            .map_or(0, |entry| entry.as_u32() as usize + 1);
+        self.preds
+            .keys()
+            .count()
+            .max(self.succs.keys().count())
+            .max(entry_bound)
+    }
+
+    fn bump_revision(&mut self) {
+        self.revision = next_revision();
+        self.order.take();
+    }
+
     fn analyze_inst(&mut self, func: &Function, inst: crate::InstId) {
         if func.dfg.is_exit(inst) {
             let exit = func.layout.inst_block(inst);
@@ -93,47 +288,92 @@ impl ControlFlowGraph {
     }
 }
 
-#[derive(Default, Clone, Debug, PartialEq, Eq)]
-struct BlockNode {
-    preds: BTreeSet<BlockId>,
-    succs: BTreeSet<BlockId>,
+/// Which destination of the terminator of the source block a CFG edge
+/// corresponds to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// The destination of a `jump`.
+    Jump,
+    /// The destination of a `br` taken if the condition is nonzero.
+    BranchTaken,
+    /// The destination of a `br` taken if the condition is zero.
+    BranchNotTaken,
+    /// The destination of the case at the index in the table of a `br_table`.
+    TableCase(usize),
+    /// The default destination of a `br_table`.
+    TableDefault,
 }
 
-impl BlockNode {
-    fn push_pred(&mut self, pred: BlockId) {
-        self.preds.insert(pred);
-    }
+/// An error returned by [`ControlFlowGraph::adjacency_matrix`] when the graph
+/// has too many blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooManyBlocks {
+    /// The number of blocks reachable from the entry.
+    pub block_num: usize,
+    pub max_blocks: usize,
+}
 
-    fn push_succ(&mut self, succ: BlockId) {
-        self.succs.insert(succ);
+fn insert_sorted(blocks: &mut SmallVec<[BlockId; 4]>, block: BlockId) {
+    if let Err(pos) = blocks.binary_search(&block) {
+        blocks.insert(pos, block);
     }
+}
 
-    fn remove_pred(&mut self, pred: BlockId) {
-        self.preds.remove(&pred);
+fn remove_sorted(blocks: &mut SmallVec<[BlockId; 4]>, block: BlockId) {
+    if let Ok(pos) = blocks.binary_search(&block) {
+        blocks.remove(pos);
     }
+}
+
+/// A cache of the post order and reverse post order of a CFG, which can be
+/// shared across analyses to avoid traversing the same CFG repeatedly.
+///
+/// The cache is tied to the [`ControlFlowGraph::revision`] it's computed from,
+/// and [`Self::update`] recomputes the orders only if the CFG has been modified
+/// since then. Each graph keeps its own cache, see [`ControlFlowGraph::order`].
+#[derive(Default, Debug, Clone)]
+pub struct CfgOrder {
+    post_order: Vec<BlockId>,
+    rpo: Vec<BlockId>,
+    revision: Option<u64>,
+}
 
-    fn remove_succ(&mut self, succ: BlockId) {
-        self.succs.remove(&succ);
+impl CfgOrder {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    fn preds(&self) -> impl Iterator<Item = &BlockId> {
-        self.preds.iter()
+    /// Recompute the orders if the cache is stale for `cfg`.
+    pub fn update(&mut self, cfg: &ControlFlowGraph) {
+        if self.is_valid_for(cfg) {
+            return;
+        }
+
+        self.post_order.clear();
+        self.post_order.extend(cfg.post_order());
+        self.rpo.clear();
+        self.rpo.extend(self.post_order.iter().rev());
+        self.revision = Some(cfg.revision());
     }
 
-    fn succs(&self) -> impl Iterator<Item = &BlockId> {
-        self.succs.iter()
+    /// Returns `true` if the cached orders reflect the current `cfg`.
+    pub fn is_valid_for(&self, cfg: &ControlFlowGraph) -> bool {
+        self.revision == Some(cfg.revision())
     }
 
-    fn pred_num(&self) -> usize {
-        self.preds.len()
+    /// Mark the cache as stale, so that the next [`Self::update`] always
+    /// recomputes the orders.
+    pub fn invalidate(&mut self) {
+        self.revision = None;
     }
 
-    fn succ_num(&self) -> usize {
-        self.succs.len()
+    pub fn post_order(&self) -> &[BlockId] {
+        &self.post_order
     }
 
-    fn reverse_edge(&mut self) {
-        std::mem::swap(&mut self.preds, &mut self.succs);
+    /// Returns blocks in RPO.
+    pub fn rpo(&self) -> &[BlockId] {
+        &self.rpo
     }
 }
 
@@ -144,17 +384,11 @@ pub struct CfgPostOrder<'a> {
 }
 
 impl<'a> CfgPostOrder<'a> {
-    fn new(cfg: &'a ControlFlowGraph) -> Self {
-        let mut stack = Vec::new();
-
-        if let Some(entry) = cfg.entry() {
-            stack.push(entry);
-        }
-
+    fn new(cfg: &'a ControlFlowGraph, root: Option<BlockId>) -> Self {
         Self {
             cfg,
             node_state: SecondaryMap::default(),
-            stack,
+            stack: root.into_iter().collect(),
         }
     }
 }
@@ -204,3 +438,345 @@ impl NodeState {
         self.0 = 2;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::test_util::*,
+        inst::control_flow::{Br, Jump, Return},
+        isa::Isa,
+        Type,
+    };
+
+    #[test]
+    fn slices_match_iterators() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Br::new(is, arg, b2, b1));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result(Br::new(is, arg, b3, b0));
+
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result(Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let mut cfg = ControlFlowGraph::new();
+        module.func_store.view(func_ref, |func| cfg.compute(func));
+
+        for block in [b0, b1, b2, b3] {
+            let preds: Vec<_> = cfg.preds_of(block).copied().collect();
+            let succs: Vec<_> = cfg.succs_of(block).copied().collect();
+            assert_eq!(cfg.preds_slice(block), preds.as_slice());
+            assert_eq!(cfg.succs_slice(block), succs.as_slice());
+        }
+
+        assert_eq!(cfg.preds_slice(b0), &[b2]);
+        assert_eq!(cfg.succs_slice(b0), &[b1, b2]);
+        assert_eq!(cfg.preds_slice(b3), &[b1, b2]);
+        assert!(cfg.succs_slice(b3).is_empty());
+    }
+
+    #[test]
+    fn incoming_edges_of_duplicated_pred() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Br::new(is, arg, b1, b1));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+
+            assert_eq!(cfg.preds_slice(b1), &[b0]);
+            assert_eq!(
+                cfg.incoming_edges(func, b1).collect::<Vec<_>>(),
+                [(b0, EdgeKind::BranchTaken), (b0, EdgeKind::BranchNotTaken)]
+            );
+        });
+    }
+
+    #[test]
+    fn build_cfg_diamond() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        let cond = builder.make_imm_value(true);
+        builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result(Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let mut expected = ControlFlowGraph::new();
+        module
+            .func_store
+            .view(module.funcs()[0], |func| expected.compute(func));
+
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        build_cfg! { builder;
+            a -> [b, c];
+            b -> [d];
+            c -> [d];
+            d -> [];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let mut cfg = ControlFlowGraph::new();
+        module
+            .func_store
+            .view(module.funcs()[0], |func| cfg.compute(func));
+
+        assert_eq!(cfg.entry(), expected.entry());
+        for (block, expected_block) in [(a, b0), (b, b1), (c, b2), (d, b3)] {
+            assert_eq!(block, expected_block);
+            assert_eq!(cfg.preds_slice(block), expected.preds_slice(expected_block));
+            assert_eq!(cfg.succs_slice(block), expected.succs_slice(expected_block));
+        }
+    }
+
+    #[test]
+    fn entry_blocks() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        build_cfg! { builder;
+            a -> [b];
+            b -> [];
+            c -> [b];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let mut cfg = ControlFlowGraph::new();
+        module.func_store.view(module.funcs()[0], |func| {
+            cfg.compute(func);
+            assert_eq!(cfg.entry_blocks(func).collect::<Vec<_>>(), [a, c]);
+        });
+    }
+
+    #[test]
+    fn cfg_order_follows_revision() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+        build_cfg! { builder;
+            a -> [b, c];
+            b -> [d];
+            c -> [d];
+            d -> [];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.modify(module.funcs()[0], |func| {
+            let mut cfg = ControlFlowGraph::new();
+            let mut order = CfgOrder::new();
+            cfg.compute(func);
+            order.update(&cfg);
+            assert!(order.is_valid_for(&cfg));
+            assert_eq!(order.post_order(), cfg.post_order().collect::<Vec<_>>());
+
+            // Make `c` unreachable by jumping from `a` to `b` directly.
+            let last_inst = func.layout.last_inst_of(a).unwrap();
+            func.dfg.replace_inst(last_inst, Box::new(Jump::new(is, b)));
+            cfg.compute(func);
+            assert!(!order.is_valid_for(&cfg));
+
+            order.update(&cfg);
+            let post_order: Vec<_> = cfg.post_order().collect();
+            assert_eq!(order.post_order(), post_order);
+            assert!(!order.rpo().contains(&c));
+            assert_eq!(order.post_order()[0], d);
+            assert!(order.rpo().iter().eq(post_order.iter().rev()));
+
+            order.invalidate();
+            assert!(!order.is_valid_for(&cfg));
+        });
+    }
+
+    #[test]
+    fn cached_order_is_dropped_on_modification() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        build_cfg! { builder;
+            a -> [b, c];
+            b -> [];
+            c -> [];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            assert!(std::ptr::eq(cfg.order(), cfg.order()));
+            assert!(cfg.order().is_valid_for(&cfg));
+
+            cfg.remove_edge(a, c);
+            assert!(cfg.order().is_valid_for(&cfg));
+            assert!(!cfg.order().rpo().contains(&c));
+            assert_eq!(
+                cfg.order().post_order(),
+                cfg.post_order().collect::<Vec<_>>()
+            );
+
+            cfg.add_edge(b, c);
+            assert_eq!(cfg.order().rpo(), [a, b, c]);
+        });
+    }
+
+    #[test]
+    fn cfg_order_is_tied_to_its_graph() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        build_cfg! { builder;
+            a -> [b];
+            b -> [];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let mut cfg = ControlFlowGraph::new();
+            let mut other = ControlFlowGraph::new();
+            cfg.compute(func);
+            other.compute(func);
+
+            let mut order = CfgOrder::new();
+            order.update(&cfg);
+            assert!(order.is_valid_for(&cfg));
+            assert!(!order.is_valid_for(&other));
+
+            // An unmodified clone is in the same state as the original.
+            let mut cloned = cfg.clone();
+            assert!(order.is_valid_for(&cloned));
+            cloned.remove_edge(a, b);
+            assert!(!order.is_valid_for(&cloned));
+            assert!(order.is_valid_for(&cfg));
+        });
+    }
+
+    #[test]
+    fn rpo_from_loop_header() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        build_cfg! { builder;
+            a -> [b];
+            b -> [c, e];
+            c -> [d];
+            d -> [b];
+            e -> [];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let mut cfg = ControlFlowGraph::new();
+        module
+            .func_store
+            .view(module.funcs()[0], |func| cfg.compute(func));
+
+        let rpo = cfg.rpo_from(b);
+        assert_eq!(rpo[0], b);
+        assert!(!rpo.contains(&a));
+        assert_eq!(rpo.len(), 4);
+
+        // `d` is reachable only via `c`.
+        let pos = |block| rpo.iter().position(|&x| x == block).unwrap();
+        assert!(pos(c) < pos(d));
+
+        assert_eq!(cfg.rpo_from(d), [d, b, c, e]);
+    }
+
+    #[test]
+    fn adjacency_matrix_if_else() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        build_cfg! { builder;
+            a -> [b, c];
+            b -> [d];
+            c -> [d];
+            d -> [];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let mut cfg = ControlFlowGraph::new();
+        module
+            .func_store
+            .view(module.funcs()[0], |func| cfg.compute(func));
+
+        assert_eq!(cfg.rpo_from(a), [a, b, c, d]);
+        assert_eq!(
+            cfg.adjacency_matrix(4).unwrap(),
+            [
+                [false, true, true, false],
+                [false, false, false, true],
+                [false, false, false, true],
+                [false, false, false, false],
+            ]
+        );
+        assert_eq!(
+            cfg.adjacency_matrix(3),
+            Err(TooManyBlocks {
+                block_num: 4,
+                max_blocks: 3,
+            })
+        );
+    }
+}
@@ -1,20 +1,24 @@
 //! This module contains Sonatine IR data flow graph.
-use std::{collections::BTreeSet, io};
+use std::{collections::BTreeSet, fmt, io};
 
-use cranelift_entity::{entity_impl, packed_option::PackedOption, PrimaryMap, SecondaryMap};
+use cranelift_entity::{
+    entity_impl, packed_option::PackedOption, EntityRef, PrimaryMap, SecondaryMap,
+};
 use rustc_hash::{FxHashMap, FxHashSet};
 
 use super::{Immediate, Type, Value, ValueId};
 use crate::{
     inst::{
         control_flow::{self, Branch, Jump, Phi},
-        InstId, SideEffect,
+        data::{Mload, Mstore},
+        InstId, OperandResultTy, SideEffect,
     },
     ir_writer::{FuncWriteCtx, IrWrite},
     module::ModuleCtx,
     GlobalVariableRef, Inst, InstDowncast, InstDowncastMut, InstSetBase,
 };
 
+#[derive(Clone)]
 pub struct DataFlowGraph {
     pub ctx: ModuleCtx,
     #[doc(hidden)]
@@ -27,6 +31,9 @@ pub struct DataFlowGraph {
     #[doc(hidden)]
     pub immediates: FxHashMap<Immediate, ValueId>,
     users: SecondaryMap<ValueId, BTreeSet<InstId>>,
+    srclocs: FxHashMap<InstId, SourceLoc>,
+    volatile_insts: FxHashSet<InstId>,
+    pinned_values: FxHashSet<ValueId>,
 }
 
 impl DataFlowGraph {
@@ -39,6 +46,9 @@ impl DataFlowGraph {
             inst_results: SecondaryMap::default(),
             immediates: FxHashMap::default(),
             users: SecondaryMap::default(),
+            srclocs: FxHashMap::default(),
+            volatile_insts: FxHashSet::default(),
+            pinned_values: FxHashSet::default(),
         }
     }
 
@@ -60,6 +70,11 @@ impl DataFlowGraph {
         inst_id
     }
 
+    /// Returns the value of the `imm`.
+    ///
+    /// Immediates are interned per function and are not insts, so equal
+    /// immediates always share a single value that dominates every use, and no
+    /// constant CSE is needed.
     pub fn make_imm_value<Imm>(&mut self, imm: Imm) -> ValueId
     where
         Imm: Into<Immediate>,
@@ -104,7 +119,28 @@ impl DataFlowGraph {
         self.make_value(value_data)
     }
 
+    /// Replaces the data of the inst with `new` in place.
+    ///
+    /// The inst keeps its `InstId` and result value, so users of the result
+    /// don't need to be rewritten. The caller must ensure that `new` produces
+    /// a value of the same type as the old inst.
+    /// The user sets of the operands are updated accordingly.
+    ///
+    /// # Panics
+    /// Panics if the result type of `new` is determined by its operands and
+    /// differs from the type of the result value.
     pub fn replace_inst(&mut self, inst_id: InstId, new: Box<dyn Inst>) {
+        if let Some(result) = self.inst_result(inst_id) {
+            let result_ty =
+                <&dyn OperandResultTy as InstDowncast>::downcast(self.inst_set(), new.as_ref())
+                    .map(|new| new.result_ty(self));
+            assert!(
+                result_ty.is_none_or(|ty| ty == self.value_ty(result)),
+                "`{}` doesn't produce a value of the result type",
+                new.as_text()
+            );
+        }
+
         let slot = &mut self.insts[inst_id];
         let old = &mut std::mem::replace(slot, new);
 
@@ -117,6 +153,54 @@ impl DataFlowGraph {
         self.attach_user(inst_id);
     }
 
+    /// Associates the inst with a location in the front-end source.
+    ///
+    /// The location is kept as long as the inst keeps its `InstId`, e.g., it
+    /// survives [`Self::replace_inst`] and moving the inst in the layout.
+    pub fn set_srcloc(&mut self, inst_id: InstId, loc: SourceLoc) {
+        self.srclocs.insert(inst_id, loc);
+    }
+
+    /// Returns the source location of the inst if it's set.
+    pub fn srcloc_of(&self, inst_id: InstId) -> Option<SourceLoc> {
+        self.srclocs.get(&inst_id).copied()
+    }
+
+    /// Marks the memory access as volatile.
+    ///
+    /// A volatile access is never removed even if its result is unused, and is
+    /// never reordered past another memory access.
+    ///
+    /// # Panics
+    /// Panics if the inst is neither `mload` nor `mstore`.
+    pub fn set_volatile(&mut self, inst_id: InstId) {
+        let data = self.inst(inst_id);
+        let is = self.inst_set();
+        assert!(
+            <&Mload as InstDowncast>::downcast(is, data).is_some()
+                || <&Mstore as InstDowncast>::downcast(is, data).is_some(),
+            "only `mload` and `mstore` can be volatile"
+        );
+        self.volatile_insts.insert(inst_id);
+    }
+
+    pub fn is_volatile(&self, inst_id: InstId) -> bool {
+        self.volatile_insts.contains(&inst_id)
+    }
+
+    /// Pins the value so that its definition is kept live for the backend,
+    /// even if the value is unused.
+    ///
+    /// The inst defining a pinned value is treated like a store by
+    /// [`Self::side_effect`], so it's neither removed nor moved.
+    pub fn pin_value(&mut self, value_id: ValueId) {
+        self.pinned_values.insert(value_id);
+    }
+
+    pub fn is_pinned(&self, value_id: ValueId) -> bool {
+        self.pinned_values.contains(&value_id)
+    }
+
     pub fn attach_result(&mut self, inst_id: InstId, value_id: ValueId) {
         debug_assert!(self.inst_results[inst_id].is_none());
         self.inst_results[inst_id] = value_id.into();
@@ -130,6 +214,16 @@ impl DataFlowGraph {
         self.insts[inst_id].as_ref()
     }
 
+    /// Returns a copy of the data of the inst, e.g., to duplicate the inst
+    /// into another block.
+    ///
+    /// The volatile flag and the source location of the inst and the pin of
+    /// its result aren't part of the data, so they need to be set on the inst
+    /// made from the copy.
+    pub fn clone_inst(&self, inst_id: InstId) -> Box<dyn Inst> {
+        self.insts[inst_id].clone()
+    }
+
     pub fn inst_mut(&mut self, inst_id: InstId) -> &mut dyn Inst {
         self.insts[inst_id].as_mut()
     }
@@ -180,11 +274,50 @@ impl DataFlowGraph {
         self.inst_results[inst_id].expand()
     }
 
+    /// Returns all insts that have a result together with their results, in
+    /// the order of inst ids.
+    pub fn results_iter(&self) -> impl Iterator<Item = (InstId, ValueId)> + '_ {
+        self.inst_results
+            .iter()
+            .filter_map(|(inst, result)| Some((inst, result.expand()?)))
+    }
+
     pub fn branch_info(&self, inst: InstId) -> Option<&dyn Branch> {
         let inst = self.inst(inst);
         InstDowncast::downcast(self.ctx.inst_set, inst)
     }
 
+    /// Returns the [`Terminator`](control_flow::Terminator) view of the
+    /// `inst`, or `None` if it's not a terminator.
+    pub fn as_terminator(&self, inst: InstId) -> Option<control_flow::Terminator<'_>> {
+        use control_flow::{BranchKind, Return, Terminator};
+
+        if let Some(branch) = self.branch_info(inst) {
+            return Some(match branch.branch_kind() {
+                BranchKind::Jump(jump) => Terminator::Jump { dest: *jump.dest() },
+                BranchKind::Br(br) => Terminator::Br {
+                    cond: *br.cond(),
+                    nz_dest: *br.nz_dest(),
+                    z_dest: *br.z_dest(),
+                },
+                BranchKind::BrTable(brt) => Terminator::BrTable {
+                    scrutinee: *brt.scrutinee(),
+                    default: *brt.default(),
+                    table: brt.table(),
+                },
+            });
+        }
+
+        let data = self.inst(inst);
+        if let Some(ret) = <&Return as InstDowncast>::downcast(self.ctx.inst_set, data) {
+            Some(Terminator::Return { arg: *ret.arg() })
+        } else if data.is_terminator() {
+            Some(Terminator::Exit)
+        } else {
+            None
+        }
+    }
+
     pub fn is_terminator(&self, inst: InstId) -> bool {
         self.inst(inst).is_terminator()
     }
@@ -237,6 +370,8 @@ impl DataFlowGraph {
         Jump::new(self.inst_set().jump(), to)
     }
 
+    /// Replace every use of `value` with `alias`, and move the users of
+    /// `value` to `alias`. Afterwards `value` has no user.
     pub fn change_to_alias(&mut self, value: ValueId, alias: ValueId) {
         let mut users = std::mem::take(&mut self.users[value]);
         for inst in &users {
@@ -249,8 +384,65 @@ impl DataFlowGraph {
         self.users[alias].append(&mut users);
     }
 
+    /// Rename every value `v` to `mapping(v)`. See
+    /// [`Function::rename_values`](crate::Function::rename_values).
+    pub(crate) fn rename_values(&mut self, mapping: &impl Fn(ValueId) -> ValueId) {
+        let value_num = self.values.len();
+        let mut values: Vec<Option<Value>> = vec![None; value_num];
+        let mut users = SecondaryMap::default();
+        for (value, data) in self.values.iter() {
+            let renamed = mapping(value);
+            assert!(
+                renamed.index() < value_num && values[renamed.index()].is_none(),
+                "`mapping` is not a permutation"
+            );
+            values[renamed.index()] = Some(data.clone());
+            users[renamed] = std::mem::take(&mut self.users[value]);
+        }
+
+        self.values = values.into_iter().map(Option::unwrap).collect();
+        self.users = users;
+        for value in self.immediates.values_mut() {
+            *value = mapping(*value);
+        }
+        for (_, result) in self.inst_results.iter_mut() {
+            if let Some(value) = result.expand() {
+                *result = mapping(value).into();
+            }
+        }
+        for (_, inst) in self.insts.iter_mut() {
+            inst.for_each_value_mut(&mut |value| *value = mapping(*value));
+        }
+        self.pinned_values = self.pinned_values.iter().map(|&v| mapping(v)).collect();
+    }
+
+    /// Returns the side effect of the inst.
+    ///
+    /// Insts other than memory accesses, calls and a few terminators, e.g.,
+    /// arithmetics, comparisons, casts, phis and branches, have no side effect.
+    /// A `call` has a side effect unless its callee is marked pure by
+    /// [`Signature::set_pure`](crate::Signature::set_pure).
+    ///
+    /// A volatile memory access and an inst defining a pinned value are
+    /// reported as [`SideEffect::Write`], so that passes neither remove them
+    /// nor reorder them with other memory accesses.
     pub fn side_effect(&self, inst: InstId) -> SideEffect {
-        self.inst(inst).side_effect()
+        if self.is_volatile(inst)
+            || self
+                .inst_result(inst)
+                .is_some_and(|result| self.is_pinned(result))
+        {
+            return SideEffect::Write;
+        }
+
+        let data = self.inst(inst);
+        if let Some(call) = <&control_flow::Call as InstDowncast>::downcast(self.inst_set(), data) {
+            if self.ctx.func_sig(*call.callee(), |sig| sig.is_pure()) {
+                return SideEffect::None;
+            }
+        }
+
+        data.side_effect()
     }
 
     pub fn is_branch(&self, inst: InstId) -> bool {
@@ -319,6 +511,26 @@ impl<'a> IrWrite<FuncWriteCtx<'a>> for BlockId {
     }
 }
 
+/// A location in the front-end source, which is used to map insts back to the
+/// source for diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceLoc {
+    pub line: u32,
+    pub col: u32,
+}
+
+impl SourceLoc {
+    pub fn new(line: u32, col: u32) -> Self {
+        Self { line, col }
+    }
+}
+
+impl fmt::Display for SourceLoc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
 /// A block data definition.
 /// A Block data doesn't hold any information for layout of a program. It is
 /// managed by [`super::layout::Layout`].
@@ -330,3 +542,303 @@ impl Block {
         Self::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::test_util::*,
+        func_cursor::{CursorLocation, FuncCursor, InstInserter},
+        inst::{
+            arith::{Add, Mul, Shl},
+            cmp::Eq,
+            control_flow::{Br, BrTable, Jump, Return, Terminator},
+        },
+        isa::Isa,
+    };
+
+    #[test]
+    fn immediates_are_interned_across_blocks() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, Type::I1], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let (arg, cond) = (builder.args()[0], builder.args()[1]);
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+        builder.switch_to_block(b1);
+        let seven_then = builder.make_imm_value(7i32);
+        builder.insert_inst(Add::new(is, arg, seven_then), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        let seven_else = builder.make_imm_value(7i32);
+        builder.insert_inst(Mul::new(is, arg, seven_else), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b3);
+        let seven_merge = builder.make_imm_value(7i32);
+        builder.insert_inst_no_result(Return::new(is, Some(seven_merge)));
+
+        builder.seal_all();
+        builder.finish();
+
+        assert_eq!(seven_then, seven_else);
+        assert_eq!(seven_then, seven_merge);
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            assert_eq!(func.dfg.value_imm(seven_then), Some(Immediate::I32(7)));
+            assert_eq!(func.dfg.users_num(seven_then), 3);
+        });
+    }
+
+    #[test]
+    fn replace_inst_keeps_result() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let two = builder.make_imm_value(2i32);
+        let v0 = builder.insert_inst(Mul::new(is, arg, two), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let inst = func.dfg.value_inst(v0).unwrap();
+            let one = func.dfg.make_imm_value(1i32);
+            func.dfg
+                .replace_inst(inst, Box::new(Shl::new(is, one, arg)));
+
+            assert_eq!(func.dfg.inst_result(inst), Some(v0));
+            assert_eq!(func.dfg.value_inst(v0), Some(inst));
+            assert_eq!(func.dfg.users_num(two), 0);
+            assert!(func.dfg.users(one).any(|&user| user == inst));
+            assert!(func.dfg.users(arg).any(|&user| user == inst));
+            assert_eq!(func.dfg.users_num(v0), 1);
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32) -> i32 {
+    block0:
+        v2.i32 = shl 1.i32 v0;
+        return v2;
+}
+"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "`eq` doesn't produce a value of the result type")]
+    fn replace_inst_with_different_result_type() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let two = builder.make_imm_value(2i32);
+        let v0 = builder.insert_inst(Mul::new(is, arg, two), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.modify(module.funcs()[0], |func| {
+            let inst = func.dfg.value_inst(v0).unwrap();
+            func.dfg.replace_inst(inst, Box::new(Eq::new(is, arg, two)));
+        });
+    }
+
+    #[test]
+    fn results_iter_yields_each_result_once() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        let v1 = builder.insert_inst(Mul::new(is, v0, arg), Type::I32);
+        let v2 = builder.insert_inst(Shl::new(is, v1, v0), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v2)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let results: Vec<_> = func.dfg.results_iter().collect();
+            assert_eq!(results.len(), 3);
+
+            let values: FxHashSet<_> = results.iter().map(|&(_, value)| value).collect();
+            assert_eq!(values, [v0, v1, v2].into_iter().collect());
+            for (inst, value) in results {
+                assert_eq!(func.dfg.value_inst(value), Some(inst));
+            }
+        });
+    }
+
+    #[test]
+    fn change_to_alias_moves_users() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        let v1 = builder.insert_inst(Mul::new(is, v0, v0), Type::I32);
+        let v2 = builder.insert_inst(Shl::new(is, v1, v0), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v2)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let mul = func.dfg.value_inst(v1).unwrap();
+            let shl = func.dfg.value_inst(v2).unwrap();
+            assert_eq!(func.dfg.users_num(v0), 2);
+            assert_eq!(func.dfg.users_num(arg), 1);
+
+            func.dfg.change_to_alias(v0, arg);
+            assert_eq!(func.dfg.users_num(v0), 0);
+            let mut users: Vec<_> = func.dfg.users(arg).copied().collect();
+            users.sort();
+            let add = func.dfg.value_inst(v0).unwrap();
+            assert_eq!(users, [add, mul, shl]);
+
+            // Removing an inst removes it from the users of its args.
+            InstInserter::at_location(CursorLocation::At(add)).remove_inst(func);
+            assert_eq!(func.dfg.users_num(arg), 2);
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32) -> i32 {
+    block0:
+        v2.i32 = mul v0 v0;
+        v3.i32 = shl v2 v0;
+        return v3;
+}
+"
+        );
+    }
+
+    #[test]
+    fn replace_with_imm_value() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let one = builder.make_imm_value(1i32);
+        let two = builder.make_imm_value(2i32);
+        let v0 = builder.insert_inst(Add::new(is, one, two), Type::I32);
+        let v1 = builder.insert_inst(Mul::new(is, arg, v0), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let add = func.dfg.value_inst(v0).unwrap();
+            let mul = func.dfg.value_inst(v1).unwrap();
+
+            // An immediate is a value, so it's usable anywhere without
+            // inserting an inst.
+            let three = func.dfg.make_imm_value(3i32);
+            assert_eq!(func.dfg.value_imm(three), Some(Immediate::I32(3)));
+            assert_eq!(func.dfg.make_imm_value(3i32), three);
+
+            func.dfg.change_to_alias(v0, three);
+            InstInserter::at_location(CursorLocation::At(add)).remove_inst(func);
+            assert!(func.dfg.users(three).any(|&user| user == mul));
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32) -> i32 {
+    block0:
+        v4.i32 = mul v0 3.i32;
+        return v4;
+}
+"
+        );
+    }
+
+    #[test]
+    fn br_table_as_terminator() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let one = builder.make_imm_value(1i32);
+        let two = builder.make_imm_value(2i32);
+        let table = vec![(one, b1), (two, b2)];
+        builder.insert_inst_no_result(BrTable::new(is, arg, Some(b3), table.clone()));
+        let br_table = builder.last_inst().unwrap();
+
+        for block in [b1, b2, b3] {
+            builder.switch_to_block(block);
+            builder.insert_inst_no_result(Return::new(is, Some(arg)));
+        }
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            assert_eq!(
+                func.dfg.as_terminator(br_table),
+                Some(Terminator::BrTable {
+                    scrutinee: arg,
+                    default: Some(b3),
+                    table: &table,
+                })
+            );
+
+            let ret = func.layout.last_inst_of(b1).unwrap();
+            assert_eq!(
+                func.dfg.as_terminator(ret),
+                Some(Terminator::Return { arg: Some(arg) })
+            );
+        });
+    }
+}
@@ -0,0 +1,1154 @@
+//! This module contains dominantor tree related structs.
+//!
+//! The algorithm is based on Keith D. Cooper., Timothy J. Harvey., and Ken
+//! Kennedy.: A Simple, Fast Dominance Algorithm: <https://www.cs.rice.edu/~keith/EMBED/dom.pdf>
+
+use std::{collections::BTreeSet, fmt};
+
+use crate::{BlockId, CfgOrder, ControlFlowGraph};
+use cranelift_entity::{packed_option::PackedOption, SecondaryMap};
+
+#[derive(Default, Debug)]
+pub struct DomTree {
+    entry: PackedOption<BlockId>,
+    doms: SecondaryMap<BlockId, PackedOption<BlockId>>,
+    rpo: Vec<BlockId>,
+    /// Preorder and postorder numbers of each block in the DFS of the tree,
+    /// which answer dominance queries in O(1). Unreachable blocks are
+    /// numbered `0`.
+    dfs_enter: SecondaryMap<BlockId, u32>,
+    dfs_exit: SecondaryMap<BlockId, u32>,
+    /// Children of each block in the tree, in RPO.
+    children: SecondaryMap<BlockId, Vec<BlockId>>,
+}
+
+impl DomTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.entry = None.into();
+        self.doms.clear();
+        self.rpo.clear();
+        self.dfs_enter.clear();
+        self.dfs_exit.clear();
+        self.children.clear();
+    }
+
+    /// Returns the immediate dominator of the `block`.
+    /// Returns None if the `block` is unreachable from the entry block, or the
+    /// `block` is the entry block itself.
+    pub fn idom_of(&self, block: BlockId) -> Option<BlockId> {
+        if self.entry.expand() == Some(block) {
+            return None;
+        }
+        self.doms[block].expand()
+    }
+
+    /// Returns the immediate dominator of every block as a flat array.
+    ///
+    /// Unlike [`Self::idom_of`], the entry block maps to itself, and
+    /// unreachable blocks map to `None`.
+    pub fn idom_array(&self) -> &SecondaryMap<BlockId, PackedOption<BlockId>> {
+        &self.doms
+    }
+
+    /// Returns `true` if block1 strictly dominates block2.
+    /// This is O(1) thanks to the DFS numbers computed along with the tree.
+    pub fn strictly_dominates(&self, block1: BlockId, block2: BlockId) -> bool {
+        let (enter1, enter2) = (self.dfs_enter[block1], self.dfs_enter[block2]);
+        if block1 == block2 || enter1 == 0 || enter2 == 0 {
+            return false;
+        }
+        enter1 <= enter2 && self.dfs_exit[block2] <= self.dfs_exit[block1]
+    }
+
+    /// Returns `true` if block1 dominates block2.
+    pub fn dominates(&self, block1: BlockId, block2: BlockId) -> bool {
+        if block1 == block2 {
+            return true;
+        }
+
+        self.strictly_dominates(block1, block2)
+    }
+
+    /// Returns the nearest block that dominates both `block1` and `block2`.
+    /// Returns `None` if either block is unreachable from the entry block.
+    ///
+    /// Each step up the tree is an O(1) query on the DFS numbers computed
+    /// along with the tree.
+    pub fn nearest_common_dominator(&self, block1: BlockId, block2: BlockId) -> Option<BlockId> {
+        if self.dfs_enter[block1] == 0 || self.dfs_enter[block2] == 0 {
+            return None;
+        }
+
+        let mut block = block1;
+        while !self.dominates(block, block2) {
+            block = self.idom_of(block)?;
+        }
+
+        Some(block)
+    }
+
+    /// Returns the nearest block that dominates all the `blocks`.
+    /// Returns `None` if `blocks` is empty or any of them is unreachable from
+    /// the entry block.
+    pub fn nearest_common_dominator_of(&self, blocks: &[BlockId]) -> Option<BlockId> {
+        let (&first, rest) = blocks.split_first()?;
+        rest.iter().try_fold(first, |acc, &block| {
+            self.nearest_common_dominator(acc, block)
+        })
+    }
+
+    /// Returns the immediate dominator shared by `block1` and `block2`.
+    /// Returns `None` if their immediate dominators differ, or either block
+    /// has none, i.e., it's unreachable or the entry block.
+    pub fn common_idom(&self, block1: BlockId, block2: BlockId) -> Option<BlockId> {
+        let idom = self.idom_of(block1)?;
+        (self.idom_of(block2)? == idom).then_some(idom)
+    }
+
+    /// Returns `true` if `block1` and `block2` are distinct blocks with the
+    /// same immediate dominator.
+    /// Returns `false` if either block is unreachable.
+    pub fn are_siblings(&self, block1: BlockId, block2: BlockId) -> bool {
+        block1 != block2 && self.common_idom(block1, block2).is_some()
+    }
+
+    /// Returns the blocks dominated by the `block` in preorder, i.e., the
+    /// subtree rooted at the `block`, including the `block` itself.
+    /// Returns nothing if the `block` is unreachable.
+    ///
+    /// Only the subtree is visited, lazily, with an explicit stack.
+    pub fn dominated_by(&self, block: BlockId) -> impl Iterator<Item = BlockId> + '_ {
+        let root = (self.dfs_enter[block] != 0).then_some(block);
+        walk_subtree(&self.children, root)
+    }
+
+    /// Returns the blocks whose immediate dominator differs between `self` and
+    /// `other`, e.g., the trees before and after a CFG edit, sorted by
+    /// `BlockId`. Each entry holds the block and its immediate dominators in
+    /// `self` and `other` in this order. See [`Self::idom_of`] for when the
+    /// immediate dominator is `None`.
+    pub fn diff(&self, other: &DomTree) -> Vec<(BlockId, Option<BlockId>, Option<BlockId>)> {
+        let blocks: BTreeSet<_> = self.doms.keys().chain(other.doms.keys()).collect();
+        blocks
+            .into_iter()
+            .filter_map(|block| {
+                let (old, new) = (self.idom_of(block), other.idom_of(block));
+                (old != new).then_some((block, old, new))
+            })
+            .collect()
+    }
+
+    /// Returns the entry block, i.e., the root of the tree.
+    pub fn entry(&self) -> Option<BlockId> {
+        self.entry.expand()
+    }
+
+    pub fn compute(&mut self, cfg: &ControlFlowGraph) {
+        self.compute_with_order(cfg, cfg.order());
+    }
+
+    /// Compute the dominator tree reusing the traversal cached in `order`,
+    /// which must be up to date with `cfg`.
+    pub fn compute_with_order(&mut self, cfg: &ControlFlowGraph, order: &CfgOrder) {
+        debug_assert!(order.is_valid_for(cfg));
+        self.clear();
+
+        self.rpo.extend_from_slice(order.rpo());
+
+        let block_num = self.rpo.len();
+
+        if self.doms.capacity() < block_num {
+            self.doms = SecondaryMap::with_capacity(block_num);
+        } else {
+            self.doms.clear();
+        }
+
+        let rpo_nums = self.rpo_nums();
+
+        match cfg.entry() {
+            Some(entry) => {
+                self.entry = entry.into();
+                self.doms[entry] = entry.into();
+            }
+            None => return,
+        }
+
+        let rpo = std::mem::take(&mut self.rpo);
+        self.solve(cfg, &rpo[1..], &rpo_nums);
+        self.rpo = rpo;
+
+        self.compute_dfs_numbers();
+    }
+
+    /// Update the tree after the edge `from -> to` is added to `cfg`.
+    ///
+    /// Only the blocks strictly dominated by the nearest common dominator of
+    /// `from` and `to` may change their immediate dominators, so the rest of
+    /// the tree is kept as is. Falls back to [`Self::compute`] if the edge
+    /// makes `to` reachable.
+    pub fn update_after_edge_insertion(
+        &mut self,
+        cfg: &ControlFlowGraph,
+        from: BlockId,
+        to: BlockId,
+    ) {
+        if self.entry.expand() != cfg.entry() {
+            self.compute(cfg);
+            return;
+        }
+        if self.doms[from].is_none() {
+            return;
+        }
+        if self.doms[to].is_none() {
+            self.compute(cfg);
+            return;
+        }
+
+        let root = self.nearest_common_dominator(from, to).unwrap();
+        self.recompute_subtree(cfg, root);
+    }
+
+    /// Update the tree after the edge `from -> to` is removed from `cfg`.
+    ///
+    /// Only the blocks strictly dominated by the immediate dominator of `to`
+    /// may change their immediate dominators, so the rest of the tree is kept
+    /// as is. Falls back to [`Self::compute`] if the removal makes any block
+    /// unreachable.
+    pub fn update_after_edge_removal(
+        &mut self,
+        cfg: &ControlFlowGraph,
+        from: BlockId,
+        to: BlockId,
+    ) {
+        if self.entry.expand() != cfg.entry() {
+            self.compute(cfg);
+            return;
+        }
+        if self.doms[from].is_none() {
+            return;
+        }
+
+        // The immediate dominator of `to` dominates `from`, too.
+        let root = self.doms[to].unwrap();
+        self.recompute_subtree(cfg, root);
+    }
+
+    /// Recompute the immediate dominators of the blocks strictly dominated by
+    /// `root`, assuming that the others are still valid for `cfg`.
+    ///
+    /// The RPO is recomputed as a whole since a single edge may reorder
+    /// unrelated blocks.
+    fn recompute_subtree(&mut self, cfg: &ControlFlowGraph, root: BlockId) {
+        let order = cfg.order();
+        if order.rpo().len() != self.rpo.len() {
+            self.compute_with_order(cfg, order);
+            return;
+        }
+
+        let subtree: Vec<_> = order
+            .rpo()
+            .iter()
+            .copied()
+            .filter(|&block| self.strictly_dominates(root, block))
+            .collect();
+        for &block in &subtree {
+            self.doms[block] = None.into();
+        }
+
+        self.rpo.clear();
+        self.rpo.extend_from_slice(order.rpo());
+        let rpo_nums = self.rpo_nums();
+        self.solve(cfg, &subtree, &rpo_nums);
+
+        self.compute_dfs_numbers();
+    }
+
+    /// Iterate the immediate dominators of `blocks` in RPO until a fixpoint.
+    ///
+    /// The `blocks` must be entered only through the blocks whose dominators
+    /// are already known, e.g., the subtree of a block in the tree.
+    fn solve(
+        &mut self,
+        cfg: &ControlFlowGraph,
+        blocks: &[BlockId],
+        rpo_nums: &SecondaryMap<BlockId, u32>,
+    ) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in blocks {
+                let preds = cfg.preds_slice(block);
+                let processed_pred = match preds.iter().find(|&&pred| self.doms[pred].is_some()) {
+                    Some(pred) => *pred,
+                    _ => continue,
+                };
+                let mut new_dom = processed_pred;
+
+                for &pred in preds {
+                    if pred != processed_pred && self.doms[pred].is_some() {
+                        new_dom = self.intersect(new_dom, pred, rpo_nums);
+                    }
+                }
+                if Some(new_dom) != self.doms[block].expand() {
+                    changed = true;
+                    self.doms[block] = new_dom.into();
+                }
+            }
+        }
+    }
+
+    /// Number the blocks so that a block in `rpo` has a larger number than its
+    /// successors, except for the back edges.
+    fn rpo_nums(&self) -> SecondaryMap<BlockId, u32> {
+        let block_num = self.rpo.len();
+        let mut rpo_nums = SecondaryMap::with_capacity(block_num);
+        for (i, &block) in self.rpo.iter().enumerate() {
+            rpo_nums[block] = (block_num - i) as u32;
+        }
+        rpo_nums
+    }
+
+    /// Compute dominance frontiers of each blocks.
+    pub fn compute_df(&self, cfg: &ControlFlowGraph) -> DFSet {
+        let mut df = DFSet {
+            rpo: self.rpo.clone(),
+            ..Default::default()
+        };
+
+        for &block in &self.rpo {
+            if cfg.pred_num_of(block) < 2 {
+                continue;
+            }
+            for pred in cfg.preds_of(block) {
+                let mut runner = *pred;
+                while PackedOption::from(runner) != self.doms[block] && self.is_reachable(runner) {
+                    df.frontiers[runner].insert(block);
+                    runner = self.doms[runner].unwrap();
+                }
+            }
+        }
+
+        df
+    }
+
+    /// Returns `true` if block is reachable from the entry block.
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.idom_of(block).is_some()
+    }
+
+    /// Returns blocks in RPO.
+    pub fn rpo(&self) -> &[BlockId] {
+        &self.rpo
+    }
+
+    /// Number the blocks in the DFS of the tree so that `block1` dominates
+    /// `block2` iff the enter/exit interval of `block1` contains the one of
+    /// `block2`.
+    fn compute_dfs_numbers(&mut self) {
+        let Some(entry) = self.entry.expand() else {
+            return;
+        };
+
+        self.children.clear();
+        for &block in self.rpo.iter().skip(1) {
+            if let Some(idom) = self.doms[block].expand() {
+                self.children[idom].push(block);
+            }
+        }
+
+        let mut counter = 1;
+        self.dfs_enter[entry] = counter;
+        // Each entry holds a block and the index of the next child to visit.
+        let mut stack = vec![(entry, 0)];
+        while let Some((block, idx)) = stack.last_mut() {
+            counter += 1;
+            match self.children[*block].get(*idx) {
+                Some(&child) => {
+                    *idx += 1;
+                    self.dfs_enter[child] = counter;
+                    stack.push((child, 0));
+                }
+                None => {
+                    self.dfs_exit[*block] = counter;
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    fn intersect(
+        &self,
+        mut b1: BlockId,
+        mut b2: BlockId,
+        rpo_nums: &SecondaryMap<BlockId, u32>,
+    ) -> BlockId {
+        while b1 != b2 {
+            while rpo_nums[b1] < rpo_nums[b2] {
+                b1 = self.doms[b1].unwrap();
+            }
+            while rpo_nums[b2] < rpo_nums[b1] {
+                b2 = self.doms[b2].unwrap();
+            }
+        }
+
+        b1
+    }
+}
+
+/// Prints the immediate dominator of each block reachable from the entry
+/// block in RPO, e.g., `block1: idom block0`.
+impl fmt::Display for DomTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &block in &self.rpo {
+            match self.idom_of(block) {
+                Some(idom) => writeln!(f, "{block}: idom {idom}")?,
+                None => writeln!(f, "{block}: entry")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Dominance frontiers of each blocks.
+#[derive(Default, Debug)]
+pub struct DFSet {
+    frontiers: SecondaryMap<BlockId, BTreeSet<BlockId>>,
+    /// Blocks reachable from the entry block in RPO.
+    rpo: Vec<BlockId>,
+}
+
+impl DFSet {
+    pub fn frontiers(&self, block: BlockId) -> impl Iterator<Item = &BlockId> {
+        self.frontiers[block].iter()
+    }
+
+    /// Returns the frontiers of all blocks reachable from the entry block,
+    /// including the ones with no frontier, in RPO.
+    pub fn iter(&self) -> impl Iterator<Item = (BlockId, &BTreeSet<BlockId>)> {
+        self.rpo
+            .iter()
+            .map(|&block| (block, &self.frontiers[block]))
+    }
+
+    pub fn in_frontier_of(&self, block: BlockId, of: BlockId) -> bool {
+        self.frontiers[of].contains(&block)
+    }
+
+    pub fn frontier_num_of(&self, of: BlockId) -> usize {
+        self.frontiers[of].len()
+    }
+
+    pub fn clear(&mut self) {
+        self.frontiers.clear();
+        self.rpo.clear();
+    }
+}
+
+/// Prints the frontiers of each block reachable from the entry block in RPO,
+/// e.g., `block1: [block3]`.
+impl fmt::Display for DFSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (block, frontiers) in self.iter() {
+            writeln!(f, "{block}: {}", display_blocks(frontiers.iter()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats the `blocks` as `[block1, block2]`.
+#[doc(hidden)]
+pub fn display_blocks<'a>(blocks: impl Iterator<Item = &'a BlockId>) -> String {
+    let blocks: Vec<_> = blocks.map(|block| block.to_string()).collect();
+    format!("[{}]", blocks.join(", "))
+}
+
+#[derive(Default)]
+pub struct DominatorTreeTraversable {
+    children: SecondaryMap<BlockId, Vec<BlockId>>,
+}
+
+impl DominatorTreeTraversable {
+    pub fn compute(&mut self, domtree: &DomTree) {
+        for &block in domtree.rpo() {
+            if let Some(idom) = domtree.idom_of(block) {
+                self.children[idom].push(block)
+            }
+        }
+    }
+
+    pub fn children_of(&self, block: BlockId) -> &[BlockId] {
+        &self.children[block]
+    }
+
+    /// Returns the blocks in the subtree rooted at `root` in preorder.
+    ///
+    /// The traversal uses an explicit stack, so it doesn't overflow the native
+    /// stack even if the tree is very deep.
+    pub fn preorder(&self, root: BlockId) -> Vec<BlockId> {
+        let mut order = Vec::new();
+        let mut stack = vec![root];
+        while let Some(block) = stack.pop() {
+            order.push(block);
+            // Push in reverse so that children are visited in order.
+            stack.extend(self.children[block].iter().rev());
+        }
+
+        order
+    }
+
+    /// Returns the blocks in the subtree rooted at `root` in postorder.
+    ///
+    /// The traversal uses an explicit stack, so it doesn't overflow the native
+    /// stack even if the tree is very deep.
+    pub fn postorder(&self, root: BlockId) -> Vec<BlockId> {
+        let mut order = Vec::new();
+        // Each entry holds a block and the index of the next child to visit.
+        let mut stack = vec![(root, 0)];
+        while let Some((block, idx)) = stack.last_mut() {
+            match self.children[*block].get(*idx) {
+                Some(&child) => {
+                    *idx += 1;
+                    stack.push((child, 0));
+                }
+                None => {
+                    order.push(*block);
+                    stack.pop();
+                }
+            }
+        }
+
+        order
+    }
+
+    /// Returns the blocks strictly dominated by the `block` in preorder, i.e.,
+    /// the subtree rooted at the `block` excluding the `block` itself.
+    ///
+    /// This is the same walk as [`DomTree::dominated_by`] without the `block`.
+    pub fn dominated_blocks(&self, block: BlockId) -> impl Iterator<Item = BlockId> + '_ {
+        walk_subtree(&self.children, Some(block)).skip(1)
+    }
+
+    pub fn clear(&mut self) {
+        self.children.clear();
+    }
+}
+
+/// Returns the blocks in the subtree rooted at `root` in preorder, where
+/// `children` maps each block to its children in the tree.
+fn walk_subtree(
+    children: &SecondaryMap<BlockId, Vec<BlockId>>,
+    root: Option<BlockId>,
+) -> impl Iterator<Item = BlockId> + '_ {
+    let mut stack: Vec<_> = root.into_iter().collect();
+    std::iter::from_fn(move || {
+        let block = stack.pop()?;
+        // Push in reverse so that children are visited in order.
+        stack.extend(children[block].iter().rev());
+        Some(block)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::many_single_char_names)]
+
+    use crate::{
+        builder::test_util::*,
+        inst::control_flow::{Br, BrTable, Jump, Return},
+        prelude::*,
+        Function, Type,
+    };
+
+    use super::*;
+
+    fn calc_dom(func: &Function) -> (DomTree, DFSet) {
+        let mut cfg = ControlFlowGraph::default();
+        cfg.compute(func);
+        let mut dom_tree = DomTree::default();
+        dom_tree.compute(&cfg);
+        let df = dom_tree.compute_df(&cfg);
+        (dom_tree, df)
+    }
+
+    /// Returns `true` if block1 strictly dominates block2 by walking up the tree
+    /// from block2.
+    fn strictly_dominates_by_walk(dom_tree: &DomTree, block1: BlockId, block2: BlockId) -> bool {
+        let mut current_block = block2;
+        while let Some(block) = dom_tree.idom_of(current_block) {
+            if block == block1 {
+                return true;
+            }
+            current_block = block;
+        }
+
+        false
+    }
+
+    fn test_df(df: &DFSet, of: BlockId, frontiers: &[BlockId]) -> bool {
+        if df.frontier_num_of(of) != frontiers.len() {
+            return false;
+        }
+
+        for &block in frontiers {
+            if !df.in_frontier_of(block, of) {
+                return false;
+            }
+        }
+        true
+    }
+
+    #[test]
+    fn idom_array() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        build_cfg! { builder;
+            a -> [b, c];
+            b -> [d];
+            c -> [d, e];
+            d -> [];
+            e -> [];
+            f -> [d];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let (dom_tree, _) = module.func_store.view(func_ref, calc_dom);
+
+        let idoms = dom_tree.idom_array();
+        assert_eq!(idoms[a].expand(), Some(a));
+        assert_eq!(idoms[f].expand(), None);
+        for block in [b, c, d, e, f] {
+            assert_eq!(idoms[block].expand(), dom_tree.idom_of(block));
+        }
+    }
+
+    #[test]
+    fn diff_after_adding_edge() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        build_cfg! { builder;
+            a -> [b, d];
+            b -> [c];
+            c -> [e];
+            d -> [];
+            e -> [];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let mut cfg = ControlFlowGraph::new();
+        module.func_store.view(func_ref, |func| cfg.compute(func));
+
+        let mut old = DomTree::new();
+        old.compute(&cfg);
+        cfg.add_edge(d, c);
+        let mut new = DomTree::new();
+        new.compute(&cfg);
+
+        assert_eq!(old.diff(&new), [(c, Some(b), Some(a))]);
+        assert!(new.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn traverse_deep_chain() {
+        const DEPTH: usize = 50_000;
+
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let blocks: Vec<_> = (0..DEPTH).map(|_| builder.append_block()).collect();
+        for pair in blocks.windows(2) {
+            builder.switch_to_block(pair[0]);
+            builder.insert_inst_no_result(Jump::new(is, pair[1]));
+        }
+        builder.switch_to_block(blocks[DEPTH - 1]);
+        builder.insert_inst_no_result(Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let (dom_tree, _) = module.func_store.view(func_ref, calc_dom);
+
+        let mut traversable = DominatorTreeTraversable::default();
+        traversable.compute(&dom_tree);
+
+        assert_eq!(traversable.preorder(blocks[0]), blocks);
+        let mut postorder = traversable.postorder(blocks[0]);
+        postorder.reverse();
+        assert_eq!(postorder, blocks);
+    }
+
+    #[test]
+    fn dom_tree_if_else() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let entry_block = builder.append_block();
+        let then_block = builder.append_block();
+        let else_block = builder.append_block();
+        let merge_block = builder.append_block();
+
+        builder.switch_to_block(entry_block);
+        let v0 = builder.make_imm_value(true);
+        builder.insert_inst_no_result_with(|| Br::new(is, v0, else_block, then_block));
+
+        builder.switch_to_block(then_block);
+        builder.insert_inst_no_result_with(|| Jump::new(is, merge_block));
+
+        builder.switch_to_block(else_block);
+        builder.insert_inst_no_result_with(|| Jump::new(is, merge_block));
+
+        builder.switch_to_block(merge_block);
+        builder.insert_inst_no_result_with(|| Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let (dom_tree, df) = module.func_store.view(func_ref, calc_dom);
+
+        assert_eq!(dom_tree.idom_of(entry_block), None);
+        assert_eq!(dom_tree.idom_of(then_block), Some(entry_block));
+        assert_eq!(dom_tree.idom_of(else_block), Some(entry_block));
+        assert_eq!(dom_tree.idom_of(merge_block), Some(entry_block));
+
+        assert!(dom_tree.are_siblings(then_block, else_block));
+        assert!(dom_tree.are_siblings(then_block, merge_block));
+        assert!(!dom_tree.are_siblings(then_block, then_block));
+        assert!(!dom_tree.are_siblings(entry_block, then_block));
+        assert_eq!(
+            dom_tree.common_idom(then_block, else_block),
+            Some(entry_block)
+        );
+        assert_eq!(dom_tree.common_idom(entry_block, then_block), None);
+
+        assert!(test_df(&df, entry_block, &[]));
+        assert!(test_df(&df, then_block, &[merge_block]));
+        assert!(test_df(&df, else_block, &[merge_block]));
+        assert!(test_df(&df, merge_block, &[]));
+
+        assert_eq!(
+            dom_tree.to_string(),
+            "block0: entry
+block1: idom block0
+block2: idom block0
+block3: idom block0
+"
+        );
+        assert_eq!(
+            df.to_string(),
+            "block0: []
+block1: [block3]
+block2: [block3]
+block3: []
+"
+        );
+    }
+
+    #[test]
+    fn unreachable_edge() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let a = builder.append_block();
+        let b = builder.append_block();
+        let c = builder.append_block();
+        let d = builder.append_block();
+        let e = builder.append_block();
+
+        builder.switch_to_block(a);
+        let v0 = builder.make_imm_value(true);
+        builder.insert_inst_no_result_with(|| Br::new(is, v0, b, c));
+
+        builder.switch_to_block(b);
+        builder.insert_inst_no_result_with(|| Jump::new(is, e));
+
+        builder.switch_to_block(c);
+        builder.insert_inst_no_result_with(|| Jump::new(is, e));
+
+        builder.switch_to_block(d);
+        builder.insert_inst_no_result_with(|| Jump::new(is, e));
+
+        builder.switch_to_block(e);
+        builder.insert_inst_no_result_with(|| Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let (dom_tree, df) = module.func_store.view(func_ref, calc_dom);
+
+        assert_eq!(dom_tree.idom_of(a), None);
+        assert_eq!(dom_tree.idom_of(b), Some(a));
+        assert_eq!(dom_tree.idom_of(c), Some(a));
+        assert_eq!(dom_tree.idom_of(d), None);
+        assert!(!dom_tree.is_reachable(d));
+        assert_eq!(dom_tree.idom_of(e), Some(a));
+        assert!(dom_tree.are_siblings(b, c));
+        assert!(!dom_tree.are_siblings(b, d));
+        assert_eq!(dom_tree.nearest_common_dominator(b, c), Some(a));
+        assert_eq!(dom_tree.nearest_common_dominator(b, d), None);
+        assert_eq!(dom_tree.nearest_common_dominator_of(&[d, a]), None);
+
+        assert!(test_df(&df, a, &[]));
+        assert!(test_df(&df, b, &[e]));
+        assert!(test_df(&df, c, &[e]));
+        assert!(test_df(&df, d, &[]));
+        assert!(test_df(&df, e, &[]));
+
+        let mut traversable = DominatorTreeTraversable::default();
+        traversable.compute(&dom_tree);
+        let mut dominated: Vec<_> = traversable.dominated_blocks(a).collect();
+        dominated.sort();
+        assert_eq!(dominated, [b, c, e]);
+        assert!(traversable
+            .dominated_blocks(a)
+            .eq(dom_tree.dominated_by(a).skip(1)));
+        assert_eq!(dom_tree.dominated_by(d).count(), 0);
+    }
+
+    #[test]
+    fn df_iter_in_rpo() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let a = builder.append_block();
+        let b = builder.append_block();
+        let c = builder.append_block();
+        let d = builder.append_block();
+        let e = builder.append_block();
+
+        builder.switch_to_block(a);
+        builder.insert_inst_no_result_with(|| Jump::new(is, c));
+
+        builder.switch_to_block(b);
+        let v0 = builder.make_imm_value(true);
+        builder.insert_inst_no_result_with(|| Br::new(is, v0, e, c));
+
+        builder.switch_to_block(c);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b));
+
+        builder.switch_to_block(d);
+        builder.insert_inst_no_result_with(|| Jump::new(is, e));
+
+        builder.switch_to_block(e);
+        builder.insert_inst_no_result_with(|| Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let (_, df) = module.func_store.view(func_ref, calc_dom);
+
+        // The only RPO is `a, c, b, e`, which differs from the layout order,
+        // and the unreachable `d` is skipped.
+        let frontiers: Vec<_> = df
+            .iter()
+            .map(|(block, frontiers)| (block, frontiers.iter().copied().collect::<Vec<_>>()))
+            .collect();
+        assert_eq!(
+            frontiers,
+            [(a, vec![]), (c, vec![c]), (b, vec![c]), (e, vec![])]
+        );
+    }
+
+    #[test]
+    fn dom_tree_complex() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let a = builder.append_block();
+        let b = builder.append_block();
+        let c = builder.append_block();
+        let d = builder.append_block();
+        let e = builder.append_block();
+        let f = builder.append_block();
+        let g = builder.append_block();
+        let h = builder.append_block();
+        let i = builder.append_block();
+        let j = builder.append_block();
+        let k = builder.append_block();
+        let l = builder.append_block();
+        let m = builder.append_block();
+
+        builder.switch_to_block(a);
+        let v0 = builder.make_imm_value(true);
+        builder.insert_inst_no_result_with(|| Br::new(is, v0, c, b));
+
+        builder.switch_to_block(b);
+        builder.insert_inst_no_result_with(|| Br::new(is, v0, g, d));
+
+        builder.switch_to_block(c);
+        builder.insert_inst_no_result_with(|| Br::new(is, v0, h, e));
+
+        builder.switch_to_block(d);
+        builder.insert_inst_no_result_with(|| Br::new(is, v0, g, f));
+
+        builder.switch_to_block(e);
+        builder.insert_inst_no_result_with(|| Br::new(is, v0, h, c));
+
+        builder.switch_to_block(f);
+        builder.insert_inst_no_result_with(|| Br::new(is, v0, k, i));
+
+        builder.switch_to_block(g);
+        builder.insert_inst_no_result_with(|| Jump::new(is, j));
+
+        builder.switch_to_block(h);
+        builder.insert_inst_no_result_with(|| Jump::new(is, m));
+
+        builder.switch_to_block(i);
+        builder.insert_inst_no_result_with(|| Jump::new(is, l));
+
+        builder.switch_to_block(j);
+        builder.insert_inst_no_result_with(|| Jump::new(is, i));
+
+        builder.switch_to_block(k);
+        builder.insert_inst_no_result_with(|| Jump::new(is, l));
+
+        builder.switch_to_block(l);
+        builder.insert_inst_no_result_with(|| Br::new(is, v0, m, b));
+
+        builder.switch_to_block(m);
+        builder.insert_inst_no_result_with(|| Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let (dom_tree, df) = module.func_store.view(func_ref, calc_dom);
+
+        assert_eq!(dom_tree.idom_of(a), None);
+        assert_eq!(dom_tree.idom_of(b), Some(a));
+        assert_eq!(dom_tree.idom_of(c), Some(a));
+        assert_eq!(dom_tree.idom_of(d), Some(b));
+        assert_eq!(dom_tree.idom_of(e), Some(c));
+        assert_eq!(dom_tree.idom_of(f), Some(d));
+        assert_eq!(dom_tree.idom_of(g), Some(b));
+        assert_eq!(dom_tree.idom_of(h), Some(c));
+        assert_eq!(dom_tree.idom_of(i), Some(b));
+        assert_eq!(dom_tree.idom_of(j), Some(g));
+        assert_eq!(dom_tree.idom_of(k), Some(f));
+
+        assert!(test_df(&df, a, &[]));
+        assert!(test_df(&df, b, &[b, m]));
+        assert!(test_df(&df, c, &[c, m]));
+        assert!(test_df(&df, d, &[g, i, l]));
+        assert!(test_df(&df, e, &[c, h]));
+        assert!(test_df(&df, f, &[i, l]));
+        assert!(test_df(&df, g, &[i]));
+        assert!(test_df(&df, h, &[m]));
+        assert!(test_df(&df, i, &[l]));
+        assert!(test_df(&df, j, &[i]));
+        assert!(test_df(&df, k, &[l]));
+        assert!(test_df(&df, l, &[b, m]));
+        assert!(test_df(&df, m, &[]));
+
+        let mut dominated: Vec<_> = dom_tree.dominated_by(b).collect();
+        assert_eq!(dominated[0], b);
+        dominated.sort();
+        assert_eq!(dominated, [b, d, f, g, i, j, k, l]);
+        assert!(dom_tree.dominated_by(k).eq([k]));
+
+        let mut traversable = DominatorTreeTraversable::default();
+        traversable.compute(&dom_tree);
+        let preorder = traversable.preorder(b);
+        let mut sorted = preorder.clone();
+        sorted.sort();
+        assert_eq!(sorted, [b, d, f, g, i, j, k, l]);
+        assert_eq!(preorder[0], b);
+        // Each subtree occupies a contiguous range right after its root.
+        for (pos, &block) in preorder.iter().enumerate() {
+            let subtree_len = traversable.dominated_blocks(block).count();
+            assert!(preorder[pos + 1..=pos + subtree_len]
+                .iter()
+                .all(|&dominated| dom_tree.strictly_dominates(block, dominated)));
+        }
+        assert!(traversable
+            .dominated_blocks(b)
+            .eq(preorder[1..].iter().copied()));
+        assert_eq!(traversable.dominated_blocks(k).count(), 0);
+        let mut postorder = traversable.postorder(b);
+        postorder.sort();
+        assert_eq!(postorder, sorted);
+
+        assert_eq!(dom_tree.nearest_common_dominator(j, k), Some(b));
+        assert_eq!(dom_tree.nearest_common_dominator(f, k), Some(f));
+        assert_eq!(dom_tree.nearest_common_dominator(h, j), Some(a));
+        assert_eq!(dom_tree.nearest_common_dominator_of(&[j, k, i]), Some(b));
+        assert_eq!(dom_tree.nearest_common_dominator_of(&[e]), Some(e));
+        assert_eq!(dom_tree.nearest_common_dominator_of(&[]), None);
+
+        let blocks = [a, b, c, d, e, f, g, h, i, j, k, l, m];
+        for block1 in blocks {
+            for block2 in blocks {
+                assert_eq!(
+                    dom_tree.strictly_dominates(block1, block2),
+                    strictly_dominates_by_walk(&dom_tree, block1, block2),
+                    "{block1} sdom {block2}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn dom_tree_br_table() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let a = builder.append_block();
+        let b = builder.append_block();
+        let c = builder.append_block();
+        let d = builder.append_block();
+        let e = builder.append_block();
+        let f = builder.append_block();
+
+        builder.switch_to_block(a);
+        let v0 = builder.make_imm_value(0i32);
+        let v1 = builder.make_imm_value(1i32);
+        let v2 = builder.make_imm_value(2i32);
+        builder
+            .insert_inst_no_result_with(|| BrTable::new(is, v0, Some(b), vec![(v1, c), (v2, d)]));
+
+        builder.switch_to_block(b);
+        let v3 = builder.make_imm_value(true);
+        builder.insert_inst_no_result_with(|| Br::new(is, v3, a, e));
+
+        builder.switch_to_block(c);
+        builder.insert_inst_no_result_with(|| Jump::new(is, f));
+
+        builder.switch_to_block(d);
+        builder.insert_inst_no_result_with(|| Jump::new(is, f));
+
+        builder.switch_to_block(e);
+        builder.insert_inst_no_result_with(|| Return::new(is, None));
+
+        builder.switch_to_block(f);
+        builder.insert_inst_no_result_with(|| Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let (dom_tree, df) = module.func_store.view(func_ref, calc_dom);
+
+        assert_eq!(dom_tree.idom_of(a), None);
+        assert_eq!(dom_tree.idom_of(b), Some(a));
+        assert_eq!(dom_tree.idom_of(c), Some(a));
+        assert_eq!(dom_tree.idom_of(d), Some(a));
+        assert_eq!(dom_tree.idom_of(e), Some(b));
+        assert_eq!(dom_tree.idom_of(f), Some(a));
+
+        assert!(test_df(&df, a, &[]));
+        assert!(test_df(&df, b, &[]));
+        assert!(test_df(&df, c, &[f]));
+        assert!(test_df(&df, d, &[f]));
+        assert!(test_df(&df, e, &[]));
+        assert!(test_df(&df, f, &[]));
+    }
+
+    #[test]
+    fn update_after_edge_edits() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        build_cfg! { builder;
+            a -> [b, d];
+            b -> [c];
+            c -> [e];
+            d -> [];
+            e -> [];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let mut cfg = ControlFlowGraph::new();
+        module.func_store.view(func_ref, |func| cfg.compute(func));
+        let mut dom_tree = DomTree::new();
+        dom_tree.compute(&cfg);
+
+        cfg.add_edge(d, c);
+        dom_tree.update_after_edge_insertion(&cfg, d, c);
+        assert_eq!(dom_tree.idom_of(c), Some(a));
+        assert_eq!(dom_tree.idom_of(e), Some(c));
+        assert!(!dom_tree.dominates(b, e));
+
+        cfg.remove_edge(b, c);
+        dom_tree.update_after_edge_removal(&cfg, b, c);
+        assert_eq!(dom_tree.idom_of(c), Some(d));
+        assert_eq!(dom_tree.idom_of(e), Some(c));
+        assert!(dom_tree.dominates(d, e));
+
+        // `b` becomes unreachable.
+        cfg.remove_edge(a, b);
+        dom_tree.update_after_edge_removal(&cfg, a, b);
+        assert!(!dom_tree.is_reachable(b));
+        assert_eq!(dom_tree.rpo(), [a, d, c, e]);
+    }
+
+    #[test]
+    fn update_matches_compute_on_random_edits() {
+        for seed in 0..200u64 {
+            let func = gen_random_function(seed, 1 + seed as usize % 16);
+            let blocks: Vec<_> = func.layout.iter_block().collect();
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(&func);
+            let mut dom_tree = DomTree::new();
+            dom_tree.compute(&cfg);
+
+            let mut state = seed ^ 0x2545_f491_4f6c_dd1d;
+            let mut rand_block = || {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                blocks[(state % blocks.len() as u64) as usize]
+            };
+
+            for _ in 0..32 {
+                let (from, to) = (rand_block(), rand_block());
+                if cfg.succs_slice(from).contains(&to) {
+                    cfg.remove_edge(from, to);
+                    dom_tree.update_after_edge_removal(&cfg, from, to);
+                } else {
+                    cfg.add_edge(from, to);
+                    dom_tree.update_after_edge_insertion(&cfg, from, to);
+                }
+
+                let mut expected = DomTree::new();
+                expected.compute(&cfg);
+                assert_eq!(dom_tree.rpo(), expected.rpo(), "seed {seed}");
+                assert!(dom_tree.diff(&expected).is_empty(), "seed {seed}");
+                for &block1 in &blocks {
+                    for &block2 in &blocks {
+                        assert_eq!(
+                            dom_tree.dominates(block1, block2),
+                            expected.dominates(block1, block2),
+                            "seed {seed}: {block1:?} and {block2:?}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -25,6 +25,9 @@ pub trait FuncCursor {
         self.set_location(loc);
     }
 
+    /// Insert the `inst` after the cursor location, i.e., after the inst the
+    /// cursor points to, at the top of the block, or at the bottom of the
+    /// block. The cursor location is unchanged.
     fn insert_inst(&mut self, func: &mut Function, inst: InstId) {
         match self.loc() {
             CursorLocation::At(at) => func.layout.insert_inst_after(inst, at),
@@ -34,6 +37,18 @@ pub trait FuncCursor {
         }
     }
 
+    /// Insert the `inst` before the cursor location, i.e., before the inst the
+    /// cursor points to, at the top of the block, or at the bottom of the
+    /// block. The cursor location is unchanged.
+    fn insert_inst_before(&mut self, func: &mut Function, inst: InstId) {
+        match self.loc() {
+            CursorLocation::At(at) => func.layout.insert_inst_before(inst, at),
+            CursorLocation::BlockTop(block) => func.layout.prepend_inst(inst, block),
+            CursorLocation::BlockBottom(block) => func.layout.append_inst(inst, block),
+            CursorLocation::NoWhere => panic!("cursor loc points to `NoWhere`"),
+        }
+    }
+
     fn append_inst(&mut self, func: &mut Function, inst: InstId) {
         let current_block = self.expect_block(func);
         func.layout.append_inst(inst, current_block);
@@ -54,6 +69,16 @@ pub trait FuncCursor {
         inst
     }
 
+    /// Make an inst from the `data` and insert it before the cursor location.
+    /// See [`FuncCursor::insert_inst_before`].
+    fn insert_inst_data_before<I: Inst>(&mut self, func: &mut Function, data: I) -> InstId {
+        let inst = func.dfg.make_inst(data);
+        self.insert_inst_before(func, inst);
+        inst
+    }
+
+    /// Make a result value of the `inst_id`. The value isn't the result of the
+    /// inst until it's attached with [`FuncCursor::attach_result`].
     fn make_result(&self, func: &mut Function, inst_id: InstId, ty: Type) -> ValueId {
         let result = Value::Inst { inst: inst_id, ty };
         func.dfg.make_value(result)
@@ -224,6 +249,33 @@ pub trait FuncCursor {
         self.set_location(self.prev_loc(func));
     }
 
+    /// Move the cursor to the next inst in the current block and return it.
+    /// Returns `None` and moves the cursor to the bottom of the block if there
+    /// is no more inst, so that the insts of a block can be visited by
+    /// `while let Some(inst) = cursor.next_inst(func)` starting from the top
+    /// of the block.
+    fn next_inst(&mut self, func: &Function) -> Option<InstId> {
+        let next = match self.loc() {
+            CursorLocation::BlockBottom(_) | CursorLocation::NoWhere => return None,
+            _ => self.next_loc(func),
+        };
+        self.set_location(next);
+        self.inst()
+    }
+
+    /// Move the cursor to the previous inst in the current block and return
+    /// it. Returns `None` and moves the cursor to the top of the block if
+    /// there is no more inst. This is the backward counterpart of
+    /// [`FuncCursor::next_inst`].
+    fn prev_inst(&mut self, func: &Function) -> Option<InstId> {
+        let prev = match self.loc() {
+            CursorLocation::BlockTop(_) | CursorLocation::NoWhere => return None,
+            _ => self.prev_loc(func),
+        };
+        self.set_location(prev);
+        self.inst()
+    }
+
     fn next_block(&self, func: &Function) -> Option<BlockId> {
         let block = self.block(func)?;
         func.layout.next_block_of(block)
@@ -253,3 +305,166 @@ impl FuncCursor for InstInserter {
         self.loc
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::test_util::*,
+        inst::{
+            arith::Add,
+            control_flow::{Jump, Return},
+        },
+        isa::Isa,
+    };
+
+    fn collect_forward(func: &Function, block: BlockId) -> Vec<InstId> {
+        let mut cursor = InstInserter::at_location(CursorLocation::BlockTop(block));
+        let mut insts = Vec::new();
+        while let Some(inst) = cursor.next_inst(func) {
+            insts.push(inst);
+        }
+        assert_eq!(cursor.loc(), CursorLocation::BlockBottom(block));
+        insts
+    }
+
+    fn collect_backward(func: &Function, block: BlockId) -> Vec<InstId> {
+        let mut cursor = InstInserter::at_location(CursorLocation::BlockBottom(block));
+        let mut insts = Vec::new();
+        while let Some(inst) = cursor.prev_inst(func) {
+            insts.push(inst);
+        }
+        assert_eq!(cursor.loc(), CursorLocation::BlockTop(block));
+        insts
+    }
+
+    #[test]
+    fn insert_and_remove_around_terminator() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Return::new(is, Some(arg)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let ret = func.layout.last_inst_of(b0).unwrap();
+            let mut cursor = InstInserter::at_location(CursorLocation::At(ret));
+
+            let add = cursor.insert_inst_data_before(func, Add::new(is, arg, arg));
+            let result = cursor.make_result(func, add, Type::I32);
+            cursor.attach_result(func, add, result);
+            assert_eq!(cursor.loc(), CursorLocation::At(ret));
+            assert_eq!(collect_forward(func, b0), [add, ret]);
+            assert_eq!(collect_backward(func, b0), [ret, add]);
+            assert_eq!(func.layout.last_inst_of(b0), Some(ret));
+            assert!(func.dfg.is_terminator(ret));
+
+            cursor.set_location(CursorLocation::At(add));
+            cursor.remove_inst(func);
+            assert_eq!(cursor.loc(), CursorLocation::At(ret));
+            assert!(!func.layout.is_inst_inserted(add));
+            assert_eq!(collect_forward(func, b0), [ret]);
+            assert_eq!(func.layout.last_inst_of(b0), Some(ret));
+        });
+    }
+
+    #[test]
+    fn insert_at_block_boundaries() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Return::new(is, Some(arg)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let ret = func.layout.last_inst_of(b0).unwrap();
+
+            let mut cursor = InstInserter::at_location(CursorLocation::BlockTop(b0));
+            let first = cursor.insert_inst_data_before(func, Add::new(is, arg, arg));
+            let second = cursor.insert_inst_data(func, Add::new(is, arg, arg));
+            assert_eq!(cursor.loc(), CursorLocation::BlockTop(b0));
+
+            // Iteration can change direction in the middle of the block.
+            assert_eq!(cursor.next_inst(func), Some(second));
+            assert_eq!(cursor.next_inst(func), Some(first));
+            assert_eq!(cursor.prev_inst(func), Some(second));
+            assert_eq!(cursor.prev_inst(func), None);
+            assert_eq!(cursor.prev_inst(func), None);
+
+            assert_eq!(collect_forward(func, b0), [second, first, ret]);
+        });
+    }
+
+    #[test]
+    fn make_block_param_and_inst_result() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let arg = builder.args()[0];
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Return::new(is, Some(arg)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            // A block param is the result of a phi at the top of the block.
+            let mut cursor = InstInserter::at_location(CursorLocation::BlockTop(b1));
+            let phi = func.dfg.make_phi(vec![(arg, b0)]);
+            let phi = cursor.prepend_inst_data(func, phi);
+            let param = cursor.make_result(func, phi, Type::I32);
+            assert_eq!(func.dfg.inst_result(phi), None);
+            cursor.attach_result(func, phi, param);
+            assert_eq!(func.dfg.inst_result(phi), Some(param));
+            assert_eq!(func.dfg.value_inst(param), Some(phi));
+            assert!(func.dfg.is_phi(phi));
+
+            // An inst result is made and attached the same way.
+            let ret = func.layout.last_inst_of(b1).unwrap();
+            cursor.set_location(CursorLocation::At(ret));
+            let add = cursor.insert_inst_data_before(func, Add::new(is, param, param));
+            let result = cursor.make_result(func, add, Type::I32);
+            cursor.attach_result(func, add, result);
+            assert_eq!(func.dfg.value_inst(result), Some(add));
+            assert_eq!(func.dfg.value_ty(result), Type::I32);
+            assert_eq!(func.dfg.users_num(param), 1);
+        });
+
+        assert_eq!(
+            dump_func(&module, func_ref),
+            "func public %test_func(v0.i32) -> i32 {
+    block0:
+        jump block1;
+
+    block1:
+        v1.i32 = phi (v0 block0);
+        v2.i32 = add v1 v1;
+        return v0;
+}
+"
+        );
+    }
+}
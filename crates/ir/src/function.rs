@@ -1,16 +1,45 @@
-use std::io;
+use std::{
+    hash::{Hash, Hasher},
+    io,
+};
 
+use cranelift_entity::SecondaryMap;
+use rustc_hash::{FxHashMap, FxHasher};
 use smallvec::SmallVec;
 
 use super::{DataFlowGraph, Layout, Type, ValueId};
-use crate::{ir_writer::IrWrite, module::ModuleCtx, InstSetBase, Linkage};
+use crate::{
+    inst::{cmp, control_flow::Br, is_commutative},
+    ir_writer::IrWrite,
+    module::{FuncRef, ModuleCtx},
+    visitor::Visitor,
+    BlockId, ControlFlowGraph, GlobalVariableRef, Immediate, Inst, InstDowncast, InstId,
+    InstSetBase, Linkage, Value,
+};
 
+#[derive(Clone)]
 pub struct Function {
     pub arg_values: smallvec::SmallVec<[ValueId; 8]>,
     pub dfg: DataFlowGraph,
     pub layout: Layout,
 }
 
+/// A flag that passes attach to a block to communicate its role to later
+/// passes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockFlag {
+    /// The block is a loop preheader.
+    Preheader,
+    /// The block is rarely executed.
+    Cold,
+}
+
+impl BlockFlag {
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
 impl Function {
     pub fn new(ctx: &ModuleCtx, sig: &Signature) -> Self {
         let mut dfg = DataFlowGraph::new(ctx.clone());
@@ -38,6 +67,671 @@ impl Function {
     pub fn inst_set(&self) -> &'static dyn InstSetBase {
         self.dfg.inst_set()
     }
+
+    /// Returns the entry block of the function, i.e., the first block in the
+    /// layout.
+    pub fn entry_block(&self) -> Option<BlockId> {
+        self.layout.entry_block()
+    }
+
+    /// Set the `flag` on the `block`. Flags survive moving the block in the
+    /// layout, and are cleared when the block is removed from the layout.
+    pub fn set_block_flag(&mut self, block: BlockId, flag: BlockFlag) {
+        self.layout.block_flags[block] |= flag.bit();
+    }
+
+    pub fn clear_block_flag(&mut self, block: BlockId, flag: BlockFlag) {
+        self.layout.block_flags[block] &= !flag.bit();
+    }
+
+    pub fn has_block_flag(&self, block: BlockId, flag: BlockFlag) -> bool {
+        self.layout.block_flags[block] & flag.bit() != 0
+    }
+
+    /// Returns the number of blocks in the layout in O(1).
+    pub fn block_count(&self) -> usize {
+        self.layout.block_num()
+    }
+
+    /// Returns the number of insts in the layout in O(1).
+    pub fn inst_count(&self) -> usize {
+        self.layout.inst_num()
+    }
+
+    /// Returns an iterator over the insts in the `block` in the layout order,
+    /// yielding each inst together with its data.
+    pub fn insts_with_data(&self, block: BlockId) -> impl Iterator<Item = (InstId, &dyn Inst)> {
+        self.layout
+            .iter_inst(block)
+            .map(|inst| (inst, self.dfg.inst(inst)))
+    }
+
+    /// Returns the values defined in the `block` in the layout order, i.e., the
+    /// results of its phis, which act as the block parameters, followed by the
+    /// results of the other insts.
+    ///
+    /// Function arguments are yielded first for the entry block, consistently
+    /// with [`Self::def_block`].
+    ///
+    /// NOTE: This lives in `Function` rather than `DataFlowGraph`, since the
+    /// insts of a block are known only to the layout.
+    pub fn defs_in(&self, block: BlockId) -> impl Iterator<Item = ValueId> + '_ {
+        let args = if self.entry_block() == Some(block) {
+            self.arg_values.as_slice()
+        } else {
+            &[]
+        };
+
+        args.iter().copied().chain(
+            self.layout
+                .iter_inst(block)
+                .filter_map(|inst| self.dfg.inst_result(inst)),
+        )
+    }
+
+    /// Returns all values defined in the function with their types, i.e., the
+    /// function arguments followed by the inst results in the layout order.
+    pub fn value_defs(&self) -> impl Iterator<Item = (ValueId, Type)> + '_ {
+        let results = self.layout.iter_block().flat_map(move |block| {
+            self.layout
+                .iter_inst(block)
+                .filter_map(|inst| self.dfg.inst_result(inst))
+        });
+
+        self.arg_values
+            .iter()
+            .copied()
+            .chain(results)
+            .map(|value| (value, self.value_type(value)))
+    }
+
+    /// Returns all phis in the function with their blocks, in the layout
+    /// order.
+    ///
+    /// Since phis are placed at the top of a block, scanning a block stops at
+    /// its first non-phi inst.
+    pub fn phis(&self) -> impl Iterator<Item = (BlockId, InstId)> + '_ {
+        self.layout.iter_block().flat_map(move |block| {
+            self.layout
+                .iter_inst(block)
+                .take_while(|&inst| self.dfg.is_phi(inst))
+                .map(move |inst| (block, inst))
+        })
+    }
+
+    /// Returns the pairs of structurally identical pure insts in the `block`,
+    /// each paired with the first identical inst preceding it, e.g., to seed
+    /// local CSE or to confirm that a pass removed redundancy.
+    ///
+    /// The operands of commutative insts are compared regardless of their
+    /// order, so `add v0 v1` and `add v1 v0` are identical.
+    pub fn find_local_duplicates(&self, block: BlockId) -> Vec<(InstId, InstId)> {
+        let mut seen: FxHashMap<_, SmallVec<[InstId; 1]>> = FxHashMap::default();
+        let mut duplicates = Vec::new();
+
+        for inst in self.layout.iter_inst(block) {
+            let Some(result) = self.dfg.inst_result(inst) else {
+                continue;
+            };
+            if self.dfg.side_effect(inst).has_effect() || self.dfg.is_phi(inst) {
+                continue;
+            }
+
+            let data = self.dfg.inst(inst);
+            let mut args: SmallVec<[ValueId; 2]> = SmallVec::new();
+            data.for_each_value(&mut |value| args.push(value));
+            let commutative = is_commutative(data.as_text());
+            if commutative {
+                args.sort_unstable();
+            }
+
+            let key = (data.as_text(), args, self.dfg.value_ty(result));
+            let candidates = seen.entry(key).or_default();
+            // The key doesn't cover non-value fields, e.g., the type of `zext`,
+            // which are compared by `is_identical`.
+            match candidates
+                .iter()
+                .find(|&&prev| commutative || self.dfg.inst(prev).is_identical(data))
+            {
+                Some(&prev) => duplicates.push((prev, inst)),
+                None => candidates.push(inst),
+            }
+        }
+
+        duplicates
+    }
+
+    /// Returns the number of the parameters of the `block`, i.e., its phis.
+    pub fn block_param_num(&self, block: BlockId) -> usize {
+        self.block_params(block).count()
+    }
+
+    /// Returns the `idx`-th parameter of the `block`, i.e., the result of its
+    /// `idx`-th phi in the layout order, so that branch args can be matched
+    /// by position.
+    /// Returns `None` if the `block` has no more than `idx` parameters.
+    pub fn block_param(&self, block: BlockId, idx: usize) -> Option<ValueId> {
+        self.block_params(block).nth(idx)
+    }
+
+    fn block_params(&self, block: BlockId) -> impl Iterator<Item = ValueId> + '_ {
+        self.layout
+            .iter_inst(block)
+            .take_while(|&inst| self.dfg.is_phi(inst))
+            .filter_map(|inst| self.dfg.inst_result(inst))
+    }
+
+    /// Returns the type of the `value`.
+    pub fn value_type(&self, value: ValueId) -> Type {
+        self.dfg.value_ty(value)
+    }
+
+    /// Returns the block where the `value` is defined.
+    /// An inst result is defined in the block containing the inst, and a
+    /// function argument is defined in the entry block.
+    ///
+    /// Returns `None` if the value is an immediate, a global or an undef, or
+    /// if its defining inst is not inserted to the layout.
+    pub fn def_block(&self, value: ValueId) -> Option<BlockId> {
+        match self.dfg.value(value) {
+            Value::Inst { inst, .. } => self
+                .layout
+                .is_inst_inserted(*inst)
+                .then(|| self.layout.inst_block(*inst)),
+            Value::Arg { .. } => self.entry_block(),
+            _ => None,
+        }
+    }
+
+    /// Negate the condition of the `br` ending the `block` and swap its
+    /// destinations, which keeps the semantics, e.g., to make the likely
+    /// destination the fallthrough. Returns the new condition.
+    ///
+    /// If the condition is the result of a comparison used only by the `br`,
+    /// the comparison is inverted in place. Otherwise, `is_zero` of the
+    /// condition is inserted before the `br`.
+    ///
+    /// # Panics
+    /// Panics if the `block` doesn't end with `br`.
+    pub fn negate_branch(&mut self, block: BlockId) -> ValueId {
+        let is = self.inst_set();
+        let term = self.layout.last_inst_of(block).unwrap();
+        let br = <&Br as InstDowncast>::downcast(is, self.dfg.inst(term))
+            .unwrap_or_else(|| panic!("{block} doesn't end with `br`"));
+        let (cond, nz_dest, z_dest) = (*br.cond(), *br.nz_dest(), *br.z_dest());
+
+        let inverted = self
+            .dfg
+            .value_inst(cond)
+            .filter(|_| self.dfg.users_num(cond) == 1)
+            .and_then(|inst| Some((inst, self.inverted_cmp(inst)?)));
+        let new_cond = if let Some((inst, cmp)) = inverted {
+            self.dfg.replace_inst(inst, cmp);
+            cond
+        } else {
+            let is_zero = cmp::IsZero::new(is.has_is_zero().unwrap(), cond);
+            let inst = self.dfg.make_inst(is_zero);
+            let result = self.dfg.make_value(Value::Inst { inst, ty: Type::I1 });
+            self.dfg.attach_result(inst, result);
+            self.layout.insert_inst_before(inst, term);
+            result
+        };
+
+        let br = Br::new(is.has_br().unwrap(), new_cond, z_dest, nz_dest);
+        self.dfg.replace_inst(term, Box::new(br));
+        new_cond
+    }
+
+    /// Returns the comparison computing the negation of the `inst` if it's a
+    /// comparison whose inverse is in the inst set.
+    fn inverted_cmp(&self, inst: InstId) -> Option<Box<dyn Inst>> {
+        let is = self.inst_set();
+        let data = self.dfg.inst(inst);
+
+        macro_rules! invert {
+            ($($from:ident => $to:ident, $has:ident;)*) => {
+                $(
+                    if let Some(cmp) = <&cmp::$from as InstDowncast>::downcast(is, data) {
+                        let inverse = cmp::$to::new(is.$has()?, *cmp.lhs(), *cmp.rhs());
+                        return Some(Box::new(inverse));
+                    }
+                )*
+            };
+        }
+        invert! {
+            Lt => Ge, has_ge;
+            Ge => Lt, has_lt;
+            Gt => Le, has_le;
+            Le => Gt, has_gt;
+            Slt => Sge, has_sge;
+            Sge => Slt, has_slt;
+            Sgt => Sle, has_sle;
+            Sle => Sgt, has_sgt;
+            Eq => Ne, has_ne;
+            Ne => Eq, has_eq;
+        }
+
+        None
+    }
+
+    /// Redirect the edge from `block` to `from_succ` so that it goes to
+    /// `to_succ` instead, keeping the terminator, the phis and `cfg`
+    /// consistent.
+    ///
+    /// The phi args of `from_succ` coming from `block` are removed. Each phi in
+    /// `to_succ` receives the value that would flow into it if the control went
+    /// through `from_succ`, i.e., its arg from `from_succ`, resolved through the
+    /// phis of `from_succ` if the arg is one of them. This is the update
+    /// required when threading the edge through `from_succ`.
+    ///
+    /// # Panics
+    /// Panics if a phi in `to_succ` has no arg from either `from_succ` or
+    /// `block`, since the incoming value can't be determined.
+    pub fn redirect_edge(
+        &mut self,
+        cfg: &mut ControlFlowGraph,
+        block: BlockId,
+        from_succ: BlockId,
+        to_succ: BlockId,
+    ) {
+        let term = self.layout.last_inst_of(block).unwrap();
+
+        // Compute the new phi args of `to_succ` before `from_succ` phis lose
+        // their args.
+        let mut new_args = Vec::new();
+        for phi_inst in self.layout.iter_inst(to_succ) {
+            let Some(phi) = self.dfg.cast_phi(phi_inst) else {
+                break;
+            };
+            if phi.args().iter().any(|(_, pred)| *pred == block) {
+                continue;
+            }
+
+            let value = phi
+                .args()
+                .iter()
+                .find_map(|(value, pred)| (*pred == from_succ).then_some(*value))
+                .unwrap_or_else(|| panic!("no incoming value for {block} -> {to_succ}"));
+            let value = match self.dfg.value_inst(value) {
+                Some(def) if self.dfg.is_phi(def) && self.layout.inst_block(def) == from_succ => {
+                    self.dfg
+                        .cast_phi(def)
+                        .unwrap()
+                        .args()
+                        .iter()
+                        .find_map(|(value, pred)| (*pred == block).then_some(*value))
+                        .unwrap()
+                }
+                _ => value,
+            };
+            new_args.push((phi_inst, value));
+        }
+
+        self.dfg.rewrite_branch_dest(term, from_succ, to_succ);
+
+        let from_phis: Vec<_> = self
+            .layout
+            .iter_inst(from_succ)
+            .take_while(|inst| self.dfg.is_phi(*inst))
+            .collect();
+        for phi_inst in from_phis {
+            let phi = self.dfg.cast_phi_mut(phi_inst).unwrap();
+            let Some(value) = phi.remove_phi_arg(block) else {
+                continue;
+            };
+            if !phi.args().iter().any(|(v, _)| *v == value) {
+                self.dfg.remove_user(value, phi_inst);
+            }
+        }
+
+        for (phi_inst, value) in new_args {
+            self.dfg.append_phi_arg(phi_inst, value, block);
+        }
+
+        cfg.remove_edge(block, from_succ);
+        cfg.add_edge(block, to_succ);
+    }
+
+    /// Split the block containing `inst` into two, so that `inst` and the
+    /// following insts are moved to a new block placed right after the
+    /// original one. The original block jumps to the new block.
+    /// Returns the new block.
+    ///
+    /// Since the successors are now reached from the new block, the phi args
+    /// of the successors coming from the original block are rewritten to come
+    /// from the new block. An external CFG must be recomputed.
+    ///
+    /// # Panics
+    /// Panics if `inst` is a phi, since phis must stay at the block top.
+    pub fn split_block_at(&mut self, inst: InstId) -> BlockId {
+        assert!(!self.dfg.is_phi(inst), "can't split a block at a phi");
+
+        let block = self.layout.inst_block(inst);
+        let new_block = self.dfg.make_block();
+        self.layout.insert_block_after(new_block, block);
+
+        let mut next = Some(inst);
+        while let Some(moved) = next {
+            next = self.layout.next_inst_of(moved);
+            self.layout.remove_inst(moved);
+            self.layout.append_inst(moved, new_block);
+        }
+
+        let jump = self.dfg.make_jump(new_block);
+        let jump = self.dfg.make_inst(jump);
+        self.layout.append_inst(jump, block);
+
+        let Some(term) = self.layout.last_inst_of(new_block) else {
+            return new_block;
+        };
+        let succs = match self.dfg.branch_info(term) {
+            Some(branch) => branch.dests(),
+            None => return new_block,
+        };
+        for succ in succs {
+            let phis: Vec<_> = self
+                .layout
+                .iter_inst(succ)
+                .take_while(|&inst| self.dfg.is_phi(inst))
+                .collect();
+            for phi in phis {
+                for (_, pred) in self.dfg.cast_phi_mut(phi).unwrap().args_mut() {
+                    if *pred == block {
+                        *pred = new_block;
+                    }
+                }
+            }
+        }
+
+        new_block
+    }
+
+    /// Remove the `block` that only forwards the control to its successor via
+    /// `jump`, and rewire its predecessors to the successor directly.
+    /// Returns `true` if the block is removed.
+    ///
+    /// The block must contain nothing but phis and the `jump`. A block with
+    /// any other inst is rejected rather than having the insts hoisted into
+    /// each predecessor, since that would duplicate them on every path.
+    ///
+    /// Each phi in the successor receives, for each predecessor of the
+    /// `block`, the value that flowed through the `block`, resolved through the
+    /// phis of the `block` if the value is one of them. The block is also
+    /// rejected if it's the entry block, if it jumps to itself, if its phi
+    /// results are used other than by the phis of the successor, or if a
+    /// predecessor already reaching the successor would get a different phi
+    /// arg. An external CFG must be recomputed.
+    pub fn remove_forwarding_block(&mut self, block: BlockId) -> bool {
+        if self.layout.entry_block() == Some(block) {
+            return false;
+        }
+        let Some(term) = self.layout.last_inst_of(block) else {
+            return false;
+        };
+        let Some(succ) = self.dfg.cast_jump(term).map(|jump| *jump.dest()) else {
+            return false;
+        };
+        if succ == block {
+            return false;
+        }
+
+        let phis: Vec<_> = self
+            .layout
+            .iter_inst(block)
+            .take_while(|&inst| self.dfg.is_phi(inst))
+            .collect();
+        if self.layout.iter_inst(block).count() != phis.len() + 1 {
+            return false;
+        }
+        for &phi in &phis {
+            let result = self.dfg.inst_result(phi).unwrap();
+            let is_forwarded = |user: InstId| {
+                self.layout.inst_block(user) == succ
+                    && self.dfg.cast_phi(user).is_some_and(|phi| {
+                        phi.args()
+                            .iter()
+                            .all(|(value, pred)| *value != result || *pred == block)
+                    })
+            };
+            if !self.dfg.users(result).all(|&user| is_forwarded(user)) {
+                return false;
+            }
+        }
+
+        let preds: Vec<_> = self
+            .layout
+            .iter_block()
+            .filter(|&pred| {
+                self.layout
+                    .last_inst_of(pred)
+                    .and_then(|inst| self.dfg.branch_info(inst))
+                    .is_some_and(|branch| branch.dests().contains(&block))
+            })
+            .collect();
+
+        // Compute the phi args of the successor before modifying anything, so
+        // that the function is left intact on rejection.
+        let mut new_args = Vec::new();
+        for succ_phi in self.layout.iter_inst(succ) {
+            let Some(phi) = self.dfg.cast_phi(succ_phi) else {
+                break;
+            };
+            let Some(value) = phi
+                .args()
+                .iter()
+                .find_map(|(value, pred)| (*pred == block).then_some(*value))
+            else {
+                return false;
+            };
+
+            for &pred in &preds {
+                let value = match self.dfg.value_inst(value) {
+                    Some(def) if phis.contains(&def) => {
+                        let def_args = self.dfg.cast_phi(def).unwrap().args();
+                        match def_args.iter().find(|(_, b)| *b == pred) {
+                            Some((value, _)) => *value,
+                            None => return false,
+                        }
+                    }
+                    _ => value,
+                };
+
+                match phi.args().iter().find(|(_, b)| *b == pred) {
+                    Some((existing, _)) if *existing != value => return false,
+                    Some(_) => {}
+                    None => new_args.push((succ_phi, value, pred)),
+                }
+            }
+        }
+
+        for &pred in &preds {
+            let pred_term = self.layout.last_inst_of(pred).unwrap();
+            self.dfg.rewrite_branch_dest(pred_term, block, succ);
+        }
+
+        let succ_phis: Vec<_> = self
+            .layout
+            .iter_inst(succ)
+            .take_while(|&inst| self.dfg.is_phi(inst))
+            .collect();
+        for succ_phi in succ_phis {
+            let phi = self.dfg.cast_phi_mut(succ_phi).unwrap();
+            let value = phi.remove_phi_arg(block).unwrap();
+            if !phi.args().iter().any(|(v, _)| *v == value) {
+                self.dfg.remove_user(value, succ_phi);
+            }
+        }
+        for (succ_phi, value, pred) in new_args {
+            self.dfg.append_phi_arg(succ_phi, value, pred);
+        }
+
+        for inst in phis.into_iter().chain([term]) {
+            self.dfg.untrack_inst(inst);
+            self.layout.remove_inst(inst);
+        }
+        self.layout.remove_block(block);
+
+        true
+    }
+
+    /// Rename every value `v` in the function to `mapping(v)`, including the
+    /// operands, the phi args, the inst results and the function args.
+    ///
+    /// `mapping` must be a permutation of the values in the function, e.g.,
+    /// swapping two values is fine since all values are renamed at once.
+    ///
+    /// # Panics
+    /// Panics if `mapping` is not a permutation.
+    pub fn rename_values(&mut self, mapping: impl Fn(ValueId) -> ValueId) {
+        self.dfg.rename_values(&mapping);
+        for arg in &mut self.arg_values {
+            *arg = mapping(*arg);
+        }
+    }
+
+    /// Returns the number of insts in the layout for each opcode name.
+    pub fn opcode_histogram(&self) -> FxHashMap<&'static str, usize> {
+        let mut histogram = FxHashMap::default();
+        for block in self.layout.iter_block() {
+            for (_, inst) in self.insts_with_data(block) {
+                *histogram.entry(inst.as_text()).or_default() += 1;
+            }
+        }
+
+        histogram
+    }
+
+    /// Returns statistics of the phis in the function, which is useful to
+    /// evaluate the quality of SSA construction.
+    pub fn ssa_stats(&self) -> SsaStats {
+        let mut stats = SsaStats::default();
+        for block in self.layout.iter_block() {
+            for inst in self.layout.iter_inst(block) {
+                let Some(phi) = self.dfg.cast_phi(inst) else {
+                    continue;
+                };
+                stats.phi_num += 1;
+                stats.max_phi_arity = stats.max_phi_arity.max(phi.args().len());
+            }
+        }
+
+        stats
+    }
+
+    /// Returns a hash of the function structure that is invariant under
+    /// renumbering of blocks, insts and values, i.e., two functions that differ
+    /// only in the allocation order of their entities hash equally.
+    ///
+    /// Blocks and insts are identified by their ordinals in the layout.
+    pub fn structural_hash(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        self.structural_form().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Returns `true` if `self` and `other` have the same structure modulo
+    /// renumbering of entities. See [`Self::structural_hash`].
+    pub fn structurally_eq(&self, other: &Function) -> bool {
+        self.structural_form() == other.structural_form()
+    }
+
+    fn structural_form(&self) -> Vec<StructuralItem> {
+        let mut block_ordinals = SecondaryMap::default();
+        let mut inst_ordinals = SecondaryMap::default();
+        for (i, block) in self.layout.iter_block().enumerate() {
+            block_ordinals[block] = i as u32;
+        }
+        for (i, inst) in self
+            .layout
+            .iter_block()
+            .flat_map(|block| self.layout.iter_inst(block))
+            .enumerate()
+        {
+            inst_ordinals[inst] = i as u32;
+        }
+
+        let mut encoder = StructuralEncoder {
+            func: self,
+            block_ordinals,
+            inst_ordinals,
+            items: Vec::new(),
+        };
+
+        for &arg in &self.arg_values {
+            encoder.visit_value_id(arg);
+        }
+        for block in self.layout.iter_block() {
+            encoder.items.push(StructuralItem::BlockStart);
+            for (inst_id, inst) in self.insts_with_data(block) {
+                encoder.items.push(StructuralItem::Inst(inst.as_text()));
+                inst.accept(&mut encoder);
+                let result_ty = self.dfg.inst_result(inst_id).map(|v| self.dfg.value_ty(v));
+                encoder.items.push(StructuralItem::Result(result_ty));
+            }
+        }
+
+        encoder.items
+    }
+}
+
+/// An item of the normalized representation of a function, in which entities
+/// are replaced with their ordinals in the layout.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum StructuralItem {
+    BlockStart,
+    Inst(&'static str),
+    Result(Option<Type>),
+    Ty(Type),
+    Block(u32),
+    Func(FuncRef),
+    InstResult(u32),
+    Arg(usize, Type),
+    Imm(Immediate),
+    Global(GlobalVariableRef),
+    Undef(Type),
+}
+
+struct StructuralEncoder<'a> {
+    func: &'a Function,
+    block_ordinals: SecondaryMap<BlockId, u32>,
+    inst_ordinals: SecondaryMap<InstId, u32>,
+    items: Vec<StructuralItem>,
+}
+
+impl Visitor for StructuralEncoder<'_> {
+    fn visit_ty(&mut self, item: &Type) {
+        self.items.push(StructuralItem::Ty(*item));
+    }
+
+    fn visit_value_id(&mut self, item: ValueId) {
+        let item = match *self.func.dfg.value(item) {
+            Value::Inst { inst, .. } => StructuralItem::InstResult(self.inst_ordinals[inst]),
+            Value::Arg { ty, idx } => StructuralItem::Arg(idx, ty),
+            Value::Immediate { imm, .. } => StructuralItem::Imm(imm),
+            Value::Global { gv, .. } => StructuralItem::Global(gv),
+            Value::Undef { ty } => StructuralItem::Undef(ty),
+        };
+        self.items.push(item);
+    }
+
+    fn visit_block_id(&mut self, item: BlockId) {
+        self.items
+            .push(StructuralItem::Block(self.block_ordinals[item]));
+    }
+
+    fn visit_func_ref(&mut self, item: FuncRef) {
+        self.items.push(StructuralItem::Func(item));
+    }
+}
+
+/// Statistics of the phis in a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SsaStats {
+    /// The number of phis, which is also the number of block parameters since
+    /// each phi defines a parameter of its block.
+    pub phi_num: usize,
+    /// The maximum number of arguments of a phi.
+    pub max_phi_arity: usize,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -50,6 +744,9 @@ pub struct Signature {
 
     args: SmallVec<[Type; 8]>,
     ret_ty: Type,
+
+    /// `true` if a call to the function has no side effect.
+    is_pure: bool,
 }
 
 impl Signature {
@@ -59,6 +756,7 @@ impl Signature {
             linkage,
             args: args.into(),
             ret_ty,
+            is_pure: false,
         }
     }
     pub fn name(&self) -> &str {
@@ -77,6 +775,17 @@ impl Signature {
         &self.args
     }
 
+    /// Returns `true` if the function is marked pure, i.e., a call to it
+    /// neither reads nor writes any state observable by the caller, so that
+    /// the call can be removed if its result is unused.
+    pub fn is_pure(&self) -> bool {
+        self.is_pure
+    }
+
+    pub fn set_pure(&mut self, is_pure: bool) {
+        self.is_pure = is_pure;
+    }
+
     pub fn ret_ty(&self) -> Type {
         self.ret_ty
     }
@@ -102,10 +811,12 @@ where
     where
         W: io::Write,
     {
-        write!(w, "func ")?;
         self.linkage.write(w, ctx)?;
+        if self.is_pure {
+            write!(w, " pure")?;
+        }
         write!(w, " %{}(", self.name)?;
-        self.args.write_with_delim(w, " ", ctx)?;
+        self.args.write_with_delim(w, ", ", ctx)?;
         write!(w, ")")?;
 
         if !self.ret_ty.is_unit() {
@@ -116,3 +827,721 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::test_util::*,
+        inst::{
+            arith::{Add, Mul, Sub},
+            cast::{Trunc, Zext},
+            cmp::Eq,
+            control_flow::{Br, Jump, Phi, Return},
+        },
+        isa::Isa,
+        SourceLoc,
+    };
+
+    #[test]
+    fn def_block() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let imm = builder.make_imm_value(1i32);
+        let v0 = builder.insert_inst(Add::new(is, arg, imm), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            assert_eq!(func.def_block(arg), Some(b0));
+            assert_eq!(func.def_block(v0), Some(b1));
+            assert_eq!(func.def_block(imm), None);
+        });
+    }
+
+    #[test]
+    fn block_flags_survive_reorder() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Jump::new(is, b2));
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result(Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.modify(module.funcs()[0], |func| {
+            func.set_block_flag(b1, BlockFlag::Preheader);
+            func.set_block_flag(b1, BlockFlag::Cold);
+            func.clear_block_flag(b1, BlockFlag::Cold);
+
+            func.layout.move_block_after(b1, b2);
+            assert_eq!(func.layout.iter_block().collect::<Vec<_>>(), [b0, b2, b1]);
+            assert_eq!(func.block_count(), 3);
+            assert_eq!(func.inst_count(), 3);
+            assert!(func.has_block_flag(b1, BlockFlag::Preheader));
+            assert!(!func.has_block_flag(b1, BlockFlag::Cold));
+            assert!(!func.has_block_flag(b2, BlockFlag::Preheader));
+
+            func.layout.remove_block(b1);
+            assert!(!func.has_block_flag(b1, BlockFlag::Preheader));
+        });
+    }
+
+    #[test]
+    fn value_type() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I8], Type::I1);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let imm = builder.make_imm_value(1i8);
+        let v0 = builder.insert_inst(Eq::new(is, arg, imm), Type::I1);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            assert_eq!(func.value_type(arg), Type::I8);
+            assert_eq!(func.value_type(v0), Type::I1);
+        });
+    }
+
+    #[test]
+    fn value_defs_of_type() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I8, Type::I256], Type::I1);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let (narrow, wide) = (builder.args()[0], builder.args()[1]);
+
+        builder.switch_to_block(b0);
+        let ext = builder.insert_inst(Zext::new(is, narrow, Type::I256), Type::I256);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let sum = builder.insert_inst(Add::new(is, ext, wide), Type::I256);
+        let trunc = builder.insert_inst(Trunc::new(is, sum, Type::I8), Type::I8);
+        let eq = builder.insert_inst(Eq::new(is, trunc, narrow), Type::I1);
+        builder.insert_inst_no_result(Return::new(is, Some(eq)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let of_type = |ty| {
+                func.value_defs()
+                    .filter_map(|(value, value_ty)| (value_ty == ty).then_some(value))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(of_type(Type::I256), [wide, ext, sum]);
+            assert_eq!(of_type(Type::I8), [narrow, trunc]);
+            assert_eq!(of_type(Type::I1), [eq]);
+        });
+    }
+
+    #[test]
+    fn local_duplicates() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let (x, y) = (builder.args()[0], builder.args()[1]);
+        let v0 = builder.insert_inst(Add::new(is, x, y), Type::I32);
+        let add0 = builder.last_inst().unwrap();
+        let v1 = builder.insert_inst(Sub::new(is, x, y), Type::I32);
+        let v2 = builder.insert_inst(Sub::new(is, y, x), Type::I32);
+        let v3 = builder.insert_inst(Add::new(is, y, x), Type::I32);
+        let add1 = builder.last_inst().unwrap();
+        let v4 = builder.insert_inst(Mul::new(is, v0, v1), Type::I32);
+        let v5 = builder.insert_inst(Mul::new(is, v2, v3), Type::I32);
+        let v6 = builder.insert_inst(Add::new(is, v4, v5), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v6)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            // `sub` isn't commutative, so only the `add`s are duplicated.
+            assert_eq!(func.find_local_duplicates(b0), [(add0, add1)]);
+        });
+
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let (x, y) = (builder.args()[0], builder.args()[1]);
+        let v0 = builder.insert_inst(Add::new(is, x, y), Type::I32);
+        let v1 = builder.insert_inst(Add::new(is, x, v0), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            assert!(func.find_local_duplicates(b0).is_empty());
+        });
+    }
+
+    #[test]
+    fn entry_block() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Jump::new(is, b2));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Return::new(is, None));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.modify(module.funcs()[0], |func| {
+            func.layout.remove_block(b1);
+            func.layout.insert_block_after(b1, b2);
+            assert_eq!(func.layout.iter_block().collect::<Vec<_>>(), [b0, b2, b1]);
+            assert_eq!(func.entry_block(), Some(b0));
+        });
+    }
+
+    #[test]
+    fn insts_with_data() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let imm = builder.make_imm_value(1i32);
+        let v0 = builder.insert_inst(Add::new(is, arg, imm), Type::I32);
+        let v1 = builder.insert_inst(Add::new(is, v0, imm), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let expected: Vec<_> = func.layout.iter_inst(b0).collect();
+            let (insts, names): (Vec<_>, Vec<_>) = func
+                .insts_with_data(b0)
+                .map(|(inst, data)| (inst, data.as_text()))
+                .unzip();
+            assert_eq!(insts, expected);
+            assert_eq!(insts[0], func.dfg.value_inst(v0).unwrap());
+            assert_eq!(insts[1], func.dfg.value_inst(v1).unwrap());
+            assert_eq!(names, ["add", "add", "return"]);
+        });
+    }
+
+    #[test]
+    fn defs_in() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let one = builder.make_imm_value(1i32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let p0 = builder.insert_inst(Phi::new(is, vec![(arg, b0)]), Type::I32);
+        let p1 = builder.insert_inst(Phi::new(is, vec![(one, b0)]), Type::I32);
+        let v0 = builder.insert_inst(Add::new(is, p0, p1), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            assert_eq!(func.defs_in(b0).collect::<Vec<_>>(), [arg]);
+            assert_eq!(func.defs_in(b1).collect::<Vec<_>>(), [p0, p1, v0]);
+        });
+    }
+
+    #[test]
+    fn phis() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let zero = builder.make_imm_value(0i32);
+        let one = builder.make_imm_value(1i32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let i = builder.insert_inst(Phi::new(is, vec![(arg, b0)]), Type::I32);
+        let acc = builder.insert_inst(Phi::new(is, vec![(zero, b0)]), Type::I32);
+        let next_i = builder.insert_inst(Sub::new(is, i, one), Type::I32);
+        let next_acc = builder.insert_inst(Add::new(is, acc, i), Type::I32);
+        let cond = builder.insert_inst(Eq::new(is, next_i, zero), Type::I1);
+        builder.insert_inst_no_result(Br::new(is, cond, b2, b1));
+        builder.append_phi_arg(i, next_i, b1);
+        builder.append_phi_arg(acc, next_acc, b1);
+
+        builder.switch_to_block(b2);
+        let ret = builder.insert_inst(Phi::new(is, vec![(next_acc, b1)]), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(ret)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let phi_of = |value| func.dfg.value_inst(value).unwrap();
+            assert_eq!(
+                func.phis().collect::<Vec<_>>(),
+                [(b1, phi_of(i)), (b1, phi_of(acc)), (b2, phi_of(ret))]
+            );
+        });
+    }
+
+    #[test]
+    fn block_param() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let one = builder.make_imm_value(1i32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let p0 = builder.insert_inst(Phi::new(is, vec![(arg, b0)]), Type::I32);
+        let p1 = builder.insert_inst(Phi::new(is, vec![(one, b0)]), Type::I32);
+        let p2 = builder.insert_inst(Phi::new(is, vec![(arg, b0)]), Type::I32);
+        let v0 = builder.insert_inst(Add::new(is, p0, p1), Type::I32);
+        let v1 = builder.insert_inst(Add::new(is, v0, p2), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            assert_eq!(func.block_param_num(b0), 0);
+            assert_eq!(func.block_param_num(b1), 3);
+            assert_eq!(func.block_param(b1, 0), Some(p0));
+            assert_eq!(func.block_param(b1, 1), Some(p1));
+            assert_eq!(func.block_param(b1, 2), Some(p2));
+            assert_eq!(func.block_param(b1, 3), None);
+        });
+    }
+
+    #[test]
+    fn clone_preserves_srcloc() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        let add = builder.last_inst().unwrap();
+        builder.insert_inst_no_result(Return::new(is, Some(arg)));
+        let ret = builder.last_inst().unwrap();
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            func.dfg.set_srcloc(add, SourceLoc::new(12, 3));
+        });
+
+        let cloned = module.func_store.view(func_ref, |func| func.clone());
+        assert_eq!(cloned.dfg.srcloc_of(add), Some(SourceLoc::new(12, 3)));
+        assert_eq!(cloned.dfg.srcloc_of(ret), None);
+    }
+
+    #[test]
+    fn redirect_edge() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Br::new(is, arg, b1, b2));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b3);
+        let one = builder.make_imm_value(1i32);
+        let two = builder.make_imm_value(2i32);
+        let phi = builder.insert_inst(Phi::new(is, vec![(one, b1), (two, b2)]), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(phi)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.modify(module.funcs()[0], |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+
+            // Thread the `b0 -> b1` edge through `b1`.
+            func.redirect_edge(&mut cfg, b0, b1, b3);
+
+            let mut expected = ControlFlowGraph::new();
+            expected.compute(func);
+            assert_eq!(cfg, expected);
+            assert_eq!(cfg.succs_slice(b0), &[b2, b3]);
+            assert_eq!(cfg.pred_num_of(b1), 0);
+
+            let phi_inst = func.dfg.value_inst(phi).unwrap();
+            let phi_args = func.dfg.cast_phi(phi_inst).unwrap().args();
+            assert_eq!(phi_args, &[(one, b1), (two, b2), (one, b0)]);
+        });
+    }
+
+    #[test]
+    fn block_and_inst_count() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        let v1 = builder.insert_inst(Mul::new(is, v0, arg), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.modify(module.funcs()[0], |func| {
+            assert_eq!(func.block_count(), 1);
+            assert_eq!(func.inst_count(), 3);
+
+            let mul = func.dfg.value_inst(v1).unwrap();
+            let sub = func.dfg.make_inst(Sub::new(is, v1, arg));
+            func.layout.insert_inst_before(sub, mul);
+            assert_eq!(func.inst_count(), 4);
+
+            func.layout.remove_inst(sub);
+            let add = func.dfg.value_inst(v0).unwrap();
+            func.layout.remove_inst(add);
+            assert_eq!(func.inst_count(), 2);
+
+            // Splitting moves the `mul` and the `return` and adds a `jump`.
+            func.split_block_at(mul);
+            assert_eq!(func.block_count(), 2);
+            assert_eq!(func.inst_count(), 3);
+        });
+    }
+
+    #[test]
+    fn split_block_at() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        let v1 = builder.insert_inst(Mul::new(is, v0, arg), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let v2 = builder.insert_inst(Phi::new(is, vec![(v1, b0)]), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v2)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.modify(module.funcs()[0], |func| {
+            let add = func.dfg.value_inst(v0).unwrap();
+            let mul = func.dfg.value_inst(v1).unwrap();
+            let jump = func.layout.last_inst_of(b0).unwrap();
+
+            let new_block = func.split_block_at(mul);
+            assert_eq!(
+                func.layout.iter_block().collect::<Vec<_>>(),
+                [b0, new_block, b1]
+            );
+
+            let b0_insts: Vec<_> = func.layout.iter_inst(b0).collect();
+            assert_eq!(b0_insts.len(), 2);
+            assert_eq!(b0_insts[0], add);
+            assert_eq!(
+                func.dfg.cast_jump(b0_insts[1]).map(|jump| *jump.dest()),
+                Some(new_block)
+            );
+            assert_eq!(
+                func.layout.iter_inst(new_block).collect::<Vec<_>>(),
+                [mul, jump]
+            );
+
+            let phi = func.dfg.value_inst(v2).unwrap();
+            assert_eq!(func.dfg.cast_phi(phi).unwrap().args(), &[(v1, new_block)]);
+        });
+    }
+
+    #[test]
+    fn remove_forwarding_block() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let b4 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Br::new(is, arg, b1, b2));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        // `b3` only merges the values and forwards them to `b4`.
+        builder.switch_to_block(b3);
+        let one = builder.make_imm_value(1i32);
+        let two = builder.make_imm_value(2i32);
+        let v0 = builder.insert_inst(Phi::new(is, vec![(one, b1), (two, b2)]), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b4));
+
+        builder.switch_to_block(b4);
+        let v1 = builder.insert_inst(Phi::new(is, vec![(v0, b3)]), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.modify(module.funcs()[0], |func| {
+            assert!(!func.remove_forwarding_block(b0));
+            assert!(func.remove_forwarding_block(b3));
+            assert_eq!(
+                func.layout.iter_block().collect::<Vec<_>>(),
+                [b0, b1, b2, b4]
+            );
+
+            let phi = func.dfg.value_inst(v1).unwrap();
+            assert_eq!(
+                func.dfg.cast_phi(phi).unwrap().args(),
+                &[(one, b1), (two, b2)]
+            );
+
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            assert_eq!(cfg.preds_slice(b4), &[b1, b2]);
+        });
+    }
+
+    #[test]
+    fn opcode_histogram() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        let v1 = builder.insert_inst(Mul::new(is, v0, arg), Type::I32);
+        let v2 = builder.insert_inst(Add::new(is, v1, v0), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v2)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            let histogram = func.opcode_histogram();
+            assert_eq!(histogram.len(), 3);
+            assert_eq!(histogram["add"], 2);
+            assert_eq!(histogram["mul"], 1);
+            assert_eq!(histogram["return"], 1);
+        });
+    }
+
+    #[test]
+    fn ssa_stats() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let zero = builder.make_imm_value(0i32);
+        let one = builder.make_imm_value(1i32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let i = builder.insert_inst(Phi::new(is, vec![(arg, b0)]), Type::I32);
+        let next_i = builder.insert_inst(Sub::new(is, i, one), Type::I32);
+        let cond = builder.insert_inst(Eq::new(is, next_i, zero), Type::I1);
+        builder.insert_inst_no_result(Br::new(is, cond, b2, b1));
+        builder.append_phi_arg(i, next_i, b1);
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result(Return::new(is, Some(next_i)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(module.funcs()[0], |func| {
+            assert_eq!(
+                func.ssa_stats(),
+                SsaStats {
+                    phi_num: 1,
+                    max_phi_arity: 2,
+                }
+            );
+        });
+    }
+
+    /// Builds `if arg != 0 { arg + 1 } else { arg - 1 }`.
+    /// If `shift` is `true`, unused entities are allocated first so that all
+    /// the ids differ. If `negate` is `true`, the then branch subtracts instead.
+    fn build_branchy(shift: bool, negate: bool) -> Function {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        if shift {
+            builder.make_block();
+            builder.make_imm_value(42i64);
+        }
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let zero = builder.make_imm_value(0i32);
+        let one = builder.make_imm_value(1i32);
+        let cond = builder.insert_inst(Eq::new(is, arg, zero), Type::I1);
+        builder.insert_inst_no_result(Br::new(is, cond, b2, b1));
+
+        builder.switch_to_block(b1);
+        let v0 = if negate {
+            builder.insert_inst(Sub::new(is, arg, one), Type::I32)
+        } else {
+            builder.insert_inst(Add::new(is, arg, one), Type::I32)
+        };
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        builder.switch_to_block(b2);
+        let v1 = builder.insert_inst(Sub::new(is, arg, one), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module
+            .func_store
+            .view(module.funcs()[0], |func| func.clone())
+    }
+
+    #[test]
+    fn structural_hash() {
+        let func = build_branchy(false, false);
+        let renumbered = build_branchy(true, false);
+        let different = build_branchy(false, true);
+
+        assert!(func.structurally_eq(&renumbered));
+        assert_eq!(func.structural_hash(), renumbered.structural_hash());
+
+        assert!(!func.structurally_eq(&different));
+        assert_ne!(func.structural_hash(), different.structural_hash());
+    }
+}
@@ -104,6 +104,9 @@ pub struct GlobalVariableData {
     pub ty: Type,
     pub linkage: Linkage,
     pub is_const: bool,
+    /// The initial value of the variable. A variable defined in the module
+    /// without an initializer is zero-initialized, and an external variable
+    /// never has one since it's defined outside of the module.
     pub initializer: Option<GvInitializer>,
 }
 
@@ -133,6 +136,12 @@ impl GlobalVariableData {
             initializer: Some(data),
         }
     }
+
+    /// Returns `true` if the variable is defined in the module without an
+    /// explicit initializer.
+    pub fn is_zero_initialized(&self) -> bool {
+        self.linkage.has_definition() && self.initializer.is_none()
+    }
 }
 
 impl<Ctx> IrWrite<Ctx> for GlobalVariableData
@@ -219,7 +228,11 @@ where
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{builder::test_util::test_isa, module::ModuleCtx};
+    use crate::{
+        builder::test_util::{test_isa, test_module_builder},
+        ir_writer::ModuleWriter,
+        module::ModuleCtx,
+    };
 
     #[test]
     fn display_gv() {
@@ -263,4 +276,45 @@ mod test {
             "global private const [i32; 3] $foo = [8, 4, 2];"
         );
     }
+
+    #[test]
+    fn module_with_globals() {
+        let mb = test_module_builder();
+        let answer = mb.declare_gv(GlobalVariableData::new(
+            String::from("answer"),
+            Type::I32,
+            Linkage::Public,
+            false,
+            Some(GvInitializer::make_imm(42i32)),
+        ));
+        let counter = mb.declare_gv(GlobalVariableData::new(
+            String::from("counter"),
+            Type::I64,
+            Linkage::Private,
+            false,
+            None,
+        ));
+        let ext = mb.declare_gv(GlobalVariableData::new(
+            String::from("ext"),
+            Type::I256,
+            Linkage::External,
+            true,
+            None,
+        ));
+
+        let module = mb.build();
+        module.ctx.with_gv_store(|s| {
+            assert!(!s.gv_data(answer).is_zero_initialized());
+            assert!(s.gv_data(counter).is_zero_initialized());
+            assert!(!s.gv_data(ext).is_zero_initialized());
+        });
+
+        let text = ModuleWriter::new(&module).dump_string();
+        assert!(text.contains(
+            "global public i32 $answer = 42;
+global private i64 $counter;
+global external const i256 $ext;
+"
+        ));
+    }
 }
@@ -2,7 +2,7 @@ use std::fmt::Write;
 
 use dot2::label;
 
-use super::function::DUMMY_BLOCK;
+use super::{function::DUMMY_BLOCK, LabelFn};
 use crate::{
     ir_writer::{FuncWriteCtx, IrWrite, ValueWithTy},
     BlockId, ControlFlowGraph,
@@ -12,18 +12,29 @@ use crate::{
 pub(super) struct BlockNode<'a> {
     pub(super) ctx: &'a FuncWriteCtx<'a>,
     pub(super) cfg: &'a ControlFlowGraph,
+    pub(super) label_fn: Option<&'a LabelFn<'a>>,
     pub(super) block: BlockId,
 }
 
 impl<'a> BlockNode<'a> {
-    pub(super) fn new(ctx: &'a FuncWriteCtx, cfg: &'a ControlFlowGraph, block: BlockId) -> Self {
-        Self { ctx, cfg, block }
+    pub(super) fn new(
+        ctx: &'a FuncWriteCtx,
+        cfg: &'a ControlFlowGraph,
+        label_fn: Option<&'a LabelFn<'a>>,
+        block: BlockId,
+    ) -> Self {
+        Self {
+            ctx,
+            cfg,
+            label_fn,
+            block,
+        }
     }
 
     pub(super) fn succs(self) -> Vec<Self> {
         self.cfg
             .succs_of(self.block)
-            .map(|block| BlockNode::new(self.ctx, self.cfg, *block))
+            .map(|block| BlockNode::new(self.ctx, self.cfg, self.label_fn, *block))
             .collect()
     }
 }
@@ -34,7 +45,7 @@ impl BlockNode<'_> {
         if block == DUMMY_BLOCK {
             return ctx.module_ctx().func_sig(ctx.func_ref, |sig| {
                 let sig = sig.dump_string(ctx);
-                label::Text::LabelStr(sig.into())
+                label::Text::LabelStr(format!("func {sig}").into())
             });
         }
 
@@ -48,6 +59,16 @@ impl BlockNode<'_> {
         )
         .unwrap();
 
+        // Write custom annotation.
+        if let Some(label_fn) = self.label_fn {
+            write!(
+                label,
+                r#"<tr><td align="left">{}</td></tr>"#,
+                dot2::escape_html(&label_fn(block))
+            )
+            .unwrap();
+        }
+
         // Write block body.
         write!(label, r#"<tr><td align="left" balign="left">"#).unwrap();
         for inst in ctx.func.layout.iter_inst(self.block) {
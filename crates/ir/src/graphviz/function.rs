@@ -2,9 +2,9 @@ use std::iter;
 
 use dot2::{label::Text, GraphWalk, Id, Labeller, Style};
 
-use super::block::BlockNode;
+use super::{block::BlockNode, LabelFn};
 use crate::{
-    inst::control_flow::Phi,
+    inst::control_flow::{BranchKind, Phi},
     ir_writer::{FuncWriteCtx, IrWrite},
     prelude::*,
     BlockId, ControlFlowGraph,
@@ -15,22 +15,33 @@ pub(super) const DUMMY_BLOCK: BlockId = BlockId(u32::MAX);
 pub(super) struct FunctionGraph<'a> {
     ctx: &'a FuncWriteCtx<'a>,
     cfg: &'a ControlFlowGraph,
+    label_fn: Option<&'a LabelFn<'a>>,
 }
 
 impl<'a> FunctionGraph<'a> {
-    pub fn new(ctx: &'a FuncWriteCtx, cfg: &'a ControlFlowGraph) -> Self {
-        Self { ctx, cfg }
+    pub fn new(
+        ctx: &'a FuncWriteCtx,
+        cfg: &'a ControlFlowGraph,
+        label_fn: Option<&'a LabelFn<'a>>,
+    ) -> Self {
+        Self { ctx, cfg, label_fn }
     }
 }
 
 impl<'a> FunctionGraph<'a> {
     pub(super) fn blocks(&self) -> Vec<BlockNode<'a>> {
-        let Self { ctx: func, cfg } = self;
+        let Self {
+            ctx: func,
+            cfg,
+            label_fn,
+        } = *self;
         // Dummy block is needed to label the graph with the function signature. Returns
         // a vector with the dummy block as a last element.
-        cfg.post_order()
-            .map(|block| BlockNode::new(func, cfg, block))
-            .chain(iter::once(BlockNode::new(func, cfg, DUMMY_BLOCK)))
+        cfg.order()
+            .post_order()
+            .iter()
+            .map(|&block| BlockNode::new(func, cfg, label_fn, block))
+            .chain(iter::once(BlockNode::new(func, cfg, label_fn, DUMMY_BLOCK)))
             .collect()
     }
 }
@@ -87,13 +98,13 @@ impl<'a> GraphWalk<'a> for FunctionGraph<'a> {
     }
 
     fn edges(&'a self) -> dot2::Edges<'a, Self::Edge> {
-        let Self { ctx, cfg } = self;
+        let Self { ctx, cfg, label_fn } = *self;
         let mut blocks = self.blocks();
 
         let dummy_block = blocks.pop().unwrap();
         let mut edges = vec![BlockEdge {
             from: dummy_block,
-            to: BlockNode::new(ctx, cfg, BlockId(0u32)),
+            to: BlockNode::new(ctx, cfg, label_fn, BlockId(0u32)),
             ctx,
         }];
         for block in blocks {
@@ -127,10 +138,59 @@ pub(super) struct BlockEdge<'a> {
 }
 
 impl BlockEdge<'_> {
+    /// Returns the branch condition of the edge followed by the phi args
+    /// passed along it, e.g., `v0 != 0; 1.i64`.
     fn label(self) -> Text<'static> {
+        let labels: Vec<_> = [self.cond_label(), self.phi_arg_label()]
+            .into_iter()
+            .flatten()
+            .collect();
+        Text::LabelStr(labels.join("; ").into())
+    }
+
+    /// Returns the condition of a `br` or the matching cases of a `br_table`
+    /// under which the edge is taken.
+    fn cond_label(self) -> Option<String> {
+        let Self { from, to, ctx } = self;
+        let (from, to) = (from.block, to.block);
+        if from == DUMMY_BLOCK {
+            return None;
+        }
+
+        let term = ctx.func.layout.last_inst_of(from)?;
+        match ctx.func.dfg.branch_info(term)?.branch_kind() {
+            BranchKind::Jump(_) => None,
+
+            BranchKind::Br(br) => {
+                if br.nz_dest() == br.z_dest() {
+                    return None;
+                }
+                let cond = br.cond().dump_string(ctx);
+                let op = if *br.nz_dest() == to { "!=" } else { "==" };
+                Some(format!("{cond} {op} 0"))
+            }
+
+            BranchKind::BrTable(br_table) => {
+                let scrutinee = br_table.scrutinee().dump_string(ctx);
+                let mut cases: Vec<_> = br_table
+                    .table()
+                    .iter()
+                    .filter(|(_, dest)| *dest == to)
+                    .map(|(value, _)| format!("{scrutinee} == {}", value.dump_string(ctx)))
+                    .collect();
+                if *br_table.default() == Some(to) {
+                    cases.push("default".to_string());
+                }
+                Some(cases.join(" || "))
+            }
+        }
+    }
+
+    /// Returns the arg of the first phi in the destination that comes along
+    /// the edge.
+    fn phi_arg_label(self) -> Option<String> {
         let Self { from, to, ctx } = self;
-        let to = to.block;
-        let from = from.block;
+        let (from, to) = (from.block, to.block);
         for inst_id in ctx.func.layout.iter_inst(to) {
             let inst = ctx.func.dfg.inst(inst_id);
             let Some(phi) = <&Phi as InstDowncast>::downcast(ctx.func.dfg.inst_set(), inst) else {
@@ -139,11 +199,10 @@ impl BlockEdge<'_> {
 
             for (value, block) in phi.args().iter() {
                 if *block == from {
-                    let value = value.dump_string(ctx);
-                    return Text::LabelStr((value).into());
+                    return Some(value.dump_string(ctx));
                 }
             }
         }
-        Text::LabelStr("".into())
+        None
     }
 }
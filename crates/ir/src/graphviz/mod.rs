@@ -1,21 +1,48 @@
 use std::io;
 
-use crate::{ir_writer::FuncWriteCtx, module::FuncRef, ControlFlowGraph, Function};
+use crate::{ir_writer::FuncWriteCtx, module::FuncRef, BlockId, ControlFlowGraph, Function};
 
 mod block;
 mod function;
 
 use function::FunctionGraph;
 
+/// A callback that returns a custom annotation of a block, e.g., its execution
+/// frequency or register pressure.
+pub type LabelFn<'a> = dyn Fn(BlockId) -> String + 'a;
+
+/// Renders the control flow graph of the function in the DOT format. Each
+/// block node lists its insts, and each edge is labeled with the condition
+/// under which it's taken and the phi arg passed along it.
 pub fn render_to<W: io::Write>(
     func: &Function,
     func_ref: FuncRef,
     output: &mut W,
+) -> io::Result<()> {
+    render_with_label_fn(func, func_ref, output, None)
+}
+
+/// Same as [`render_to`], but annotates each block node with the text returned
+/// by `label_fn`, which is shown below the block name.
+pub fn render_to_with_label<W: io::Write>(
+    func: &Function,
+    func_ref: FuncRef,
+    output: &mut W,
+    label_fn: &LabelFn,
+) -> io::Result<()> {
+    render_with_label_fn(func, func_ref, output, Some(label_fn))
+}
+
+fn render_with_label_fn<W: io::Write>(
+    func: &Function,
+    func_ref: FuncRef,
+    output: &mut W,
+    label_fn: Option<&LabelFn>,
 ) -> io::Result<()> {
     let mut cfg = ControlFlowGraph::new();
     cfg.compute(func);
     let ctx = FuncWriteCtx::new(func, func_ref);
-    let func_graph = FunctionGraph::new(&ctx, &cfg);
+    let func_graph = FunctionGraph::new(&ctx, &cfg, label_fn);
     dot2::render(&func_graph, output).map_err(|err| match err {
         dot2::Error::Io(err) => err,
         _ => panic!("invalid graphviz id"),
@@ -29,7 +56,7 @@ mod test {
         builder::test_util::{test_func_builder, test_module_builder},
         inst::{
             arith::Add,
-            control_flow::{Br, Jump, Phi, Return},
+            control_flow::{Br, BrTable, Jump, Phi, Return},
         },
         isa::Isa,
         Type,
@@ -91,10 +118,82 @@ mod test {
     dummy_block -> block0[label=""][style="invis"];
     block2 -> block3[label="2.i64"];
     block1 -> block3[label="1.i64"];
-    block0 -> block1[label=""];
-    block0 -> block2[label=""];
+    block0 -> block1[label="v0 != 0"];
+    block0 -> block2[label="v0 == 0"];
 }
 "#;
         assert_eq!(text, expected);
     }
+
+    #[test]
+    fn br_table_edge_label() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let table = (1..=3i32)
+            .map(|i| (builder.make_imm_value(i), if i == 2 { b2 } else { b1 }))
+            .collect();
+        builder.insert_inst_no_result(BrTable::new(is, arg, Some(b3), table));
+
+        for block in [b1, b2, b3] {
+            builder.switch_to_block(block);
+            builder.insert_inst_no_result(Return::new(is, None));
+        }
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+
+        let mut text = vec![];
+        module.func_store.view(func_ref, |func| {
+            render_to(func, func_ref, &mut text).unwrap();
+        });
+        let text = String::from_utf8(text).unwrap();
+
+        assert!(text.contains(r#"block0 -> block1[label="v0 == 1.i32 || v0 == 3.i32"];"#));
+        assert!(text.contains(r#"block0 -> block2[label="v0 == 2.i32"];"#));
+        assert!(text.contains(r#"block0 -> block3[label="default"];"#));
+    }
+
+    #[test]
+    fn custom_label() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+
+        let mut text = vec![];
+        let label_fn = |block: BlockId| format!("freq: {} & more", block.0 * 10);
+        module.func_store.view(func_ref, |func| {
+            render_to_with_label(func, func_ref, &mut text, &label_fn).unwrap();
+        });
+        let text = String::from_utf8(text).unwrap();
+
+        assert!(text.contains("<td align=\"left\">freq: 0 &amp; more</td>"));
+        assert!(text.contains("<td align=\"left\">freq: 10 &amp; more</td>"));
+    }
 }
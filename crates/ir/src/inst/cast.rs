@@ -26,6 +26,20 @@ pub struct Bitcast {
     ty: Type,
 }
 
+/// Copies `arg` into a new value of the same type `ty`.
+///
+/// This makes copies explicit, e.g., to keep a source-level name of a value
+/// across merges. Copy propagation removes it.
+///
+/// The type is named `CopyVal` so that it doesn't shadow [`Copy`], but the
+/// text form is `copy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Inst)]
+#[inst(text = "copy")]
+pub struct CopyVal {
+    arg: ValueId,
+    ty: Type,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Inst)]
 pub struct IntToPtr {
     from: ValueId,
@@ -48,6 +48,45 @@ impl Phi {
     {
         self.args.retain(|(_, block)| f(*block))
     }
+
+    /// Collapse duplicated arguments so that each predecessor appears at most
+    /// once. A predecessor may be listed twice if it reaches the phi block via
+    /// two distinct edges, e.g., a `br` whose destinations are the same.
+    ///
+    /// Returns an error without modifying the phi if the duplicated arguments
+    /// of a predecessor disagree on the value.
+    pub fn normalize(&mut self) -> Result<(), PhiArgConflict> {
+        for (i, &(value, block)) in self.args.iter().enumerate() {
+            if let Some(&(other, _)) = self.args[..i].iter().find(|(_, b)| *b == block) {
+                if other != value {
+                    return Err(PhiArgConflict {
+                        block,
+                        values: (other, value),
+                    });
+                }
+            }
+        }
+
+        let mut seen = Vec::with_capacity(self.args.len());
+        self.args.retain(|(_, block)| {
+            if seen.contains(block) {
+                false
+            } else {
+                seen.push(*block);
+                true
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// An error returned by [`Phi::normalize`] when a predecessor is associated
+/// with different values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhiArgConflict {
+    pub block: BlockId,
+    pub values: (ValueId, ValueId),
 }
 
 // TODO: We need to perform analysis or modify function signature definition to
@@ -211,6 +250,31 @@ pub enum BranchKind<'i> {
     BrTable(&'i BrTable),
 }
 
+/// A view of a terminator that exposes its operands without downcasting to
+/// each terminator type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terminator<'i> {
+    Jump {
+        dest: BlockId,
+    },
+    Br {
+        cond: ValueId,
+        nz_dest: BlockId,
+        z_dest: BlockId,
+    },
+    BrTable {
+        scrutinee: ValueId,
+        default: Option<BlockId>,
+        table: &'i [(ValueId, BlockId)],
+    },
+    Return {
+        arg: Option<ValueId>,
+    },
+    /// Any other terminator, e.g., `evm_revert`, which leaves the function
+    /// without a successor.
+    Exit,
+}
+
 /// Attempts to convert a branch instruction into a jump instruction.
 ///
 /// This function checks if all the destinations of the branch instruction
@@ -236,3 +300,68 @@ fn rewrite_if_match(block: &mut BlockId, from: BlockId, to: BlockId) {
         *block = to
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::test_util::test_isa,
+        isa::Isa,
+        visitor::{Operand, OperandMut, Visitable, VisitableMut},
+    };
+
+    #[test]
+    fn normalize_phi() {
+        let is = test_isa().inst_set();
+        let (v0, v1) = (ValueId(0), ValueId(1));
+        let (b0, b1) = (BlockId(0), BlockId(1));
+
+        let mut phi = Phi::new(is, vec![(v0, b0), (v1, b1), (v0, b0)]);
+        assert_eq!(phi.normalize(), Ok(()));
+        assert_eq!(phi.args(), &[(v0, b0), (v1, b1)]);
+
+        let mut phi = Phi::new(is, vec![(v0, b0), (v1, b0)]);
+        assert_eq!(
+            phi.normalize(),
+            Err(PhiArgConflict {
+                block: b0,
+                values: (v0, v1)
+            })
+        );
+        assert_eq!(phi.args().len(), 2);
+    }
+
+    #[test]
+    fn br_table_operands() {
+        let is = test_isa().inst_set();
+        let (v0, v1, v2) = (ValueId(0), ValueId(1), ValueId(2));
+        let (b0, b1, b2) = (BlockId(0), BlockId(1), BlockId(2));
+
+        let mut brt = BrTable::new(is, v0, Some(b0), vec![(v1, b1), (v2, b2)]);
+        let mut operands = Vec::new();
+        brt.for_each_operand(&mut |operand| operands.push(operand));
+        assert_eq!(
+            operands,
+            [
+                Operand::Value(v0),
+                Operand::Block(b0),
+                Operand::Value(v1),
+                Operand::Block(b1),
+                Operand::Value(v2),
+                Operand::Block(b2),
+            ]
+        );
+
+        // Remap both values and blocks in a single pass.
+        brt.for_each_operand_mut(&mut |operand| match operand {
+            OperandMut::Value(value) => value.0 += 10,
+            OperandMut::Block(block) => block.0 += 10,
+        });
+        assert_eq!(*brt.scrutinee(), ValueId(10));
+        assert_eq!(*brt.default(), Some(BlockId(10)));
+        assert_eq!(
+            brt.table(),
+            &[(ValueId(11), BlockId(11)), (ValueId(12), BlockId(12))]
+        );
+    }
+}
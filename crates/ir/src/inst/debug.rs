@@ -0,0 +1,14 @@
+use macros::Inst;
+
+use crate::{builder::Variable, ValueId};
+
+/// Pins the `value` of the source-level variable `var` live for debugging.
+///
+/// The inst has no side effect so that it doesn't prevent reordering, but
+/// dead code elimination keeps it, and so its operand. The interpreter ignores
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Inst)]
+pub struct DebugValue {
+    value: ValueId,
+    var: Variable,
+}
@@ -15,6 +15,7 @@ pub struct EvmInstSet(
     cast::Zext,
     cast::Trunc,
     cast::Bitcast,
+    cast::CopyVal,
     cast::IntToPtr,
     cast::PtrToInt,
     cmp::Lt,
@@ -40,10 +41,12 @@ pub struct EvmInstSet(
     data::Alloca,
     data::InsertValue,
     data::ExtractValue,
+    debug::DebugValue,
     logic::Not,
     logic::And,
     logic::Or,
     logic::Xor,
+    logic::Select,
     evm::EvmSdiv,
     evm::EvmUdiv,
     evm::EvmUmod,
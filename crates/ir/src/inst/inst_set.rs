@@ -1,6 +1,6 @@
 use macros::define_inst_set_base;
 
-use super::{arith, cast, cmp, control_flow, data, evm, logic, Inst};
+use super::{arith, cast, cmp, control_flow, data, debug, evm, logic, Inst};
 
 define_inst_set_base! {
     /// This trait is used to determine whether a certain instruction set includes a specific inst in runtime.
@@ -39,10 +39,12 @@ define_inst_set_base! {
         logic::And,
         logic::Or,
         logic::Xor,
+        logic::Select,
         cast::Sext,
         cast::Zext,
         cast::Trunc,
         cast::Bitcast,
+        cast::CopyVal,
         cast::IntToPtr,
         cast::PtrToInt,
         data::Mload,
@@ -58,6 +60,7 @@ define_inst_set_base! {
         control_flow::BrTable,
         control_flow::Return,
         control_flow::Phi,
+        debug::DebugValue,
         // Evm specific
         evm::EvmUdiv,
         evm::EvmSdiv,
@@ -24,3 +24,16 @@ pub struct Xor {
     lhs: ValueId,
     rhs: ValueId,
 }
+
+/// Selects `nz_val` if `cond` is non-zero, otherwise `z_val`.
+///
+/// Unlike a branch, `select` doesn't short-circuit: both `nz_val` and `z_val`
+/// are computed regardless of `cond`, so neither of them may trap or have a
+/// side effect that must happen only on one side. Such a conditional must be
+/// lowered to a branch instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Inst)]
+pub struct Select {
+    cond: ValueId,
+    nz_val: ValueId,
+    z_val: ValueId,
+}
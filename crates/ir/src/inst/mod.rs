@@ -3,6 +3,7 @@ pub mod cast;
 pub mod cmp;
 pub mod control_flow;
 pub mod data;
+pub mod debug;
 pub mod evm;
 #[macro_use]
 pub mod inst_set;
@@ -19,7 +20,7 @@ use macros::inst_prop;
 use crate::{
     ir_writer::{FuncWriteCtx, IrWrite},
     visitor::{Visitable, VisitableMut},
-    InstSetBase,
+    DataFlowGraph, InstSetBase, Type,
 };
 
 /// An opaque reference to dynamic [`Inst`].
@@ -33,8 +34,15 @@ pub trait Inst:
     fn side_effect(&self) -> SideEffect;
     fn as_text(&self) -> &'static str;
     fn is_terminator(&self) -> bool;
+    fn as_any(&self) -> &dyn Any;
+
+    /// Returns `true` if `other` is the same kind of inst with the same
+    /// operands.
+    fn is_identical(&self, other: &dyn Inst) -> bool;
 }
 
+dyn_clone::clone_trait_object!(Inst);
+
 pub trait InstExt: Inst {
     /// Checks if the this instruction type belongs to the given `InstSetBase`.
     ///
@@ -112,6 +120,104 @@ impl SideEffect {
     }
 }
 
+/// Returns `true` if the inst named `name` gives the same result when its two
+/// operands are swapped.
+pub fn is_commutative(name: &str) -> bool {
+    matches!(name, "add" | "mul" | "and" | "or" | "xor" | "eq" | "ne")
+}
+
+/// Insts whose result type is determined by the types of their operands.
+#[inst_prop]
+pub trait OperandResultTy {
+    fn result_ty(&self, dfg: &DataFlowGraph) -> Type;
+
+    type Members = (
+        arith::Neg,
+        arith::Add,
+        arith::Mul,
+        arith::Sub,
+        arith::Sdiv,
+        arith::Udiv,
+        arith::Umod,
+        arith::Smod,
+        arith::Shl,
+        arith::Shr,
+        arith::Sar,
+        cmp::Lt,
+        cmp::Gt,
+        cmp::Slt,
+        cmp::Sgt,
+        cmp::Le,
+        cmp::Ge,
+        cmp::Sle,
+        cmp::Sge,
+        cmp::Eq,
+        cmp::Ne,
+        cmp::IsZero,
+        logic::Not,
+        logic::And,
+        logic::Or,
+        logic::Xor,
+        logic::Select,
+    );
+}
+
+macro_rules! impl_operand_result_ty {
+    ($($ty:ty => $operand:ident),* $(,)?) => {
+        $(
+            impl OperandResultTy for $ty {
+                fn result_ty(&self, dfg: &DataFlowGraph) -> Type {
+                    dfg.value_ty(*self.$operand())
+                }
+            }
+        )*
+    };
+
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl OperandResultTy for $ty {
+                fn result_ty(&self, _dfg: &DataFlowGraph) -> Type {
+                    Type::I1
+                }
+            }
+        )*
+    };
+}
+
+impl_operand_result_ty! {
+    arith::Neg => arg,
+    arith::Add => lhs,
+    arith::Mul => lhs,
+    arith::Sub => lhs,
+    arith::Sdiv => lhs,
+    arith::Udiv => lhs,
+    arith::Umod => lhs,
+    arith::Smod => lhs,
+    arith::Shl => value,
+    arith::Shr => value,
+    arith::Sar => value,
+    logic::Not => arg,
+    logic::And => lhs,
+    logic::Or => lhs,
+    logic::Xor => lhs,
+    logic::Select => nz_val,
+}
+
+// Comparisons always produce a boolean.
+impl_operand_result_ty! {
+    cmp::Lt,
+    cmp::Gt,
+    cmp::Slt,
+    cmp::Sgt,
+    cmp::Le,
+    cmp::Ge,
+    cmp::Sle,
+    cmp::Sge,
+    cmp::Eq,
+    cmp::Ne,
+    cmp::IsZero,
+}
+
 #[inst_prop]
 trait InstWrite {
     fn write(&self, w: &mut dyn io::Write, ctx: &FuncWriteCtx) -> io::Result<()>;
@@ -40,7 +40,7 @@ impl Interpret for Mul {
         let rhs = state.lookup_val(*self.rhs());
         state.set_action(Action::Continue);
 
-        EvalValue::zip_with_imm(lhs, rhs, |lhs, rhs| lhs * rhs)
+        EvalValue::zip_with_imm_absorbing_zero(lhs, rhs, |lhs, rhs| lhs * rhs)
     }
 }
 
@@ -116,13 +116,6 @@ impl Interpret for Sar {
         let bits = state.lookup_val(*self.bits());
         let value = state.lookup_val(*self.value());
 
-        EvalValue::zip_with_imm(bits, value, |bits, value| {
-            let shifted = value >> bits;
-            if value.is_positive() {
-                shifted
-            } else {
-                -shifted
-            }
-        })
+        EvalValue::zip_with_imm(bits, value, |bits, value| value.sar(bits))
     }
 }
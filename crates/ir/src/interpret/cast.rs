@@ -40,6 +40,13 @@ impl Interpret for Bitcast {
     }
 }
 
+impl Interpret for CopyVal {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        state.lookup_val(*self.arg())
+    }
+}
+
 impl Interpret for IntToPtr {
     fn interpret(&self, state: &mut dyn State) -> EvalValue {
         state.set_action(Action::Continue);
@@ -47,7 +47,7 @@ impl Interpret for BrTable {
         if let Some(default) = self.default() {
             state.set_action(Action::JumpTo(*default))
         } else {
-            state.set_action(Action::FallThrough)
+            state.set_action(Action::UnreachableReached)
         }
 
         EvalValue::Undef
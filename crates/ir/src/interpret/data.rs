@@ -43,14 +43,7 @@ impl Interpret for Gep {
             let idx_value = idx_value.as_usize();
 
             let cmpd = match current_ty {
-                Type::I1
-                | Type::I16
-                | Type::I8
-                | Type::I32
-                | Type::I64
-                | Type::I128
-                | Type::I256
-                | Type::Unit => {
+                Type::Int(_) | Type::Unit => {
                     panic!("Invalid GEP: indexing into a scalar type or unit with more indices remaining");
                 }
                 Type::Compound(cmpd) => cmpd,
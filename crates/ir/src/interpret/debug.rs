@@ -0,0 +1,9 @@
+use super::{Action, EvalValue, Interpret, State};
+use crate::inst::debug::*;
+
+impl Interpret for DebugValue {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        EvalValue::Undef
+    }
+}
@@ -16,7 +16,7 @@ impl Interpret for And {
         let rhs = state.lookup_val(*self.rhs());
         state.set_action(Action::Continue);
 
-        EvalValue::zip_with_imm(lhs, rhs, |lhs, rhs| lhs & rhs)
+        EvalValue::zip_with_imm_absorbing_zero(lhs, rhs, |lhs, rhs| lhs & rhs)
     }
 }
 
@@ -39,3 +39,20 @@ impl Interpret for Xor {
         EvalValue::zip_with_imm(lhs, rhs, |lhs, rhs| lhs ^ rhs)
     }
 }
+
+impl Interpret for Select {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        // `select` is branchless, so both arms are evaluated regardless of
+        // `cond`.
+        let cond = state.lookup_val(*self.cond());
+        let nz_val = state.lookup_val(*self.nz_val());
+        let z_val = state.lookup_val(*self.z_val());
+        state.set_action(Action::Continue);
+
+        match cond {
+            EvalValue::Imm(cond) if cond.is_zero() => z_val,
+            EvalValue::Imm(_) => nz_val,
+            _ => EvalValue::Undef,
+        }
+    }
+}
@@ -9,6 +9,7 @@ mod cast;
 mod cmp;
 mod control_flow;
 mod data;
+mod debug;
 mod evm;
 mod logic;
 
@@ -34,12 +35,14 @@ pub trait Interpret {
         inst::logic::And,
         inst::logic::Or,
         inst::logic::Xor,
+        inst::logic::Select,
         inst::cast::Sext,
         inst::cast::Zext,
         inst::cast::Trunc,
         inst::cast::IntToPtr,
         inst::cast::PtrToInt,
         inst::cast::Bitcast,
+        inst::cast::CopyVal,
         inst::cmp::Lt,
         inst::cmp::Gt,
         inst::cmp::Slt,
@@ -63,6 +66,7 @@ pub trait Interpret {
         inst::control_flow::Phi,
         inst::control_flow::Call,
         inst::control_flow::Return,
+        inst::debug::DebugValue,
         inst::evm::EvmUdiv,
         inst::evm::EvmSdiv,
         inst::evm::EvmUmod,
@@ -118,6 +122,10 @@ pub enum Action {
     /// This happens e.g, the `BrTable` doesn't have a table entry that
     /// corresponds to scrutinee.
     FallThrough,
+    /// Indicate that the control reached a point that must be unreachable,
+    /// e.g., a `BrTable` without default gets a scrutinee that no table entry
+    /// corresponds to.
+    UnreachableReached,
     Return(EvalValue),
 }
 
@@ -158,6 +166,22 @@ impl EvalValue {
         }
     }
 
+    /// Same as [`Self::zip_with_imm`], but returns zero if either operand is
+    /// zero even when the other one is not known, e.g., `mul undef 0`.
+    pub fn zip_with_imm_absorbing_zero<F, R>(lhs: Self, rhs: Self, f: F) -> Self
+    where
+        F: FnOnce(Immediate, Immediate) -> R,
+        R: Into<Self>,
+    {
+        for value in [&lhs, &rhs] {
+            if let Some(imm) = value.as_imm().filter(|imm| imm.is_zero()) {
+                return imm.into();
+            }
+        }
+
+        Self::zip_with_imm(lhs, rhs, f)
+    }
+
     pub fn as_imm(&self) -> Option<Immediate> {
         match self {
             Self::Imm(imm) => Some(*imm),
@@ -24,7 +24,7 @@ impl<'a> ModuleWriter<'a> {
 
     pub fn write(&mut self, w: &mut impl io::Write) -> io::Result<()> {
         // Write target.
-        writeln!(w, "target = {}", self.module.ctx.triple)?;
+        writeln!(w, "target = \"{}\"", self.module.ctx.triple)?;
         writeln!(w)?;
 
         // Write struct types defined in the module.
@@ -161,6 +161,9 @@ impl<'a> FuncWriter<'a> {
         write!(w, "func ")?;
         m_ctx.func_sig(func_ref, |sig| {
             sig.linkage().write(w, &self.ctx)?;
+            if sig.is_pure() {
+                write!(w, " pure")?;
+            }
             write!(w, " %{}(", sig.name())?;
             io::Result::Ok(())
         })?;
@@ -421,14 +424,29 @@ impl IrWrite<FuncWriteCtx<'_>> for InstStatement {
     where
         W: io::Write,
     {
-        if let Some(result) = ctx.func.dfg.inst_result(self.0) {
+        let dfg = &ctx.func.dfg;
+        if let Some(result) = dfg.inst_result(self.0) {
             let result_with_ty = ValueWithTy(result);
             result_with_ty.write(w, ctx)?;
             write!(w, " = ")?;
+            if dfg.is_pinned(result) {
+                write!(w, "pinned ")?;
+            }
         };
+        if dfg.is_volatile(self.0) {
+            write!(w, "volatile ")?;
+        }
 
         self.0.write(w, ctx)?;
-        write!(w, ";")
+        write!(w, ";")?;
+
+        if ctx.dbg.write_srcloc() {
+            if let Some(loc) = ctx.func.dfg.srcloc_of(self.0) {
+                write!(w, " # loc {loc}")?;
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -437,6 +455,12 @@ pub trait DebugProvider {
     fn value_name(&self, func: &Function, func_ref: FuncRef, value: ValueId) -> Option<&str> {
         None
     }
+
+    /// Returns `true` if the source location of each inst should be annotated
+    /// as a trailing comment, e.g., `# loc 12:3`.
+    fn write_srcloc(&self) -> bool {
+        false
+    }
 }
 
 const DEFAULT_PROVIDER: DefaultDebugProvider = DefaultDebugProvider {};
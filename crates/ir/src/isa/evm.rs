@@ -40,13 +40,7 @@ impl TypeLayout for EvmTypeLayout {
     fn size_of(&self, ty: crate::Type, ctx: &ModuleCtx) -> Result<usize, TypeLayoutError> {
         let size = match ty {
             Type::Unit => 0,
-            Type::I1 => 1,
-            Type::I8 => 1,
-            Type::I16 => 2,
-            Type::I32 => 4,
-            Type::I64 => 8,
-            Type::I128 => 16,
-            Type::I256 => 32,
+            Type::Int(bits) => (bits as usize).div_ceil(8),
 
             Type::Compound(cmpd) => {
                 let cmpd_data = ctx.with_ty_store(|s| s.resolve_compound(cmpd).clone());
@@ -57,7 +51,7 @@ impl TypeLayout for EvmTypeLayout {
 
                     CompoundType::Struct(s) => {
                         if s.packed {
-                            panic!("packed data is not supported yet!");
+                            return Err(TypeLayoutError::UnsupportedType(ty));
                         }
                         let mut size = 0;
                         for &field in &s.fields {
@@ -89,3 +83,50 @@ impl TypeLayout for EvmTypeLayout {
         Endian::Be
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{builder::test_util::test_isa, ir_writer::IrWrite};
+
+    #[test]
+    fn size_of_nested_aggregates() {
+        let ctx = ModuleCtx::new(&test_isa());
+        let (point, points, nested) = ctx.with_ty_store_mut(|s| {
+            let point = s.make_struct("point", &[Type::I8, Type::I256], false);
+            let points = s.make_array(point, 4);
+            let ptr = s.make_ptr(point);
+            let nested = s.make_struct("nested", &[points, Type::I32, ptr], false);
+            (point, points, nested)
+        });
+
+        assert_eq!(ctx.size_of(Type::I1).unwrap(), 1);
+        assert_eq!(ctx.size_of(Type::I32).unwrap(), 4);
+        assert_eq!(ctx.size_of(point).unwrap(), 1 + 32);
+        assert_eq!(ctx.size_of(points).unwrap(), 4 * 33);
+        assert_eq!(ctx.size_of(nested).unwrap(), 4 * 33 + 4 + 32);
+        assert_eq!(ctx.align_of(nested).unwrap(), 1);
+
+        assert_eq!(points.dump_string(&ctx), "[@point; 4]");
+        assert_eq!(nested.dump_string(&ctx), "@nested");
+    }
+
+    #[test]
+    fn size_of_unrepresentable() {
+        let ctx = ModuleCtx::new(&test_isa());
+        let (func, packed) = ctx.with_ty_store_mut(|s| {
+            let func = s.make_func(&[Type::I32], Type::I32);
+            let packed = s.make_struct("packed", &[Type::I8, Type::I32], true);
+            (func, packed)
+        });
+
+        assert!(matches!(
+            ctx.size_of(func),
+            Err(TypeLayoutError::UnrepresentableType(ty)) if ty == func
+        ));
+        assert!(matches!(
+            ctx.size_of(packed),
+            Err(TypeLayoutError::UnsupportedType(ty)) if ty == packed
+        ));
+    }
+}
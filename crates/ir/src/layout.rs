@@ -10,6 +10,13 @@ pub struct Layout {
     insts: SecondaryMap<InstId, InstNode>,
     entry_block: Option<BlockId>,
     last_block: Option<BlockId>,
+    /// The number of the inserted blocks and insts, which are maintained
+    /// incrementally.
+    block_num: usize,
+    inst_num: usize,
+    /// The bits of the [`BlockFlag`](crate::function::BlockFlag)s set on each
+    /// block, which are cleared when the block is removed.
+    pub(crate) block_flags: SecondaryMap<BlockId, u8>,
 }
 
 impl Default for Layout {
@@ -25,6 +32,9 @@ impl Layout {
             insts: SecondaryMap::new(),
             entry_block: None,
             last_block: None,
+            block_num: 0,
+            inst_num: 0,
+            block_flags: SecondaryMap::new(),
         }
     }
 
@@ -36,6 +46,16 @@ impl Layout {
         self.last_block
     }
 
+    /// Returns the number of blocks in the layout in O(1).
+    pub fn block_num(&self) -> usize {
+        self.block_num
+    }
+
+    /// Returns the number of insts in the layout in O(1).
+    pub fn inst_num(&self) -> usize {
+        self.inst_num
+    }
+
     pub fn is_last_block(&self, block: BlockId) -> bool {
         self.last_block == Some(block)
     }
@@ -122,6 +142,7 @@ impl Layout {
 
         self.blocks[block] = block_node;
         self.last_block = Some(block);
+        self.block_num += 1;
     }
 
     pub fn insert_block_before(&mut self, block: BlockId, before: BlockId) {
@@ -141,6 +162,7 @@ impl Layout {
         block_node.next = Some(before);
         self.blocks[before].prev = Some(block);
         self.blocks[block] = block_node;
+        self.block_num += 1;
     }
 
     pub fn insert_block_after(&mut self, block: BlockId, after: BlockId) {
@@ -159,10 +181,15 @@ impl Layout {
         block_node.prev = Some(after);
         self.blocks[after].next = Some(block);
         self.blocks[block] = block_node;
+        self.block_num += 1;
     }
 
+    /// Remove the block from the layout.
+    /// The insts left in the block are no longer reachable from the layout,
+    /// so they are not counted by [`Self::inst_num`] anymore.
     pub fn remove_block(&mut self, block: BlockId) {
         debug_assert!(self.is_block_inserted(block));
+        self.inst_num -= self.iter_inst(block).count();
 
         let block_node = &mut self.blocks[block];
         let prev_block = block_node.prev;
@@ -187,6 +214,28 @@ impl Layout {
         }
 
         self.blocks[block] = BlockNode::default();
+        self.block_flags[block] = 0;
+        self.block_num -= 1;
+    }
+
+    /// Move the block after the `after` block, keeping its insts and flags.
+    pub fn move_block_after(&mut self, block: BlockId, after: BlockId) {
+        debug_assert_ne!(block, after);
+        let BlockNode {
+            first_inst,
+            last_inst,
+            ..
+        } = self.blocks[block].clone();
+        let (inst_num, flags) = (self.inst_num, self.block_flags[block]);
+
+        self.remove_block(block);
+        self.insert_block_after(block, after);
+
+        let node = &mut self.blocks[block];
+        node.first_inst = first_inst;
+        node.last_inst = last_inst;
+        self.inst_num = inst_num;
+        self.block_flags[block] = flags;
     }
 
     pub fn append_inst(&mut self, inst: InstId, block: BlockId) {
@@ -205,6 +254,28 @@ impl Layout {
 
         block_node.last_inst = Some(inst);
         self.insts[inst] = inst_node;
+        self.inst_num += 1;
+    }
+
+    /// Same as [`Self::append_inst`], but doesn't unlink the `inst` even if it's
+    /// already inserted, which corrupts the layout.
+    /// This is only for testing layout verification.
+    #[cfg(any(test, feature = "test-util"))]
+    #[doc(hidden)]
+    pub fn append_inst_unchecked(&mut self, inst: InstId, block: BlockId) {
+        let block_node = &mut self.blocks[block];
+        let mut inst_node = InstNode::with_block(block);
+
+        if let Some(last_inst) = block_node.last_inst {
+            inst_node.prev = Some(last_inst);
+            self.insts[last_inst].next = Some(inst);
+        } else {
+            block_node.first_inst = Some(inst);
+        }
+
+        block_node.last_inst = Some(inst);
+        self.insts[inst] = inst_node;
+        self.inst_num += 1;
     }
 
     pub fn prepend_inst(&mut self, inst: InstId, block: BlockId) {
@@ -223,6 +294,7 @@ impl Layout {
 
         block_node.first_inst = Some(inst);
         self.insts[inst] = inst_node;
+        self.inst_num += 1;
     }
 
     pub fn insert_inst_before(&mut self, inst: InstId, before: InstId) {
@@ -243,6 +315,7 @@ impl Layout {
         inst_node.next = Some(before);
         self.insts[before].prev = Some(inst);
         self.insts[inst] = inst_node;
+        self.inst_num += 1;
     }
 
     pub fn insert_inst_after(&mut self, inst: InstId, after: InstId) {
@@ -263,6 +336,7 @@ impl Layout {
         inst_node.prev = Some(after);
         self.insts[after].next = Some(inst);
         self.insts[inst] = inst_node;
+        self.inst_num += 1;
     }
 
     /// Remove instruction from the layout.
@@ -293,6 +367,7 @@ impl Layout {
         }
 
         self.insts[inst] = InstNode::default();
+        self.inst_num -= 1;
     }
 }
 
@@ -1,6 +1,8 @@
 pub mod builder;
+pub mod call_graph;
 pub mod cfg;
 pub mod dfg;
+pub mod domtree;
 pub mod func_cursor;
 pub mod function;
 pub mod global_variable;
@@ -13,6 +15,8 @@ pub mod layout;
 pub mod linkage;
 pub mod module;
 pub mod module_linker;
+#[cfg(feature = "petgraph")]
+pub mod petgraph_adapter;
 pub mod types;
 pub mod value;
 pub mod visitor;
@@ -21,11 +25,12 @@ mod bigint;
 
 pub use bigint::{I256, U256};
 pub use builder::Variable;
-pub use cfg::ControlFlowGraph;
-pub use dfg::{Block, BlockId, DataFlowGraph};
-pub use function::{Function, Signature};
+pub use call_graph::CallGraph;
+pub use cfg::{CfgOrder, ControlFlowGraph, EdgeKind, TooManyBlocks};
+pub use dfg::{Block, BlockId, DataFlowGraph, SourceLoc};
+pub use function::{BlockFlag, Function, Signature, SsaStats};
 pub use global_variable::{GlobalVariableData, GlobalVariableRef};
-pub use graphviz::render_to;
+pub use graphviz::{render_to, render_to_with_label};
 pub use inst::{
     inst_set::{InstSetBase, InstSetExt},
     HasInst, Inst, InstDowncast, InstDowncastMut, InstExt, InstId,
@@ -34,7 +39,7 @@ pub use layout::Layout;
 pub use linkage::Linkage;
 pub use module::Module;
 pub use types::Type;
-pub use value::{Immediate, Value, ValueId};
+pub use value::{Immediate, IntImm, Value, ValueId};
 
 pub mod prelude {
     pub use crate::{
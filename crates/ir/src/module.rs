@@ -1,4 +1,7 @@
-use std::sync::{Arc, Mutex, RwLock};
+use std::{
+    io,
+    sync::{Arc, Mutex, RwLock},
+};
 
 use cranelift_entity::entity_impl;
 use dashmap::{DashMap, ReadOnlyView};
@@ -7,7 +10,7 @@ use sonatina_triple::TargetTriple;
 
 use crate::{
     global_variable::GlobalVariableStore,
-    ir_writer::IrWrite,
+    ir_writer::{IrWrite, ModuleWriter},
     isa::{Endian, Isa, TypeLayout, TypeLayoutError},
     types::TypeStore,
     Function, InstSetBase, Linkage, Signature, Type,
@@ -30,6 +33,12 @@ impl Module {
     pub fn funcs(&self) -> Vec<FuncRef> {
         self.func_store.funcs()
     }
+
+    /// Write the whole module in the textual IR format.
+    /// The output is re-parseable by `sonatina-parser`.
+    pub fn write(&self, w: &mut impl io::Write) -> io::Result<()> {
+        ModuleWriter::new(self).write(w)
+    }
 }
 
 pub struct FuncStore {
@@ -0,0 +1,191 @@
+//! This module implements the graph traits of [`petgraph`] for
+//! [`ControlFlowGraph`], so that the algorithms in `petgraph::algo`, e.g.,
+//! `tarjan_scc` and `toposort`, run directly on the CFG without copying it.
+//!
+//! The nodes are the blocks that have any edge, and the entry block. Edges are
+//! identified by their source and destination blocks.
+
+use std::{collections::HashSet, iter::Copied, slice};
+
+use cranelift_entity::EntityRef;
+use petgraph::{
+    visit::{
+        GraphBase, IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers, NodeIndexable,
+        Visitable,
+    },
+    Direction,
+};
+
+use crate::{BlockId, ControlFlowGraph};
+
+impl GraphBase for ControlFlowGraph {
+    type NodeId = BlockId;
+    type EdgeId = (BlockId, BlockId);
+}
+
+impl<'a> IntoNeighbors for &'a ControlFlowGraph {
+    type Neighbors = Copied<slice::Iter<'a, BlockId>>;
+
+    fn neighbors(self, block: BlockId) -> Self::Neighbors {
+        self.succs_slice(block).iter().copied()
+    }
+}
+
+impl<'a> IntoNeighborsDirected for &'a ControlFlowGraph {
+    type NeighborsDirected = Copied<slice::Iter<'a, BlockId>>;
+
+    fn neighbors_directed(self, block: BlockId, dir: Direction) -> Self::NeighborsDirected {
+        match dir {
+            Direction::Outgoing => self.succs_slice(block).iter().copied(),
+            Direction::Incoming => self.preds_slice(block).iter().copied(),
+        }
+    }
+}
+
+impl<'a> IntoNodeIdentifiers for &'a ControlFlowGraph {
+    type NodeIdentifiers = NodeIdentifiers<'a>;
+
+    fn node_identifiers(self) -> Self::NodeIdentifiers {
+        NodeIdentifiers {
+            cfg: self,
+            next: 0,
+            bound: self.block_bound(),
+        }
+    }
+}
+
+impl NodeIndexable for ControlFlowGraph {
+    fn node_bound(&self) -> usize {
+        self.block_bound()
+    }
+
+    fn to_index(&self, block: BlockId) -> usize {
+        block.index()
+    }
+
+    fn from_index(&self, index: usize) -> BlockId {
+        BlockId::new(index)
+    }
+}
+
+impl Visitable for ControlFlowGraph {
+    type Map = HashSet<BlockId>;
+
+    fn visit_map(&self) -> Self::Map {
+        HashSet::default()
+    }
+
+    fn reset_map(&self, map: &mut Self::Map) {
+        map.clear();
+    }
+}
+
+/// An iterator over the blocks in a [`ControlFlowGraph`].
+pub struct NodeIdentifiers<'a> {
+    cfg: &'a ControlFlowGraph,
+    next: usize,
+    bound: usize,
+}
+
+impl Iterator for NodeIdentifiers<'_> {
+    type Item = BlockId;
+
+    fn next(&mut self) -> Option<BlockId> {
+        while self.next < self.bound {
+            let block = BlockId::new(self.next);
+            self.next += 1;
+            if self.cfg.entry() == Some(block)
+                || self.cfg.pred_num_of(block) > 0
+                || self.cfg.succ_num_of(block) > 0
+            {
+                return Some(block);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use petgraph::algo::tarjan_scc;
+
+    use super::*;
+    use crate::{
+        builder::test_util::*,
+        inst::control_flow::{Br, Jump, Return},
+        isa::Isa,
+        Type,
+    };
+
+    #[test]
+    fn tarjan_scc_complex() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let a = builder.append_block();
+        let b = builder.append_block();
+        let c = builder.append_block();
+        let d = builder.append_block();
+        let e = builder.append_block();
+        let f = builder.append_block();
+        let g = builder.append_block();
+        let h = builder.append_block();
+        let i = builder.append_block();
+        let j = builder.append_block();
+        let k = builder.append_block();
+        let l = builder.append_block();
+        let m = builder.append_block();
+
+        let v0 = builder.make_imm_value(true);
+        for (block, z_dest, nz_dest) in [
+            (a, c, b),
+            (b, g, d),
+            (c, h, e),
+            (d, g, f),
+            (e, h, c),
+            (f, k, i),
+            (l, m, b),
+        ] {
+            builder.switch_to_block(block);
+            builder.insert_inst_no_result(Br::new(is, v0, z_dest, nz_dest));
+        }
+        for (block, dest) in [(g, j), (h, m), (i, l), (j, i), (k, l)] {
+            builder.switch_to_block(block);
+            builder.insert_inst_no_result(Jump::new(is, dest));
+        }
+        builder.switch_to_block(m);
+        builder.insert_inst_no_result(Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let cfg = module.func_store.view(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+            cfg
+        });
+
+        // The order of the components and of the blocks in each component is
+        // unspecified, so compare them as sets.
+        let sccs: BTreeSet<BTreeSet<_>> = tarjan_scc(&cfg)
+            .into_iter()
+            .map(|scc| scc.into_iter().collect())
+            .collect();
+        let expected: BTreeSet<BTreeSet<_>> = [
+            vec![a],
+            vec![b, d, f, g, i, j, k, l],
+            vec![c, e],
+            vec![h],
+            vec![m],
+        ]
+        .into_iter()
+        .map(|scc| scc.into_iter().collect())
+        .collect();
+        assert_eq!(sccs, expected);
+    }
+}
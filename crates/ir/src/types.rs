@@ -186,24 +186,54 @@ impl TypeStore {
 /// Sonatina IR types definition.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum Type {
-    I1,
-    I8,
-    I16,
-    I32,
-    I64,
-    I128,
-    I256,
+    /// An integer type of the bit width in `1..=Type::MAX_INT_BITS`.
+    ///
+    /// Use [`Type::int`] to make a type of an arbitrary width, or the aliases
+    /// like [`Type::I32`] for the common ones.
+    Int(u16),
     Compound(CompoundTypeRef),
     #[default]
     Unit,
 }
 
 impl Type {
+    pub const I1: Self = Self::Int(1);
+    pub const I8: Self = Self::Int(8);
+    pub const I16: Self = Self::Int(16);
+    pub const I32: Self = Self::Int(32);
+    pub const I64: Self = Self::Int(64);
+    pub const I128: Self = Self::Int(128);
+    pub const I256: Self = Self::Int(256);
+
+    /// The maximum bit width of integer types.
+    ///
+    /// Immediates and the interpreter are backed by [`I256`](crate::I256), so
+    /// wider integers are not representable.
+    pub const MAX_INT_BITS: u16 = 256;
+
+    /// Returns the integer type of the `bits` width.
+    ///
+    /// # Panics
+    /// Panics if `bits` is not in `1..=Type::MAX_INT_BITS`.
+    pub fn int(bits: u16) -> Self {
+        assert!(
+            (1..=Self::MAX_INT_BITS).contains(&bits),
+            "integer width must be in 1..={}, but got {bits}",
+            Self::MAX_INT_BITS
+        );
+        Self::Int(bits)
+    }
+
+    /// Returns the bit width if the type is an integer type.
+    pub fn int_bits(self) -> Option<u16> {
+        match self {
+            Self::Int(bits) => Some(bits),
+            _ => None,
+        }
+    }
+
     pub fn is_integral(self) -> bool {
-        matches!(
-            self,
-            Self::I1 | Self::I8 | Self::I16 | Self::I32 | Self::I64 | Self::I128 | Self::I256
-        )
+        matches!(self, Self::Int(_))
     }
 
     pub fn is_compound(self) -> bool {
@@ -233,30 +263,13 @@ impl Type {
 
 impl cmp::PartialOrd for Type {
     fn partial_cmp(&self, rhs: &Self) -> Option<cmp::Ordering> {
-        use Type::*;
-
         if self == rhs {
             return Some(cmp::Ordering::Equal);
         }
 
-        if !self.is_integral() || !rhs.is_integral() {
-            return None;
-        }
-
         match (self, rhs) {
-            (I1, _) => Some(cmp::Ordering::Less),
-            (I8, I1) => Some(cmp::Ordering::Greater),
-            (I8, _) => Some(cmp::Ordering::Less),
-            (I16, I1 | I8) => Some(cmp::Ordering::Greater),
-            (I16, _) => Some(cmp::Ordering::Less),
-            (I32, I1 | I8 | I16) => Some(cmp::Ordering::Greater),
-            (I32, _) => Some(cmp::Ordering::Less),
-            (I64, I128 | I256) => Some(cmp::Ordering::Less),
-            (I64, _) => Some(cmp::Ordering::Greater),
-            (I128, I256) => Some(cmp::Ordering::Less),
-            (I128, _) => Some(cmp::Ordering::Greater),
-            (I256, _) => Some(cmp::Ordering::Greater),
-            (_, _) => unreachable!(),
+            (Type::Int(lhs), Type::Int(rhs)) => Some(lhs.cmp(rhs)),
+            _ => None,
         }
     }
 }
@@ -270,13 +283,7 @@ where
         W: io::Write,
     {
         match self {
-            Type::I1 => write!(w, "i1"),
-            Type::I8 => write!(w, "i8"),
-            Type::I16 => write!(w, "i16"),
-            Type::I32 => write!(w, "i32"),
-            Type::I64 => write!(w, "i64"),
-            Type::I128 => write!(w, "i128"),
-            Type::I256 => write!(w, "i256"),
+            Type::Int(bits) => write!(w, "i{bits}"),
             Type::Compound(cmpd_ty) => cmpd_ty.write(w, ctx),
             Type::Unit => write!(w, "unit"),
         }
@@ -7,7 +7,7 @@ use crate::{
     inst::InstId,
     ir_writer::{FuncWriteCtx, IrWrite},
     module::ModuleCtx,
-    GlobalVariableRef, I256,
+    GlobalVariableRef, I256, U256,
 };
 
 /// An opaque reference to [`Value`].
@@ -52,32 +52,22 @@ impl IrWrite<FuncWriteCtx<'_>> for ValueId {
 #[derive(Debug, Clone)]
 pub enum Value {
     /// The value is defined by an instruction.
-    Inst {
-        inst: InstId,
-        ty: Type,
-    },
+    Inst { inst: InstId, ty: Type },
 
     /// The value is a function argument.
-    Arg {
-        ty: Type,
-        idx: usize,
-    },
+    Arg { ty: Type, idx: usize },
 
     /// The value is immediate value.
-    Immediate {
-        imm: Immediate,
-        ty: Type,
-    },
+    Immediate { imm: Immediate, ty: Type },
 
     /// The value is global value.
-    Global {
-        gv: GlobalVariableRef,
-        ty: Type,
-    },
-
-    Undef {
-        ty: Type,
-    },
+    Global { gv: GlobalVariableRef, ty: Type },
+
+    /// The value is undefined, e.g., a read of an uninitialized memory slot.
+    ///
+    /// Passes may assume that an undef value is any value of its type, and
+    /// each use may observe a different value.
+    Undef { ty: Type },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -89,6 +79,48 @@ pub enum Immediate {
     I64(i64),
     I128(i128),
     I256(I256),
+    /// An integer of a bit width other than the above.
+    /// Use [`Immediate::from_i256`] to make one.
+    Int(IntImm),
+}
+
+/// An integer immediate of a bit width that has no dedicated [`Immediate`]
+/// variant.
+///
+/// The fields are private so that the value is always kept sign-extended from
+/// `bits` to 256 bits, which makes equal immediates compare equal. Widths
+/// above [`Type::MAX_INT_BITS`] are not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IntImm {
+    bits: u16,
+    value: I256,
+}
+
+impl IntImm {
+    fn new(bits: u16, value: I256) -> Self {
+        debug_assert!(
+            !matches!(bits, 1 | 8 | 16 | 32 | 64 | 128 | 256),
+            "`i{bits}` has a dedicated variant"
+        );
+        assert!(
+            (1..=Type::MAX_INT_BITS).contains(&bits),
+            "integer width must be in 1..={}, but got {bits}",
+            Type::MAX_INT_BITS
+        );
+        Self {
+            bits,
+            value: value.trunc_to_bits(bits),
+        }
+    }
+
+    pub fn bits(self) -> u16 {
+        self.bits
+    }
+
+    /// Returns the value sign-extended to 256 bits.
+    pub fn value(self) -> I256 {
+        self.value
+    }
 }
 
 impl Immediate {
@@ -101,6 +133,7 @@ impl Immediate {
             Self::I64(..) => Type::I64,
             Self::I128(..) => Type::I128,
             Self::I256(..) => Type::I256,
+            Self::Int(imm) => Type::Int(imm.bits),
         }
     }
 
@@ -120,6 +153,33 @@ impl Immediate {
         self.apply_binop(rhs, |lhs, rhs| lhs.overflowing_rem(rhs).0)
     }
 
+    /// Returns `None` if `rhs` is zero.
+    pub fn checked_udiv(self, rhs: Self) -> Option<Self> {
+        (!rhs.is_zero()).then(|| self.udiv(rhs))
+    }
+
+    /// Returns `None` if `rhs` is zero. `MIN / -1` wraps around to `MIN`.
+    pub fn checked_sdiv(self, rhs: Self) -> Option<Self> {
+        (!rhs.is_zero()).then(|| self.sdiv(rhs))
+    }
+
+    /// Returns `None` if `rhs` is zero.
+    pub fn checked_urem(self, rhs: Self) -> Option<Self> {
+        (!rhs.is_zero()).then(|| self.urem(rhs))
+    }
+
+    /// Returns `None` if `rhs` is zero.
+    pub fn checked_srem(self, rhs: Self) -> Option<Self> {
+        (!rhs.is_zero()).then(|| self.srem(rhs))
+    }
+
+    /// Shifts right with the sign bit filled in. The shift amount is
+    /// interpreted as unsigned.
+    pub fn sar(self, rhs: Self) -> Self {
+        let amount = I256::from_u256(rhs.as_u256());
+        Self::from_i256(self.as_i256().sar(amount), self.ty())
+    }
+
     pub fn lt(self, rhs: Self) -> Self {
         self.apply_binop_raw(rhs, |lhs, rhs| (lhs.to_u256() < rhs.to_u256()).into())
     }
@@ -167,6 +227,7 @@ impl Immediate {
             Self::I64(val) => (val as u64).into(),
             Self::I128(val) => (val as u128).into(),
             Self::I256(val) => val,
+            Self::Int(imm) => imm.value.zext_from_bits(imm.bits),
         };
 
         Self::from_i256(i256, ty)
@@ -200,10 +261,25 @@ impl Immediate {
         Self::from_i256(val, ty)
     }
 
+    /// Returns the value with all bits set, which is also the maximum
+    /// unsigned value of the `ty`.
     pub fn all_one(ty: Type) -> Self {
         Self::from_i256(I256::all_one(), ty)
     }
 
+    /// Returns the minimum signed value of the `ty`.
+    /// The minimum unsigned value is [`Self::zero`].
+    pub fn min(ty: Type) -> Self {
+        !Self::max(ty)
+    }
+
+    /// Returns the maximum signed value of the `ty`.
+    /// The maximum unsigned value is [`Self::all_one`].
+    pub fn max(ty: Type) -> Self {
+        let bits = ty.int_bits().unwrap();
+        Self::from_i256(I256::all_one().zext_from_bits(bits - 1), ty)
+    }
+
     pub fn is_zero(self) -> bool {
         self.apply_unop_raw(|val| val.is_zero())
     }
@@ -241,9 +317,43 @@ impl Immediate {
             Self::I64(val) => val.into(),
             Self::I128(val) => val.into(),
             Self::I256(val) => val,
+            Self::Int(imm) => imm.value,
         }
     }
 
+    /// Returns the value interpreted as a signed integer as `i64` if it fits,
+    /// e.g., for a backend that only supports 64-bit immediates.
+    pub fn try_as_i64(self) -> Option<i64> {
+        let val = self.as_i256();
+        (val >= i64::MIN.into() && val <= i64::MAX.into()).then(|| val.trunc_to_i64())
+    }
+
+    /// Returns the value interpreted as a signed integer as `i128` if it fits.
+    pub fn try_as_i128(self) -> Option<i128> {
+        let val = self.as_i256();
+        (val >= i128::MIN.into() && val <= i128::MAX.into()).then(|| val.trunc_to_i128())
+    }
+
+    /// Returns the value interpreted as an unsigned integer as `u64` if it
+    /// fits.
+    pub fn try_as_u64(self) -> Option<u64> {
+        let val = self.as_u256();
+        (val <= u64::MAX.into()).then(|| val.low_u64())
+    }
+
+    /// Returns the value interpreted as an unsigned integer as `u128` if it
+    /// fits.
+    pub fn try_as_u128(self) -> Option<u128> {
+        let val = self.as_u256();
+        (val <= u128::MAX.into()).then(|| val.low_u128())
+    }
+
+    /// Returns the value zero-extended to `U256`.
+    fn as_u256(self) -> U256 {
+        let bits = self.ty().int_bits().unwrap();
+        self.as_i256().zext_from_bits(bits).to_u256()
+    }
+
     pub fn as_usize(self) -> usize {
         let val = if self.is_negative() { -self } else { self };
         val.as_i256().to_u256().as_usize()
@@ -258,6 +368,7 @@ impl Immediate {
             Type::I64 => Self::I64(val.trunc_to_i64()),
             Type::I128 => Self::I128(val.trunc_to_i128()),
             Type::I256 => Self::I256(val),
+            Type::Int(bits) => Self::Int(IntImm::new(bits, val)),
             _ => unreachable!(),
         }
     }
@@ -371,11 +482,14 @@ impl ops::Shl for Immediate {
     }
 }
 
+/// Shifts right logically. The shift amount is interpreted as unsigned.
 impl ops::Shr for Immediate {
     type Output = Self;
 
     fn shr(self, rhs: Self) -> Self::Output {
-        self.apply_binop(rhs, ops::Shl::shl)
+        let value = I256::from_u256(self.as_u256());
+        let amount = I256::from_u256(rhs.as_u256());
+        Self::from_i256(value >> amount, self.ty())
     }
 }
 
@@ -401,12 +515,21 @@ where
             Self::I64(v) => write!(w, "{}", v),
             Self::I128(v) => write!(w, "{}", v),
             Self::I256(v) => write!(w, "{}", v),
+            Self::Int(imm) => write!(w, "{}", imm.value),
         }
     }
 }
 
+/// Prints the value in decimal by default. The alternate flag, i.e., `{:#}`,
+/// prints the bit pattern of the value in hex instead, e.g., `0xfb` for `-5`
+/// of `i8`, which is easier to read in bitwise code.
 impl fmt::Display for Immediate {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            let bits = self.ty().int_bits().unwrap();
+            return write!(f, "{:#x}", self.as_i256().zext_from_bits(bits));
+        }
+
         match self {
             Self::I1(v) => {
                 if *v {
@@ -421,6 +544,7 @@ impl fmt::Display for Immediate {
             Self::I64(v) => write!(f, "{}", v),
             Self::I128(v) => write!(f, "{}", v),
             Self::I256(v) => write!(f, "{}", v),
+            Self::Int(imm) => write!(f, "{}", imm.value),
         }
     }
 }
@@ -447,3 +571,97 @@ imm_from_primary!(u64, i64, Immediate::I64);
 imm_from_primary!(i128, i128, Immediate::I128);
 imm_from_primary!(u128, i128, Immediate::I128);
 imm_from_primary!(I256, I256, Immediate::I256);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_hex() {
+        let minus_one = Immediate::I256(I256::all_one());
+        assert_eq!(minus_one.to_string(), "-1");
+        assert_eq!(format!("{minus_one:#}"), format!("0x{}", "f".repeat(64)));
+
+        assert_eq!(format!("{:#}", Immediate::I8(-5)), "0xfb");
+        assert_eq!(format!("{:#}", Immediate::I32(200)), "0xc8");
+    }
+
+    #[test]
+    fn constructors() {
+        assert_eq!(Immediate::one(Type::I8), Immediate::I8(1));
+        assert_eq!(Immediate::all_one(Type::I8), Immediate::I8(-1));
+        assert_eq!(Immediate::min(Type::I8), Immediate::I8(i8::MIN));
+        assert_eq!(Immediate::max(Type::I8), Immediate::I8(i8::MAX));
+
+        // Unsigned bounds of `i8` seen as `u8`.
+        assert_eq!(Immediate::zero(Type::I8).as_i256().to_u256(), 0u8.into());
+        assert_eq!(
+            Immediate::all_one(Type::I8)
+                .as_i256()
+                .zext_from_bits(8)
+                .to_u256(),
+            u8::MAX.into()
+        );
+
+        let max = I256::all_one().zext_from_bits(255);
+        assert_eq!(Immediate::one(Type::I256), Immediate::I256(I256::one()));
+        assert_eq!(
+            Immediate::all_one(Type::I256),
+            Immediate::I256(I256::all_one())
+        );
+        assert_eq!(Immediate::max(Type::I256), Immediate::I256(max));
+        assert_eq!(Immediate::min(Type::I256), Immediate::I256(!max));
+    }
+
+    #[test]
+    fn native_conversions() {
+        let imm = Immediate::I256(I256::from(300i64));
+        assert_eq!(imm.try_as_i64(), Some(300));
+        assert_eq!(imm.try_as_u64(), Some(300));
+
+        let imm = Immediate::I256(I256::from_u256(U256::one() << 100));
+        assert_eq!(imm.try_as_i64(), None);
+        assert_eq!(imm.try_as_u64(), None);
+        assert_eq!(imm.try_as_u128(), Some(1 << 100));
+
+        // `-1` fits in `i64`, but its unsigned value is the maximum of the type.
+        assert_eq!(Immediate::I8(-1).try_as_i64(), Some(-1));
+        assert_eq!(Immediate::I8(-1).try_as_u64(), Some(u8::MAX.into()));
+        assert_eq!(Immediate::all_one(Type::I256).try_as_u128(), None);
+        assert_eq!(Immediate::min(Type::I128).try_as_i128(), Some(i128::MIN));
+    }
+
+    #[test]
+    fn int_imm_is_normalized() {
+        let i17 = Type::int(17);
+        let wrapped = Immediate::from_i256(I256::from_u256(U256::one() << 17), i17);
+        assert_eq!(wrapped, Immediate::zero(i17));
+
+        // The value is kept sign-extended, so the same bits compare equal.
+        let minus_one = Immediate::from_i256(I256::from_u256(U256::from(0x1ffffu32)), i17);
+        assert_eq!(minus_one, Immediate::all_one(i17));
+        let Immediate::Int(imm) = minus_one else {
+            panic!("`i17` must be kept in `Immediate::Int`");
+        };
+        assert_eq!(imm.bits(), 17);
+        assert_eq!(imm.value(), I256::all_one());
+
+        // Widths with a dedicated variant never make `Immediate::Int`.
+        assert_eq!(
+            Immediate::from_i256(I256::all_one(), Type::int(8)),
+            Immediate::I8(-1)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "integer width must be in 1..=256, but got 0")]
+    fn zero_width_int_imm() {
+        Immediate::from_i256(I256::one(), Type::Int(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "integer width must be in 1..=256, but got 300")]
+    fn too_wide_int_imm() {
+        Immediate::from_i256(I256::one(), Type::Int(300));
+    }
+}
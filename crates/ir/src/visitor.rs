@@ -5,7 +5,7 @@
 //! visited in order to cover the whole sonatina-IR.
 use smallvec::{Array, SmallVec};
 
-use crate::{module::FuncRef, BlockId, Type, ValueId};
+use crate::{builder::Variable, module::FuncRef, BlockId, Type, ValueId};
 
 pub trait Visitable {
     fn accept(&self, visitor: &mut dyn Visitor);
@@ -27,6 +27,42 @@ pub trait Visitable {
         let mut visitor = ValueVisitor { f };
         self.accept(&mut visitor);
     }
+
+    /// Passes values and blocks in the item to the `f` in the order they
+    /// appear, e.g., so that a single remapper can rewrite both when cloning
+    /// insts.
+    fn for_each_operand(&self, f: &mut dyn FnMut(Operand)) {
+        struct OperandVisitor<'a> {
+            f: &'a mut dyn FnMut(Operand),
+        }
+
+        impl Visitor for OperandVisitor<'_> {
+            fn visit_value_id(&mut self, item: ValueId) {
+                (self.f)(Operand::Value(item))
+            }
+
+            fn visit_block_id(&mut self, item: BlockId) {
+                (self.f)(Operand::Block(item))
+            }
+        }
+
+        let mut visitor = OperandVisitor { f };
+        self.accept(&mut visitor);
+    }
+}
+
+/// An operand of an item, i.e., either a value or a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Value(ValueId),
+    Block(BlockId),
+}
+
+/// A mutable reference to an operand of an item.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub enum OperandMut<'a> {
+    Value(&'a mut ValueId),
+    Block(&'a mut BlockId),
 }
 
 pub trait VisitableMut {
@@ -49,6 +85,27 @@ pub trait VisitableMut {
         let mut visitor = ValueVisitorMut { f };
         self.accept_mut(&mut visitor);
     }
+
+    /// Passes mutable references to values and blocks in the item to the `f`
+    /// in the order they appear.
+    fn for_each_operand_mut(&mut self, f: &mut dyn FnMut(OperandMut)) {
+        struct OperandVisitorMut<'a> {
+            f: &'a mut dyn FnMut(OperandMut),
+        }
+
+        impl VisitorMut for OperandVisitorMut<'_> {
+            fn visit_value_id(&mut self, item: &mut ValueId) {
+                (self.f)(OperandMut::Value(item))
+            }
+
+            fn visit_block_id(&mut self, item: &mut BlockId) {
+                (self.f)(OperandMut::Block(item))
+            }
+        }
+
+        let mut visitor = OperandVisitorMut { f };
+        self.accept_mut(&mut visitor);
+    }
 }
 
 #[allow(unused_variables)]
@@ -117,6 +174,14 @@ impl VisitableMut for FuncRef {
     }
 }
 
+// A source-level variable is not an IR item, so it's never visited.
+impl Visitable for Variable {
+    fn accept(&self, _visitor: &mut dyn Visitor) {}
+}
+impl VisitableMut for Variable {
+    fn accept_mut(&mut self, _visitor: &mut dyn VisitorMut) {}
+}
+
 impl<T> Visitable for Option<T>
 where
     T: Visitable,
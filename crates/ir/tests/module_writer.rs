@@ -0,0 +1,53 @@
+use sonatina_parser::parse_module;
+
+const MODULE: &str = r#"target = "evm-ethereum-london"
+
+global public const i256 $ONE = 1;
+
+declare external %ext(i256) -> i256;
+
+func public %main(v0.i256) -> i256 {
+    block0:
+        v1.i256 = mload $ONE i256;
+        v2.i256 = call %ext v1;
+        v3.i256 = add v0 v2;
+        return v3;
+}
+"#;
+
+fn write_module(module: &sonatina_ir::Module) -> String {
+    let mut text = Vec::new();
+    module.write(&mut text).unwrap();
+    String::from_utf8(text).unwrap()
+}
+
+#[test]
+fn module_write_order() {
+    let parsed = parse_module(MODULE).unwrap();
+    let text = write_module(&parsed.module);
+
+    let target = text.find("target = ").unwrap();
+    let gv = text.find("global public const i256 $ONE").unwrap();
+    let decl = text.find("declare external %ext(i256) -> i256;").unwrap();
+    let def = text.find("func public %main").unwrap();
+    assert!(target < gv);
+    assert!(gv < decl);
+    assert!(decl < def);
+}
+
+#[test]
+fn module_write_reparse() {
+    let parsed = parse_module(MODULE).unwrap();
+    let text = write_module(&parsed.module);
+
+    let reparsed = match parse_module(&text) {
+        Ok(reparsed) => reparsed,
+        Err(_) => panic!("failed to re-parse the written module:\n{text}"),
+    };
+    assert_eq!(reparsed.module.funcs().len(), parsed.module.funcs().len());
+
+    let retext = write_module(&reparsed.module);
+    assert!(retext.contains("global public const i256 $ONE = 1;"));
+    assert!(retext.contains("declare external %ext(i256) -> i256;"));
+    assert!(retext.contains("func public %main("));
+}
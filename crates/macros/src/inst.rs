@@ -20,6 +20,7 @@ struct InstStruct {
     struct_name: syn::Ident,
     side_effect: Option<syn::Path>,
     is_terminator: bool,
+    text: Option<syn::LitStr>,
     fields: Vec<InstField>,
 }
 
@@ -30,7 +31,7 @@ struct InstField {
 
 impl InstStruct {
     fn new(item_struct: syn::ItemStruct) -> syn::Result<Self> {
-        let (side_effect, is_terminator) = Self::check_attr(&item_struct)?;
+        let (side_effect, is_terminator, text) = Self::check_attr(&item_struct)?;
 
         let struct_ident = item_struct.ident;
 
@@ -47,6 +48,7 @@ impl InstStruct {
             struct_name: struct_ident,
             side_effect,
             is_terminator,
+            text,
             fields,
         })
     }
@@ -68,9 +70,13 @@ impl InstStruct {
         })
     }
 
-    fn check_attr(item_struct: &syn::ItemStruct) -> syn::Result<(Option<syn::Path>, bool)> {
+    #[allow(clippy::type_complexity)]
+    fn check_attr(
+        item_struct: &syn::ItemStruct,
+    ) -> syn::Result<(Option<syn::Path>, bool, Option<syn::LitStr>)> {
         let mut side_effect = None;
         let mut is_terminator = false;
+        let mut text = None;
 
         for attr in &item_struct.attrs {
             if attr.path().is_ident("inst") {
@@ -92,10 +98,25 @@ impl InstStruct {
                         is_terminator = true;
                     }
                 }
+                if let syn::Meta::NameValue(nv) = &meta {
+                    if nv.path.is_ident("text") {
+                        let syn::Expr::Lit(syn::ExprLit {
+                            lit: syn::Lit::Str(lit),
+                            ..
+                        }) = &nv.value
+                        else {
+                            return Err(syn::Error::new_spanned(
+                                &nv.value,
+                                "`text = \"...\"` is required",
+                            ));
+                        };
+                        text = Some(lit.clone());
+                    }
+                }
             }
         }
 
-        Ok((side_effect, is_terminator))
+        Ok((side_effect, is_terminator, text))
     }
 
     fn parse_fields(fields: &syn::Fields) -> syn::Result<Vec<InstField>> {
@@ -208,7 +229,10 @@ impl InstStruct {
 
     fn impl_method(&self) -> proc_macro2::TokenStream {
         let struct_name = &self.struct_name;
-        let text_form = convert_to_snake(&self.struct_name.to_string());
+        let text_form = match &self.text {
+            Some(text) => text.value(),
+            None => convert_to_snake(&self.struct_name.to_string()),
+        };
         let ctor = self.make_ctor();
         let accessors = self.make_accessors();
 
@@ -246,6 +270,17 @@ impl InstStruct {
                 fn as_text(&self) -> &'static str {
                     Self::inst_name()
                 }
+
+                fn as_any(&self) -> &dyn std::any::Any {
+                    self
+                }
+
+                fn is_identical(&self, other: &dyn crate::Inst) -> bool {
+                    other
+                        .as_any()
+                        .downcast_ref::<Self>()
+                        .is_some_and(|other| self == other)
+                }
             }
         }
     }
@@ -23,6 +23,8 @@ mod inst_set_base;
 ///
 /// # Arguments
 /// - `has_side_effect`: Marks the instruction as having a side effect.
+/// - `text = "..."`: Overrides the text form of the instruction, which defaults
+///   to the snake case of the type name.
 /// - `value`: Marks the field that contains value, the specified field must
 ///   implements `sonatina-ir::inst::ValueVisitable` trait.
 ///
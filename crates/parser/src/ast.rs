@@ -121,6 +121,7 @@ impl FromSyntax<Error> for SmolStr {
 #[derive(Debug)]
 pub struct FuncDeclaration {
     pub linkage: Linkage,
+    pub is_pure: bool,
     pub name: FunctionName,
     pub params: Vec<Type>,
     pub ret_type: Option<Type>,
@@ -132,6 +133,7 @@ impl FromSyntax<Error> for FuncDeclaration {
 
         FuncDeclaration {
             linkage,
+            is_pure: node.get_opt(Rule::function_pure).is_some(),
             name: node.single(Rule::function_identifier),
             params: node.descend_into(Rule::function_param_type_list, |n| n.multi(Rule::type_name)),
             ret_type: node.descend_into_opt(Rule::function_ret_type, |n| n.single(Rule::type_name)),
@@ -278,6 +280,7 @@ impl FromSyntax<Error> for Func {
 #[derive(Debug)]
 pub struct FuncSignature {
     pub linkage: Linkage,
+    pub is_pure: bool,
     pub name: FunctionName,
     pub params: Vec<ValueDeclaration>,
     pub ret_type: Option<Type>,
@@ -289,6 +292,7 @@ impl FromSyntax<Error> for FuncSignature {
 
         FuncSignature {
             linkage,
+            is_pure: node.get_opt(Rule::function_pure).is_some(),
             name: node.single(Rule::function_identifier),
             params: node.descend_into(Rule::function_params, |n| n.multi(Rule::value_declaration)),
             ret_type: node.descend_into_opt(Rule::function_ret_type, |n| n.single(Rule::type_name)),
@@ -353,6 +357,27 @@ impl FromSyntax<Error> for BlockId {
     }
 }
 
+/// A source-level variable referred by a debug inst.
+#[derive(Dbg)]
+pub struct Variable {
+    pub id: Option<u32>,
+    #[debug(skip)]
+    pub span: Span,
+}
+
+impl FromSyntax<Error> for Variable {
+    fn from_syntax(node: &mut Node<Error>) -> Self {
+        let span = node.span;
+        node.descend();
+        debug_assert_eq!(node.rule, Rule::variable_number);
+        let id = node.txt.parse().ok();
+        if id.is_none() {
+            node.error(Error::NumberOutOfBounds(node.span));
+        }
+        Variable { id, span }
+    }
+}
+
 #[derive(Dbg)]
 pub struct Stmt {
     pub kind: StmtKind,
@@ -387,6 +412,10 @@ pub enum StmtKind {
 
 #[derive(Dbg)]
 pub struct Inst {
+    /// `true` if the result of the inst is pinned, e.g., `v1.i8 = pinned add v0 1.i8`.
+    pub is_pinned: bool,
+    /// `true` if the inst is a volatile memory access, e.g., `volatile mstore v0 v1 i8`.
+    pub is_volatile: bool,
     pub name: InstName,
     pub args: Vec<InstArg>,
     #[debug(skip)]
@@ -395,9 +424,13 @@ pub struct Inst {
 
 impl FromSyntax<Error> for Inst {
     fn from_syntax(node: &mut Node<Error>) -> Self {
+        let is_pinned = node.get_opt(Rule::inst_pinned).is_some();
+        let is_volatile = node.get_opt(Rule::inst_volatile).is_some();
         let name = node.single(Rule::inst_name);
         let args = node.multi(Rule::inst_arg);
         Self {
+            is_pinned,
+            is_volatile,
             name,
             args,
             span: node.span,
@@ -522,6 +555,22 @@ impl<'a> TryFrom<&'a InstArg> for &'a FunctionName {
     }
 }
 
+impl<'a> TryFrom<&'a InstArg> for &'a Variable {
+    type Error = Box<Error>;
+
+    fn try_from(arg: &'a InstArg) -> Result<Self, Self::Error> {
+        if let InstArgKind::Variable(var) = &arg.kind {
+            Ok(var)
+        } else {
+            Err(Box::new(Error::InstArgKindMismatch {
+                expected: "variable".into(),
+                actual: arg.kind.discriminant_name().into(),
+                span: arg.span,
+            }))
+        }
+    }
+}
+
 impl FromSyntax<Error> for InstArg {
     fn from_syntax(node: &mut Node<Error>) -> Self {
         let kind = if let Some(value) = node.single_opt(Rule::value) {
@@ -534,6 +583,8 @@ impl FromSyntax<Error> for InstArg {
             InstArgKind::ValueBlockMap(vb_map)
         } else if let Some(func) = node.single_opt(Rule::function_identifier) {
             InstArgKind::FuncRef(func)
+        } else if let Some(var) = node.single_opt(Rule::variable) {
+            InstArgKind::Variable(var)
         } else {
             unreachable!()
         };
@@ -552,6 +603,7 @@ pub enum InstArgKind {
     Block(BlockId),
     ValueBlockMap((Value, BlockId)),
     FuncRef(FunctionName),
+    Variable(Variable),
 }
 
 impl InstArgKind {
@@ -562,6 +614,7 @@ impl InstArgKind {
             Self::Block(_) => "block",
             Self::ValueBlockMap(_) => "(value, block)",
             Self::FuncRef(_) => "function name",
+            Self::Variable(_) => "variable",
         }
         .into()
     }
@@ -584,7 +637,16 @@ impl FromSyntax<Error> for Type {
     fn from_syntax(node: &mut Node<Error>) -> Self {
         node.descend();
         let kind = match node.rule {
-            Rule::primitive_type => TypeKind::Int(IntType::from_str(node.txt).unwrap()),
+            Rule::primitive_type => {
+                let Ok(ty) = IntType::from_str(node.txt) else {
+                    node.error(Error::NumberOutOfBounds(node.span));
+                    return Type {
+                        kind: TypeKind::Error,
+                        span: node.span,
+                    };
+                };
+                TypeKind::Int(ty)
+            }
             Rule::ptr_type => TypeKind::Ptr(Box::new(node.single(Rule::type_name))),
             Rule::array_type => {
                 let Ok(size) = usize::from_str(node.get(Rule::array_size).as_str()) else {
@@ -636,6 +698,8 @@ pub enum IntType {
     I64,
     I128,
     I256,
+    /// An integer type of a bit width other than the above.
+    Int(u16),
 }
 
 impl From<IntType> for ir::Type {
@@ -648,6 +712,7 @@ impl From<IntType> for ir::Type {
             IntType::I64 => ir::Type::I64,
             IntType::I128 => ir::Type::I128,
             IntType::I256 => ir::Type::I256,
+            IntType::Int(bits) => ir::Type::int(bits),
         }
     }
 }
@@ -731,7 +796,13 @@ impl FromSyntax<Error> for Value {
         let kind = match node.rule {
             Rule::value_name => ValueKind::Named(ValueName::from_syntax(node)),
             Rule::imm_number => {
-                let ty: IntType = node.parse_str(Rule::primitive_type);
+                let Ok(ty) = IntType::from_str(node.get(Rule::primitive_type).as_str()) else {
+                    node.error(Error::NumberOutOfBounds(node.span));
+                    return Value {
+                        kind: ValueKind::Error,
+                        span: node.span,
+                    };
+                };
                 node.descend();
                 let mut txt = node.txt;
                 match node.rule {
@@ -771,6 +842,10 @@ impl FromSyntax<Error> for Value {
                                 ValueKind::Error,
                             )
                         }
+
+                        IntType::Int(bits) => {
+                            imm_or_err(node, || parse_int_imm(txt, bits, false), ValueKind::Error)
+                        }
                     },
 
                     Rule::hex => match ty {
@@ -799,6 +874,10 @@ impl FromSyntax<Error> for Value {
                                 ValueKind::Error
                             }
                         }
+
+                        IntType::Int(bits) => {
+                            imm_or_err(node, || parse_int_imm(txt, bits, true), ValueKind::Error)
+                        }
                     },
                     _ => unreachable!(),
                 }
@@ -835,11 +914,42 @@ impl FromStr for IntType {
             "i64" => Ok(Self::I64),
             "i128" => Ok(Self::I128),
             "i256" => Ok(Self::I256),
-            _ => Err(()),
+            _ => {
+                let bits = s.strip_prefix('i').ok_or(())?;
+                let bits = u16::from_str(bits).map_err(|_| ())?;
+                if (1..=ir::Type::MAX_INT_BITS).contains(&bits) {
+                    Ok(Self::Int(bits))
+                } else {
+                    Err(())
+                }
+            }
         }
     }
 }
 
+/// Parses an immediate of the `bits` width, which may be written either as a
+/// signed or an unsigned number.
+fn parse_int_imm(mut txt: &str, bits: u16, is_hex: bool) -> Option<Immediate> {
+    let s = txt.strip_prefix('-');
+    let is_negative = s.is_some();
+    txt = s.unwrap_or(txt);
+
+    let abs = if is_hex {
+        U256::from_big_endian(&hex_bytes::<32>(txt)?)
+    } else {
+        U256::from_dec_str(txt).ok()?
+    };
+    let mut val: I256 = abs.into();
+    if is_negative {
+        val = I256::zero().overflowing_sub(val).0;
+    }
+
+    if val.trunc_to_bits(bits) != val && val.zext_from_bits(bits) != val {
+        return None;
+    }
+    Some(Immediate::from_i256(val, ir::Type::int(bits)))
+}
+
 fn imm_or_err<F, R>(node: &mut Node<Error>, f: F, default: R) -> R
 where
     F: FnOnce() -> Option<Immediate>,
@@ -30,6 +30,10 @@ pub enum Error {
 
     UnexpectedTrailingInstArg(Span),
 
+    /// `pinned` on an inst without a result, or `volatile` on an inst other
+    /// than a memory access.
+    InvalidInstAttr(&'static str, Span),
+
     UnsupportedInst {
         triple: TargetTriple,
         inst: SmolStr,
@@ -65,6 +69,7 @@ impl Error {
                 pest::error::InputLocation::Span((s, e)) => Span(s as u32, e as u32),
             },
             Error::UnexpectedTrailingInstArg(span) => *span,
+            Error::InvalidInstAttr(_, span) => *span,
             Error::InstArgKindMismatch { span, .. } => *span,
             Error::InstArgNumMismatch { span, .. } => *span,
             Error::UnsupportedInst { span, .. } => *span,
@@ -99,6 +104,8 @@ impl Error {
 
             Error::UnexpectedTrailingInstArg(_) => "unexpected trailing inst argument".to_string(),
 
+            Error::InvalidInstAttr(attr, _) => format!("`{attr}` isn't allowed on this inst"),
+
             Error::InstArgKindMismatch {
                 expected, actual, ..
             } => {
@@ -0,0 +1,3 @@
+use ir::inst::debug::*;
+
+super::impl_inst_build! {DebugValue, (value: ValueId, var: Variable)}
@@ -4,3 +4,4 @@ super::impl_inst_build! {Not, (arg: ValueId)}
 super::impl_inst_build! {And, (lhs: ValueId, rhs: ValueId)}
 super::impl_inst_build! {Or, (lhs: ValueId, rhs: ValueId)}
 super::impl_inst_build! {Xor, (lhs: ValueId, rhs: ValueId)}
+super::impl_inst_build! {Select, (cond: ValueId, nz_val: ValueId, z_val: ValueId)}
@@ -10,6 +10,7 @@ mod cast;
 mod cmp;
 mod control_flow;
 mod data;
+mod debug;
 mod evm;
 mod logic;
 
@@ -110,6 +111,14 @@ macro_rules! process_arg {
         $ctx.block($arg_iter.next().unwrap().try_into()?)
     };
 
+    ($ctx:ident, $fb:ident, $arg_iter:expr, Variable) => {{
+        let var: &crate::ast::Variable = $arg_iter.next().unwrap().try_into()?;
+        let Some(id) = var.id else {
+            return Err(Box::new(crate::Error::NumberOutOfBounds(var.span)));
+        };
+        ir::builder::Variable::from_u32(id)
+    }};
+
     ($ctx:ident, $fb:ident, $arg_iter:expr, FuncRef) => {
         $ctx.func_ref(
             &mut $fb.module_builder,
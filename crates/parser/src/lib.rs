@@ -8,10 +8,11 @@ use ir::{
     builder::{FunctionBuilder, ModuleBuilder},
     func_cursor::{CursorLocation, FuncCursor, InstInserter},
     global_variable::GvInitializer,
+    inst::data::{Mload, Mstore},
     ir_writer::{DebugProvider, IrWrite},
     isa::evm::Evm,
     module::{FuncRef, Module, ModuleCtx},
-    Function, GlobalVariableData, GlobalVariableRef, Immediate, Signature, Type,
+    Function, GlobalVariableData, GlobalVariableRef, Immediate, InstDowncast, Signature, Type,
 };
 use rustc_hash::{FxHashMap, FxHashSet, FxHasher};
 use smol_str::SmolStr;
@@ -70,7 +71,8 @@ pub fn parse_module(input: &str) -> Result<ParsedModule, Vec<Error>> {
             .map(|t| ctx.type_(&builder, t))
             .unwrap_or(ir::Type::Unit);
 
-        let sig = Signature::new(&func.name.name, func.linkage, &params, ret_ty);
+        let mut sig = Signature::new(&func.name.name, func.linkage, &params, ret_ty);
+        sig.set_pure(func.is_pure);
         builder.declare_function(sig);
     }
 
@@ -91,7 +93,9 @@ pub fn parse_module(input: &str) -> Result<ParsedModule, Vec<Error>> {
             .as_ref()
             .map(|t| ctx.type_(&builder, t))
             .unwrap_or(ir::Type::Unit);
-        let sig = Signature::new(&sig.name.name, sig.linkage, &args, ret_ty);
+        let is_pure = sig.is_pure;
+        let mut sig = Signature::new(&sig.name.name, sig.linkage, &args, ret_ty);
+        sig.set_pure(is_pure);
 
         builder.declare_function(sig);
     }
@@ -177,7 +181,7 @@ impl BuildCtx {
             fb.cursor.set_location(CursorLocation::BlockTop(block_id));
 
             for stmt in &block.stmts {
-                let inst_id = match &stmt.kind {
+                let (inst_id, ast_inst) = match &stmt.kind {
                     ast::StmtKind::Assign(ValueDeclaration(name, type_), ast_inst) => {
                         let inst = match InstBuild::build(self, &mut fb, ast_inst) {
                             Ok(inst) => inst,
@@ -193,7 +197,7 @@ impl BuildCtx {
                         let inst_id = fb.cursor.insert_inst_data_dyn(&mut fb.func, inst);
                         fb.func.dfg.values[value] = ir::Value::Inst { inst: inst_id, ty };
                         fb.cursor.attach_result(&mut fb.func, inst_id, value);
-                        inst_id
+                        (inst_id, ast_inst)
                     }
 
                     ast::StmtKind::Inst(ast_inst) => {
@@ -205,9 +209,11 @@ impl BuildCtx {
                             }
                         };
 
-                        fb.cursor.insert_inst_data_dyn(&mut fb.func, inst)
+                        let inst_id = fb.cursor.insert_inst_data_dyn(&mut fb.func, inst);
+                        (inst_id, ast_inst)
                     }
                 };
+                self.set_inst_attrs(&mut fb.func, inst_id, ast_inst);
                 fb.cursor.set_location(CursorLocation::At(inst_id));
             }
         }
@@ -237,6 +243,30 @@ impl BuildCtx {
         block
     }
 
+    fn set_inst_attrs(&mut self, func: &mut ir::Function, inst_id: ir::InstId, inst: &ast::Inst) {
+        if inst.is_pinned {
+            match func.dfg.inst_result(inst_id) {
+                Some(result) => func.dfg.pin_value(result),
+                None => self
+                    .errors
+                    .push(Error::InvalidInstAttr("pinned", inst.span)),
+            }
+        }
+
+        if inst.is_volatile {
+            let is = func.inst_set();
+            let data = func.dfg.inst(inst_id);
+            if <&Mload as InstDowncast>::downcast(is, data).is_some()
+                || <&Mstore as InstDowncast>::downcast(is, data).is_some()
+            {
+                func.dfg.set_volatile(inst_id);
+            } else {
+                self.errors
+                    .push(Error::InvalidInstAttr("volatile", inst.span));
+            }
+        }
+    }
+
     fn declare_value(&mut self, func: &mut ir::Function, name: &ast::ValueName, ty: ir::Type) {
         // Abusing Immediate here; we just need a dummy value with a given type.
         // The Value will be replaced when create the Inst that defines the value.
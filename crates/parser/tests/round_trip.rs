@@ -0,0 +1,229 @@
+use ir::{
+    builder::{test_util::*, ModuleBuilder},
+    func_cursor::InstInserter,
+    inst::{
+        arith::{Add, Mul, Sub},
+        cast::CopyVal,
+        cmp::Lt,
+        control_flow::{Br, BrTable, Call, Jump, Phi, Return},
+        data::{Mload, Mstore},
+    },
+    ir_writer::ModuleWriter,
+    isa::Isa,
+    Linkage, Module, Signature, Type,
+};
+use sonatina_parser::parse_module;
+
+/// Print the `module`, parse the text, print the parsed module again, and
+/// check that both texts are the same.
+fn assert_round_trip(module: &Module) {
+    let text = ModuleWriter::new(module).dump_string();
+    let parsed = match parse_module(&text) {
+        Ok(parsed) => parsed,
+        Err(errs) => {
+            let mut v = vec![];
+            for err in errs {
+                err.print(&mut v, "round_trip.sntn", &text, false).unwrap();
+            }
+            panic!("{text}\n{}", String::from_utf8(v).unwrap());
+        }
+    };
+
+    let reprinted = ModuleWriter::with_debug_provider(&parsed.module, &parsed.debug).dump_string();
+    assert_eq!(text, reprinted);
+}
+
+/// Build a loop computing `x * n` by repeated addition. The branch in the
+/// entry block jumps forward to blocks defined later in the text.
+fn build_loop(mb: &ModuleBuilder) {
+    let (evm, mut builder) = test_func_builder(mb, &[Type::I32, Type::I32], Type::I32);
+    let is = evm.inst_set();
+
+    let entry = builder.append_block();
+    let header = builder.append_block();
+    let body = builder.append_block();
+    let exit = builder.append_block();
+    let (x, n) = (builder.args()[0], builder.args()[1]);
+
+    builder.switch_to_block(entry);
+    let zero = builder.make_imm_value(0i32);
+    builder.insert_inst_no_result(Jump::new(is, header));
+
+    builder.switch_to_block(header);
+    let acc = builder.insert_inst(Phi::new(is, vec![(zero, entry)]), Type::I32);
+    let i = builder.insert_inst(Phi::new(is, vec![(zero, entry)]), Type::I32);
+    let cond = builder.insert_inst(Lt::new(is, i, n), Type::I1);
+    builder.insert_inst_no_result(Br::new(is, cond, body, exit));
+
+    builder.switch_to_block(body);
+    let next_acc = builder.insert_inst(Add::new(is, acc, x), Type::I32);
+    let one = builder.make_imm_value(1i32);
+    let next_i = builder.insert_inst(Add::new(is, i, one), Type::I32);
+    builder.append_phi_arg(acc, next_acc, body);
+    builder.append_phi_arg(i, next_i, body);
+    builder.insert_inst_no_result(Jump::new(is, header));
+
+    builder.switch_to_block(exit);
+    builder.insert_inst_no_result(Return::new(is, Some(acc)));
+
+    builder.seal_all();
+    builder.finish();
+}
+
+/// Build a `br_table` over `i8` immediates whose destinations are all
+/// defined after it.
+fn build_switch(mb: &ModuleBuilder) {
+    let sig = Signature::new("switch", Linkage::Private, &[Type::I8], Type::I64);
+    let func_ref = mb.declare_function(sig);
+    let mut builder = mb.func_builder::<InstInserter>(func_ref);
+    let is = test_isa().inst_set();
+
+    let entry = builder.append_block();
+    let on_one = builder.append_block();
+    let on_two = builder.append_block();
+    let default = builder.append_block();
+    let arg = builder.args()[0];
+
+    builder.switch_to_block(entry);
+    let one = builder.make_imm_value(1i8);
+    let two = builder.make_imm_value(2i8);
+    builder.insert_inst_no_result(BrTable::new(
+        is,
+        arg,
+        Some(default),
+        vec![(one, on_one), (two, on_two)],
+    ));
+
+    builder.switch_to_block(on_one);
+    let lhs = builder.make_imm_value(-1i64);
+    let rhs = builder.make_imm_value(i64::MAX);
+    let v = builder.insert_inst(Mul::new(is, lhs, rhs), Type::I64);
+    builder.insert_inst_no_result(Return::new(is, Some(v)));
+
+    builder.switch_to_block(on_two);
+    let is_less = builder.insert_inst(Lt::new(is, arg, two), Type::I1);
+    builder.insert_inst_no_result(Br::new(is, is_less, default, on_one));
+
+    builder.switch_to_block(default);
+    let lhs = builder.make_imm_value(7i64);
+    let rhs = builder.make_imm_value(3i64);
+    let v = builder.insert_inst(Sub::new(is, lhs, rhs), Type::I64);
+    builder.insert_inst_no_result(Return::new(is, Some(v)));
+
+    builder.seal_all();
+    builder.finish();
+}
+
+#[test]
+fn round_trip_loop() {
+    let mb = test_module_builder();
+    build_loop(&mb);
+    assert_round_trip(&mb.build());
+}
+
+#[test]
+fn round_trip_switch() {
+    let mb = test_module_builder();
+    build_switch(&mb);
+    assert_round_trip(&mb.build());
+}
+
+#[test]
+fn round_trip_multiple_functions() {
+    let mb = test_module_builder();
+    build_loop(&mb);
+    build_switch(&mb);
+    assert_round_trip(&mb.build());
+}
+
+#[test]
+fn round_trip_build_cfg() {
+    let mb = test_module_builder();
+    let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+    build_cfg! { builder;
+        a -> [b, c];
+        b -> [d];
+        c -> [e, d, b];
+        d -> [];
+        e -> [a];
+    }
+    builder.seal_all();
+    builder.finish();
+    assert_round_trip(&mb.build());
+}
+
+#[test]
+fn round_trip_pure() {
+    let mb = test_module_builder();
+    let mut callee_sig = Signature::new("callee", Linkage::External, &[Type::I32], Type::I32);
+    callee_sig.set_pure(true);
+    let callee = mb.declare_function(callee_sig);
+    let mut sig = Signature::new("pure", Linkage::Public, &[Type::I32], Type::I32);
+    sig.set_pure(true);
+    let func_ref = mb.declare_function(sig);
+
+    let mut builder = mb.func_builder::<InstInserter>(func_ref);
+    let is = test_isa().inst_set();
+    let b0 = builder.append_block();
+    let arg = builder.args()[0];
+    builder.switch_to_block(b0);
+    let v0 = builder.insert_inst(Call::new(is, callee, vec![arg].into()), Type::I32);
+    builder.insert_inst_no_result(Return::new(is, Some(v0)));
+    builder.seal_all();
+    builder.finish();
+
+    let module = mb.build();
+    let text = ModuleWriter::new(&module).dump_string();
+    assert!(text.contains("declare external pure %callee(i32) -> i32;"));
+    assert!(text.contains("func public pure %pure(v0.i32) -> i32"));
+    assert_round_trip(&module);
+}
+
+#[test]
+fn round_trip_volatile_and_pinned() {
+    let mb = test_module_builder();
+    let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+    let is = evm.inst_set();
+
+    let b0 = builder.append_block();
+    let addr = builder.args()[0];
+    builder.switch_to_block(b0);
+    let v0 = builder.insert_inst(Mload::new(is, addr, Type::I32), Type::I32);
+    let load = builder.last_inst().unwrap();
+    let v1 = builder.insert_inst(Add::new(is, v0, v0), Type::I32);
+    builder.insert_inst_no_result(Mstore::new(is, addr, v1, Type::I32));
+    let store = builder.last_inst().unwrap();
+    builder.insert_inst_no_result(Return::new(is, Some(v0)));
+    builder.func.dfg.set_volatile(load);
+    builder.func.dfg.set_volatile(store);
+    builder.func.dfg.pin_value(v1);
+    builder.seal_all();
+    builder.finish();
+
+    let module = mb.build();
+    let text = ModuleWriter::new(&module).dump_string();
+    assert!(text.contains("v1.i32 = volatile mload v0 i32;"));
+    assert!(text.contains("v2.i32 = pinned add v1 v1;"));
+    assert!(text.contains("volatile mstore v0 v2"));
+    assert_round_trip(&module);
+}
+
+#[test]
+fn round_trip_copy() {
+    let mb = test_module_builder();
+    let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+    let is = evm.inst_set();
+
+    let b0 = builder.append_block();
+    let arg = builder.args()[0];
+    builder.switch_to_block(b0);
+    let v0 = builder.insert_inst(CopyVal::new(is, arg, Type::I32), Type::I32);
+    builder.insert_inst_no_result(Return::new(is, Some(v0)));
+    builder.seal_all();
+    builder.finish();
+
+    let module = mb.build();
+    let text = ModuleWriter::new(&module).dump_string();
+    assert!(text.contains("v1.i32 = copy v0 i32;"));
+    assert_round_trip(&module);
+}
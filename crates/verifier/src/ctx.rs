@@ -1,22 +1,24 @@
 //! Verification context
 
-use sonatina_ir::{ControlFlowGraph, Function};
+use sonatina_ir::{module::FuncRef, ControlFlowGraph, Function};
 
 use crate::{error::ErrorData, ErrorStack};
 
 pub struct VerificationCtx<'a> {
     pub func: &'a Function,
+    pub func_ref: FuncRef,
     pub cfg: ControlFlowGraph,
     pub error_stack: ErrorStack,
 }
 
 impl<'a> VerificationCtx<'a> {
-    pub fn new(func: &'a Function) -> Self {
+    pub fn new(func: &'a Function, func_ref: FuncRef) -> Self {
         let mut cfg = ControlFlowGraph::new();
         cfg.compute(func);
 
         Self {
             func,
+            func_ref,
             cfg,
             error_stack: ErrorStack::default(),
         }
@@ -1,7 +1,7 @@
 use std::fmt;
 
 use sonatina_ir::{
-    ir_writer::{FuncWriteCtx, IrWrite, ValueWithTy},
+    ir_writer::{FuncWriteCtx, InstStatement, IrWrite, ValueWithTy},
     module::FuncRef,
     BlockId, Function, InstId, Type, ValueId,
 };
@@ -10,12 +10,19 @@ use sonatina_ir::{
 pub enum ErrorKind {
     // Function errors
     PhiInEntryBlock(InstId),
+    MultipleEntryBlocks(BlockId),
     // Block errors
     EmptyBlock(BlockId),
+    UnreachableBlock(BlockId),
+    EffectlessSelfLoop(BlockId),
+    BrokenInstList(BlockId),
     TerminatorBeforeEnd(InstId),
     NotEndedByTerminator(InstId),
     InstructionMapMismatched(InstId),
+    InstInMultipleBlocks(InstId),
     BranchBrokenLink(InstId),
+    BrTableUncoveredSelector(InstId),
+    ImpureInstInPureFunction(InstId),
     // Instruction errors
     ValueIsNullReference(ValueId),
     BlockIsNullReference(BlockId),
@@ -23,8 +30,28 @@ pub enum ErrorKind {
     BranchToEntryBlock(BlockId),
     // SSA form errors
     ValueLeak(ValueId),
+    UseNotDominatedByDef {
+        user: InstId,
+        value: ValueId,
+    },
+    PhiMissingIncoming {
+        phi: InstId,
+        pred: BlockId,
+    },
     // Type errors
     InstArgWrongType(Type),
+    PhiArgWrongType {
+        phi: InstId,
+        pred: BlockId,
+        idx: usize,
+        expected: Type,
+        got: Type,
+    },
+    SelectArmWrongType {
+        select: InstId,
+        expected: Type,
+        got: Type,
+    },
     InstResultWrongType(Type),
     CalleeArgWrongType(Type),
     CalleeResultWrongType(Type),
@@ -37,15 +64,25 @@ impl ErrorKind {
 
         match *self {
             PhiInEntryBlock(i) => IrSource::Inst(i),
-            EmptyBlock(b) => IrSource::Block(b),
+            EmptyBlock(b)
+            | UnreachableBlock(b)
+            | EffectlessSelfLoop(b)
+            | MultipleEntryBlocks(b)
+            | BrokenInstList(b) => IrSource::Block(b),
             TerminatorBeforeEnd(i)
             | NotEndedByTerminator(i)
             | InstructionMapMismatched(i)
-            | BranchBrokenLink(i) => IrSource::Inst(i),
+            | InstInMultipleBlocks(i)
+            | BranchBrokenLink(i)
+            | BrTableUncoveredSelector(i)
+            | ImpureInstInPureFunction(i) => IrSource::Inst(i),
             ValueIsNullReference(v) => IrSource::Value(v),
             BlockIsNullReference(b) | BranchToEntryBlock(b) => IrSource::Block(b),
             FunctionIsNullReference(f) => IrSource::Callee(f),
             ValueLeak(v) => IrSource::Value(v),
+            UseNotDominatedByDef { user, .. } => IrSource::Inst(user),
+            PhiMissingIncoming { phi, .. } | PhiArgWrongType { phi, .. } => IrSource::Inst(phi),
+            SelectArmWrongType { select, .. } => IrSource::Inst(select),
             InstArgWrongType(ty)
             | InstResultWrongType(ty)
             | CalleeArgWrongType(ty)
@@ -75,7 +112,20 @@ impl fmt::Display for DisplayErrorKind<'_> {
                 let inst = inst.dump_string(&self.ctx);
                 write!(f, "phi instruction in entry block, {inst}")
             }
+            MultipleEntryBlocks(block) => {
+                write!(f, "entry of a body disconnected from entry block, {block}")
+            }
             EmptyBlock(block) => write!(f, "empty block, {block}"),
+            UnreachableBlock(block) => write!(f, "block unreachable from entry, {block}"),
+            EffectlessSelfLoop(block) => {
+                write!(f, "block loops to itself with no side effect, {block}")
+            }
+            BrokenInstList(block) => {
+                write!(
+                    f,
+                    "instruction list inconsistent with layout links, {block}"
+                )
+            }
             TerminatorBeforeEnd(inst) => {
                 let inst = inst.dump_string(&self.ctx);
                 write!(f, "terminator instruction mid-block, {inst}")
@@ -88,10 +138,25 @@ impl fmt::Display for DisplayErrorKind<'_> {
                 let inst = inst.dump_string(&self.ctx);
                 write!(f, "instruction not mapped to block, {inst}")
             }
+            InstInMultipleBlocks(inst) => {
+                let inst = inst.dump_string(&self.ctx);
+                write!(f, "instruction linked into multiple blocks, {inst}")
+            }
             BranchBrokenLink(inst) => {
                 let inst = inst.dump_string(&self.ctx);
                 write!(f, "branch instruction not linked in cfg, {inst}")
             }
+            BrTableUncoveredSelector(inst) => {
+                let inst = inst.dump_string(&self.ctx);
+                write!(
+                    f,
+                    "br_table without default doesn't cover all selector values, {inst}"
+                )
+            }
+            ImpureInstInPureFunction(inst) => {
+                let inst = inst.dump_string(&self.ctx);
+                write!(f, "instruction with side effect in pure function, {inst}")
+            }
             ValueIsNullReference(value) => {
                 let value = ValueWithTy(value).dump_string(&self.ctx);
                 write!(f, "instruction references inexistent value, {value}")
@@ -117,10 +182,53 @@ impl fmt::Display for DisplayErrorKind<'_> {
                     "value not assigned by instruction nor from function args, {value}"
                 )
             }
+            UseNotDominatedByDef { user, value } => {
+                let user = InstStatement(user).dump_string(&self.ctx);
+                let value = ValueWithTy(value).dump_string(&self.ctx);
+                write!(f, "use not dominated by definition of {value}, {user}")
+            }
+            PhiMissingIncoming { phi, pred } => {
+                let block = self.ctx.func.layout.inst_block(phi);
+                let phi = phi.dump_string(&self.ctx);
+                write!(
+                    f,
+                    "phi without incoming value, edge {pred} -> {block}, {phi}"
+                )
+            }
             InstArgWrongType(ty) => {
                 let ty = ty.dump_string(self.ctx.module_ctx());
                 write!(f, "argument type inconsistent with instruction, {ty}")
             }
+            PhiArgWrongType {
+                phi,
+                pred,
+                idx,
+                expected,
+                got,
+            } => {
+                let block = self.ctx.func.layout.inst_block(phi);
+                let expected = expected.dump_string(self.ctx.module_ctx());
+                let got = got.dump_string(self.ctx.module_ctx());
+                write!(
+                    f,
+                    "phi argument type inconsistent with phi result, \
+                     edge {pred} -> {block}, index {idx}, expected {expected}, got {got}"
+                )
+            }
+            SelectArmWrongType {
+                select,
+                expected,
+                got,
+            } => {
+                let select = select.dump_string(&self.ctx);
+                let expected = expected.dump_string(self.ctx.module_ctx());
+                let got = got.dump_string(self.ctx.module_ctx());
+                write!(
+                    f,
+                    "select arm type inconsistent with select result, \
+                     expected {expected}, got {got}, {select}"
+                )
+            }
             InstResultWrongType(ty) => {
                 let ty = ty.dump_string(self.ctx.module_ctx());
                 write!(f, "argument type inconsistent with instruction, {ty}")
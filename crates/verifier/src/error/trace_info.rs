@@ -122,7 +122,7 @@ impl fmt::Display for DisplayTraceInfo<'_, '_> {
         }
 
         let sig = module_ctx.func_sig(self.ctx.func_ref, |sig| sig.dump_string(module_ctx));
-        write!(f, "\n{line}: {sig}")
+        write!(f, "\n{line}: func {sig}")
     }
 }
 
@@ -14,6 +14,11 @@ impl ErrorStack {
         self.non_fatal_errors.push(err)
     }
 
+    /// Returns `true` if no error is reported.
+    pub fn is_empty(&self) -> bool {
+        self.fatal_error.is_none() && self.non_fatal_errors.is_empty()
+    }
+
     pub fn into_errs_iter(
         self,
         func: &Function,
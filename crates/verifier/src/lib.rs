@@ -2,7 +2,10 @@ pub mod ctx;
 pub mod error;
 pub mod error_stack;
 pub mod pass;
+pub mod passes;
+pub mod verify;
 
 pub use ctx::VerificationCtx;
 pub use error_stack::ErrorStack;
 pub use pass::VerificationPass;
+pub use verify::{verify_function, verify_function_with, VerifierConfig};
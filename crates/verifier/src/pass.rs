@@ -3,5 +3,5 @@
 use crate::VerificationCtx;
 
 pub trait VerificationPass {
-    fn run(&mut self, ctx: VerificationCtx);
+    fn run(&mut self, ctx: &mut VerificationCtx);
 }
@@ -0,0 +1,56 @@
+use rustc_hash::FxHashSet;
+use sonatina_ir::{inst::control_flow::BrTable, BlockId, InstDowncast, Type};
+
+use crate::{
+    error::{ErrorData, ErrorKind, TraceInfoBuilder},
+    VerificationCtx, VerificationPass,
+};
+
+/// Checks that a `br_table` without a default destination covers all possible
+/// selector values.
+///
+/// Coverage can only be proven for tiny selector types, i.e., `i1` and `i8`,
+/// so a default-less table with a wider selector is always rejected.
+#[derive(Debug, Default)]
+pub struct BrTableCoverageCheck;
+
+impl VerificationPass for BrTableCoverageCheck {
+    fn run(&mut self, ctx: &mut VerificationCtx) {
+        let func = ctx.func;
+        let blocks: Vec<BlockId> = func.layout.iter_block().collect();
+
+        for block in blocks {
+            let Some(inst) = func.layout.last_inst_of(block) else {
+                continue;
+            };
+            let Some(br_table) =
+                <&BrTable as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(inst))
+            else {
+                continue;
+            };
+            if br_table.default().is_some() {
+                continue;
+            }
+
+            let selector_num: usize = match func.dfg.value_ty(*br_table.scrutinee()) {
+                Type::Int(bits) if bits <= 8 => 1 << bits,
+                _ => usize::MAX,
+            };
+            let covered: FxHashSet<_> = br_table
+                .table()
+                .iter()
+                .filter_map(|(value, _)| func.dfg.value_imm(*value))
+                .collect();
+            if covered.len() >= selector_num {
+                continue;
+            }
+
+            let trace_info = TraceInfoBuilder::new(ctx.func_ref)
+                .block(block)
+                .inst_id(inst)
+                .build();
+            let kind = ErrorKind::BrTableUncoveredSelector(inst);
+            ctx.report_nonfatal(&[ErrorData::new(kind, trace_info)]);
+        }
+    }
+}
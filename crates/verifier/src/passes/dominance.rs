@@ -0,0 +1,99 @@
+use cranelift_entity::SecondaryMap;
+use sonatina_ir::{domtree::DomTree, BlockId, Function, InstId, Value};
+
+use crate::{
+    error::{ErrorData, ErrorKind, TraceInfoBuilder},
+    VerificationCtx, VerificationPass,
+};
+
+/// Checks that every inst argument is a function argument, a constant, or the
+/// result of an inst in the layout that dominates the use. An argument of a
+/// phi is used at the end of the corresponding predecessor.
+///
+/// Dominance is meaningless for blocks unreachable from the entry block, so
+/// uses in them are not checked.
+#[derive(Debug, Default)]
+pub struct DominanceCheck;
+
+impl VerificationPass for DominanceCheck {
+    fn run(&mut self, ctx: &mut VerificationCtx) {
+        let func = ctx.func;
+        let mut domtree = DomTree::new();
+        domtree.compute(&ctx.cfg);
+
+        // Position of each inst in its block.
+        let mut positions: SecondaryMap<InstId, usize> = SecondaryMap::default();
+        for block in func.layout.iter_block() {
+            for (pos, inst) in func.layout.iter_inst(block).enumerate() {
+                positions[inst] = pos;
+            }
+        }
+
+        let mut errs = Vec::new();
+        for &block in domtree.rpo() {
+            for inst in func.layout.iter_inst(block) {
+                let mut uses = Vec::new();
+                if let Some(phi) = func.dfg.cast_phi(inst) {
+                    uses.extend(
+                        phi.args()
+                            .iter()
+                            .filter(|&&(_, pred)| is_reachable(&domtree, pred))
+                            .map(|&(value, pred)| (value, pred, None)),
+                    );
+                } else {
+                    func.dfg
+                        .inst(inst)
+                        .for_each_value(&mut |value| uses.push((value, block, Some(inst))));
+                }
+
+                for (value, use_block, use_inst) in uses {
+                    let Value::Inst { inst: def, .. } = *func.dfg.value(value) else {
+                        continue;
+                    };
+                    let kind = if !func.layout.is_inst_inserted(def) {
+                        ErrorKind::ValueLeak(value)
+                    } else if !is_available(func, &domtree, &positions, def, use_block, use_inst) {
+                        ErrorKind::UseNotDominatedByDef { user: inst, value }
+                    } else {
+                        continue;
+                    };
+
+                    let trace_info = TraceInfoBuilder::new(ctx.func_ref)
+                        .block(block)
+                        .inst_id(inst)
+                        .value(value)
+                        .build();
+                    errs.push(ErrorData::new(kind, trace_info));
+                }
+            }
+        }
+        ctx.report_nonfatal(&errs);
+    }
+}
+
+/// Returns `true` if the `block` is reachable from the entry block, which has
+/// no immediate dominator but is trivially reachable.
+fn is_reachable(domtree: &DomTree, block: BlockId) -> bool {
+    domtree.entry() == Some(block) || domtree.is_reachable(block)
+}
+
+/// Returns `true` if the result of `def` is available right before `use_inst`
+/// in `use_block`, or at the end of `use_block` if `use_inst` is `None`.
+fn is_available(
+    func: &Function,
+    domtree: &DomTree,
+    positions: &SecondaryMap<InstId, usize>,
+    def: InstId,
+    use_block: BlockId,
+    use_inst: Option<InstId>,
+) -> bool {
+    let def_block = func.layout.inst_block(def);
+    if def_block != use_block {
+        return domtree.dominates(def_block, use_block);
+    }
+
+    match use_inst {
+        Some(use_inst) => positions[def] < positions[use_inst],
+        None => true,
+    }
+}
@@ -0,0 +1,54 @@
+use rustc_hash::FxHashSet;
+use sonatina_ir::{BlockId, ControlFlowGraph};
+
+use crate::{
+    error::{ErrorData, ErrorKind, TraceInfoBuilder},
+    VerificationCtx, VerificationPass,
+};
+
+/// Checks that the entry block is the only entry of the CFG.
+///
+/// A block without predecessors other than the entry block is reported if
+/// the blocks reachable from it never join the blocks reachable from the
+/// entry block, i.e., it is the entry of a second, disconnected body. A block
+/// without predecessors that flows into the entry's body is dead code, e.g.,
+/// left behind by folding a branch, and is only reported by
+/// [`ReachabilityCheck`](super::ReachabilityCheck) on request.
+#[derive(Debug, Default)]
+pub struct EntryCheck;
+
+impl VerificationPass for EntryCheck {
+    fn run(&mut self, ctx: &mut VerificationCtx) {
+        let entry = ctx.func.entry_block();
+
+        let mut body = FxHashSet::default();
+        if let Some(entry) = entry {
+            mark_reachable(&ctx.cfg, entry, &mut body);
+        }
+
+        let errs: Vec<_> = ctx
+            .cfg
+            .entry_blocks(ctx.func)
+            .filter(|&block| Some(block) != entry)
+            .filter(|&block| {
+                let mut reachable = FxHashSet::default();
+                mark_reachable(&ctx.cfg, block, &mut reachable);
+                reachable.is_disjoint(&body)
+            })
+            .map(|block| {
+                let trace_info = TraceInfoBuilder::new(ctx.func_ref).block(block).build();
+                ErrorData::new(ErrorKind::MultipleEntryBlocks(block), trace_info)
+            })
+            .collect();
+        ctx.report_nonfatal(&errs);
+    }
+}
+
+fn mark_reachable(cfg: &ControlFlowGraph, from: BlockId, visited: &mut FxHashSet<BlockId>) {
+    let mut stack = vec![from];
+    while let Some(block) = stack.pop() {
+        if visited.insert(block) {
+            stack.extend(cfg.succs_of(block).filter(|succ| !visited.contains(succ)));
+        }
+    }
+}
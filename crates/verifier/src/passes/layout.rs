@@ -0,0 +1,61 @@
+use rustc_hash::FxHashSet;
+use sonatina_ir::{BlockId, InstId};
+
+use crate::{
+    error::{ErrorData, ErrorKind, TraceInfoBuilder},
+    VerificationCtx, VerificationPass,
+};
+
+/// Checks that every inst belongs to exactly one block, and that the inst list
+/// of each block is consistent with the layout links.
+///
+/// Other passes iterate the layout freely, so a caller should stop verifying
+/// once this pass reports an error.
+#[derive(Debug, Default)]
+pub struct LayoutCheck;
+
+impl VerificationPass for LayoutCheck {
+    fn run(&mut self, ctx: &mut VerificationCtx) {
+        let layout = &ctx.func.layout;
+        let blocks: Vec<BlockId> = layout.iter_block().collect();
+        let mut seen = FxHashSet::default();
+
+        for block in blocks {
+            let mut prev = None;
+            let mut next = layout.first_inst_of(block);
+
+            while let Some(inst) = next {
+                // Stop walking the block on the first broken link, which also
+                // prevents looping forever on a cyclic inst list.
+                if !seen.insert(inst) {
+                    report(ctx, ErrorKind::InstInMultipleBlocks(inst), block, inst);
+                    break;
+                }
+                if !layout.is_inst_inserted(inst)
+                    || layout.inst_block(inst) != block
+                    || layout.prev_inst_of(inst) != prev
+                {
+                    report(ctx, ErrorKind::InstructionMapMismatched(inst), block, inst);
+                    break;
+                }
+
+                prev = Some(inst);
+                next = layout.next_inst_of(inst);
+            }
+
+            if next.is_none() && prev != layout.last_inst_of(block) {
+                let trace_info = TraceInfoBuilder::new(ctx.func_ref).block(block).build();
+                let kind = ErrorKind::BrokenInstList(block);
+                ctx.report_nonfatal(&[ErrorData::new(kind, trace_info)]);
+            }
+        }
+    }
+}
+
+fn report(ctx: &mut VerificationCtx, kind: ErrorKind, block: BlockId, inst: InstId) {
+    let trace_info = TraceInfoBuilder::new(ctx.func_ref)
+        .block(block)
+        .inst_id(inst)
+        .build();
+    ctx.report_nonfatal(&[ErrorData::new(kind, trace_info)]);
+}
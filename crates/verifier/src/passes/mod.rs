@@ -0,0 +1,25 @@
+//! Verification passes
+
+mod br_table;
+mod dominance;
+mod entry;
+mod layout;
+mod phi_incoming;
+mod phi_type;
+mod purity;
+mod reachability;
+mod select_type;
+mod self_loop;
+mod terminator;
+
+pub use br_table::BrTableCoverageCheck;
+pub use dominance::DominanceCheck;
+pub use entry::EntryCheck;
+pub use layout::LayoutCheck;
+pub use phi_incoming::PhiIncomingCheck;
+pub use phi_type::PhiTypeCheck;
+pub use purity::PurityCheck;
+pub use reachability::ReachabilityCheck;
+pub use select_type::SelectTypeCheck;
+pub use self_loop::EffectlessSelfLoopCheck;
+pub use terminator::TerminatorCheck;
@@ -0,0 +1,41 @@
+use sonatina_ir::BlockId;
+
+use crate::{
+    error::{ErrorData, ErrorKind, TraceInfoBuilder},
+    VerificationCtx, VerificationPass,
+};
+
+/// Checks that every phi has an incoming value for each predecessor of its
+/// block.
+#[derive(Debug, Default)]
+pub struct PhiIncomingCheck;
+
+impl VerificationPass for PhiIncomingCheck {
+    fn run(&mut self, ctx: &mut VerificationCtx) {
+        let func = ctx.func;
+        let blocks: Vec<BlockId> = func.layout.iter_block().collect();
+
+        let mut errs = Vec::new();
+        for block in blocks {
+            for inst in func.layout.iter_inst(block) {
+                let Some(phi) = func.dfg.cast_phi(inst) else {
+                    continue;
+                };
+
+                for &pred in ctx.cfg.preds_of(block) {
+                    if phi.args().iter().any(|&(_, block)| block == pred) {
+                        continue;
+                    }
+
+                    let trace_info = TraceInfoBuilder::new(ctx.func_ref)
+                        .block(block)
+                        .inst_id(inst)
+                        .build();
+                    let kind = ErrorKind::PhiMissingIncoming { phi: inst, pred };
+                    errs.push(ErrorData::new(kind, trace_info));
+                }
+            }
+        }
+        ctx.report_nonfatal(&errs);
+    }
+}
@@ -0,0 +1,52 @@
+use sonatina_ir::BlockId;
+
+use crate::{
+    error::{ErrorData, ErrorKind, TraceInfoBuilder},
+    VerificationCtx, VerificationPass,
+};
+
+/// Checks that every incoming value of a phi has the same type as the phi
+/// result.
+#[derive(Debug, Default)]
+pub struct PhiTypeCheck;
+
+impl VerificationPass for PhiTypeCheck {
+    fn run(&mut self, ctx: &mut VerificationCtx) {
+        let func = ctx.func;
+        let blocks: Vec<BlockId> = func.layout.iter_block().collect();
+
+        for block in blocks {
+            for inst in func.layout.iter_inst(block) {
+                let Some(phi) = func.dfg.cast_phi(inst) else {
+                    continue;
+                };
+                let Some(result) = func.dfg.inst_result(inst) else {
+                    continue;
+                };
+                let expected = func.dfg.value_ty(result);
+
+                for (idx, &(arg, pred)) in phi.args().iter().enumerate() {
+                    let got = func.dfg.value_ty(arg);
+                    if got == expected {
+                        continue;
+                    }
+
+                    let kind = ErrorKind::PhiArgWrongType {
+                        phi: inst,
+                        pred,
+                        idx,
+                        expected,
+                        got,
+                    };
+                    let trace_info = TraceInfoBuilder::new(ctx.func_ref)
+                        .block(block)
+                        .inst_id(inst)
+                        .value(arg)
+                        .ty(got)
+                        .build();
+                    ctx.report_nonfatal(&[ErrorData::new(kind, trace_info)]);
+                }
+            }
+        }
+    }
+}
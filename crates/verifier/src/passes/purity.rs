@@ -0,0 +1,47 @@
+use sonatina_ir::{inst::control_flow::Return, BlockId, InstDowncast};
+
+use crate::{
+    error::{ErrorData, ErrorKind, TraceInfoBuilder},
+    VerificationCtx, VerificationPass,
+};
+
+/// Checks that a function whose signature is marked pure contains no inst
+/// with a side effect, i.e., no inst reading or writing memory or calling an
+/// impure function. A `return` is allowed even though it's modeled as a
+/// write, since it only hands the result back to the caller.
+#[derive(Debug, Default)]
+pub struct PurityCheck;
+
+impl VerificationPass for PurityCheck {
+    fn run(&mut self, ctx: &mut VerificationCtx) {
+        let func = ctx.func;
+        let is_pure = func
+            .dfg
+            .ctx
+            .declared_funcs
+            .get(&ctx.func_ref)
+            .is_some_and(|sig| sig.is_pure());
+        if !is_pure {
+            return;
+        }
+
+        let blocks: Vec<BlockId> = func.layout.iter_block().collect();
+        for block in blocks {
+            for inst in func.layout.iter_inst(block) {
+                let is_return =
+                    <&Return as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(inst))
+                        .is_some();
+                if is_return || !func.dfg.side_effect(inst).has_effect() {
+                    continue;
+                }
+
+                let trace_info = TraceInfoBuilder::new(ctx.func_ref)
+                    .block(block)
+                    .inst_id(inst)
+                    .build();
+                let kind = ErrorKind::ImpureInstInPureFunction(inst);
+                ctx.report_nonfatal(&[ErrorData::new(kind, trace_info)]);
+            }
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use rustc_hash::FxHashSet;
+
+use crate::{
+    error::{ErrorData, ErrorKind, TraceInfoBuilder},
+    VerificationCtx, VerificationPass,
+};
+
+/// Checks that all blocks in the layout are reachable from the entry block.
+///
+/// Passes may leave unreachable blocks behind for a later cleanup, so this
+/// check only runs if [`VerifierConfig::require_all_reachable`] is set.
+///
+/// [`VerifierConfig::require_all_reachable`]: crate::verify::VerifierConfig::require_all_reachable
+#[derive(Debug, Default)]
+pub struct ReachabilityCheck;
+
+impl VerificationPass for ReachabilityCheck {
+    fn run(&mut self, ctx: &mut VerificationCtx) {
+        let reachable: FxHashSet<_> = ctx.cfg.order().post_order().iter().copied().collect();
+
+        let errs: Vec<_> = ctx
+            .func
+            .layout
+            .iter_block()
+            .filter(|block| !reachable.contains(block))
+            .map(|block| {
+                let trace_info = TraceInfoBuilder::new(ctx.func_ref).block(block).build();
+                ErrorData::new(ErrorKind::UnreachableBlock(block), trace_info)
+            })
+            .collect();
+        ctx.report_nonfatal(&errs);
+    }
+}
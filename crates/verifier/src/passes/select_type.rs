@@ -0,0 +1,53 @@
+use sonatina_ir::{inst::logic::Select, BlockId, InstDowncast};
+
+use crate::{
+    error::{ErrorData, ErrorKind, TraceInfoBuilder},
+    VerificationCtx, VerificationPass,
+};
+
+/// Checks that both arms of a `select` have the same type as its result.
+#[derive(Debug, Default)]
+pub struct SelectTypeCheck;
+
+impl VerificationPass for SelectTypeCheck {
+    fn run(&mut self, ctx: &mut VerificationCtx) {
+        let func = ctx.func;
+        let blocks: Vec<BlockId> = func.layout.iter_block().collect();
+
+        let mut errs = Vec::new();
+        for block in blocks {
+            for inst in func.layout.iter_inst(block) {
+                let Some(select) =
+                    <&Select as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(inst))
+                else {
+                    continue;
+                };
+                let Some(result) = func.dfg.inst_result(inst) else {
+                    continue;
+                };
+                let expected = func.dfg.value_ty(result);
+
+                for arm in [*select.nz_val(), *select.z_val()] {
+                    let got = func.dfg.value_ty(arm);
+                    if got == expected {
+                        continue;
+                    }
+
+                    let kind = ErrorKind::SelectArmWrongType {
+                        select: inst,
+                        expected,
+                        got,
+                    };
+                    let trace_info = TraceInfoBuilder::new(ctx.func_ref)
+                        .block(block)
+                        .inst_id(inst)
+                        .value(arm)
+                        .ty(got)
+                        .build();
+                    errs.push(ErrorData::new(kind, trace_info));
+                }
+            }
+        }
+        ctx.report_nonfatal(&errs);
+    }
+}
@@ -0,0 +1,48 @@
+use sonatina_ir::{inst::control_flow::BranchKind, BlockId};
+
+use crate::{
+    error::{ErrorData, ErrorKind, TraceInfoBuilder},
+    VerificationCtx, VerificationPass,
+};
+
+/// Checks that no block jumps to itself unconditionally without any inst
+/// having a side effect, i.e., an infinite loop that does nothing observable.
+///
+/// Such a loop may legitimately remain after folding a branch, so this check
+/// only runs if [`VerifierConfig::reject_effectless_self_loops`] is set.
+///
+/// [`VerifierConfig::reject_effectless_self_loops`]: crate::verify::VerifierConfig::reject_effectless_self_loops
+#[derive(Debug, Default)]
+pub struct EffectlessSelfLoopCheck;
+
+impl VerificationPass for EffectlessSelfLoopCheck {
+    fn run(&mut self, ctx: &mut VerificationCtx) {
+        let func = ctx.func;
+
+        let blocks: Vec<BlockId> = func.layout.iter_block().collect();
+        for block in blocks {
+            let Some(term) = func.layout.last_inst_of(block) else {
+                continue;
+            };
+            let is_self_jump = func
+                .dfg
+                .branch_info(term)
+                .is_some_and(|branch| matches!(branch.branch_kind(), BranchKind::Jump(jump) if *jump.dest() == block));
+            if !is_self_jump
+                || func
+                    .layout
+                    .iter_inst(block)
+                    .any(|inst| func.dfg.side_effect(inst).has_effect())
+            {
+                continue;
+            }
+
+            let trace_info = TraceInfoBuilder::new(ctx.func_ref)
+                .block(block)
+                .inst_id(term)
+                .build();
+            let kind = ErrorKind::EffectlessSelfLoop(block);
+            ctx.report_nonfatal(&[ErrorData::new(kind, trace_info)]);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use sonatina_ir::{BlockId, InstId};
+
+use crate::{
+    error::{ErrorData, ErrorKind, TraceInfoBuilder},
+    VerificationCtx, VerificationPass,
+};
+
+/// Checks that every block ends with exactly one terminator, and that every
+/// branch destination is a block in the layout.
+///
+/// The control flow graph is meaningless otherwise, so a caller should stop
+/// verifying once this pass reports an error.
+#[derive(Debug, Default)]
+pub struct TerminatorCheck;
+
+impl VerificationPass for TerminatorCheck {
+    fn run(&mut self, ctx: &mut VerificationCtx) {
+        let func = ctx.func;
+        let blocks: Vec<BlockId> = func.layout.iter_block().collect();
+
+        for block in blocks {
+            let Some(last) = func.layout.last_inst_of(block) else {
+                let trace_info = TraceInfoBuilder::new(ctx.func_ref).block(block).build();
+                ctx.report_nonfatal(&[ErrorData::new(ErrorKind::EmptyBlock(block), trace_info)]);
+                continue;
+            };
+
+            for inst in func.layout.iter_inst(block) {
+                if inst != last && func.dfg.is_terminator(inst) {
+                    report(ctx, ErrorKind::TerminatorBeforeEnd(inst), block, inst);
+                }
+            }
+            if !func.dfg.is_terminator(last) {
+                report(ctx, ErrorKind::NotEndedByTerminator(last), block, last);
+                continue;
+            }
+
+            let Some(branch) = func.dfg.branch_info(last) else {
+                continue;
+            };
+            for dest in branch.dests() {
+                if !func.layout.is_block_inserted(dest) {
+                    report(ctx, ErrorKind::BlockIsNullReference(dest), block, last);
+                }
+            }
+        }
+    }
+}
+
+fn report(ctx: &mut VerificationCtx, kind: ErrorKind, block: BlockId, inst: InstId) {
+    let trace_info = TraceInfoBuilder::new(ctx.func_ref)
+        .block(block)
+        .inst_id(inst)
+        .build();
+    ctx.report_nonfatal(&[ErrorData::new(kind, trace_info)]);
+}
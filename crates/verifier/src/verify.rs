@@ -0,0 +1,633 @@
+//! Function verification
+
+use sonatina_ir::{module::FuncRef, Function};
+
+use crate::{
+    passes::{
+        BrTableCoverageCheck, DominanceCheck, EffectlessSelfLoopCheck, EntryCheck, LayoutCheck,
+        PhiIncomingCheck, PhiTypeCheck, PurityCheck, ReachabilityCheck, SelectTypeCheck,
+        TerminatorCheck,
+    },
+    ErrorStack, VerificationCtx, VerificationPass,
+};
+
+/// Options enabling checks that are too strict for some valid functions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VerifierConfig {
+    /// Report blocks unreachable from the entry block, e.g., to catch a pass
+    /// that forgot to remove them. Intermediate states between passes may
+    /// legitimately contain unreachable blocks.
+    pub require_all_reachable: bool,
+
+    /// Report blocks that jump to themselves without any side effect, which
+    /// never terminate and do nothing observable. Such a loop may be left
+    /// after folding a branch whose exit is never taken.
+    pub reject_effectless_self_loops: bool,
+}
+
+/// Run all verification passes on the function.
+/// Returns reported errors if the function is malformed.
+///
+/// Each error in the returned [`ErrorStack`] carries the offending inst,
+/// block or value with a message. The `func_ref` is needed to render the
+/// messages with the function signature.
+pub fn verify_function(func: &Function, func_ref: FuncRef) -> Result<(), ErrorStack> {
+    verify_function_with(func, func_ref, &VerifierConfig::default())
+}
+
+/// Run all verification passes on the function, including the optional checks
+/// enabled in the `config`.
+pub fn verify_function_with(
+    func: &Function,
+    func_ref: FuncRef,
+    config: &VerifierConfig,
+) -> Result<(), ErrorStack> {
+    let mut ctx = VerificationCtx::new(func, func_ref);
+
+    LayoutCheck.run(&mut ctx);
+    // The other passes assume the layout is well-formed.
+    if !ctx.error_stack.is_empty() {
+        return Err(ctx.error_stack);
+    }
+
+    TerminatorCheck.run(&mut ctx);
+    // The other passes assume the control flow graph is well-formed.
+    if !ctx.error_stack.is_empty() {
+        return Err(ctx.error_stack);
+    }
+
+    EntryCheck.run(&mut ctx);
+    PhiIncomingCheck.run(&mut ctx);
+    PhiTypeCheck.run(&mut ctx);
+    SelectTypeCheck.run(&mut ctx);
+    DominanceCheck.run(&mut ctx);
+    BrTableCoverageCheck.run(&mut ctx);
+    PurityCheck.run(&mut ctx);
+    if config.require_all_reachable {
+        ReachabilityCheck.run(&mut ctx);
+    }
+    if config.reject_effectless_self_loops {
+        EffectlessSelfLoopCheck.run(&mut ctx);
+    }
+
+    if ctx.error_stack.is_empty() {
+        Ok(())
+    } else {
+        Err(ctx.error_stack)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        func_cursor::InstInserter,
+        inst::{
+            arith::{Add, Mul},
+            control_flow::{Br, BrTable, Call, Jump, Phi, Return},
+            data::{Mload, Mstore},
+            logic::Select,
+        },
+        isa::Isa,
+        Linkage, Signature, Type,
+    };
+
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn phi_arg_type_mismatch() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let arg = builder.make_imm_value(1i8);
+        let phi = builder.insert_inst(Phi::new(is, vec![(arg, b0)]), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(phi)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let errs = verify_function(func, func_ref).unwrap_err();
+            assert!(errs.fatal_error.is_none());
+            assert_eq!(errs.non_fatal_errors.len(), 1);
+
+            let phi_inst = func.layout.first_inst_of(b1).unwrap();
+            let err = errs.non_fatal_errors.values().next().unwrap();
+            assert!(matches!(
+                err.kind,
+                ErrorKind::PhiArgWrongType {
+                    phi,
+                    pred,
+                    idx: 0,
+                    expected: Type::I32,
+                    got: Type::I8,
+                } if phi == phi_inst && pred == b0
+            ));
+
+            let msg = errs.into_errs_iter(func, func_ref).into_iter().next().unwrap().to_string();
+            assert_eq!(
+                msg,
+                "phi argument type inconsistent with phi result, edge block0 -> block1, index 0, expected i32, got i8
+trace_info:
+0: i8
+1: 1.i8
+2: v1.i32 = phi (1.i8 block0);
+3: block1
+4: func public %test_func() -> i32"
+            );
+        });
+    }
+
+    #[test]
+    fn select_arm_type_mismatch() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let (cond, arg) = (builder.args()[0], builder.args()[1]);
+
+        builder.switch_to_block(b0);
+        let imm = builder.make_imm_value(1i8);
+        let v0 = builder.insert_inst(Select::new(is, cond, arg, imm), Type::I32);
+        let select = builder.last_inst().unwrap();
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let errs = verify_function(func, func_ref).unwrap_err();
+            assert_eq!(errs.non_fatal_errors.len(), 1);
+            let err = errs.non_fatal_errors.values().next().unwrap();
+            assert!(matches!(
+                err.kind,
+                ErrorKind::SelectArmWrongType {
+                    select: inst,
+                    expected: Type::I32,
+                    got: Type::I8,
+                } if inst == select
+            ));
+        });
+    }
+
+    #[test]
+    fn use_before_def() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v1 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        let v2 = builder.insert_inst(Mul::new(is, v1, arg), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v2)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            assert!(verify_function(func, func_ref).is_ok());
+
+            // Move `add` after its user.
+            let add = func.dfg.value_inst(v1).unwrap();
+            let mul = func.dfg.value_inst(v2).unwrap();
+            func.layout.remove_inst(add);
+            func.layout.insert_inst_after(add, mul);
+
+            let errs = verify_function(func, func_ref).unwrap_err();
+            assert_eq!(errs.non_fatal_errors.len(), 1);
+            let err = errs.non_fatal_errors.values().next().unwrap();
+            assert!(matches!(
+                err.kind,
+                ErrorKind::UseNotDominatedByDef { user, value } if user == mul && value == v1
+            ));
+
+            let msg = errs
+                .into_errs_iter(func, func_ref)
+                .into_iter()
+                .next()
+                .unwrap()
+                .to_string();
+            assert_eq!(
+                msg.lines().next().unwrap(),
+                "use not dominated by definition of v1.i32, v2.i32 = mul v1 v0;"
+            );
+        });
+    }
+
+    #[test]
+    fn use_in_non_dominated_block() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let (cond, arg) = (builder.args()[0], builder.args()[1]);
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+        builder.switch_to_block(b1);
+        let v0 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result(Jump::new(is, b3));
+
+        // `b1` doesn't dominate `b3`.
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+        let ret = builder.last_inst().unwrap();
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let errs = verify_function(func, func_ref).unwrap_err();
+            assert_eq!(errs.non_fatal_errors.len(), 1);
+            let err = errs.non_fatal_errors.values().next().unwrap();
+            assert!(matches!(
+                err.kind,
+                ErrorKind::UseNotDominatedByDef { user, value } if user == ret && value == v0
+            ));
+        });
+    }
+
+    #[test]
+    fn phi_arg_on_entry_edge_not_dominated() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let (cond, arg) = (builder.args()[0], builder.args()[1]);
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+        builder.switch_to_block(b2);
+        let v0 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        // `v0` is passed along the edge from the entry block, which `b2`
+        // doesn't dominate.
+        builder.switch_to_block(b1);
+        let v1 = builder.insert_inst(Phi::new(is, vec![(v0, b0), (arg, b2)]), Type::I32);
+        let phi = builder.last_inst().unwrap();
+        builder.insert_inst_no_result(Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let errs = verify_function(func, func_ref).unwrap_err();
+            assert_eq!(errs.non_fatal_errors.len(), 1);
+            let err = errs.non_fatal_errors.values().next().unwrap();
+            assert!(matches!(
+                err.kind,
+                ErrorKind::UseNotDominatedByDef { user, value } if user == phi && value == v0
+            ));
+        });
+    }
+
+    #[test]
+    fn phi_missing_incoming() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let cond = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result(Br::new(is, cond, b1, b2));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Jump::new(is, b2));
+
+        // `b0` is also a predecessor of `b2`.
+        builder.switch_to_block(b2);
+        let one = builder.make_imm_value(1i32);
+        let phi = builder.insert_inst(Phi::new(is, vec![(one, b1)]), Type::I32);
+        let phi_inst = builder.last_inst().unwrap();
+        builder.insert_inst_no_result(Return::new(is, Some(phi)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let errs = verify_function(func, func_ref).unwrap_err();
+            assert_eq!(errs.non_fatal_errors.len(), 1);
+            let err = errs.non_fatal_errors.values().next().unwrap();
+            assert!(matches!(
+                err.kind,
+                ErrorKind::PhiMissingIncoming { phi, pred } if phi == phi_inst && pred == b0
+            ));
+        });
+    }
+
+    #[test]
+    fn missing_terminator() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+        let ret = builder.last_inst().unwrap();
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let add = func.dfg.value_inst(v0).unwrap();
+            func.layout.remove_inst(ret);
+            let b1 = func.dfg.make_block();
+            func.layout.append_block(b1);
+
+            let errs = verify_function(func, func_ref).unwrap_err();
+            let kinds: Vec<_> = errs.non_fatal_errors.values().map(|err| err.kind).collect();
+            assert_eq!(kinds.len(), 2);
+            assert!(matches!(kinds[0], ErrorKind::NotEndedByTerminator(inst) if inst == add));
+            assert!(matches!(kinds[1], ErrorKind::EmptyBlock(block) if block == b1));
+
+            // A terminator in the middle of a block is also reported.
+            func.layout.insert_inst_before(ret, add);
+            let errs = verify_function(func, func_ref).unwrap_err();
+            assert!(errs.non_fatal_errors.values().any(
+                |err| matches!(err.kind, ErrorKind::TerminatorBeforeEnd(inst) if inst == ret)
+            ));
+        });
+    }
+
+    #[test]
+    fn effectless_self_loop() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        // `b` may exit, but `c` never does.
+        build_cfg! { builder;
+            a -> [b];
+            b -> [b, c];
+            c -> [c];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        let config = VerifierConfig {
+            reject_effectless_self_loops: true,
+            ..Default::default()
+        };
+
+        module.func_store.view(func_ref, |func| {
+            assert!(verify_function(func, func_ref).is_ok());
+
+            let errs = verify_function_with(func, func_ref, &config).unwrap_err();
+            let self_loops: Vec<_> = errs
+                .non_fatal_errors
+                .values()
+                .filter_map(|err| match err.kind {
+                    ErrorKind::EffectlessSelfLoop(block) => Some(block),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(self_loops, [c]);
+        });
+    }
+
+    #[test]
+    fn headless_component() {
+        let mb = test_module_builder();
+        let (_, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        build_cfg! { builder;
+            a -> [b];
+            b -> [];
+            c -> [d];
+            d -> [];
+            // Dead code flowing into the entry's body isn't a second entry.
+            e -> [b];
+        }
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let errs = verify_function(func, func_ref).unwrap_err();
+            assert_eq!(errs.non_fatal_errors.len(), 1);
+
+            let err = errs.non_fatal_errors.values().next().unwrap();
+            assert!(matches!(err.kind, ErrorKind::MultipleEntryBlocks(block) if block == c));
+        });
+    }
+
+    #[test]
+    fn br_table_uncovered_selector() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I8], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        // Every value but `i8::MAX` is covered.
+        let mut table: Vec<_> = (i8::MIN..i8::MAX)
+            .map(|i| (builder.make_imm_value(i), if i % 2 == 0 { b1 } else { b2 }))
+            .collect();
+        builder.insert_inst_no_result(BrTable::new(is, arg, None, table.clone()));
+        let br_table = builder.last_inst().unwrap();
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Return::new(is, None));
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result(Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            let errs = verify_function(func, func_ref).unwrap_err();
+            assert_eq!(errs.non_fatal_errors.len(), 1);
+            let err = errs.non_fatal_errors.values().next().unwrap();
+            assert!(matches!(
+                err.kind,
+                ErrorKind::BrTableUncoveredSelector(inst) if inst == br_table
+            ));
+
+            // Covering the last value makes the table exhaustive.
+            table.push((func.dfg.make_imm_value(i8::MAX), b2));
+            let exhaustive = BrTable::new(is, arg, None, table);
+            func.dfg.replace_inst(br_table, Box::new(exhaustive));
+            assert!(verify_function(func, func_ref).is_ok());
+        });
+    }
+
+    #[test]
+    fn moved_inst_keeps_layout_consistent() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            // Move `add` to the top of `block1`.
+            let add = func.dfg.value_inst(v0).unwrap();
+            func.layout.remove_inst(add);
+            func.layout.prepend_inst(add, b1);
+
+            let mut ctx = VerificationCtx::new(func, func_ref);
+            LayoutCheck.run(&mut ctx);
+            assert!(ctx.error_stack.non_fatal_errors.is_empty());
+            assert_eq!(func.layout.inst_block(add), b1);
+        });
+    }
+
+    #[test]
+    fn inst_in_multiple_blocks() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Add::new(is, arg, arg), Type::I32);
+        builder.insert_inst_no_result(Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result(Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.modify(func_ref, |func| {
+            assert!(verify_function(func, func_ref).is_ok());
+
+            // Link `add` into `block1` without unlinking it from `block0`.
+            let add = func.dfg.value_inst(v0).unwrap();
+            func.layout.append_inst_unchecked(add, b1);
+
+            let errs = verify_function(func, func_ref).unwrap_err();
+            assert!(errs.non_fatal_errors.values().any(
+                |err| matches!(err.kind, ErrorKind::InstInMultipleBlocks(inst) if inst == add)
+            ));
+        });
+    }
+
+    #[test]
+    fn random_functions() {
+        for seed in 0..1000 {
+            let func = gen_random_function(seed, 1 + seed as usize % 16);
+            let func_ref = FuncRef::from_u32(0);
+            if let Err(errs) = verify_function(&func, func_ref) {
+                panic!("seed {seed}: {errs:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn side_effect_in_pure_function() {
+        let mb = test_module_builder();
+        let mut callee_sig = Signature::new("callee", Linkage::External, &[Type::I32], Type::I32);
+        callee_sig.set_pure(true);
+        let callee = mb.declare_function(callee_sig);
+        let mut sig = Signature::new("pure", Linkage::Public, &[Type::I32], Type::I32);
+        sig.set_pure(true);
+        let func_ref = mb.declare_function(sig);
+        let mut builder = mb.func_builder::<InstInserter>(func_ref);
+        let is = test_isa().inst_set();
+
+        let b0 = builder.append_block();
+        let arg = builder.args()[0];
+
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst(Call::new(is, callee, vec![arg].into()), Type::I32);
+        let v1 = builder.insert_inst(Mload::new(is, arg, Type::I32), Type::I32);
+        let load = builder.last_inst().unwrap();
+        builder.insert_inst_no_result(Mstore::new(is, arg, v0, Type::I32));
+        let store = builder.last_inst().unwrap();
+        builder.insert_inst_no_result(Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        module.func_store.view(func_ref, |func| {
+            // Calling a pure function is fine, but neither the load nor the
+            // store is.
+            let errs = verify_function(func, func_ref).unwrap_err();
+            let mut impure: Vec<_> = errs
+                .non_fatal_errors
+                .values()
+                .map(|err| match err.kind {
+                    ErrorKind::ImpureInstInPureFunction(inst) => inst,
+                    _ => panic!("unexpected error: {:?}", err.kind),
+                })
+                .collect();
+            impure.sort();
+            assert_eq!(impure, vec![load, store]);
+        });
+    }
+}
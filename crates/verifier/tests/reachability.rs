@@ -0,0 +1,58 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use sonatina_codegen::optim::dce::remove_unreachable_blocks;
+use sonatina_ir::{BlockId, ControlFlowGraph};
+use sonatina_parser::ParsedModule;
+use sonatina_verifier::{error::ErrorKind, verify_function, verify_function_with, VerifierConfig};
+
+fn parse_fixture(name: &str) -> ParsedModule {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let path: PathBuf = Path::new(&manifest_dir).join("tests/fixtures").join(name);
+    let content = std::fs::read_to_string(&path).unwrap();
+
+    match sonatina_parser::parse_module(&content) {
+        Ok(parsed) => parsed,
+        Err(errs) => {
+            let mut v = vec![];
+            for err in errs {
+                err.print(&mut v, name, &content, false).unwrap();
+            }
+            panic!("{}", String::from_utf8(v).unwrap());
+        }
+    }
+}
+
+#[test]
+fn unreachable_edge() {
+    let parsed = parse_fixture("unreachable_edge.sntn");
+    let module = parsed.module;
+    let func_ref = module.funcs()[0];
+    let config = VerifierConfig {
+        require_all_reachable: true,
+        ..Default::default()
+    };
+
+    module.func_store.modify(func_ref, |func| {
+        // Unreachable blocks are only reported on request.
+        assert!(verify_function(func, func_ref).is_ok());
+
+        let errs = verify_function_with(func, func_ref, &config).unwrap_err();
+        let unreachable: Vec<_> = errs
+            .non_fatal_errors
+            .values()
+            .map(|err| match err.kind {
+                ErrorKind::UnreachableBlock(block) => block,
+                _ => panic!("unexpected error: {:?}", err.kind),
+            })
+            .collect();
+        assert_eq!(unreachable, [BlockId(3)]);
+
+        let mut cfg = ControlFlowGraph::new();
+        cfg.compute(func);
+        assert!(remove_unreachable_blocks(func, &mut cfg));
+        assert!(verify_function_with(func, func_ref, &config).is_ok());
+    });
+}